@@ -1,4 +1,5 @@
 use std::fmt;
+use crate::span::Span;
 
 #[derive(Debug, Clone)]
 pub struct Program {
@@ -10,8 +11,13 @@ pub enum Statement {
     Expression(Expr),
     LetDeclaration {
         name: String,
+        /// The type written in `store name: type = ...`, if any.
+        var_type: Option<Type>,
         initializer: Option<Expr>,
         is_exported: bool,
+        /// The `///` doc comment written above the declaration.
+        doc: Option<String>,
+        span: Span,
     },
     FunctionDeclaration {
         name: String,
@@ -19,34 +25,76 @@ pub enum Statement {
         body: Vec<Statement>,
         return_type: Option<Type>,
         is_exported: bool,
+        /// Declared as `test def name() { ... }`, to be run by `nlang test`.
+        is_test: bool,
+        /// The `///` doc comment written above the declaration.
+        doc: Option<String>,
+        span: Span,
+    },
+    /// `struct Name { field: type, ... }`
+    StructDeclaration {
+        name: String,
+        fields: Vec<StructField>,
+        span: Span,
+    },
+    /// `enum Name { Variant, Variant(type, ...), ... }`
+    EnumDeclaration {
+        name: String,
+        variants: Vec<EnumVariant>,
+        span: Span,
     },
     Block {
         statements: Vec<Statement>,
+        span: Span,
     },
     If {
         condition: Box<Expr>,
         then_branch: Box<Statement>,
         else_branch: Option<Box<Statement>>,
+        span: Span,
     },
     While {
         condition: Box<Expr>,
         body: Box<Statement>,
+        span: Span,
+    },
+    /// `for (variable in iterable) body`, where `iterable` is either an
+    /// `Expr::Range` or an array.
+    For {
+        variable: String,
+        iterable: Box<Expr>,
+        body: Box<Statement>,
+        span: Span,
+    },
+    /// `match (scrutinee) { pattern => statement ... }`
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<MatchArm<Statement>>,
+        span: Span,
     },
     Return {
         value: Option<Box<Expr>>,
+        span: Span,
+    },
+    Break {
+        span: Span,
+    },
+    Continue {
+        span: Span,
     },
-    Break,
-    Continue,
     Import {
         module: String,
         alias: Option<String>,
+        span: Span,
     },
     ImportFrom {
         module: String,
         items: Vec<(String, Option<String>)>,
+        span: Span,
     },
     AssignMain {
         function_name: String,
+        span: Span,
     },
 }
 
@@ -54,6 +102,47 @@ pub enum Statement {
 pub struct Parameter {
     pub name: String,
     pub param_type: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructField {
+    pub name: String,
+    pub field_type: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumVariant {
+    pub name: String,
+    /// Payload types; empty for a unit variant.
+    pub fields: Vec<Type>,
+    pub span: Span,
+}
+
+/// One `pattern => body` arm of a match statement (`B = Statement`) or
+/// match expression (`B = Expr`).
+#[derive(Debug, Clone)]
+pub struct MatchArm<B> {
+    pub pattern: Pattern,
+    pub body: B,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// `_`, which matches anything without binding it
+    Wildcard { span: Span },
+    /// A name, which matches anything and binds it
+    Binding { name: String, span: Span },
+    Literal { value: Literal, span: Span },
+    /// `Enum::Variant` or `Enum::Variant(pattern, ...)`
+    Variant {
+        enum_name: String,
+        variant: String,
+        fields: Vec<Pattern>,
+        span: Span,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -63,51 +152,171 @@ pub enum Type {
     Boolean,
     String,
     Array(Box<Type>),
+    /// A user-defined struct or enum, referred to by name.
+    Named(String),
     Function { params: Vec<Type>, return_type: Box<Type> },
     Void,
+    /// The type of something that failed semantic analysis. It is compatible
+    /// with every other type so one mistake isn't reported over and over.
+    Error,
+    /// The type of a parameter written without one, until semantic analysis
+    /// infers it.
+    Unknown,
 }
 
 #[derive(Debug, Clone)]
 pub enum Expr {
-    Literal(Literal),
-    Variable(String),
+    Literal {
+        value: Literal,
+        span: Span,
+    },
+    Variable {
+        name: String,
+        span: Span,
+    },
     Binary {
         left: Box<Expr>,
         operator: BinaryOperator,
         right: Box<Expr>,
+        span: Span,
     },
     Unary {
         operator: UnaryOperator,
         operand: Box<Expr>,
+        span: Span,
     },
     Call {
         callee: Box<Expr>,
         arguments: Vec<Expr>,
+        span: Span,
     },
     Function {
         parameters: Vec<Parameter>,
         body: Vec<Statement>,
         return_type: Option<Type>,
+        span: Span,
     },
     Get {
         object: Box<Expr>,
         name: String,
+        span: Span,
     },
     Set {
         object: Box<Expr>,
         name: String,
         value: Box<Expr>,
+        span: Span,
     },
     Index {
         sequence: Box<Expr>,
         index: Box<Expr>,
+        span: Span,
+    },
+    /// `sequence[index] = value`
+    SetIndex {
+        sequence: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+        span: Span,
+    },
+    /// Struct construction `Name { field: value, ... }`
+    StructLiteral {
+        name: String,
+        fields: Vec<(String, Expr)>,
+        span: Span,
+    },
+    /// Enum construction `Enum::Variant` or `Enum::Variant(a, b)`
+    EnumVariant {
+        enum_name: String,
+        variant: String,
+        arguments: Vec<Expr>,
+        span: Span,
+    },
+    /// `match (scrutinee) { pattern => expression, ... }`
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<MatchArm<Expr>>,
+        span: Span,
+    },
+    /// Array literal `[a, b, c]`
+    Array {
+        elements: Vec<Expr>,
+        span: Span,
     },
     Assign {
         name: String,
         value: Box<Expr>,
+        span: Span,
+    },
+    /// Half-open integer range `start..end`, only valid as a `for` iterable.
+    Range {
+        start: Box<Expr>,
+        end: Box<Expr>,
+        span: Span,
+    },
+    /// Interpolated string `f"text {expr} text {expr:spec}"`
+    FormatString {
+        parts: Vec<FormatPart>,
+        span: Span,
     },
 }
 
+#[derive(Debug, Clone)]
+pub enum FormatPart {
+    /// Text, with its escapes and `{{`/`}}` already decoded
+    Literal(String),
+    /// `{expr}` or `{expr:spec}`, which is written like `str(expr)` would
+    /// be, then padded as the spec asks.
+    Expr { expr: Box<Expr>, spec: FormatSpec },
+}
+
+/// The `[<|>][0][width][.precision]` after the colon in `{expr:spec}`. Values
+/// are right-aligned unless the spec starts with `<`; `0` pads numbers with
+/// zeros after their sign rather than with spaces; a precision sets how many
+/// digits a float has after the point.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FormatSpec {
+    pub left_align: bool,
+    pub zero_pad: bool,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+}
+
+impl FormatSpec {
+    /// Pads `text`, which is already written with the precision, to the width.
+    pub fn pad(&self, text: &str) -> String {
+        let width = self.width.unwrap_or(0);
+        let fill = width.saturating_sub(text.chars().count());
+        if self.left_align {
+            format!("{}{}", text, " ".repeat(fill))
+        } else if self.zero_pad {
+            let digits = text.strip_prefix('-').unwrap_or(text);
+            format!("{}{}{}", &text[..text.len() - digits.len()], "0".repeat(fill), digits)
+        } else {
+            format!("{}{}", " ".repeat(fill), text)
+        }
+    }
+    
+    /// The flags, width and precision of the `printf` conversion that
+    /// formats like this spec, e.g. `-8` or `06.2`.
+    pub fn printf_flags(&self) -> String {
+        let mut flags = String::new();
+        if self.left_align {
+            flags.push('-');
+        }
+        if self.zero_pad {
+            flags.push('0');
+        }
+        if let Some(width) = self.width {
+            flags.push_str(&width.to_string());
+        }
+        if let Some(precision) = self.precision {
+            flags.push_str(&format!(".{}", precision));
+        }
+        flags
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Literal {
     Integer(i64),
@@ -140,6 +349,64 @@ pub enum UnaryOperator {
     Not,
 }
 
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Expression(expr) => expr.span(),
+            Statement::LetDeclaration { span, .. }
+            | Statement::FunctionDeclaration { span, .. }
+            | Statement::StructDeclaration { span, .. }
+            | Statement::EnumDeclaration { span, .. }
+            | Statement::Match { span, .. }
+            | Statement::Block { span, .. }
+            | Statement::If { span, .. }
+            | Statement::While { span, .. }
+            | Statement::For { span, .. }
+            | Statement::Return { span, .. }
+            | Statement::Break { span }
+            | Statement::Continue { span }
+            | Statement::Import { span, .. }
+            | Statement::ImportFrom { span, .. }
+            | Statement::AssignMain { span, .. } => *span,
+        }
+    }
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Wildcard { span }
+            | Pattern::Binding { span, .. }
+            | Pattern::Literal { span, .. }
+            | Pattern::Variant { span, .. } => *span,
+        }
+    }
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal { span, .. }
+            | Expr::Variable { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Function { span, .. }
+            | Expr::Get { span, .. }
+            | Expr::Set { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::SetIndex { span, .. }
+            | Expr::StructLiteral { span, .. }
+            | Expr::EnumVariant { span, .. }
+            | Expr::Match { span, .. }
+            | Expr::Array { span, .. }
+            | Expr::Assign { span, .. }
+            | Expr::Range { span, .. }
+            | Expr::FormatString { span, .. } => *span,
+        }
+    }
+}
+
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -148,11 +415,18 @@ impl fmt::Display for Type {
             Type::Boolean => write!(f, "bool"),
             Type::String => write!(f, "string"),
             Type::Array(inner) => write!(f, "array[{}]", inner),
+            Type::Named(name) => write!(f, "{}", name),
             Type::Function { params, return_type } => {
                 let param_types: Vec<String> = params.iter().map(|p| format!("{}", p)).collect();
-                write!(f, "fn({}) -> {}", param_types.join(", "), return_type)
+                write!(f, "def({})", param_types.join(", "))?;
+                if **return_type != Type::Void {
+                    write!(f, ": {}", return_type)?;
+                }
+                Ok(())
             }
             Type::Void => write!(f, "void"),
+            Type::Error => write!(f, "{{error}}"),
+            Type::Unknown => write!(f, "{{unknown}}"),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,1130 @@
+//! Compiles an analyzed [`Program`] into a bytecode [`Module`]
+//!
+//! The compiler tracks how many values each function has on the VM stack at
+//! every instruction, so a local variable is simply the stack slot its
+//! initializer was pushed to. A local that some lambda refers to is moved
+//! into a shared cell as soon as it is defined, and lambdas capture those
+//! cells, so assignments on either side stay visible to the other.
+
+use super::{
+    Builtin, Capture, CompileError, Failure, Function, Instruction, Module, StructLayout, StructShape, Value,
+    VariantInfo,
+};
+use crate::ast::{BinaryOperator, Expr, FormatPart, FormatSpec, Literal, MatchArm, Parameter, Pattern, Program, Statement, UnaryOperator};
+use crate::lexer::Lexer;
+use crate::parser::parse_strict;
+use crate::span::Span;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::rc::Rc;
+
+/// Compiles `program`, resolving imports relative to `file_path`.
+pub fn compile(program: &Program, file_path: Option<&Path>) -> Result<Module, CompileError> {
+    let mut compiler = Compiler::default();
+    compiler.define_global("PI");
+
+    // Imported functions and constants come first, so the program's own
+    // declarations take precedence over them
+    let mut functions = Vec::new();
+    let mut constants = Vec::new();
+    for statement in &program.statements {
+        match statement {
+            Statement::Import { module, alias, span } => {
+                let module_program = parse_module(module, file_path).map_err(|message| CompileError::new(message, *span))?;
+                let namespace = alias.as_deref().unwrap_or(module);
+                for statement in module_program.statements {
+                    match statement {
+                        Statement::FunctionDeclaration { name, parameters, body, is_exported: true, .. } => {
+                            let qualified_name = format!("{}.{}", namespace, name);
+                            functions.push(PendingFunction { name: qualified_name, parameters, body, namespace: Some(namespace.to_string()) });
+                        }
+                        Statement::LetDeclaration { name, initializer: Some(initializer), is_exported: true, .. } => {
+                            constants.push((format!("{}.{}", namespace, name), initializer));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Statement::ImportFrom { module, items, span } => {
+                let module_program = parse_module(module, file_path).map_err(|message| CompileError::new(message, *span))?;
+                for (item_name, alias) in items {
+                    let local_name = alias.as_ref().unwrap_or(item_name);
+                    for statement in &module_program.statements {
+                        match statement {
+                            Statement::FunctionDeclaration { name, parameters, body, is_exported: true, .. } if name == item_name => {
+                                functions.push(PendingFunction {
+                                    name: local_name.clone(),
+                                    parameters: parameters.clone(),
+                                    body: body.clone(),
+                                    namespace: None,
+                                });
+                            }
+                            Statement::LetDeclaration { name, initializer: Some(initializer), is_exported: true, .. } if name == item_name => {
+                                constants.push((local_name.clone(), initializer.clone()));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for statement in &program.statements {
+        match statement {
+            Statement::FunctionDeclaration { name, parameters, body, .. } => {
+                functions.push(PendingFunction { name: name.clone(), parameters: parameters.clone(), body: body.clone(), namespace: None });
+            }
+            Statement::StructDeclaration { name, fields, .. } => {
+                let layout = StructLayout {
+                    name: name.as_str().into(),
+                    fields: fields.iter().map(|field| field.name.as_str().into()).collect(),
+                };
+                compiler.struct_layouts.insert(name.clone(), Rc::new(layout));
+            }
+            Statement::LetDeclaration { name, .. } => {
+                compiler.define_global(name);
+            }
+            _ => {}
+        }
+    }
+    for (name, _) in &constants {
+        compiler.define_global(name);
+    }
+
+    // Every function gets its index before any body is compiled, so calls
+    // can refer to functions declared later
+    let mut indices = Vec::with_capacity(functions.len());
+    for function in &functions {
+        let index = compiler.reserve_function();
+        compiler.function_indices.insert(function.name.clone(), index);
+        indices.push(index);
+    }
+    for (function, index) in functions.iter().zip(indices) {
+        let compiled = compiler.compile_function(function)?;
+        compiler.module.functions[index as usize] = Rc::new(compiled);
+    }
+
+    let entry = compiler.compile_script(program, &constants)?;
+    compiler.module.entry = entry as usize;
+    Ok(compiler.module)
+}
+
+/// Reads and parses the module imported as `module_path`, which is relative
+/// to the directory of the importing file.
+fn parse_module(module_path: &str, importing_file: Option<&Path>) -> Result<Program, String> {
+    let file_path = match importing_file {
+        Some(importing_file) => importing_file.parent().unwrap_or(Path::new(".")).join(format!("{}.nlang", module_path)),
+        None => format!("{}.nlang", module_path).into(),
+    };
+    let content = std::fs::read_to_string(&file_path)
+        .map_err(|_| format!("Could not read module file: {}", file_path.display()))?;
+    let tokens = Lexer::new(&content).tokenize()
+        .map_err(|e| format!("Lexer error in module {}: {:?}", module_path, e))?;
+    parse_strict(&tokens).map_err(|e| format!("Parser error in module {}: {}", module_path, e))
+}
+
+struct PendingFunction {
+    name: String,
+    parameters: Vec<Parameter>,
+    body: Vec<Statement>,
+    /// The namespace of the module a function was imported from, whose
+    /// other exported functions it can call without qualifying them.
+    namespace: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    /// The top-level code. Variables it declares outside any block are globals.
+    Script,
+    Declared,
+    Lambda,
+}
+
+struct Local {
+    name: String,
+    slot: u32,
+    boxed: bool,
+    scope_depth: usize,
+}
+
+struct Loop {
+    /// The stack depth that `break` and `continue` unwind to.
+    stack_depth: u32,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+/// A function whose body is being compiled.
+struct FunctionState {
+    function: Function,
+    kind: FunctionKind,
+    namespace: Option<String>,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    /// The number of values on the stack above the frame base.
+    stack_depth: u32,
+    /// Names used inside lambdas in this function's body. Locals with these
+    /// names are boxed so the lambdas can capture them.
+    captured_names: HashSet<String>,
+    loops: Vec<Loop>,
+}
+
+impl FunctionState {
+    fn new(name: String, kind: FunctionKind, namespace: Option<String>, body: &[Statement]) -> Self {
+        let mut captured_names = HashSet::new();
+        for statement in body {
+            collect_statement_names(statement, false, &mut captured_names);
+        }
+        FunctionState {
+            function: Function { name, is_lambda: kind == FunctionKind::Lambda, ..Function::default() },
+            kind,
+            namespace,
+            locals: Vec::new(),
+            scope_depth: 0,
+            stack_depth: 0,
+            captured_names,
+            loops: Vec::new(),
+        }
+    }
+
+    fn find_local(&self, name: &str) -> Option<&Local> {
+        self.locals.iter().rev().find(|local| local.name == name)
+    }
+}
+
+/// Where a variable lives.
+#[derive(Debug, Clone, Copy)]
+enum Resolved {
+    Local { slot: u32, boxed: bool },
+    Upvalue(u32),
+    Global(u32),
+}
+
+#[derive(Default)]
+struct Compiler {
+    module: Module,
+    /// The function being compiled and those enclosing it, innermost last.
+    states: Vec<FunctionState>,
+    function_indices: HashMap<String, u32>,
+    global_indices: HashMap<String, u32>,
+    struct_layouts: HashMap<String, Rc<StructLayout>>,
+    variant_indices: HashMap<(String, String), u32>,
+}
+
+impl Compiler {
+    fn define_global(&mut self, name: &str) -> u32 {
+        if let Some(&index) = self.global_indices.get(name) {
+            return index;
+        }
+        let index = self.module.globals.len() as u32;
+        self.module.globals.push(name.to_string());
+        self.global_indices.insert(name.to_string(), index);
+        index
+    }
+
+    fn reserve_function(&mut self) -> u32 {
+        self.module.functions.push(Rc::new(Function::default()));
+        (self.module.functions.len() - 1) as u32
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        self.module.constants.push(value);
+        (self.module.constants.len() - 1) as u32
+    }
+
+    fn failure(&mut self, failure: Failure) -> u32 {
+        self.module.failures.push(failure);
+        (self.module.failures.len() - 1) as u32
+    }
+
+    fn format_spec(&mut self, spec: &FormatSpec) -> u32 {
+        self.module.format_specs.push(spec.clone());
+        (self.module.format_specs.len() - 1) as u32
+    }
+
+    fn variant(&mut self, enum_name: &str, variant: &str) -> u32 {
+        let key = (enum_name.to_string(), variant.to_string());
+        if let Some(&index) = self.variant_indices.get(&key) {
+            return index;
+        }
+        let index = self.module.variants.len() as u32;
+        self.module.variants.push(Rc::new(VariantInfo { enum_name: enum_name.into(), name: variant.into() }));
+        self.variant_indices.insert(key, index);
+        index
+    }
+
+    fn state(&mut self) -> &mut FunctionState {
+        self.states.last_mut().expect("no function is being compiled")
+    }
+
+    /// Appends an instruction and returns its index.
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        let effect = self.stack_effect(instruction);
+        let state = self.state();
+        state.stack_depth = (state.stack_depth as i64 + effect) as u32;
+        state.function.code.push(instruction);
+        state.function.spans.push(span);
+        state.function.code.len() - 1
+    }
+
+    /// How many values `instruction` adds to the stack, or removes if negative.
+    fn stack_effect(&self, instruction: Instruction) -> i64 {
+        use Instruction::*;
+        match instruction {
+            Constant(_) | GetLocal(_) | GetBoxed(_) | GetUpvalue(_) | GetGlobal(_) | FunctionValue(_) | Closure(_) | Fail(_) => 1,
+            Pop | DefineGlobal(_) | JumpIfFalse(_) | Return | MatchFailed | Index | SetField(_) => -1,
+            PopN(n) => -(n as i64),
+            SetLocal(_) | BoxLocal(_) | SetBoxed(_) | SetUpvalue(_) | SetGlobal(_) | Negate | Not | Jump(_)
+            | Snapshot | GetField(_) | IsVariant(_) | EnumField(_) | Format(_) => 0,
+            Add | Subtract | Multiply | Divide | Modulo | Equal | NotEqual | Less | LessEqual | Greater
+            | GreaterEqual | And | Or => -1,
+            CallFunction { arguments, .. } | CallBuiltin { arguments, .. } | Enum { arguments, .. } => 1 - arguments as i64,
+            Call(arguments) => -(arguments as i64),
+            Array(n) | Concat(n) => 1 - n as i64,
+            SetIndex => -2,
+            Struct(shape) => 1 - self.module.struct_shapes[shape as usize].positions.len() as i64,
+        }
+    }
+
+    fn current_offset(&mut self) -> usize {
+        self.state().function.code.len()
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch_jump(&mut self, at: usize) {
+        let target = self.current_offset() as u32;
+        self.patch_jump_to(at, target);
+    }
+
+    fn patch_jump_to(&mut self, at: usize, target: u32) {
+        match &mut self.state().function.code[at] {
+            Instruction::Jump(offset) | Instruction::JumpIfFalse(offset) => *offset = target,
+            other => unreachable!("patching {:?}, which is not a jump", other),
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.state().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self, span: Span) {
+        let state = self.state();
+        let depth = state.scope_depth;
+        state.scope_depth -= 1;
+        let count = state.locals.iter().rev().take_while(|local| local.scope_depth == depth).count();
+        let remaining = state.locals.len() - count;
+        state.locals.truncate(remaining);
+        if count > 0 {
+            self.emit(Instruction::PopN(count as u32), span);
+        }
+    }
+
+    /// Turns the value on top of the stack into a new local variable.
+    fn declare_local(&mut self, name: &str, span: Span) {
+        let state = self.state();
+        let slot = state.stack_depth - 1;
+        let boxed = state.captured_names.contains(name);
+        let scope_depth = state.scope_depth;
+        state.locals.push(Local { name: name.to_string(), slot, boxed, scope_depth });
+        if boxed {
+            self.emit(Instruction::BoxLocal(slot), span);
+        }
+    }
+
+    /// Defines a local from the value on top of the stack. Defining a name
+    /// again in the same scope assigns to the existing variable, as the
+    /// interpreter does.
+    fn define_local(&mut self, name: &str, span: Span) {
+        let state = self.state();
+        let existing = state.locals.iter()
+            .rev()
+            .take_while(|local| local.scope_depth == state.scope_depth)
+            .find(|local| local.name == name)
+            .map(|local| (local.slot, local.boxed));
+        match existing {
+            Some((slot, boxed)) => {
+                self.emit(if boxed { Instruction::SetBoxed(slot) } else { Instruction::SetLocal(slot) }, span);
+                self.emit(Instruction::Pop, span);
+            }
+            None => self.declare_local(name, span),
+        }
+    }
+
+    fn resolve(&mut self, name: &str) -> Option<Resolved> {
+        let innermost = self.states.len() - 1;
+        self.resolve_in(innermost, name)
+            .or_else(|| self.global_indices.get(name).map(|&index| Resolved::Global(index)))
+    }
+
+    /// Resolves `name` to a local of `self.states[state]` or, for lambdas,
+    /// to a variable they capture from the functions enclosing them.
+    fn resolve_in(&mut self, state: usize, name: &str) -> Option<Resolved> {
+        if let Some(local) = self.states[state].find_local(name) {
+            return Some(Resolved::Local { slot: local.slot, boxed: local.boxed });
+        }
+        if self.states[state].kind != FunctionKind::Lambda || state == 0 {
+            return None;
+        }
+        let capture = match self.resolve_in(state - 1, name)? {
+            Resolved::Local { slot, boxed } => {
+                debug_assert!(boxed, "captured local '{}' was not boxed", name);
+                Capture::Local(slot)
+            }
+            Resolved::Upvalue(index) => Capture::Upvalue(index),
+            Resolved::Global(_) => return None,
+        };
+        let captures = &mut self.states[state].function.captures;
+        let index = match captures.iter().position(|existing| *existing == capture) {
+            Some(index) => index,
+            None => {
+                captures.push(capture);
+                captures.len() - 1
+            }
+        };
+        Some(Resolved::Upvalue(index as u32))
+    }
+
+    /// Finds a declared or imported function by the name it is called by.
+    fn resolve_function(&mut self, name: &str) -> Option<u32> {
+        if let Some(&index) = self.function_indices.get(name) {
+            return Some(index);
+        }
+        // Functions in an imported module can call each other unqualified
+        let namespace = self.states.iter().rev().find_map(|state| state.namespace.clone())?;
+        self.function_indices.get(&format!("{}.{}", namespace, name)).copied()
+    }
+
+    fn compile_function(&mut self, pending: &PendingFunction) -> Result<Function, CompileError> {
+        let state = FunctionState::new(pending.name.clone(), FunctionKind::Declared, pending.namespace.clone(), &pending.body);
+        self.compile_body(state, &pending.parameters, &pending.body)
+    }
+
+    /// Compiles a function body in a new frame, with its parameters in the
+    /// first slots.
+    fn compile_body(&mut self, state: FunctionState, parameters: &[Parameter], body: &[Statement]) -> Result<Function, CompileError> {
+        self.states.push(state);
+        self.state().function.arity = parameters.len();
+        self.state().scope_depth = 1;
+        for parameter in parameters {
+            self.state().stack_depth += 1;
+            self.declare_local(&parameter.name, parameter.span);
+        }
+        let result = self.compile_statements(body);
+        let end = body.last().map(Statement::span).unwrap_or_default();
+        self.emit_default_return(end);
+        let state = self.states.pop().expect("function state was pushed above");
+        result.map(|()| state.function)
+    }
+
+    fn emit_default_return(&mut self, span: Span) {
+        let zero = self.constant(Value::Integer(0));
+        self.emit(Instruction::Constant(zero), span);
+        self.emit(Instruction::Return, span);
+    }
+
+    /// Compiles the code that runs first: it defines PI and the imported
+    /// constants, then either initializes the globals and calls `main` or
+    /// runs the top-level statements in order.
+    fn compile_script(&mut self, program: &Program, constants: &[(String, Expr)]) -> Result<u32, CompileError> {
+        let index = self.reserve_function();
+        self.states.push(FunctionState::new("<script>".to_string(), FunctionKind::Script, None, &program.statements));
+        let result = self.compile_script_body(program, constants);
+        let state = self.states.pop().expect("script state was pushed above");
+        result?;
+        self.module.functions[index as usize] = Rc::new(state.function);
+        Ok(index)
+    }
+
+    fn compile_script_body(&mut self, program: &Program, constants: &[(String, Expr)]) -> Result<(), CompileError> {
+        let pi = self.constant(Value::Float(std::f64::consts::PI));
+        self.emit(Instruction::Constant(pi), Span::default());
+        self.emit(Instruction::DefineGlobal(self.global_indices["PI"]), Span::default());
+        for (name, initializer) in constants {
+            self.compile_expr(initializer)?;
+            self.emit(Instruction::DefineGlobal(self.global_indices[name]), initializer.span());
+        }
+
+        match self.function_indices.get("main").copied() {
+            Some(main) => {
+                for statement in &program.statements {
+                    if let Statement::LetDeclaration { .. } = statement {
+                        self.compile_statement(statement)?;
+                    }
+                }
+                let span = program.statements.iter()
+                    .find(|statement| matches!(statement, Statement::FunctionDeclaration { name, .. } if name == "main"))
+                    .map(Statement::span)
+                    .unwrap_or_default();
+                self.emit(Instruction::CallFunction { function: main, arguments: 0 }, span);
+                self.emit(Instruction::Return, span);
+            }
+            None => {
+                self.compile_statements(&program.statements)?;
+                let end = program.statements.last().map(Statement::span).unwrap_or_default();
+                self.emit_default_return(end);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_statements(&mut self, statements: &[Statement]) -> Result<(), CompileError> {
+        for statement in statements {
+            self.compile_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    /// Compiles a statement that runs conditionally or repeatedly, in its
+    /// own scope so the stack is the same on every path through it.
+    fn compile_branch(&mut self, statement: &Statement) -> Result<(), CompileError> {
+        self.begin_scope();
+        let result = self.compile_statement(statement);
+        self.end_scope(statement.span());
+        result
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
+        match statement {
+            Statement::Expression(expr) => {
+                self.compile_expr(expr)?;
+                self.emit(Instruction::Pop, expr.span());
+            }
+            Statement::LetDeclaration { name, initializer, span, .. } => {
+                match initializer {
+                    Some(initializer) => self.compile_expr(initializer)?,
+                    None => {
+                        let zero = self.constant(Value::Integer(0));
+                        self.emit(Instruction::Constant(zero), *span);
+                    }
+                }
+                let is_global = self.state().kind == FunctionKind::Script && self.state().scope_depth == 0;
+                if is_global {
+                    let index = self.define_global(name);
+                    self.emit(Instruction::DefineGlobal(index), *span);
+                } else {
+                    self.define_local(name, *span);
+                }
+            }
+            Statement::Block { statements, span } => {
+                self.begin_scope();
+                let result = self.compile_statements(statements);
+                self.end_scope(*span);
+                result?;
+            }
+            Statement::If { condition, then_branch, else_branch, span } => {
+                self.compile_expr(condition)?;
+                let else_jump = self.emit(Instruction::JumpIfFalse(0), condition.span());
+                self.compile_branch(then_branch)?;
+                match else_branch {
+                    Some(else_branch) => {
+                        let end_jump = self.emit(Instruction::Jump(0), *span);
+                        self.patch_jump(else_jump);
+                        self.compile_branch(else_branch)?;
+                        self.patch_jump(end_jump);
+                    }
+                    None => self.patch_jump(else_jump),
+                }
+            }
+            Statement::While { condition, body, span } => {
+                let start = self.current_offset() as u32;
+                self.compile_expr(condition)?;
+                let exit_jump = self.emit(Instruction::JumpIfFalse(0), condition.span());
+                self.compile_loop_body(body, start)?;
+                self.emit(Instruction::Jump(start), *span);
+                self.patch_jump(exit_jump);
+                self.finish_loop();
+            }
+            Statement::For { variable, iterable, body, span } => {
+                self.compile_for(variable, iterable, body, *span)?;
+            }
+            Statement::Match { scrutinee, arms, span } => {
+                self.compile_match(scrutinee, arms, *span, |compiler, body| compiler.compile_statement(body))?;
+                self.emit(Instruction::Pop, *span);
+            }
+            Statement::Return { value, span } => {
+                let depth = self.state().stack_depth;
+                match value {
+                    Some(value) => self.compile_expr(value)?,
+                    None => {
+                        let zero = self.constant(Value::Integer(0));
+                        self.emit(Instruction::Constant(zero), *span);
+                    }
+                }
+                self.emit(Instruction::Return, *span);
+                self.state().stack_depth = depth;
+            }
+            Statement::Break { span } | Statement::Continue { span } => {
+                let is_break = matches!(statement, Statement::Break { .. });
+                let Some(target_depth) = self.state().loops.last().map(|inner| inner.stack_depth) else {
+                    let keyword = if is_break { "break" } else { "continue" };
+                    return Err(CompileError::new(format!("'{}' outside of a loop", keyword), *span));
+                };
+                let depth = self.state().stack_depth;
+                if depth > target_depth {
+                    self.emit(Instruction::PopN(depth - target_depth), *span);
+                }
+                let jump = self.emit(Instruction::Jump(0), *span);
+                let inner = self.state().loops.last_mut().expect("checked above");
+                if is_break {
+                    inner.breaks.push(jump);
+                } else {
+                    inner.continues.push(jump);
+                }
+                self.state().stack_depth = depth;
+            }
+            // Declarations were collected up front, and nested function
+            // declarations are ignored like in the interpreter
+            Statement::FunctionDeclaration { .. }
+            | Statement::StructDeclaration { .. }
+            | Statement::EnumDeclaration { .. }
+            | Statement::Import { .. }
+            | Statement::ImportFrom { .. }
+            | Statement::AssignMain { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Compiles a loop body whose `continue`s jump to `continue_target`,
+    /// which may be patched later by the caller.
+    fn compile_loop_body(&mut self, body: &Statement, continue_target: u32) -> Result<(), CompileError> {
+        let stack_depth = self.state().stack_depth;
+        self.state().loops.push(Loop { stack_depth, breaks: Vec::new(), continues: Vec::new() });
+        self.compile_branch(body)?;
+        let continues = std::mem::take(&mut self.state().loops.last_mut().expect("pushed above").continues);
+        for jump in continues {
+            self.patch_jump_to(jump, continue_target);
+        }
+        Ok(())
+    }
+
+    /// Points the `break`s of the innermost loop at the next instruction.
+    fn finish_loop(&mut self) {
+        let finished = self.state().loops.pop().expect("a loop is being compiled");
+        for jump in finished.breaks {
+            self.patch_jump(jump);
+        }
+    }
+
+    fn compile_for(&mut self, variable: &str, iterable: &Expr, body: &Statement, span: Span) -> Result<(), CompileError> {
+        // The loop keeps its position, and the array or the end of the range,
+        // in two unnamed locals below the loop variable
+        let (position, bound) = match iterable {
+            Expr::Range { start, end, .. } => {
+                self.compile_expr(start)?;
+                self.compile_expr(end)?;
+                let depth = self.state().stack_depth;
+                (depth - 2, depth - 1)
+            }
+            _ => {
+                let zero = self.constant(Value::Integer(0));
+                self.emit(Instruction::Constant(zero), span);
+                self.compile_expr(iterable)?;
+                self.emit(Instruction::Snapshot, iterable.span());
+                let depth = self.state().stack_depth;
+                (depth - 2, depth - 1)
+            }
+        };
+        let is_range = matches!(iterable, Expr::Range { .. });
+
+        let start = self.current_offset() as u32;
+        self.emit(Instruction::GetLocal(position), span);
+        self.emit(Instruction::GetLocal(bound), span);
+        if !is_range {
+            self.emit(Instruction::CallBuiltin { builtin: Builtin::Len, arguments: 1 }, span);
+        }
+        self.emit(Instruction::Less, span);
+        let exit_jump = self.emit(Instruction::JumpIfFalse(0), span);
+
+        // A new loop variable for each iteration, so closures created in the
+        // body keep the value they saw
+        self.begin_scope();
+        if is_range {
+            self.emit(Instruction::GetLocal(position), span);
+        } else {
+            self.emit(Instruction::GetLocal(bound), span);
+            self.emit(Instruction::GetLocal(position), span);
+            self.emit(Instruction::Index, span);
+        }
+        self.declare_local(variable, span);
+
+        let stack_depth = self.state().stack_depth - 1;
+        self.state().loops.push(Loop { stack_depth, breaks: Vec::new(), continues: Vec::new() });
+        self.compile_branch(body)?;
+        self.end_scope(span);
+
+        let increment = self.current_offset() as u32;
+        let continues = std::mem::take(&mut self.state().loops.last_mut().expect("pushed above").continues);
+        for jump in continues {
+            self.patch_jump_to(jump, increment);
+        }
+        let one = self.constant(Value::Integer(1));
+        self.emit(Instruction::GetLocal(position), span);
+        self.emit(Instruction::Constant(one), span);
+        self.emit(Instruction::Add, span);
+        self.emit(Instruction::SetLocal(position), span);
+        self.emit(Instruction::Pop, span);
+        self.emit(Instruction::Jump(start), span);
+
+        self.patch_jump(exit_jump);
+        self.finish_loop();
+        self.emit(Instruction::PopN(2), span);
+        Ok(())
+    }
+
+    /// Compiles a match, leaving one value on the stack: the scrutinee for a
+    /// match statement, or the value of the chosen arm for a match
+    /// expression, whose `compile_body` pushes it.
+    fn compile_match<B>(
+        &mut self,
+        scrutinee: &Expr,
+        arms: &[MatchArm<B>],
+        span: Span,
+        compile_body: impl Fn(&mut Self, &B) -> Result<(), CompileError>,
+    ) -> Result<(), CompileError> {
+        self.compile_expr(scrutinee)?;
+        let slot = self.state().stack_depth - 1;
+        let mut end_jumps = Vec::new();
+
+        for arm in arms {
+            let mut next_arm = Vec::new();
+            self.compile_pattern_test(&arm.pattern, slot, &mut Vec::new(), &mut next_arm);
+
+            self.begin_scope();
+            self.bind_pattern(&arm.pattern, slot, &mut Vec::new());
+            let before = self.state().stack_depth;
+            compile_body(self, &arm.body)?;
+            if self.state().stack_depth > before {
+                // The arm's value replaces the scrutinee
+                self.emit(Instruction::SetLocal(slot), arm.span);
+                self.emit(Instruction::Pop, arm.span);
+            }
+            self.end_scope(arm.span);
+            end_jumps.push(self.emit(Instruction::Jump(0), arm.span));
+
+            for jump in next_arm {
+                self.patch_jump(jump);
+            }
+        }
+
+        self.emit(Instruction::GetLocal(slot), span);
+        self.emit(Instruction::MatchFailed, span);
+        for jump in end_jumps {
+            self.patch_jump(jump);
+        }
+        Ok(())
+    }
+
+    /// Pushes the part of the scrutinee in `slot` found by following `path`
+    /// through enum fields.
+    fn load_path(&mut self, slot: u32, path: &[u32], span: Span) {
+        self.emit(Instruction::GetLocal(slot), span);
+        for &field in path {
+            self.emit(Instruction::EnumField(field), span);
+        }
+    }
+
+    /// Emits the checks that jump to one of `failures` unless the value at
+    /// `path` matches `pattern`.
+    fn compile_pattern_test(&mut self, pattern: &Pattern, slot: u32, path: &mut Vec<u32>, failures: &mut Vec<usize>) {
+        match pattern {
+            Pattern::Wildcard { .. } | Pattern::Binding { .. } => {}
+            Pattern::Literal { value, span } => {
+                match value {
+                    // Nothing matches a null pattern
+                    Literal::Null => {
+                        let no = self.constant(Value::Boolean(false));
+                        self.emit(Instruction::Constant(no), *span);
+                    }
+                    literal => {
+                        self.load_path(slot, path, *span);
+                        let constant = self.constant(literal_value(literal));
+                        self.emit(Instruction::Constant(constant), *span);
+                        self.emit(Instruction::Equal, *span);
+                    }
+                }
+                failures.push(self.emit(Instruction::JumpIfFalse(0), *span));
+            }
+            Pattern::Variant { enum_name, variant, fields, span } => {
+                self.load_path(slot, path, *span);
+                let variant = self.variant(enum_name, variant);
+                self.emit(Instruction::IsVariant(variant), *span);
+                failures.push(self.emit(Instruction::JumpIfFalse(0), *span));
+                for (i, field) in fields.iter().enumerate() {
+                    path.push(i as u32);
+                    self.compile_pattern_test(field, slot, path, failures);
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    /// Defines the variables `pattern` binds, in the order they are written.
+    fn bind_pattern(&mut self, pattern: &Pattern, slot: u32, path: &mut Vec<u32>) {
+        match pattern {
+            Pattern::Binding { name, span } => {
+                self.load_path(slot, path, *span);
+                self.define_local(name, *span);
+            }
+            Pattern::Variant { fields, .. } => {
+                for (i, field) in fields.iter().enumerate() {
+                    path.push(i as u32);
+                    self.bind_pattern(field, slot, path);
+                    path.pop();
+                }
+            }
+            Pattern::Wildcard { .. } | Pattern::Literal { .. } => {}
+        }
+    }
+
+    fn emit_get(&mut self, resolved: Resolved, span: Span) {
+        let instruction = match resolved {
+            Resolved::Local { slot, boxed: false } => Instruction::GetLocal(slot),
+            Resolved::Local { slot, boxed: true } => Instruction::GetBoxed(slot),
+            Resolved::Upvalue(index) => Instruction::GetUpvalue(index),
+            Resolved::Global(index) => Instruction::GetGlobal(index),
+        };
+        self.emit(instruction, span);
+    }
+
+    fn emit_failure(&mut self, failure: Failure, span: Span) {
+        let index = self.failure(failure);
+        self.emit(Instruction::Fail(index), span);
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        match expr {
+            Expr::Literal { value, span } => {
+                let constant = self.constant(literal_value(value));
+                self.emit(Instruction::Constant(constant), *span);
+            }
+            Expr::Variable { name, span } => match self.resolve(name) {
+                Some(resolved) => self.emit_get(resolved, *span),
+                None => match self.resolve_function(name) {
+                    // A declared function used as a value
+                    Some(function) => {
+                        self.emit(Instruction::FunctionValue(function), *span);
+                    }
+                    None => self.emit_failure(Failure::VariableNotFound(name.clone()), *span),
+                },
+            },
+            Expr::Function { parameters, body, span, .. } => {
+                let namespace = self.state().namespace.clone();
+                let state = FunctionState::new("lambda".to_string(), FunctionKind::Lambda, namespace, body);
+                let function = self.compile_body(state, parameters, body)?;
+                let index = self.reserve_function();
+                self.module.functions[index as usize] = Rc::new(function);
+                self.emit(Instruction::Closure(index), *span);
+            }
+            Expr::Binary { left, operator, right, span } => {
+                self.compile_expr(left)?;
+                self.compile_expr(right)?;
+                let instruction = match operator {
+                    BinaryOperator::Plus => Instruction::Add,
+                    BinaryOperator::Minus => Instruction::Subtract,
+                    BinaryOperator::Star => Instruction::Multiply,
+                    BinaryOperator::Slash => Instruction::Divide,
+                    BinaryOperator::Percent => Instruction::Modulo,
+                    BinaryOperator::EqualEqual => Instruction::Equal,
+                    BinaryOperator::NotEqual => Instruction::NotEqual,
+                    BinaryOperator::Less => Instruction::Less,
+                    BinaryOperator::LessEqual => Instruction::LessEqual,
+                    BinaryOperator::Greater => Instruction::Greater,
+                    BinaryOperator::GreaterEqual => Instruction::GreaterEqual,
+                    BinaryOperator::And => Instruction::And,
+                    BinaryOperator::Or => Instruction::Or,
+                };
+                self.emit(instruction, *span);
+            }
+            Expr::Unary { operator, operand, span } => {
+                self.compile_expr(operand)?;
+                let instruction = match operator {
+                    UnaryOperator::Negate => Instruction::Negate,
+                    UnaryOperator::Not => Instruction::Not,
+                };
+                self.emit(instruction, *span);
+            }
+            Expr::Call { callee, arguments, span } => self.compile_call(callee, arguments, *span)?,
+            Expr::Get { object, name, span } => {
+                // What's left of `namespace.NAME` after semantic analysis
+                // rewrote the imported constants
+                if let Expr::Variable { name: namespace, .. } = object.as_ref()
+                    && self.resolve(namespace).is_none() {
+                    if namespace == "math" && name == "PI" {
+                        let pi = self.constant(Value::Float(std::f64::consts::PI));
+                        self.emit(Instruction::Constant(pi), *span);
+                    } else {
+                        let message = match namespace.as_str() {
+                            "math" => format!("Unknown math property: {}", name),
+                            _ => format!("Unknown namespace: {}", namespace),
+                        };
+                        self.emit_failure(Failure::InvalidOperation(message), *span);
+                    }
+                    return Ok(());
+                }
+                self.compile_expr(object)?;
+                let field = self.constant(Value::String(name.as_str().into()));
+                self.emit(Instruction::GetField(field), *span);
+            }
+            Expr::Set { object, name, value, span } => {
+                self.compile_expr(object)?;
+                self.compile_expr(value)?;
+                let field = self.constant(Value::String(name.as_str().into()));
+                self.emit(Instruction::SetField(field), *span);
+            }
+            Expr::Index { sequence, index, span } => {
+                self.compile_expr(sequence)?;
+                self.compile_expr(index)?;
+                self.emit(Instruction::Index, *span);
+            }
+            Expr::SetIndex { sequence, index, value, span } => {
+                self.compile_expr(sequence)?;
+                self.compile_expr(index)?;
+                self.compile_expr(value)?;
+                self.emit(Instruction::SetIndex, *span);
+            }
+            Expr::StructLiteral { name, fields, span } => {
+                let layout = match self.struct_layouts.get(name) {
+                    Some(layout) => layout.clone(),
+                    None => Rc::new(StructLayout {
+                        name: name.as_str().into(),
+                        fields: fields.iter().map(|(field, _)| field.as_str().into()).collect(),
+                    }),
+                };
+                let mut positions = Vec::with_capacity(fields.len());
+                for (field, value) in fields {
+                    let position = layout.fields.iter()
+                        .position(|declared| **declared == **field)
+                        .ok_or_else(|| CompileError::new(format!("Struct '{}' has no field named '{}'", name, field), value.span()))?;
+                    positions.push(position);
+                    self.compile_expr(value)?;
+                }
+                self.module.struct_shapes.push(StructShape { layout, positions });
+                let shape = (self.module.struct_shapes.len() - 1) as u32;
+                self.emit(Instruction::Struct(shape), *span);
+            }
+            Expr::EnumVariant { enum_name, variant, arguments, span } => {
+                for argument in arguments {
+                    self.compile_expr(argument)?;
+                }
+                let variant = self.variant(enum_name, variant);
+                self.emit(Instruction::Enum { variant, arguments: arguments.len() as u32 }, *span);
+            }
+            Expr::Match { scrutinee, arms, span } => {
+                self.compile_match(scrutinee, arms, *span, |compiler, body| compiler.compile_expr(body))?;
+            }
+            Expr::Array { elements, span } => {
+                for element in elements {
+                    self.compile_expr(element)?;
+                }
+                self.emit(Instruction::Array(elements.len() as u32), *span);
+            }
+            Expr::Assign { name, value, span } => {
+                self.compile_expr(value)?;
+                let instruction = match self.resolve(name) {
+                    Some(Resolved::Local { slot, boxed: false }) => Instruction::SetLocal(slot),
+                    Some(Resolved::Local { slot, boxed: true }) => Instruction::SetBoxed(slot),
+                    Some(Resolved::Upvalue(index)) => Instruction::SetUpvalue(index),
+                    Some(Resolved::Global(index)) => Instruction::SetGlobal(index),
+                    None => {
+                        self.emit(Instruction::Pop, *span);
+                        self.emit_failure(Failure::VariableNotFound(name.clone()), *span);
+                        return Ok(());
+                    }
+                };
+                self.emit(instruction, *span);
+            }
+            Expr::Range { span, .. } => {
+                self.emit_failure(Failure::InvalidOperation("Expression type not yet supported".to_string()), *span);
+            }
+            Expr::FormatString { parts, span } => {
+                for part in parts {
+                    match part {
+                        FormatPart::Literal(text) => {
+                            let constant = self.constant(Value::String(text.as_str().into()));
+                            self.emit(Instruction::Constant(constant), *span);
+                        }
+                        FormatPart::Expr { expr, spec } => {
+                            self.compile_expr(expr)?;
+                            let spec = self.format_spec(spec);
+                            self.emit(Instruction::Format(spec), expr.span());
+                        }
+                    }
+                }
+                self.emit(Instruction::Concat(parts.len() as u32), *span);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_call(&mut self, callee: &Expr, arguments: &[Expr], span: Span) -> Result<(), CompileError> {
+        // Calls by name go to a function value held in a variable, then to a
+        // built-in, then to a declared function
+        let name = match callee {
+            Expr::Variable { name, .. } if self.resolve(name).is_none() => Some(name.clone()),
+            Expr::Get { object, name, .. }
+                if let Expr::Variable { name: namespace, .. } = object.as_ref()
+                    && self.resolve(namespace).is_none() => Some(format!("{}.{}", namespace, name)),
+            _ => None,
+        };
+        let arity = arguments.len() as u32;
+
+        let Some(name) = name else {
+            self.compile_expr(callee)?;
+            for argument in arguments {
+                self.compile_expr(argument)?;
+            }
+            self.emit(Instruction::Call(arity), span);
+            return Ok(());
+        };
+
+        if let Some(builtin) = Builtin::from_name(&name) {
+            if arguments.len() != builtin.arity() {
+                let plural = if builtin.arity() == 1 { "argument" } else { "arguments" };
+                let message = format!("{} function requires {} {}", name, builtin.arity(), plural);
+                self.emit_failure(Failure::InvalidOperation(message), span);
+                return Ok(());
+            }
+            for argument in arguments {
+                self.compile_expr(argument)?;
+            }
+            self.emit(Instruction::CallBuiltin { builtin, arguments: arity }, span);
+            return Ok(());
+        }
+
+        match self.resolve_function(&name) {
+            Some(function) => {
+                for argument in arguments {
+                    self.compile_expr(argument)?;
+                }
+                self.emit(Instruction::CallFunction { function, arguments: arity }, span);
+            }
+            None => self.emit_failure(Failure::FunctionNotFound(name), span),
+        }
+        Ok(())
+    }
+}
+
+fn literal_value(literal: &Literal) -> Value {
+    match literal {
+        Literal::Integer(i) => Value::Integer(*i),
+        Literal::Float(f) => Value::Float(*f),
+        Literal::Boolean(b) => Value::Boolean(*b),
+        Literal::String(s) => Value::String(s.as_str().into()),
+        Literal::Null => Value::Integer(0),
+    }
+}
+
+/// Adds the variable names used inside lambdas in `statement` to `names`.
+/// `in_lambda` is whether `statement` is itself inside one.
+fn collect_statement_names(statement: &Statement, in_lambda: bool, names: &mut HashSet<String>) {
+    let expr = |expr: &Expr, names: &mut HashSet<String>| collect_expr_names(expr, in_lambda, names);
+    match statement {
+        Statement::Expression(e) => expr(e, names),
+        Statement::LetDeclaration { initializer, .. } => {
+            if let Some(initializer) = initializer {
+                expr(initializer, names);
+            }
+        }
+        Statement::Block { statements, .. } => {
+            for statement in statements {
+                collect_statement_names(statement, in_lambda, names);
+            }
+        }
+        Statement::If { condition, then_branch, else_branch, .. } => {
+            expr(condition, names);
+            collect_statement_names(then_branch, in_lambda, names);
+            if let Some(else_branch) = else_branch {
+                collect_statement_names(else_branch, in_lambda, names);
+            }
+        }
+        Statement::While { condition, body, .. } => {
+            expr(condition, names);
+            collect_statement_names(body, in_lambda, names);
+        }
+        Statement::For { iterable, body, .. } => {
+            expr(iterable, names);
+            collect_statement_names(body, in_lambda, names);
+        }
+        Statement::Match { scrutinee, arms, .. } => {
+            expr(scrutinee, names);
+            for arm in arms {
+                collect_statement_names(&arm.body, in_lambda, names);
+            }
+        }
+        Statement::Return { value, .. } => {
+            if let Some(value) = value {
+                expr(value, names);
+            }
+        }
+        Statement::FunctionDeclaration { .. }
+        | Statement::StructDeclaration { .. }
+        | Statement::EnumDeclaration { .. }
+        | Statement::Break { .. }
+        | Statement::Continue { .. }
+        | Statement::Import { .. }
+        | Statement::ImportFrom { .. }
+        | Statement::AssignMain { .. } => {}
+    }
+}
+
+fn collect_expr_names(expr: &Expr, in_lambda: bool, names: &mut HashSet<String>) {
+    let mut visit = |expr: &Expr| collect_expr_names(expr, in_lambda, names);
+    match expr {
+        Expr::Variable { name, .. } => {
+            if in_lambda {
+                names.insert(name.clone());
+            }
+        }
+        Expr::Assign { name, value, .. } => {
+            visit(value);
+            if in_lambda {
+                names.insert(name.clone());
+            }
+        }
+        Expr::Function { body, .. } => {
+            for statement in body {
+                collect_statement_names(statement, true, names);
+            }
+        }
+        Expr::Literal { .. } => {}
+        Expr::Binary { left, right, .. } | Expr::Range { start: left, end: right, .. } => {
+            visit(left);
+            visit(right);
+        }
+        Expr::Unary { operand, .. } => visit(operand),
+        Expr::Call { callee, arguments, .. } => {
+            visit(callee);
+            arguments.iter().for_each(visit);
+        }
+        Expr::Get { object, .. } => visit(object),
+        Expr::Set { object, value, .. } => {
+            visit(object);
+            visit(value);
+        }
+        Expr::Index { sequence, index, .. } => {
+            visit(sequence);
+            visit(index);
+        }
+        Expr::SetIndex { sequence, index, value, .. } => {
+            visit(sequence);
+            visit(index);
+            visit(value);
+        }
+        Expr::StructLiteral { fields, .. } => fields.iter().for_each(|(_, value)| visit(value)),
+        Expr::EnumVariant { arguments, .. } => arguments.iter().for_each(visit),
+        Expr::Array { elements, .. } => elements.iter().for_each(visit),
+        Expr::FormatString { parts, .. } => {
+            for part in parts {
+                if let FormatPart::Expr { expr, .. } = part {
+                    visit(expr);
+                }
+            }
+        }
+        Expr::Match { scrutinee, arms, .. } => {
+            visit(scrutinee);
+            arms.iter().for_each(|arm| visit(&arm.body));
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Bytecode compiler and stack virtual machine
+//!
+//! `nlang run --vm` compiles the analyzed program into a flat instruction
+//! set and runs it on [`Vm`] instead of walking the AST. Names are resolved
+//! once, at compile time: locals become stack slots relative to the current
+//! call frame, globals become indices into a table, and calls to declared
+//! functions refer to them by index. The VM reproduces the interpreter's
+//! behavior, including its runtime errors, which it reports as
+//! [`InterpreterError`](crate::interpreter::InterpreterError)s, and its
+//! values share the interpreter's [`RuntimeValue`] semantics.
+
+use crate::ast::FormatSpec;
+use crate::interpreter::{write_separated, RuntimeValue, Scalar};
+use crate::span::Span;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+mod compiler;
+mod vm;
+
+pub use compiler::compile;
+pub use vm::Vm;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl CompileError {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        CompileError { message: message.into(), span: Some(span) }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "Bytecode error on line {}, column {}: {}", span.line, span.column, self.message),
+            None => write!(f, "Bytecode error: {}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// One VM instruction. Operands are indices resolved at compile time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    /// Pushes `constants[index]`.
+    Constant(u32),
+    Pop,
+    /// Pops this many values, e.g. the locals of a scope that just ended.
+    PopN(u32),
+    /// Locals are addressed relative to the base of the current frame.
+    GetLocal(u32),
+    SetLocal(u32),
+    /// Moves a local into a shared cell so closures can capture it.
+    BoxLocal(u32),
+    GetBoxed(u32),
+    SetBoxed(u32),
+    /// Cells captured by the running closure.
+    GetUpvalue(u32),
+    SetUpvalue(u32),
+    GetGlobal(u32),
+    /// Assigns to a global that must already be defined.
+    SetGlobal(u32),
+    /// Pops a value into a global, defining it if it isn't yet.
+    DefineGlobal(u32),
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    Negate,
+    Not,
+    /// Jumps to an absolute instruction index.
+    Jump(u32),
+    /// Pops a condition and jumps if it is false.
+    JumpIfFalse(u32),
+    /// Calls `functions[function]` with the top `arguments` values.
+    CallFunction { function: u32, arguments: u32 },
+    /// Calls the function value below the top `arguments` values.
+    Call(u32),
+    CallBuiltin { builtin: Builtin, arguments: u32 },
+    Return,
+    /// Pushes a declared function as a value.
+    FunctionValue(u32),
+    /// Creates a closure over `functions[index]`, capturing the cells its
+    /// `captures` describe.
+    Closure(u32),
+    /// Collects the top `n` values into an array.
+    Array(u32),
+    Index,
+    SetIndex,
+    /// Copies the array on top of the stack for a `for` loop to iterate
+    /// over, so changes made by the loop body don't affect it.
+    Snapshot,
+    /// Builds `struct_shapes[shape]` from the top values, in source order.
+    Struct(u32),
+    /// Field names are string constants.
+    GetField(u32),
+    SetField(u32),
+    /// Builds `variants[variant]` from the top `arguments` values.
+    Enum { variant: u32, arguments: u32 },
+    /// Pops a value and pushes whether it is `variants[variant]`.
+    IsVariant(u32),
+    /// Pops an enum value and pushes its field at this position.
+    EnumField(u32),
+    /// Pops the value a match was on and reports that no arm matched it.
+    MatchFailed,
+    /// Pops a value and pushes it as text, padded by `format_specs[index]`.
+    Format(u32),
+    /// Pops this many strings and pushes them joined together.
+    Concat(u32),
+    /// Raises `failures[index]`. Used for names that can't be resolved,
+    /// which are only an error once the code using them runs.
+    Fail(u32),
+}
+
+/// Functions built into the language, called directly by the VM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Print,
+    Println,
+    Str,
+    Len,
+    Push,
+    Int,
+    Float,
+    Abs,
+    AbsFloat,
+    Add,
+    Multiply,
+    Assert,
+    AssertEq,
+    AssertNe,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        let builtin = match name {
+            "print" => Builtin::Print,
+            "println" => Builtin::Println,
+            "str" => Builtin::Str,
+            "len" => Builtin::Len,
+            "push" => Builtin::Push,
+            "int" => Builtin::Int,
+            "float" => Builtin::Float,
+            "abs" => Builtin::Abs,
+            "abs_float" => Builtin::AbsFloat,
+            "add" => Builtin::Add,
+            "multiply" => Builtin::Multiply,
+            "assert" => Builtin::Assert,
+            "assert_eq" => Builtin::AssertEq,
+            "assert_ne" => Builtin::AssertNe,
+            _ => return None,
+        };
+        Some(builtin)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Print => "print",
+            Builtin::Println => "println",
+            Builtin::Str => "str",
+            Builtin::Len => "len",
+            Builtin::Push => "push",
+            Builtin::Int => "int",
+            Builtin::Float => "float",
+            Builtin::Abs => "abs",
+            Builtin::AbsFloat => "abs_float",
+            Builtin::Add => "add",
+            Builtin::Multiply => "multiply",
+            Builtin::Assert => "assert",
+            Builtin::AssertEq => "assert_eq",
+            Builtin::AssertNe => "assert_ne",
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Builtin::Push | Builtin::Add | Builtin::Multiply | Builtin::AssertEq | Builtin::AssertNe => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Where a closure gets one of its captured cells from when it is created.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capture {
+    /// A boxed local of the enclosing function.
+    Local(u32),
+    /// One of the enclosing closure's own captures.
+    Upvalue(u32),
+}
+
+/// A compiled function body.
+#[derive(Debug, Default)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub is_lambda: bool,
+    pub code: Vec<Instruction>,
+    /// The source location of each instruction, for runtime errors.
+    pub spans: Vec<Span>,
+    pub captures: Vec<Capture>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StructLayout {
+    pub name: Rc<str>,
+    pub fields: Vec<Rc<str>>,
+}
+
+/// How to build a struct from a literal whose fields were pushed in the
+/// order they are written.
+#[derive(Debug)]
+pub struct StructShape {
+    pub layout: Rc<StructLayout>,
+    /// The declared position of each written field.
+    pub positions: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VariantInfo {
+    pub enum_name: Rc<str>,
+    pub name: Rc<str>,
+}
+
+/// A runtime error known at compile time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Failure {
+    VariableNotFound(String),
+    FunctionNotFound(String),
+    InvalidOperation(String),
+}
+
+/// A whole compiled program.
+#[derive(Debug, Default)]
+pub struct Module {
+    pub functions: Vec<Rc<Function>>,
+    /// Index into `functions` of the code that runs first: it initializes
+    /// the globals and calls `main`, or runs the top-level statements.
+    pub entry: usize,
+    pub constants: Vec<Value>,
+    /// Global variable names, indexed like the VM's globals.
+    pub globals: Vec<String>,
+    pub struct_shapes: Vec<StructShape>,
+    pub variants: Vec<Rc<VariantInfo>>,
+    pub failures: Vec<Failure>,
+    pub format_specs: Vec<FormatSpec>,
+}
+
+/// A runtime value of the VM.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(Rc<str>),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Struct(Rc<RefCell<StructValue>>),
+    Enum(Rc<EnumValue>),
+    Function(Rc<Closure>),
+    /// A local captured by a closure. Only ever stored in a stack slot or a
+    /// closure; reading the variable goes through the cell.
+    Cell(Rc<RefCell<Value>>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StructValue {
+    pub layout: Rc<StructLayout>,
+    /// Field values in declaration order.
+    pub fields: Vec<Value>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EnumValue {
+    pub variant: Rc<VariantInfo>,
+    pub fields: Vec<Value>,
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Value>>>,
+}
+
+impl PartialEq for Closure {
+    /// A closure is only equal to itself, even when another one was
+    /// created from the same function with the same cells.
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl RuntimeValue for Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "int",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "bool",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Struct(_) => "struct",
+            Value::Enum(_) => "enum",
+            Value::Function(_) => "function",
+            Value::Cell(cell) => cell.borrow().type_name(),
+        }
+    }
+
+    /// Cells are never operands, so they aren't scalars even when their
+    /// contents are.
+    fn scalar(&self) -> Option<Scalar<'_>> {
+        match self {
+            Value::Integer(i) => Some(Scalar::Integer(*i)),
+            Value::Float(f) => Some(Scalar::Float(*f)),
+            Value::Boolean(b) => Some(Scalar::Boolean(*b)),
+            Value::String(s) => Some(Scalar::String(s)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                write_separated(f, elements.borrow().iter(), |f, element| write!(f, "{}", element))?;
+                write!(f, "]")
+            }
+            // Field names live in the layout rather than in each instance
+            Value::Struct(instance) => {
+                let instance = instance.borrow();
+                write!(f, "{} {{ ", instance.layout.name)?;
+                let fields = instance.layout.fields.iter().zip(&instance.fields);
+                write_separated(f, fields, |f, (name, value)| write!(f, "{}: {}", name, value))?;
+                write!(f, " }}")
+            }
+            Value::Enum(value) => {
+                write!(f, "{}::{}", value.variant.enum_name, value.variant.name)?;
+                if !value.fields.is_empty() {
+                    write!(f, "(")?;
+                    write_separated(f, &value.fields, |f, field| write!(f, "{}", field))?;
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Value::Function(closure) => match closure.function.is_lambda {
+                true => write!(f, "<lambda>"),
+                false => write!(f, "<function {}>", closure.function.name),
+            },
+            Value::Cell(cell) => write!(f, "{}", cell.borrow()),
+        }
+    }
+}
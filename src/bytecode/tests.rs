@@ -0,0 +1,195 @@
+#[cfg(test)]
+mod bytecode_tests {
+    use crate::bytecode::{compile, Vm};
+    use crate::interpreter::{Interpreter, InterpreterError};
+    use crate::lexer::tokenize;
+    use crate::parser::parse_strict;
+    use crate::semantic::check_module;
+
+    fn run_vm(source: &str) -> Result<i32, InterpreterError> {
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        let module = compile(&program, None).unwrap();
+        Vm::new().run(&module)
+    }
+
+    /// Runs `source` on the VM and checks it agrees with the interpreter.
+    fn run_both(source: &str) -> i32 {
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        // Analyzed like a REPL input, since a test may run without a `main`
+        let analysis = check_module(program, None);
+        assert!(analysis.errors.is_empty(), "{:?}", analysis.errors);
+        let expected = Interpreter::new().execute_program(&analysis.into_typed()).unwrap();
+        let actual = run_vm(source).unwrap();
+        assert_eq!(actual, expected);
+        actual
+    }
+
+    #[test]
+    fn test_recursion_and_globals() {
+        let source = "
+            store base = 2;
+            def fib(n: int): int {
+                if (n < 2) { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            def main(): int {
+                return fib(15) + base;
+            }
+        ";
+        assert_eq!(run_both(source), 612);
+    }
+
+    #[test]
+    fn test_loops() {
+        let source = "
+            store total = 0;
+            for (i in 0..10) {
+                if (i == 2) { continue; }
+                if (i == 5) { break; }
+                total = total + i;
+            }
+            store n = 0;
+            while (n < 100) {
+                n = n + 7;
+                if (n % 2 == 0) { continue; }
+                total = total + 1;
+            }
+            return total;
+        ";
+        assert_eq!(run_both(source), 16);
+    }
+
+    #[test]
+    fn test_arrays_and_structs() {
+        let source = "
+            struct Point { x: int, y: int }
+            store xs = [1, 2];
+            store alias = xs;
+            push(alias, 3);
+            xs[0] = 10;
+            store total = 0;
+            for (x in xs) {
+                push(xs, x);
+                total = total + x;
+            }
+            store p = Point { y: 2, x: 1 };
+            store q = p;
+            q.x = q.x + total;
+            return p.x * 100 + p.y * 10 + len(xs);
+        ";
+        assert_eq!(run_both(source), 1626);
+    }
+
+    #[test]
+    fn test_enums_and_match() {
+        let source = "
+            enum Shape { Square(int), Rect(int, int), Empty }
+            store shapes = [Shape::Square(3), Shape::Rect(2, 5), Shape::Empty, Shape::Square(0)];
+            store total = 0;
+            for (shape in shapes) {
+                total = total + match (shape) {
+                    Shape::Square(0) => 1000,
+                    Shape::Square(side) => side * side,
+                    Shape::Rect(w, h) => w * h,
+                    Shape::Empty => 0,
+                };
+            }
+            match (shapes[1] == Shape::Rect(2, 5)) {
+                true => total = total * 2,
+                false => {}
+            }
+            return total;
+        ";
+        assert_eq!(run_both(source), 2038);
+    }
+
+    #[test]
+    fn test_closures() {
+        let source = "
+            def make_counter(): def(): int {
+                store count = 0;
+                return def() {
+                    count = count + 1;
+                    return count;
+                };
+            }
+            def twice(f: def(int): int, x: int): int {
+                return f(f(x));
+            }
+            def triple(x: int): int {
+                return x * 3;
+            }
+            def main(): int {
+                store x = 1;
+                if (true) {
+                    store x = 2;
+                    x = x + 1;
+                }
+                store getters: array[def(): int] = [];
+                for (i in 0..3) {
+                    push(getters, def() => i);
+                }
+                store counter = make_counter();
+                counter();
+                counter();
+                store offset = 5;
+                store shift = def(y: int) => y + offset;
+                offset = 6;
+                return x * 10 + getters[1]() * 100 + counter() * 1000 + twice(shift, 0) + twice(triple, 1);
+            }
+        ";
+        assert_eq!(run_both(source), 3131);
+    }
+
+    #[test]
+    fn test_mixed_number_equality() {
+        let source = "
+            store f = 3.0;
+            store total = 0;
+            if (f == 3) { total = total + 1; }
+            if (3 != f) { total = total + 10; }
+            if (2 == f) { total = total + 100; }
+            if (f != 3.5) { total = total + 1000; }
+            return total;
+        ";
+        assert_eq!(run_both(source), 1001);
+    }
+
+    #[test]
+    fn test_format_strings() {
+        let source = r#"
+            store n = 7;
+            store ratio = 2.0 / 3.0;
+            store label = f"{n:03}|{-n:04}|{ratio:<8.2}|{"ok":>4}|{n > 5}|{ratio}|{{}}";
+            assert_eq(label, "007|-007|0.67    |  ok|true|0.6666666666666666|{}");
+            store greet = def(name: string) => f"hi {name}!";
+            return len(greet("nlang") + label);
+        "#;
+        assert_eq!(run_both(source), 58);
+    }
+
+    #[test]
+    fn test_runtime_error_location() {
+        let error = run_vm("store xs = [1, 2];\nreturn xs[2];").unwrap_err();
+        assert_eq!(error.to_string(), "Index out of bounds: the length is 2 but the index is 2 (line 2, column 8)");
+
+        let error = run_vm("def main(): int {\n    return 1 / 0;\n}").unwrap_err();
+        assert_eq!(error.to_string(), "Division by zero (line 2, column 12)");
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let source = "def down(n: int): int {\n    return down(n + 1);\n}\nreturn down(0);";
+        let error = run_vm(source).unwrap_err();
+        assert_eq!(error.to_string(), "Stack overflow: more than 10000 nested calls (line 2, column 12)");
+
+        let source = "store down = def(n: int) => n;\ndown = def(n: int) => down(n + 1);\nreturn down(0);";
+        let error = run_vm(source).unwrap_err();
+        assert!(error.to_string().starts_with("Stack overflow: more than 10000 nested calls (line 2"), "{}", error);
+
+        let source = "def down(n: int): int {\n    if (n == 0) { return 0; }\n    return down(n - 1);\n}\nreturn down(9000);";
+        assert_eq!(run_vm(source).unwrap(), 0);
+    }
+}
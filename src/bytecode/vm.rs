@@ -0,0 +1,551 @@
+//! Executes a bytecode [`Module`]
+//!
+//! All values live on one stack. Each call gets a frame whose base is the
+//! stack slot of its first argument; its locals follow the arguments, and
+//! the temporaries of the expression being evaluated sit above them.
+
+use super::{Builtin, Capture, Closure, EnumValue, Failure, Function, Instruction, Module, StructValue, Value};
+use crate::interpreter::{InterpreterError, RuntimeValue};
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+/// The most calls that can be running at once; one more is reported as a
+/// stack overflow at the call instead of exhausting memory.
+pub const MAX_FRAMES: usize = 10_000;
+
+struct Frame {
+    function: Rc<Function>,
+    /// The closure being run, for lambdas that capture variables.
+    closure: Option<Rc<Closure>>,
+    ip: usize,
+    base: usize,
+    /// The stack length to restore on return, which also drops the callee
+    /// of a call through a function value.
+    return_to: usize,
+}
+
+#[derive(Default)]
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    /// `None` until the global is defined.
+    globals: Vec<Option<Value>>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the module's entry code and returns the program's exit code.
+    pub fn run(&mut self, module: &Module) -> Result<i32, InterpreterError> {
+        self.stack.clear();
+        self.frames.clear();
+        self.globals = vec![None; module.globals.len()];
+        self.frames.push(Frame {
+            function: module.functions[module.entry].clone(),
+            closure: None,
+            ip: 0,
+            base: 0,
+            return_to: 0,
+        });
+        let result = self.execute(module).map_err(|error| {
+            let frame = self.frames.last().expect("a frame is running");
+            // `ip` has already moved past the failing instruction
+            let span = frame.function.spans[frame.ip.saturating_sub(1)];
+            error.with_span(span)
+        })?;
+        Ok(result.to_int().unwrap_or(0) as i32)
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("a frame is running")
+    }
+
+    fn push_frame(&mut self, frame: Frame) -> Result<(), InterpreterError> {
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(InterpreterError::StackOverflow { depth: MAX_FRAMES });
+        }
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler keeps the stack balanced")
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().expect("the compiler keeps the stack balanced")
+    }
+
+    /// Pops the top `count` values, in the order they were pushed.
+    fn pop_n(&mut self, count: usize) -> Vec<Value> {
+        let start = self.stack.len() - count;
+        self.stack.split_off(start)
+    }
+
+    fn cell(&self, slot: u32) -> &Rc<RefCell<Value>> {
+        match &self.stack[self.frame().base + slot as usize] {
+            Value::Cell(cell) => cell,
+            other => unreachable!("local {} holds a {} instead of a cell", slot, other.type_name()),
+        }
+    }
+
+    fn upvalue(&self, index: u32) -> &Rc<RefCell<Value>> {
+        let closure = self.frame().closure.as_ref().expect("only closures have upvalues");
+        &closure.upvalues[index as usize]
+    }
+
+    fn execute(&mut self, module: &Module) -> Result<Value, InterpreterError> {
+        loop {
+            let frame = self.frames.last_mut().expect("a frame is running");
+            let instruction = frame.function.code[frame.ip];
+            frame.ip += 1;
+
+            match instruction {
+                Instruction::Constant(index) => self.stack.push(module.constants[index as usize].clone()),
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::PopN(count) => {
+                    let length = self.stack.len() - count as usize;
+                    self.stack.truncate(length);
+                }
+                Instruction::GetLocal(slot) => {
+                    let value = self.stack[self.frame().base + slot as usize].clone();
+                    self.stack.push(value);
+                }
+                Instruction::SetLocal(slot) => {
+                    let value = self.peek().clone();
+                    let base = self.frame().base;
+                    self.stack[base + slot as usize] = value;
+                }
+                Instruction::BoxLocal(slot) => {
+                    let index = self.frame().base + slot as usize;
+                    let value = std::mem::replace(&mut self.stack[index], Value::Integer(0));
+                    self.stack[index] = Value::Cell(Rc::new(RefCell::new(value)));
+                }
+                Instruction::GetBoxed(slot) => {
+                    let value = self.cell(slot).borrow().clone();
+                    self.stack.push(value);
+                }
+                Instruction::SetBoxed(slot) => {
+                    let value = self.peek().clone();
+                    *self.cell(slot).borrow_mut() = value;
+                }
+                Instruction::GetUpvalue(index) => {
+                    let value = self.upvalue(index).borrow().clone();
+                    self.stack.push(value);
+                }
+                Instruction::SetUpvalue(index) => {
+                    let value = self.peek().clone();
+                    *self.upvalue(index).borrow_mut() = value;
+                }
+                Instruction::GetGlobal(index) => match &self.globals[index as usize] {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => return Err(InterpreterError::VariableNotFound { name: module.globals[index as usize].clone() }),
+                },
+                Instruction::SetGlobal(index) => {
+                    let value = self.peek().clone();
+                    match &mut self.globals[index as usize] {
+                        Some(global) => *global = value,
+                        None => return Err(InterpreterError::VariableNotFound { name: module.globals[index as usize].clone() }),
+                    }
+                }
+                Instruction::DefineGlobal(index) => {
+                    let value = self.pop();
+                    self.globals[index as usize] = Some(value);
+                }
+                Instruction::Add
+                | Instruction::Subtract
+                | Instruction::Multiply
+                | Instruction::Divide
+                | Instruction::Modulo
+                | Instruction::Less
+                | Instruction::LessEqual
+                | Instruction::Greater
+                | Instruction::GreaterEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(arithmetic(instruction, &left, &right)?);
+                }
+                Instruction::Equal => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(left.equals(&right)));
+                }
+                Instruction::NotEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(!left.equals(&right)));
+                }
+                Instruction::And => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(left.to_bool()? && right.to_bool()?));
+                }
+                Instruction::Or => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(left.to_bool()? || right.to_bool()?));
+                }
+                Instruction::Negate => {
+                    let value = match self.pop() {
+                        Value::Integer(i) => Value::Integer(-i),
+                        Value::Float(f) => Value::Float(-f),
+                        other => return Err(type_mismatch("numeric", other.type_name())),
+                    };
+                    self.stack.push(value);
+                }
+                Instruction::Not => {
+                    let value = self.pop().to_bool()?;
+                    self.stack.push(Value::Boolean(!value));
+                }
+                Instruction::Jump(target) => {
+                    self.frames.last_mut().expect("a frame is running").ip = target as usize;
+                }
+                Instruction::JumpIfFalse(target) => {
+                    if !self.pop().to_bool()? {
+                        self.frames.last_mut().expect("a frame is running").ip = target as usize;
+                    }
+                }
+                Instruction::CallFunction { function, arguments } => {
+                    let function = module.functions[function as usize].clone();
+                    check_arity(&function, arguments as usize)?;
+                    let base = self.stack.len() - arguments as usize;
+                    self.push_frame(Frame { function, closure: None, ip: 0, base, return_to: base })?;
+                }
+                Instruction::Call(arguments) => {
+                    let callee = self.stack.len() - arguments as usize - 1;
+                    let closure = match &self.stack[callee] {
+                        Value::Function(closure) => closure.clone(),
+                        other => return Err(type_mismatch("function", other.type_name())),
+                    };
+                    check_arity(&closure.function, arguments as usize)?;
+                    self.push_frame(Frame {
+                        function: closure.function.clone(),
+                        closure: Some(closure),
+                        ip: 0,
+                        base: callee + 1,
+                        return_to: callee,
+                    })?;
+                }
+                Instruction::CallBuiltin { builtin, arguments } => {
+                    let arguments = self.pop_n(arguments as usize);
+                    self.stack.push(call_builtin(builtin, arguments)?);
+                }
+                Instruction::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("a frame is running");
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.stack.truncate(frame.return_to);
+                    self.stack.push(result);
+                }
+                Instruction::FunctionValue(index) => {
+                    let function = module.functions[index as usize].clone();
+                    self.stack.push(Value::Function(Rc::new(Closure { function, upvalues: Vec::new() })));
+                }
+                Instruction::Closure(index) => {
+                    let function = module.functions[index as usize].clone();
+                    let upvalues = function.captures.iter()
+                        .map(|capture| match *capture {
+                            Capture::Local(slot) => self.cell(slot).clone(),
+                            Capture::Upvalue(index) => self.upvalue(index).clone(),
+                        })
+                        .collect();
+                    self.stack.push(Value::Function(Rc::new(Closure { function, upvalues })));
+                }
+                Instruction::Array(count) => {
+                    let elements = self.pop_n(count as usize);
+                    self.stack.push(Value::Array(Rc::new(RefCell::new(elements))));
+                }
+                Instruction::Index => {
+                    let index = self.pop();
+                    let sequence = self.pop();
+                    let (elements, position) = element(&sequence, &index)?;
+                    let value = elements.borrow()[position].clone();
+                    self.stack.push(value);
+                }
+                Instruction::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let sequence = self.pop();
+                    let (elements, position) = element(&sequence, &index)?;
+                    elements.borrow_mut()[position] = value.clone();
+                    self.stack.push(value);
+                }
+                Instruction::Snapshot => {
+                    let snapshot = match self.pop() {
+                        Value::Array(elements) => elements.borrow().clone(),
+                        other => return Err(InterpreterError::InvalidOperation {
+                            message: format!("Cannot iterate over a value of type {}", other.type_name()),
+                        }),
+                    };
+                    self.stack.push(Value::Array(Rc::new(RefCell::new(snapshot))));
+                }
+                Instruction::Struct(shape) => {
+                    let shape = &module.struct_shapes[shape as usize];
+                    let values = self.pop_n(shape.positions.len());
+                    let mut fields = vec![Value::Integer(0); shape.layout.fields.len()];
+                    for (&position, value) in shape.positions.iter().zip(values) {
+                        fields[position] = value;
+                    }
+                    let instance = StructValue { layout: shape.layout.clone(), fields };
+                    self.stack.push(Value::Struct(Rc::new(RefCell::new(instance))));
+                }
+                Instruction::GetField(name) => {
+                    let instance = self.pop();
+                    let instance = as_struct(&instance)?.borrow();
+                    let position = field_position(&instance, &module.constants[name as usize])?;
+                    let value = instance.fields[position].clone();
+                    drop(instance);
+                    self.stack.push(value);
+                }
+                Instruction::SetField(name) => {
+                    let value = self.pop();
+                    let instance = self.pop();
+                    let mut instance = as_struct(&instance)?.borrow_mut();
+                    let position = field_position(&instance, &module.constants[name as usize])?;
+                    instance.fields[position] = value.clone();
+                    drop(instance);
+                    self.stack.push(value);
+                }
+                Instruction::Enum { variant, arguments } => {
+                    let fields = self.pop_n(arguments as usize);
+                    let variant = module.variants[variant as usize].clone();
+                    self.stack.push(Value::Enum(Rc::new(EnumValue { variant, fields })));
+                }
+                Instruction::IsVariant(variant) => {
+                    let is_variant = match self.pop() {
+                        Value::Enum(value) => *value.variant == *module.variants[variant as usize],
+                        _ => false,
+                    };
+                    self.stack.push(Value::Boolean(is_variant));
+                }
+                Instruction::EnumField(position) => {
+                    let field = match self.pop() {
+                        Value::Enum(value) => value.fields.get(position as usize).cloned(),
+                        _ => None,
+                    };
+                    let field = field.ok_or_else(|| InterpreterError::InvalidOperation {
+                        message: "Pattern has more fields than the matched variant".to_string(),
+                    })?;
+                    self.stack.push(field);
+                }
+                Instruction::MatchFailed => {
+                    let value = self.pop();
+                    return Err(InterpreterError::InvalidOperation {
+                        message: format!("No match arm matches the value {}", value),
+                    });
+                }
+                Instruction::Format(spec) => {
+                    let spec = &module.format_specs[spec as usize];
+                    let text = match (self.pop(), spec.precision) {
+                        (Value::Float(f), Some(precision)) => format!("{:.*}", precision, f),
+                        (value, _) => value.to_string(),
+                    };
+                    self.stack.push(Value::String(spec.pad(&text).into()));
+                }
+                Instruction::Concat(count) => {
+                    let text: String = self.pop_n(count as usize).iter().map(Value::to_string).collect();
+                    self.stack.push(Value::String(text.into()));
+                }
+                Instruction::Fail(index) => {
+                    return Err(match &module.failures[index as usize] {
+                        Failure::VariableNotFound(name) => InterpreterError::VariableNotFound { name: name.clone() },
+                        Failure::FunctionNotFound(name) => InterpreterError::FunctionNotFound { name: name.clone() },
+                        Failure::InvalidOperation(message) => InterpreterError::InvalidOperation { message: message.clone() },
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn type_mismatch(expected: &str, actual: impl Into<String>) -> InterpreterError {
+    InterpreterError::TypeMismatch { expected: expected.to_string(), actual: actual.into() }
+}
+
+fn check_arity(function: &Function, arguments: usize) -> Result<(), InterpreterError> {
+    if function.arity == arguments {
+        return Ok(());
+    }
+    Err(InterpreterError::InvalidOperation {
+        message: format!("Function '{}' expects {} arguments, but {} were provided", function.name, function.arity, arguments),
+    })
+}
+
+/// Applies an arithmetic or comparison instruction, promoting a mix of
+/// integers and floats to floats.
+fn arithmetic(instruction: Instruction, left: &Value, right: &Value) -> Result<Value, InterpreterError> {
+    use Instruction::*;
+    let mismatch = |expected: &str| type_mismatch(expected, format!("{} and {}", left.type_name(), right.type_name()));
+    let value = match (left, right) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            let (a, b) = (*a, *b);
+            match instruction {
+                Add => Value::Integer(a + b),
+                Subtract => Value::Integer(a - b),
+                Multiply => Value::Integer(a * b),
+                Divide if b == 0 => return Err(InterpreterError::DivisionByZero),
+                Divide => Value::Float(a as f64 / b as f64),
+                Modulo if b == 0 => return Err(InterpreterError::DivisionByZero),
+                Modulo => Value::Integer(a % b),
+                Less => Value::Boolean(a < b),
+                LessEqual => Value::Boolean(a <= b),
+                Greater => Value::Boolean(a > b),
+                GreaterEqual => Value::Boolean(a >= b),
+                _ => unreachable!("{:?} is not arithmetic", instruction),
+            }
+        }
+        (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+            let (a, b) = (as_float(left), as_float(right));
+            match instruction {
+                Add => Value::Float(a + b),
+                Subtract => Value::Float(a - b),
+                Multiply => Value::Float(a * b),
+                Divide if b == 0.0 => return Err(InterpreterError::DivisionByZero),
+                Divide => Value::Float(a / b),
+                Modulo => return Err(mismatch("integer")),
+                Less => Value::Boolean(a < b),
+                LessEqual => Value::Boolean(a <= b),
+                Greater => Value::Boolean(a > b),
+                GreaterEqual => Value::Boolean(a >= b),
+                _ => unreachable!("{:?} is not arithmetic", instruction),
+            }
+        }
+        (Value::String(a), Value::String(b)) if instruction == Add => Value::String(format!("{}{}", a, b).into()),
+        _ => {
+            return Err(match instruction {
+                Add => mismatch("numeric or string"),
+                Modulo => mismatch("integer"),
+                _ => mismatch("numeric"),
+            });
+        }
+    };
+    Ok(value)
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Float(f) => *f,
+        other => unreachable!("{} is not a number", other.type_name()),
+    }
+}
+
+/// Resolves `sequence[index]` to the array and a bounds-checked position in it.
+fn element<'a>(sequence: &'a Value, index: &Value) -> Result<(&'a RefCell<Vec<Value>>, usize), InterpreterError> {
+    let Value::Array(elements) = sequence else {
+        return Err(type_mismatch("array", sequence.type_name()));
+    };
+    let index = index.to_int()?;
+    let length = elements.borrow().len();
+    match usize::try_from(index) {
+        Ok(position) if position < length => Ok((elements, position)),
+        _ => Err(InterpreterError::IndexOutOfBounds { index, length }),
+    }
+}
+
+fn as_struct(value: &Value) -> Result<&RefCell<StructValue>, InterpreterError> {
+    match value {
+        Value::Struct(instance) => Ok(instance),
+        other => Err(type_mismatch("struct", other.type_name())),
+    }
+}
+
+fn field_position(instance: &StructValue, name: &Value) -> Result<usize, InterpreterError> {
+    let Value::String(name) = name else {
+        unreachable!("field names are string constants");
+    };
+    instance.layout.fields.iter().position(|field| field == name).ok_or_else(|| InterpreterError::InvalidOperation {
+        message: format!("Struct '{}' has no field named '{}'", instance.layout.name, name),
+    })
+}
+
+fn call_builtin(builtin: Builtin, arguments: Vec<Value>) -> Result<Value, InterpreterError> {
+    let mut arguments = arguments.into_iter();
+    let mut next = || arguments.next().expect("builtin arity is checked at compile time");
+    let value = match builtin {
+        Builtin::Print => {
+            print!("{}", next());
+            std::io::stdout().flush().unwrap();
+            Value::Integer(0)
+        }
+        Builtin::Println => {
+            println!("{}", next());
+            Value::Integer(0)
+        }
+        Builtin::Str => match next() {
+            value @ Value::String(_) => value,
+            value => Value::String(value.to_string().into()),
+        },
+        Builtin::Len => match next() {
+            Value::String(s) => Value::Integer(s.len() as i64),
+            Value::Array(elements) => Value::Integer(elements.borrow().len() as i64),
+            other => return Err(type_mismatch("string or array", other.type_name())),
+        },
+        Builtin::Push => {
+            let array = next();
+            let value = next();
+            match array {
+                Value::Array(elements) => elements.borrow_mut().push(value),
+                other => return Err(type_mismatch("array", other.type_name())),
+            }
+            Value::Integer(0)
+        }
+        Builtin::Int => match next() {
+            Value::String(s) => match s.parse::<i64>() {
+                Ok(i) => Value::Integer(i),
+                Err(_) => return Err(InterpreterError::InvalidOperation { message: format!("Cannot convert '{}' to integer", s) }),
+            },
+            value @ (Value::Integer(_) | Value::Float(_) | Value::Boolean(_)) => Value::Integer(value.to_int()?),
+            other => return Err(type_mismatch("int", other.type_name())),
+        },
+        Builtin::Float => match next() {
+            Value::Integer(i) => Value::Float(i as f64),
+            Value::Float(f) => Value::Float(f),
+            Value::Boolean(b) => Value::Float(if b { 1.0 } else { 0.0 }),
+            Value::String(s) => match s.parse::<f64>() {
+                Ok(f) => Value::Float(f),
+                Err(_) => return Err(InterpreterError::InvalidOperation { message: format!("Cannot convert '{}' to float", s) }),
+            },
+            other => return Err(type_mismatch("float", other.type_name())),
+        },
+        Builtin::Abs => match next() {
+            Value::Integer(i) => Value::Integer(i.abs()),
+            other => return Err(type_mismatch("integer", other.type_name())),
+        },
+        Builtin::AbsFloat => match next() {
+            Value::Float(f) => Value::Float(f.abs()),
+            Value::Integer(i) => Value::Float((i as f64).abs()),
+            other => return Err(type_mismatch("float or integer", other.type_name())),
+        },
+        Builtin::Add => {
+            let (left, right) = (next(), next());
+            arithmetic(Instruction::Add, &left, &right)?
+        }
+        Builtin::Multiply => {
+            let (left, right) = (next(), next());
+            arithmetic(Instruction::Multiply, &left, &right)?
+        }
+        Builtin::Assert => {
+            if !next().to_bool()? {
+                return Err(InterpreterError::AssertionFailed { message: "condition is false".to_string() });
+            }
+            Value::Integer(0)
+        }
+        Builtin::AssertEq | Builtin::AssertNe => {
+            let (left, right) = (next(), next());
+            let expect_equal = builtin == Builtin::AssertEq;
+            if (left == right) != expect_equal {
+                return Err(InterpreterError::failed_comparison(expect_equal, &left.repr(), &right.repr()));
+            }
+            Value::Integer(0)
+        }
+    };
+    Ok(value)
+}
@@ -1,40 +1,132 @@
 use crate::ast::*;
+use crate::semantic::{DefinitionKind, SymbolIndex, TypeTable, TypedProgram};
+use crate::span::Span;
 use std::collections::HashMap;
+use std::rc::Rc;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum CCodeGenError {
     #[error("Unsupported feature: {message}")]
     UnsupportedFeature { message: String },
-    #[error("Variable not found: {name}")]
-    VariableNotFound { name: String },
+    /// Analysis recorded no type for an expression, or nothing for a name,
+    /// so it and the C backend disagree about the program.
+    #[error("Internal error: analysis recorded no type for this expression")]
+    Untyped,
+    #[error("Internal error: analysis did not resolve '{name}'")]
+    Unresolved { name: String },
+    #[error("{error} (line {}, column {})", span.line, span.column)]
+    At { span: Span, error: Box<CCodeGenError> },
 }
 
+impl CCodeGenError {
+    /// Attaches a source location unless the error already has one.
+    pub fn with_span(self, span: Span) -> Self {
+        match self {
+            CCodeGenError::At { .. } => self,
+            error => CCodeGenError::At { span, error: Box::new(error) },
+        }
+    }
+    
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CCodeGenError::At { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+}
+
+/// Growable arrays, one struct per element type. Arrays are passed around
+/// by pointer so `push` through a copy is visible to every holder.
+const ARRAY_RUNTIME: &str = r#"// Array runtime
+static inline void nl_print_int(long long value) { printf("%lld", value); }
+static inline void nl_print_double(double value) { printf("%f", value); }
+static inline void nl_print_str(const char* value) { printf("%s", value); }
+static inline void nl_print_bool(int value) { printf("%s", value ? "true" : "false"); }
+
+#define NL_DEFINE_ARRAY(name, T, PRINT) \
+typedef struct { T* data; int len; int cap; } name; \
+static inline name* name##_new(int len, T const* items) { \
+    name* array = malloc(sizeof(name)); \
+    array->cap = len > 4 ? len : 4; \
+    array->data = malloc(sizeof(T) * array->cap); \
+    array->len = len; \
+    if (len > 0) memcpy(array->data, items, sizeof(T) * len); \
+    return array; \
+} \
+static inline void name##_push(name* array, T value) { \
+    if (array->len == array->cap) { \
+        array->cap *= 2; \
+        array->data = realloc(array->data, sizeof(T) * array->cap); \
+    } \
+    array->data[array->len++] = value; \
+} \
+static inline T* name##_at(name* array, long long index, int line, int column) { \
+    if (index < 0 || index >= array->len) { \
+        fprintf(stderr, "Index out of bounds: the length is %d but the index is %lld (line %d, column %d)\n", array->len, index, line, column); \
+        exit(1); \
+    } \
+    return &array->data[index]; \
+} \
+static inline void name##_print(name* array) { \
+    printf("["); \
+    for (int i = 0; i < array->len; i++) { \
+        if (i > 0) printf(", "); \
+        PRINT(array->data[i]); \
+    } \
+    printf("]"); \
+}
+
+NL_DEFINE_ARRAY(nl_array_int, long long, nl_print_int)
+NL_DEFINE_ARRAY(nl_array_double, double, nl_print_double)
+NL_DEFINE_ARRAY(nl_array_str, const char*, nl_print_str)
+
+"#;
+
+/// C element type and the array struct holding it. Arrays of structs are
+/// named after the struct instead, e.g. `nl_array_Point`.
+const ARRAY_TYPES: &[(&str, &str)] = &[
+    ("long long", "nl_array_int"),
+    ("double", "nl_array_double"),
+    ("const char*", "nl_array_str"),
+];
+
 pub struct CCodeGenerator {
     #[allow(dead_code)]
     module_name: String,
-    variables: HashMap<String, String>, // variable name -> C type
-    #[allow(dead_code)]
+    structs: HashMap<String, Vec<(String, String)>>, // struct name -> fields and their C types
+    enums: HashMap<String, Vec<(String, Vec<String>)>>, // enum name -> variants and their field C types
     temp_counter: usize,
     string_constants: HashMap<String, String>, // string literal -> constant name
     string_counter: usize,
+    types: TypeTable, // what analysis found each expression's type to be
+    symbols: Rc<SymbolIndex>, // what analysis resolved each name to
+    globals_init: String, // assignments of the top-level variables' initial values
 }
 
 impl CCodeGenerator {
     pub fn new(module_name: String) -> Self {
         Self {
             module_name,
-            variables: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
             temp_counter: 0,
             string_constants: HashMap::new(),
             string_counter: 0,
+            types: TypeTable::default(),
+            symbols: Rc::default(),
+            globals_init: String::new(),
         }
     }
 
-    pub fn generate_program(&mut self, program: &Program) -> Result<String, CCodeGenError> {
+    pub fn generate_program(&mut self, typed: &TypedProgram) -> Result<String, CCodeGenError> {
+        let program = &typed.program;
+        self.types = typed.types.clone();
+        self.symbols = typed.symbols.clone();
         let mut code = String::new();
         
         // Add includes
+        code.push_str("#include <stdarg.h>\n");
         code.push_str("#include <stdio.h>\n");
         code.push_str("#include <string.h>\n");
         code.push_str("#include <stdlib.h>\n");
@@ -42,18 +134,43 @@ impl CCodeGenerator {
         
         // Add helper functions for built-in conversions
         code.push_str("// Helper functions for built-in conversions\n");
-        code.push_str("char* int_to_str(int value) {\n");
+        code.push_str("char* int_to_str(long long value) {\n");
         code.push_str("    char* buffer = malloc(32);\n");
-        code.push_str("    sprintf(buffer, \"%d\", value);\n");
+        code.push_str("    sprintf(buffer, \"%lld\", value);\n");
         code.push_str("    return buffer;\n");
         code.push_str("}\n\n");
         
+        // Like the interpreter, uses the fewest digits that read back as the same value
         code.push_str("char* float_to_str(double value) {\n");
         code.push_str("    char* buffer = malloc(32);\n");
-        code.push_str("    sprintf(buffer, \"%f\", value);\n");
+        code.push_str("    for (int precision = 15; precision <= 17; precision++) {\n");
+        code.push_str("        snprintf(buffer, 32, \"%.*g\", precision, value);\n");
+        code.push_str("        if (strtod(buffer, NULL) == value) break;\n");
+        code.push_str("    }\n");
         code.push_str("    return buffer;\n");
         code.push_str("}\n\n");
         
+        code.push_str("char* nl_str_concat(const char* left, const char* right) {\n");
+        code.push_str("    char* buffer = malloc(strlen(left) + strlen(right) + 1);\n");
+        code.push_str("    strcpy(buffer, left);\n");
+        code.push_str("    strcat(buffer, right);\n");
+        code.push_str("    return buffer;\n");
+        code.push_str("}\n\n");
+        
+        code.push_str("char* nl_format(const char* format, ...) {\n");
+        code.push_str("    va_list args;\n");
+        code.push_str("    va_start(args, format);\n");
+        code.push_str("    int size = vsnprintf(NULL, 0, format, args) + 1;\n");
+        code.push_str("    va_end(args);\n");
+        code.push_str("    char* buffer = malloc(size);\n");
+        code.push_str("    va_start(args, format);\n");
+        code.push_str("    vsnprintf(buffer, size, format, args);\n");
+        code.push_str("    va_end(args);\n");
+        code.push_str("    return buffer;\n");
+        code.push_str("}\n\n");
+        
+        code.push_str(ARRAY_RUNTIME);
+        
         // Collect string literals first
         self.collect_string_literals(program);
         
@@ -64,7 +181,36 @@ impl CCodeGenerator {
         }
         
         if !self.string_constants.is_empty() {
-            code.push_str("\n");
+            code.push('\n');
+        }
+        
+        code.push_str(&self.generate_type_definitions(program));
+        
+        // Top-level variables are globals, given their initial values in
+        // order when `main` starts, like the interpreter does before calling it
+        let mut has_globals = false;
+        for statement in &program.statements {
+            match statement {
+                Statement::LetDeclaration { name, initializer, span, .. } => {
+                    let c_type = self.declared_c_type(name, *span)?;
+                    code.push_str(&format!("static {} {};\n", c_type, name));
+                    has_globals = true;
+                    if let Some(init) = initializer {
+                        let init_code = self.generate_value(init, &c_type).map_err(|e| e.with_span(*span))?;
+                        self.globals_init.push_str(&format!("    {} = {};\n", name, init_code));
+                    }
+                }
+                Statement::Import { span, .. } | Statement::ImportFrom { span, .. } => {
+                    return Err(CCodeGenError::UnsupportedFeature {
+                        message: "Imports are not supported by the C backend".to_string(),
+                    }.with_span(*span));
+                }
+                _ => {}
+            }
+        }
+        
+        if has_globals {
+            code.push('\n');
         }
         
         // First pass: Generate function declarations
@@ -72,25 +218,266 @@ impl CCodeGenerator {
             if let Statement::FunctionDeclaration { name, parameters, return_type, .. } = statement {
                 let decl = self.generate_function_declaration(name, parameters, return_type.as_ref())?;
                 code.push_str(&decl);
-                code.push_str("\n");
+                code.push('\n');
             }
         }
         
         if program.statements.iter().any(|s| matches!(s, Statement::FunctionDeclaration { .. })) {
-            code.push_str("\n");
+            code.push('\n');
         }
         
         // Second pass: Generate function implementations
         for statement in &program.statements {
             if let Statement::FunctionDeclaration { .. } = statement {
                 code.push_str(&self.generate_function_from_statement(statement)?);
-                code.push_str("\n");
+                code.push('\n');
             }
         }
         
         Ok(code)
     }
     
+    /// Emits a C struct for every struct and enum declaration, plus
+    /// constructors and a print function for each. Both are heap-allocated
+    /// and passed by pointer, so structs are shared by reference like arrays.
+    /// An enum is a tagged struct with a slot for every field of every
+    /// variant; only the slots of its current variant are set.
+    fn generate_type_definitions(&mut self, program: &Program) -> String {
+        let mut structs: Vec<(&String, &Vec<StructField>)> = Vec::new();
+        let mut enums: Vec<(&String, &Vec<EnumVariant>)> = Vec::new();
+        for statement in &program.statements {
+            match statement {
+                Statement::StructDeclaration { name, fields, .. } => structs.push((name, fields)),
+                Statement::EnumDeclaration { name, variants, .. } => enums.push((name, variants)),
+                _ => {}
+            }
+        }
+        // Register every type first so fields can refer to any of them
+        for (name, _) in &structs {
+            self.structs.insert(name.to_string(), Vec::new());
+        }
+        for (name, _) in &enums {
+            self.enums.insert(name.to_string(), Vec::new());
+        }
+        for (name, fields) in &structs {
+            let c_fields = fields.iter()
+                .map(|field| (field.name.clone(), self.type_to_c(&field.field_type)))
+                .collect();
+            self.structs.insert(name.to_string(), c_fields);
+        }
+        for (name, variants) in &enums {
+            let c_variants = variants.iter()
+                .map(|variant| (variant.name.clone(), variant.fields.iter().map(|field| self.type_to_c(field)).collect()))
+                .collect();
+            self.enums.insert(name.to_string(), c_variants);
+        }
+        
+        let nested_arrays = self.nested_arrays(program);
+        if structs.is_empty() && enums.is_empty() && nested_arrays.is_empty() {
+            return String::new();
+        }
+        
+        let mut code = String::from("// Structs and enums\n");
+        for name in structs.iter().map(|(name, _)| name).chain(enums.iter().map(|(name, _)| name)) {
+            code.push_str(&format!("typedef struct {name} {name};\n"));
+            code.push_str(&format!("static inline void {name}_print({name}* self);\n"));
+            code.push_str(&format!("NL_DEFINE_ARRAY(nl_array_{name}, {name}*, {name}_print)\n"));
+        }
+        // Arrays of arrays hold their rows by pointer, like arrays of structs
+        for (array, element_type) in &nested_arrays {
+            let element = element_type.trim_end_matches('*');
+            code.push_str(&format!("NL_DEFINE_ARRAY({array}, {element_type}, {element}_print)\n"));
+        }
+        code.push('\n');
+        
+        for (name, _) in &structs {
+            code.push_str(&format!("struct {} {{\n", name));
+            for (field, c_type) in &self.structs[name.as_str()] {
+                code.push_str(&format!("    {} {};\n", c_type, field));
+            }
+            code.push_str("};\n\n");
+        }
+        
+        for (name, fields) in &structs {
+            let c_fields = &self.structs[name.as_str()];
+            let params: Vec<String> = c_fields.iter().map(|(field, c_type)| format!("{} {}", c_type, field)).collect();
+            code.push_str(&format!("static inline {name}* {name}_new({}) {{\n", params.join(", ")));
+            code.push_str(&format!("    {name}* self = malloc(sizeof({name}));\n"));
+            for (field, _) in c_fields {
+                code.push_str(&format!("    self->{field} = {field};\n"));
+            }
+            code.push_str("    return self;\n}\n\n");
+            
+            code.push_str(&format!("static inline void {name}_print({name}* self) {{\n"));
+            code.push_str(&format!("    printf(\"{} {{\");\n", name));
+            for (i, (field, (_, c_type))) in fields.iter().zip(c_fields).enumerate() {
+                let separator = if i == 0 { " " } else { ", " };
+                code.push_str(&format!("    printf(\"{}{}: \");\n", separator, field.name));
+                code.push_str(&format!("    {}(self->{});\n", Self::printer_for(&field.field_type, c_type), field.name));
+            }
+            let close = if fields.is_empty() { "}" } else { " }" };
+            code.push_str(&format!("    printf(\"{}\");\n}}\n\n", close));
+        }
+        
+        for (name, variants) in &enums {
+            let c_variants = &self.enums[name.as_str()];
+            let tags: Vec<String> = variants.iter().map(|variant| format!("{}_{}_tag", name, variant.name)).collect();
+            code.push_str(&format!("enum {{ {} }};\n\n", tags.join(", ")));
+            
+            code.push_str(&format!("struct {} {{\n    int tag;\n", name));
+            for (variant, c_fields) in c_variants {
+                for (i, c_type) in c_fields.iter().enumerate() {
+                    code.push_str(&format!("    {} {}_{};\n", c_type, variant, i));
+                }
+            }
+            code.push_str("};\n\n");
+            
+            for (variant, c_fields) in c_variants {
+                let params: Vec<String> = c_fields.iter().enumerate().map(|(i, c_type)| format!("{} _{}", c_type, i)).collect();
+                let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+                code.push_str(&format!("static inline {name}* {name}_{variant}({params}) {{\n"));
+                code.push_str(&format!("    {name}* self = malloc(sizeof({name}));\n"));
+                code.push_str(&format!("    self->tag = {name}_{variant}_tag;\n"));
+                for i in 0..c_fields.len() {
+                    code.push_str(&format!("    self->{variant}_{i} = _{i};\n"));
+                }
+                code.push_str("    return self;\n}\n\n");
+            }
+            
+            code.push_str(&format!("static inline void {name}_print({name}* self) {{\n"));
+            code.push_str("    switch (self->tag) {\n");
+            for (variant, (_, c_fields)) in variants.iter().zip(c_variants) {
+                code.push_str(&format!("    case {}_{}_tag:\n", name, variant.name));
+                code.push_str(&format!("        printf(\"{}::{}{}\");\n", name, variant.name, if c_fields.is_empty() { "" } else { "(" }));
+                for (i, (field_type, c_type)) in variant.fields.iter().zip(c_fields).enumerate() {
+                    if i > 0 {
+                        code.push_str("        printf(\", \");\n");
+                    }
+                    code.push_str(&format!("        {}(self->{}_{});\n", Self::printer_for(field_type, c_type), variant.name, i));
+                }
+                if !c_fields.is_empty() {
+                    code.push_str("        printf(\")\");\n");
+                }
+                code.push_str("        break;\n");
+            }
+            code.push_str("    }\n}\n\n");
+        }
+        
+        code
+    }
+    
+    /// The array structs of the arrays of arrays `program` uses and the C
+    /// types of their elements, inner arrays before the arrays holding them.
+    fn nested_arrays(&self, program: &Program) -> Vec<(String, String)> {
+        fn visit(ty: &Type, found: &mut Vec<Type>) {
+            match ty {
+                Type::Array(element) => {
+                    visit(element, found);
+                    if matches!(element.as_ref(), Type::Array(_)) && !found.contains(ty) {
+                        found.push(ty.clone());
+                    }
+                }
+                Type::Function { params, return_type } => {
+                    params.iter().for_each(|param| visit(param, found));
+                    visit(return_type, found);
+                }
+                _ => {}
+            }
+        }
+        fn is_resolved(ty: &Type) -> bool {
+            match ty {
+                Type::Array(element) => is_resolved(element),
+                Type::Function { params, return_type } => params.iter().all(is_resolved) && is_resolved(return_type),
+                Type::Error | Type::Unknown => false,
+                _ => true,
+            }
+        }
+        fn depth(ty: &Type) -> usize {
+            match ty {
+                Type::Array(element) => 1 + depth(element),
+                _ => 0,
+            }
+        }
+        
+        let mut found = Vec::new();
+        for ty in self.types.types() {
+            visit(ty, &mut found);
+        }
+        for statement in &program.statements {
+            match statement {
+                Statement::StructDeclaration { fields, .. } => {
+                    fields.iter().for_each(|field| visit(&field.field_type, &mut found));
+                }
+                Statement::EnumDeclaration { variants, .. } => {
+                    variants.iter().flat_map(|variant| &variant.fields).for_each(|field| visit(field, &mut found));
+                }
+                Statement::FunctionDeclaration { parameters, return_type, .. } => {
+                    parameters.iter().for_each(|param| visit(&param.param_type, &mut found));
+                    if let Some(return_type) = return_type {
+                        visit(return_type, &mut found);
+                    }
+                }
+                _ => {}
+            }
+        }
+        found.retain(is_resolved);
+        found.sort_by_cached_key(|ty| (depth(ty), self.type_to_c(ty)));
+        found.iter()
+            .map(|ty| {
+                let Type::Array(element) = ty else { unreachable!("only arrays are collected") };
+                (self.type_to_c(ty).trim_end_matches('*').to_string(), self.type_to_c(element))
+            })
+            .collect()
+    }
+    
+    /// The function that prints a value of type `field_type`, whose C type is
+    /// `c_type`.
+    fn printer_for(field_type: &Type, c_type: &str) -> String {
+        match field_type {
+            Type::Integer => "nl_print_int".to_string(),
+            Type::Float => "nl_print_double".to_string(),
+            Type::Boolean => "nl_print_bool".to_string(),
+            Type::String => "nl_print_str".to_string(),
+            _ => format!("{}_print", c_type.trim_end_matches('*')),
+        }
+    }
+    
+    /// The array struct for elements of C type `element_type`.
+    fn array_struct_for(&self, element_type: &str) -> Option<String> {
+        let element_type = if element_type == "char*" { "const char*" } else { element_type };
+        if let Some((_, array)) = ARRAY_TYPES.iter().find(|(element, _)| *element == element_type) {
+            return Some(array.to_string());
+        }
+        if self.array_struct_of(element_type).is_some() {
+            return Some(format!("nl_array_{}", element_type.trim_end_matches('*')));
+        }
+        self.named_type_of(element_type).map(|name| format!("nl_array_{}", name))
+    }
+    
+    /// The array struct behind the C type `array_type` (e.g. `nl_array_int*`)
+    /// and the C type of its elements.
+    fn array_struct_of(&self, array_type: &str) -> Option<(String, String)> {
+        let name = array_type.strip_suffix('*')?;
+        if let Some((element, array)) = ARRAY_TYPES.iter().find(|(_, array)| *array == name) {
+            return Some((array.to_string(), element.to_string()));
+        }
+        let element_type = format!("{}*", name.strip_prefix("nl_array_")?);
+        let known = self.named_type_of(&element_type).is_some() || self.array_struct_of(&element_type).is_some();
+        known.then(|| (name.to_string(), element_type))
+    }
+    
+    /// The struct or enum behind the C type `c_type` (e.g. `Point*`).
+    fn named_type_of<'a>(&self, c_type: &'a str) -> Option<&'a str> {
+        let name = c_type.strip_suffix('*')?;
+        (self.structs.contains_key(name) || self.enums.contains_key(name)).then_some(name)
+    }
+    
+    /// The fields of the struct behind the C type `struct_type` (e.g. `Point*`).
+    fn struct_fields_of(&self, struct_type: &str) -> Option<(&str, &Vec<(String, String)>)> {
+        let name = struct_type.strip_suffix('*')?;
+        self.structs.get_key_value(name).map(|(name, fields)| (name.as_str(), fields))
+    }
+    
     fn collect_string_literals(&mut self, program: &Program) {
         for statement in &program.statements {
             self.collect_strings_from_statement(statement);
@@ -102,49 +489,54 @@ impl CCodeGenerator {
             Statement::Expression(expr) => {
                 self.collect_strings_from_expression(expr);
             }
-            Statement::LetDeclaration { initializer, .. } => {
-                if let Some(init) = initializer {
-                    self.collect_strings_from_expression(init);
-                }
+            Statement::LetDeclaration { initializer: Some(init), .. } => {
+                self.collect_strings_from_expression(init);
             }
             Statement::FunctionDeclaration { body, .. } => {
                 for stmt in body {
                     self.collect_strings_from_statement(stmt);
                 }
             }
-            Statement::If { condition, then_branch, else_branch } => {
+            Statement::If { condition, then_branch, else_branch, .. } => {
                 self.collect_strings_from_expression(condition);
                 self.collect_strings_from_statement(then_branch);
                 if let Some(else_stmt) = else_branch {
                     self.collect_strings_from_statement(else_stmt);
                 }
             }
-            Statement::While { condition, body } => {
+            Statement::While { condition, body, .. } => {
                 self.collect_strings_from_expression(condition);
                 self.collect_strings_from_statement(body);
             }
-            Statement::Return { value } => {
-                if let Some(e) = value {
-                    self.collect_strings_from_expression(e);
-                }
+            Statement::For { iterable, body, .. } => {
+                self.collect_strings_from_expression(iterable);
+                self.collect_strings_from_statement(body);
             }
-            Statement::Block { statements } => {
+            Statement::Return { value: Some(e), .. } => {
+                self.collect_strings_from_expression(e);
+            }
+            Statement::Block { statements, .. } => {
                 for stmt in statements {
                     self.collect_strings_from_statement(stmt);
                 }
             }
+            Statement::Match { scrutinee, arms, .. } => {
+                self.collect_strings_from_expression(scrutinee);
+                for arm in arms {
+                    self.collect_strings_from_pattern(&arm.pattern);
+                    self.collect_strings_from_statement(&arm.body);
+                }
+            }
             _ => {}
         }
     }
     
     fn collect_strings_from_expression(&mut self, expression: &Expr) {
         match expression {
-            Expr::Literal(Literal::String(s)) => {
-                if !self.string_constants.contains_key(s) {
-                    let const_name = format!("str_const_{}", self.string_counter);
-                    self.string_counter += 1;
-                    self.string_constants.insert(s.clone(), const_name);
-                }
+            Expr::Literal { value: Literal::String(s), .. } if !self.string_constants.contains_key(s) => {
+                let const_name = format!("str_const_{}", self.string_counter);
+                self.string_counter += 1;
+                self.string_constants.insert(s.clone(), const_name);
             }
             Expr::Binary { left, right, .. } => {
                 self.collect_strings_from_expression(left);
@@ -158,10 +550,76 @@ impl CCodeGenerator {
                     self.collect_strings_from_expression(arg);
                 }
             }
+            Expr::Range { start, end, .. } => {
+                self.collect_strings_from_expression(start);
+                self.collect_strings_from_expression(end);
+            }
+            Expr::FormatString { parts, .. } => {
+                for part in parts {
+                    if let FormatPart::Expr { expr, .. } = part {
+                        self.collect_strings_from_expression(expr);
+                    }
+                }
+            }
+            Expr::Array { elements, .. } => {
+                for element in elements {
+                    self.collect_strings_from_expression(element);
+                }
+            }
+            Expr::Index { sequence, index, .. } => {
+                self.collect_strings_from_expression(sequence);
+                self.collect_strings_from_expression(index);
+            }
+            Expr::SetIndex { sequence, index, value, .. } => {
+                self.collect_strings_from_expression(sequence);
+                self.collect_strings_from_expression(index);
+                self.collect_strings_from_expression(value);
+            }
+            Expr::StructLiteral { fields, .. } => {
+                for (_, value) in fields {
+                    self.collect_strings_from_expression(value);
+                }
+            }
+            Expr::Get { object, .. } => {
+                self.collect_strings_from_expression(object);
+            }
+            Expr::Set { object, value, .. } => {
+                self.collect_strings_from_expression(object);
+                self.collect_strings_from_expression(value);
+            }
+            Expr::EnumVariant { arguments, .. } => {
+                for arg in arguments {
+                    self.collect_strings_from_expression(arg);
+                }
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                self.collect_strings_from_expression(scrutinee);
+                for arm in arms {
+                    self.collect_strings_from_pattern(&arm.pattern);
+                    self.collect_strings_from_expression(&arm.body);
+                }
+            }
+            Expr::Assign { value, .. } => {
+                self.collect_strings_from_expression(value);
+            }
             _ => {}
         }
     }
     
+    fn collect_strings_from_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Literal { value, span } => {
+                self.collect_strings_from_expression(&Expr::Literal { value: value.clone(), span: *span });
+            }
+            Pattern::Variant { fields, .. } => {
+                for field in fields {
+                    self.collect_strings_from_pattern(field);
+                }
+            }
+            Pattern::Wildcard { .. } | Pattern::Binding { .. } => {}
+        }
+    }
+    
     fn generate_function_from_statement(&mut self, statement: &Statement) -> Result<String, CCodeGenError> {
         if let Statement::FunctionDeclaration { name, parameters, body, return_type, .. } = statement {
             let mut code = String::new();
@@ -183,7 +641,6 @@ impl CCodeGenerator {
                 }
                 let param_type = self.type_to_c(&param.param_type);
                 code.push_str(&format!("{} {}", param_type, param.name));
-                self.variables.insert(param.name.clone(), param_type);
             }
             
             if parameters.is_empty() {
@@ -191,6 +648,9 @@ impl CCodeGenerator {
             }
             
             code.push_str(") {\n");
+            if name == "main" {
+                code.push_str(&self.globals_init);
+            }
             
             // Function body
             for stmt in body {
@@ -213,35 +673,26 @@ impl CCodeGenerator {
     }
     
     fn generate_statement(&mut self, statement: &Statement) -> Result<String, CCodeGenError> {
+        self.generate_statement_kind(statement)
+            .map_err(|e| e.with_span(statement.span()))
+    }
+    
+    fn generate_statement_kind(&mut self, statement: &Statement) -> Result<String, CCodeGenError> {
         match statement {
             Statement::Expression(expr) => {
                 let expr_code = self.generate_expression(expr)?;
                 Ok(format!("    {};\n", expr_code))
             }
-            Statement::LetDeclaration { name, initializer, .. } => {
-                // Determine the C type based on the initializer
-                let c_type = if let Some(init) = initializer {
-                    match init {
-                        Expr::Literal(Literal::String(_)) => "char*".to_string(),
-                        Expr::Literal(Literal::Float(_)) => "double".to_string(),
-                        Expr::Literal(Literal::Boolean(_)) => "int".to_string(),
-                        Expr::Literal(Literal::Integer(_)) => "int".to_string(),
-                        _ => "int".to_string(), // Default fallback
-                    }
-                } else {
-                    "int".to_string() // Default for uninitialized variables
-                };
-                
-                self.variables.insert(name.clone(), c_type.clone());
-                
+            Statement::LetDeclaration { name, initializer, span, .. } => {
+                let c_type = self.declared_c_type(name, *span)?;
                 if let Some(init) = initializer {
-                    let init_code = self.generate_expression(init)?;
+                    let init_code = self.generate_value(init, &c_type)?;
                     Ok(format!("    {} {} = {};\n", c_type, name, init_code))
                 } else {
                     Ok(format!("    {} {};\n", c_type, name))
                 }
             }
-            Statement::If { condition, then_branch, else_branch } => {
+            Statement::If { condition, then_branch, else_branch, .. } => {
                 let mut code = String::new();
                 let cond_code = self.generate_expression(condition)?;
                 code.push_str(&format!("    if ({}) {{\n", cond_code));
@@ -258,7 +709,7 @@ impl CCodeGenerator {
                 code.push_str("    }\n");
                 Ok(code)
             }
-            Statement::While { condition, body } => {
+            Statement::While { condition, body, .. } => {
                 let mut code = String::new();
                 let cond_code = self.generate_expression(condition)?;
                 code.push_str(&format!("    while ({}) {{\n", cond_code));
@@ -269,7 +720,47 @@ impl CCodeGenerator {
                 code.push_str("    }\n");
                 Ok(code)
             }
-            Statement::Return { value } => {
+            Statement::For { variable, iterable, body, .. } => {
+                let mut code = String::new();
+                let counter = self.temp_counter;
+                self.temp_counter += 1;
+                
+                if let Expr::Range { start, end, .. } = iterable.as_ref() {
+                    let start_code = self.generate_expression(start)?;
+                    let end_code = self.generate_expression(end)?;
+                    // The end bound is evaluated once, like in the interpreter
+                    code.push_str(&format!(
+                        "    for (long long {var} = {}, {end} = {}; {var} < {end}; {var}++) {{\n",
+                        start_code, end_code, var = variable, end = format!("__for_end_{}", counter)
+                    ));
+                } else {
+                    let array_type = self.expression_c_type(iterable)?;
+                    let Some((_, element_type)) = self.array_struct_of(&array_type) else {
+                        return Err(CCodeGenError::UnsupportedFeature {
+                            message: format!("Cannot iterate over a value of C type {}", array_type),
+                        });
+                    };
+                    let array_code = self.generate_expression(iterable)?;
+                    let array_var = format!("__for_array_{}", counter);
+                    let index_var = format!("__for_index_{}", counter);
+                    code.push_str(&format!("    {{ {} {} = {};\n", array_type, array_var, array_code));
+                    code.push_str(&format!(
+                        "    for (int {index} = 0; {index} < {array}->len; {index}++) {{\n",
+                        index = index_var, array = array_var
+                    ));
+                    code.push_str(&format!("    {} {} = {}->data[{}];\n", element_type, variable, array_var, index_var));
+                }
+                
+                let body_code = self.generate_statement(body)?;
+                code.push_str(&format!("    {}", body_code));
+                
+                code.push_str("    }\n");
+                if !matches!(iterable.as_ref(), Expr::Range { .. }) {
+                    code.push_str("    }\n");
+                }
+                Ok(code)
+            }
+            Statement::Return { value, .. } => {
                 if let Some(e) = value {
                     let expr_code = self.generate_expression(e)?;
                     Ok(format!("    return {};\n", expr_code))
@@ -277,17 +768,20 @@ impl CCodeGenerator {
                     Ok("    return;\n".to_string())
                 }
             }
-            Statement::Block { statements } => {
+            Statement::Block { statements, .. } => {
                 let mut code = String::new();
                 for stmt in statements {
                     code.push_str(&self.generate_statement(stmt)?);
                 }
                 Ok(code)
             }
-            Statement::Break => {
+            Statement::Match { scrutinee, arms, .. } => {
+                self.generate_match(scrutinee, arms, |this, body| Ok(format!("    {}", this.generate_statement(body)?)))
+            }
+            Statement::Break { .. } => {
                 Ok("    break;\n".to_string())
             }
-            Statement::Continue => {
+            Statement::Continue { .. } => {
                 Ok("    continue;\n".to_string())
             }
             _ => {
@@ -299,20 +793,40 @@ impl CCodeGenerator {
     }
     
     fn generate_expression(&mut self, expression: &Expr) -> Result<String, CCodeGenError> {
+        self.generate_expression_kind(expression)
+            .map_err(|e| e.with_span(expression.span()))
+    }
+    
+    fn generate_expression_kind(&mut self, expression: &Expr) -> Result<String, CCodeGenError> {
         match expression {
-            Expr::Literal(literal) => self.generate_literal(literal),
-            Expr::Variable(name) => {
-                if self.variables.contains_key(name) {
-                    Ok(name.clone())
-                } else {
-                    Err(CCodeGenError::VariableNotFound { name: name.clone() })
+            Expr::Literal { value: literal, .. } => self.generate_literal(literal),
+            Expr::Variable { name, span } => {
+                let reference = self.symbols.reference(*span)
+                    .ok_or_else(|| CCodeGenError::Unresolved { name: name.clone() })?;
+                match self.symbols.definition_of(reference) {
+                    Some(definition) if definition.kind != DefinitionKind::Function => Ok(name.clone()),
+                    _ => Err(CCodeGenError::UnsupportedFeature {
+                        message: "Function values are not supported by the C backend".to_string(),
+                    }),
                 }
             }
             Expr::Binary { left, right, operator, .. } => {
                 let left_code = self.generate_expression(left)?;
                 let right_code = self.generate_expression(right)?;
                 let op_str = self.binary_op_to_c(operator);
-                Ok(format!("({} {} {})", left_code, op_str, right_code))
+                let strings = self.types.type_of(left) == Some(&Type::String);
+                let integers = self.types.type_of(left) == Some(&Type::Integer) && self.types.type_of(right) == Some(&Type::Integer);
+                match operator {
+                    BinaryOperator::Plus if strings => Ok(format!("nl_str_concat({}, {})", left_code, right_code)),
+                    // Strings compare by their contents
+                    BinaryOperator::EqualEqual | BinaryOperator::NotEqual | BinaryOperator::Less
+                    | BinaryOperator::LessEqual | BinaryOperator::Greater | BinaryOperator::GreaterEqual if strings => {
+                        Ok(format!("(strcmp({}, {}) {} 0)", left_code, right_code, op_str))
+                    }
+                    // Dividing integers gives a float
+                    BinaryOperator::Slash if integers => Ok(format!("((double){} / {})", left_code, right_code)),
+                    _ => Ok(format!("({} {} {})", left_code, op_str, right_code)),
+                }
             }
             Expr::Unary { operand, operator, .. } => {
                 let operand_code = self.generate_expression(operand)?;
@@ -320,73 +834,95 @@ impl CCodeGenerator {
                 Ok(format!("({}{})", op_str, operand_code))
             }
             Expr::Call { callee, arguments, .. } => {
-                let func_name = if let Expr::Variable(name) = callee.as_ref() {
-                    name.clone()
-                } else {
-                    return Err(CCodeGenError::UnsupportedFeature {
-                        message: "Complex function calls not supported".to_string(),
-                    });
+                let (func_name, builtin) = match callee.as_ref() {
+                    Expr::Variable { name, span } => {
+                        let reference = self.symbols.reference(*span)
+                            .ok_or_else(|| CCodeGenError::Unresolved { name: name.clone() })?;
+                        match self.symbols.definition_of(reference) {
+                            None => (name.clone(), true),
+                            Some(definition) if definition.kind == DefinitionKind::Function => (name.clone(), false),
+                            Some(_) => return Err(CCodeGenError::UnsupportedFeature {
+                                message: "Calling a function value is not supported by the C backend".to_string(),
+                            }),
+                        }
+                    }
+                    _ => return Err(CCodeGenError::UnsupportedFeature {
+                        message: "Calling a function value is not supported by the C backend".to_string(),
+                    }),
                 };
                 
                 // Handle built-in functions specially
                 match func_name.as_str() {
-                    "print" | "println" => {
-                        if arguments.len() == 1 {
-                            let arg_code = self.generate_expression(&arguments[0])?;
-                            let format_and_code = self.generate_print_format_and_arg(&arguments[0], &arg_code)?;
-                            if func_name == "println" {
-                                return Ok(format!("printf(\"{}\\n\", {})", format_and_code.0, format_and_code.1));
-                            } else {
-                                return Ok(format!("printf(\"{}\", {})", format_and_code.0, format_and_code.1));
-                            }
+                    // A function of the program may be named like a built-in
+                    _ if !builtin => {}
+                    "print" | "println" if arguments.len() == 1 => {
+                        let arg_code = self.generate_expression(&arguments[0])?;
+                        let arg_type = self.expression_c_type(&arguments[0])?;
+                        let printer = self.array_struct_of(&arg_type).map(|(array, _)| array)
+                            .or_else(|| self.named_type_of(&arg_type).map(str::to_string));
+                        if let Some(printer) = printer {
+                            let newline = if func_name == "println" { ", printf(\"\\n\")" } else { "" };
+                            return Ok(format!("({}_print({}){})", printer, arg_code, newline));
                         }
-                    }
-                    "str" => {
-                        if arguments.len() == 1 {
-                            let arg_code = self.generate_expression(&arguments[0])?;
-                            // For str() conversion, we need to determine the type
-                            // This is a simplified approach - ideally we'd have type information
-                            // For now, assume integer conversion (int_to_str)
-                            return Ok(format!("int_to_str({})", arg_code));
+                        let format_and_code = self.generate_print_format_and_arg(&arguments[0], &arg_code)?;
+                        if func_name == "println" {
+                            return Ok(format!("printf(\"{}\\n\", {})", format_and_code.0, format_and_code.1));
+                        } else {
+                            return Ok(format!("printf(\"{}\", {})", format_and_code.0, format_and_code.1));
                         }
                     }
-                    "int" => {
-                        if arguments.len() == 1 {
-                            let arg_code = self.generate_expression(&arguments[0])?;
-                            // For int() conversion, check if it's a string or numeric
-                            // If it's a string (from str() call), use atoi(), otherwise cast
-                            if arg_code.contains("int_to_str") || arg_code.contains("float_to_str") {
-                                return Ok(format!("atoi({})", arg_code));
-                            } else {
-                                return Ok(format!("((int){})", arg_code));
-                            }
-                        }
+                    "str" if arguments.len() == 1 => {
+                        let arg_code = self.generate_expression(&arguments[0])?;
+                        return Ok(match self.types.type_of(&arguments[0]) {
+                            Some(Type::String) => arg_code,
+                            Some(Type::Float) => format!("float_to_str({})", arg_code),
+                            Some(Type::Boolean) => format!("(({}) ? \"true\" : \"false\")", arg_code),
+                            _ => format!("int_to_str({})", arg_code),
+                        });
                     }
-                    "float" => {
-                        if arguments.len() == 1 {
-                            let arg_code = self.generate_expression(&arguments[0])?;
-                            // For float() conversion, check if it's a string or numeric
-                            // If it's a string (from str() call), use atof(), otherwise cast
-                            if arg_code.contains("int_to_str") || arg_code.contains("float_to_str") {
-                                return Ok(format!("atof({})", arg_code));
-                            } else {
-                                return Ok(format!("((double){})", arg_code));
-                            }
+                    "int" if arguments.len() == 1 => {
+                        let arg_code = self.generate_expression(&arguments[0])?;
+                        if self.types.type_of(&arguments[0]) == Some(&Type::String) {
+                            return Ok(format!("atoll({})", arg_code));
+                        } else {
+                            return Ok(format!("((long long){})", arg_code));
                         }
                     }
-                    "abs_float" => {
-                        if arguments.len() == 1 {
-                            let arg_code = self.generate_expression(&arguments[0])?;
-                            // Use fabs() from math.h for floating point absolute value
-                            return Ok(format!("fabs({})", arg_code));
+                    "float" if arguments.len() == 1 => {
+                        let arg_code = self.generate_expression(&arguments[0])?;
+                        if self.types.type_of(&arguments[0]) == Some(&Type::String) {
+                            return Ok(format!("atof({})", arg_code));
+                        } else {
+                            return Ok(format!("((double){})", arg_code));
                         }
                     }
-                    "abs" => {
-                        if arguments.len() == 1 {
-                            let arg_code = self.generate_expression(&arguments[0])?;
-                            // Use abs() from stdlib.h for integer absolute value
-                            return Ok(format!("abs({})", arg_code));
+                    "abs_float" if arguments.len() == 1 => {
+                        let arg_code = self.generate_expression(&arguments[0])?;
+                        // Use fabs() from math.h for floating point absolute value
+                        return Ok(format!("fabs({})", arg_code));
+                    }
+                    "len" if arguments.len() == 1 => {
+                        let arg_code = self.generate_expression(&arguments[0])?;
+                        if self.array_struct_of(&self.expression_c_type(&arguments[0])?).is_some() {
+                            return Ok(format!("({})->len", arg_code));
                         }
+                        return Ok(format!("((long long)strlen({}))", arg_code));
+                    }
+                    "push" if arguments.len() == 2 => {
+                        let array_type = self.expression_c_type(&arguments[0])?;
+                        let Some((array, element_type)) = self.array_struct_of(&array_type) else {
+                            return Err(CCodeGenError::UnsupportedFeature {
+                                message: format!("Cannot push to a value of C type {}", array_type),
+                            });
+                        };
+                        let array_code = self.generate_expression(&arguments[0])?;
+                        let value_code = self.generate_value(&arguments[1], &element_type)?;
+                        return Ok(format!("{}_push({}, {})", array, array_code, value_code));
+                    }
+                    "abs" if arguments.len() == 1 => {
+                        let arg_code = self.generate_expression(&arguments[0])?;
+                        // Use llabs() from stdlib.h for integer absolute value
+                        return Ok(format!("llabs({})", arg_code));
                     }
                     _ => {
                         // Regular function call - fall through to default handling
@@ -401,50 +937,251 @@ impl CCodeGenerator {
             }
             Expr::Function { .. } => {
                 Err(CCodeGenError::UnsupportedFeature {
-                    message: "Function expressions not supported".to_string(),
+                    message: "Lambdas are not supported by the C backend".to_string(),
                 })
             }
-            Expr::Get { object, name } => {
-                // Handle module-qualified access like math.PI
-                if let Expr::Variable(module_name) = object.as_ref() {
-                    let qualified_name = format!("{}.{}", module_name, name);
-                    if self.variables.contains_key(&qualified_name) {
-                        Ok(qualified_name)
-                    } else {
-                        Err(CCodeGenError::UnsupportedFeature {
-                            message: format!("Undefined variable: {}", qualified_name),
-                        })
-                    }
-                } else {
-                    Err(CCodeGenError::UnsupportedFeature {
-                        message: "Complex object access not supported".to_string(),
-                    })
+            Expr::Get { object, name, .. } => self.generate_field(object, name),
+            Expr::Set { object, name, value, .. } => {
+                let field = self.generate_field(object, name)?;
+                let field_type = self.expression_c_type(expression)?;
+                let value_code = self.generate_value(value, &field_type)?;
+                Ok(format!("({} = {})", field, value_code))
+            }
+            Expr::StructLiteral { name, fields, .. } => {
+                let Some(declared) = self.structs.get(name).cloned() else {
+                    return Err(CCodeGenError::UnsupportedFeature {
+                        message: format!("Unknown struct: {}", name),
+                    });
+                };
+                // The constructor takes the fields in declaration order
+                let mut args_code = Vec::new();
+                for (field, c_type) in &declared {
+                    let Some((_, value)) = fields.iter().find(|(name, _)| name == field) else {
+                        return Err(CCodeGenError::UnsupportedFeature {
+                            message: format!("Missing field '{}' in initializer of '{}'", field, name),
+                        });
+                    };
+                    args_code.push(self.generate_value(value, c_type)?);
                 }
+                Ok(format!("{}_new({})", name, args_code.join(", ")))
             }
-            Expr::Set { .. } => {
-                Err(CCodeGenError::UnsupportedFeature {
-                    message: "Property assignment not supported".to_string(),
-                })
+            Expr::EnumVariant { enum_name, variant, arguments, .. } => {
+                let Some(c_fields) = self.enums.get(enum_name)
+                    .and_then(|variants| variants.iter().find(|(name, _)| name == variant))
+                    .map(|(_, c_fields)| c_fields.clone()) else {
+                    return Err(CCodeGenError::UnsupportedFeature {
+                        message: format!("Unknown enum variant: {}::{}", enum_name, variant),
+                    });
+                };
+                let mut args_code = Vec::new();
+                for (argument, c_type) in arguments.iter().zip(&c_fields) {
+                    args_code.push(self.generate_value(argument, c_type)?);
+                }
+                Ok(format!("{}_{}({})", enum_name, variant, args_code.join(", ")))
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                // A GNU statement expression, so the arms can declare their bindings
+                let result_type = self.expression_c_type(expression)?;
+                let result = format!("__match_result_{}", self.temp_counter);
+                let chain = self.generate_match(scrutinee, arms, |this, body| {
+                    Ok(format!("    {} = {};\n", result, this.generate_expression(body)?))
+                })?;
+                Ok(format!("({{ {} {};\n{}    {}; }})", result_type, result, chain, result))
             }
-            Expr::Index { .. } => {
+            Expr::Index { sequence, index, span } => {
+                self.generate_element(sequence, index, *span)
+            }
+            Expr::SetIndex { sequence, index, value, span } => {
+                let element = self.generate_element(sequence, index, *span)?;
+                let element_type = self.expression_c_type(expression)?;
+                let value_code = self.generate_value(value, &element_type)?;
+                Ok(format!("({} = {})", element, value_code))
+            }
+            Expr::Array { .. } => {
+                let array_type = self.expression_c_type(expression)?;
+                self.generate_value(expression, &array_type)
+            }
+            Expr::Range { .. } => {
                 Err(CCodeGenError::UnsupportedFeature {
-                    message: "Array indexing not supported".to_string(),
+                    message: "Ranges are only supported as for loop iterables".to_string(),
                 })
             }
-            Expr::Assign { name, value } => {
-                let value_code = self.generate_expression(value)?;
-                // Register the variable as int type (simplified)
-                self.variables.insert(name.clone(), "int".to_string());
+            Expr::FormatString { parts, .. } => {
+                // One printf-style format with a conversion per interpolation
+                let mut format = String::new();
+                let mut arguments = String::new();
+                for part in parts {
+                    let (expr, spec) = match part {
+                        FormatPart::Literal(text) => {
+                            format.push_str(&text.replace('%', "%%"));
+                            continue;
+                        }
+                        FormatPart::Expr { expr, spec } => (expr, spec),
+                    };
+                    let code = self.generate_expression(expr)?;
+                    let (conversion, argument) = match self.types.type_of(expr) {
+                        // A literal or a length is an `int`, too narrow for `%lld`
+                        Some(Type::Integer) => ("lld", format!("(long long){}", code)),
+                        Some(Type::Float) if spec.precision.is_some() => ("f", code),
+                        Some(Type::Float) => ("s", format!("float_to_str({})", code)),
+                        Some(Type::Boolean) => ("s", format!("(({}) ? \"true\" : \"false\")", code)),
+                        _ => ("s", code),
+                    };
+                    format.push_str(&format!("%{}{}", spec.printf_flags(), conversion));
+                    arguments.push_str(&format!(", {}", argument));
+                }
+                Ok(format!("nl_format(\"{}\"{})", self.escape_c_string(&format), arguments))
+            }
+            Expr::Assign { name, value, .. } => {
+                let c_type = self.expression_c_type(expression)?;
+                let value_code = self.generate_value(value, &c_type)?;
                 // Return assignment expression
                 Ok(format!("({} = {})", name, value_code))
             }
         }
     }
     
+    /// Generates a match as an if/else chain over the arms, testing a copy
+    /// of the scrutinee. `generate_body` generates the code for an arm's body
+    /// once the variables its pattern binds are declared.
+    fn generate_match<B>(
+        &mut self,
+        scrutinee: &Expr,
+        arms: &[MatchArm<B>],
+        mut generate_body: impl FnMut(&mut Self, &B) -> Result<String, CCodeGenError>,
+    ) -> Result<String, CCodeGenError> {
+        let subject = format!("__match_{}", self.temp_counter);
+        self.temp_counter += 1;
+        let c_type = self.expression_c_type(scrutinee)?;
+        let scrutinee_code = self.generate_expression(scrutinee)?;
+        let mut code = format!("    {{ {} {} = {};\n", c_type, subject, scrutinee_code);
+        
+        for (i, arm) in arms.iter().enumerate() {
+            let mut tests = Vec::new();
+            let mut bindings = Vec::new();
+            self.pattern_tests(&arm.pattern, &subject, &c_type, &mut tests, &mut bindings)?;
+            let condition = if tests.is_empty() { "1".to_string() } else { tests.join(" && ") };
+            let keyword = if i == 0 { "if" } else { "} else if" };
+            code.push_str(&format!("    {} ({}) {{\n", keyword, condition));
+            
+            for (name, c_type, value) in bindings {
+                code.push_str(&format!("    {} {} = {};\n", c_type, name, value));
+            }
+            code.push_str(&generate_body(self, &arm.body)?);
+        }
+        
+        if !arms.is_empty() {
+            code.push_str("    }\n");
+        }
+        code.push_str("    }\n");
+        Ok(code)
+    }
+    
+    /// Collects the C conditions under which `pattern` matches `value`, of C
+    /// type `c_type`, and the variables it binds with their C types and values.
+    fn pattern_tests(
+        &self,
+        pattern: &Pattern,
+        value: &str,
+        c_type: &str,
+        tests: &mut Vec<String>,
+        bindings: &mut Vec<(String, String, String)>,
+    ) -> Result<(), CCodeGenError> {
+        match pattern {
+            Pattern::Wildcard { .. } => {}
+            Pattern::Binding { name, .. } => bindings.push((name.clone(), c_type.to_string(), value.to_string())),
+            Pattern::Literal { value: literal @ Literal::String(_), .. } => {
+                tests.push(format!("strcmp({}, {}) == 0", value, self.generate_literal(literal)?));
+            }
+            Pattern::Literal { value: literal, .. } => {
+                tests.push(format!("{} == {}", value, self.generate_literal(literal)?));
+            }
+            Pattern::Variant { enum_name, variant, fields, .. } => {
+                let Some((_, c_fields)) = self.enums.get(enum_name)
+                    .and_then(|variants| variants.iter().find(|(name, _)| name == variant)) else {
+                    return Err(CCodeGenError::UnsupportedFeature {
+                        message: format!("Unknown enum variant: {}::{}", enum_name, variant),
+                    });
+                };
+                tests.push(format!("{}->tag == {}_{}_tag", value, enum_name, variant));
+                for (i, (field, field_type)) in fields.iter().zip(c_fields).enumerate() {
+                    self.pattern_tests(field, &format!("{}->{}_{}", value, variant, i), field_type, tests, bindings)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Generates `expression` where a value of C type `c_type` is expected,
+    /// which is what gives an empty array literal its element type.
+    fn generate_value(&mut self, expression: &Expr, c_type: &str) -> Result<String, CCodeGenError> {
+        let Expr::Array { elements, .. } = expression else {
+            return self.generate_expression(expression);
+        };
+        
+        let Some((array, element_type)) = self.array_struct_of(c_type) else {
+            return Err(CCodeGenError::UnsupportedFeature {
+                message: format!("Unsupported array type: {}", c_type),
+            });
+        };
+        if elements.is_empty() {
+            return Ok(format!("{}_new(0, NULL)", array));
+        }
+        
+        let mut elements_code = Vec::new();
+        for element in elements {
+            elements_code.push(self.generate_value(element, &element_type)?);
+        }
+        Ok(format!("{}_new({}, ({}[]){{{}}})", array, elements.len(), element_type, elements_code.join(", ")))
+    }
+    
+    /// Generates a reference to `object.field` that can be read or assigned.
+    fn generate_field(&mut self, object: &Expr, field: &str) -> Result<String, CCodeGenError> {
+        let object_type = self.expression_c_type(object)?;
+        if self.struct_fields_of(&object_type).is_none() {
+            return Err(CCodeGenError::UnsupportedFeature {
+                message: format!("Cannot access field '{}' on a value of C type {}", field, object_type),
+            });
+        }
+        let object_code = self.generate_expression(object)?;
+        Ok(format!("({})->{}", object_code, field))
+    }
+    
+    /// Generates a bounds-checked reference to `sequence[index]` that can be
+    /// read or assigned.
+    fn generate_element(&mut self, sequence: &Expr, index: &Expr, span: Span) -> Result<String, CCodeGenError> {
+        let array_type = self.expression_c_type(sequence)?;
+        let Some((array, _)) = self.array_struct_of(&array_type) else {
+            return Err(CCodeGenError::UnsupportedFeature {
+                message: format!("Cannot index into a value of C type {}", array_type),
+            });
+        };
+        let sequence_code = self.generate_expression(sequence)?;
+        let index_code = self.generate_expression(index)?;
+        Ok(format!("(*{}_at({}, {}, {}, {}))", array, sequence_code, index_code, span.line, span.column))
+    }
+    
+    /// C type of `expression`, from the type analysis gave it.
+    fn expression_c_type(&self, expression: &Expr) -> Result<String, CCodeGenError> {
+        self.types.type_of(expression)
+            .map(|ty| self.type_to_c(ty))
+            .ok_or(CCodeGenError::Untyped)
+    }
+    
+    /// C type of the variable `name` declared by the statement at `span`.
+    fn declared_c_type(&self, name: &str, span: Span) -> Result<String, CCodeGenError> {
+        self.symbols.declared(name, span)
+            .map(|definition| self.type_to_c(&definition.ty))
+            .ok_or_else(|| CCodeGenError::Unresolved { name: name.to_string() })
+    }
+    
     fn generate_literal(&self, literal: &Literal) -> Result<String, CCodeGenError> {
         match literal {
+            // C has no literal for the most negative integer, only the
+            // negation of a constant too large to be signed
+            Literal::Integer(i64::MIN) => Ok(format!("({}LL - 1)", i64::MIN + 1)),
             Literal::Integer(i) => Ok(i.to_string()),
-            Literal::Float(f) => Ok(f.to_string()),
+            Literal::Float(f) => Ok(format!("{:?}", f)),
             Literal::String(s) => {
                 if let Some(const_name) = self.string_constants.get(s) {
                     Ok(const_name.clone())
@@ -461,13 +1198,18 @@ impl CCodeGenerator {
     
     fn type_to_c(&self, nlang_type: &Type) -> String {
         match nlang_type {
-            Type::Integer => "int".to_string(),
+            Type::Integer => "long long".to_string(),
             Type::Float => "double".to_string(),
             Type::String => "const char*".to_string(),
             Type::Boolean => "int".to_string(),
             Type::Void => "void".to_string(),
-            Type::Array(_) => "void*".to_string(), // Simplified array handling
+            Type::Array(element_type) => match self.array_struct_for(&self.type_to_c(element_type)) {
+                Some(array) => format!("{}*", array),
+                None => "void*".to_string(),
+            },
+            Type::Named(name) => format!("{}*", name),
             Type::Function { .. } => "void*".to_string(), // Simplified function pointer handling
+            Type::Error | Type::Unknown => unreachable!("programs are analyzed before they are compiled"),
         }
     }
     
@@ -532,51 +1274,40 @@ impl CCodeGenerator {
         Ok(format!("{} {}({});", ret_type_str, name, params_str))
     }
     
+    /// The printf format that prints `expr`, and the argument to pass for
+    /// it given its code `arg_code`.
     fn generate_print_format_and_arg(&self, expr: &Expr, arg_code: &str) -> Result<(String, String), CCodeGenError> {
-        match expr {
-            Expr::Literal(Literal::Integer(_)) => Ok(("%d".to_string(), arg_code.to_string())),
-            Expr::Literal(Literal::Float(_)) => Ok(("%f".to_string(), arg_code.to_string())),
-            Expr::Literal(Literal::Boolean(b)) => {
-                let bool_str = if *b { "true" } else { "false" };
-                Ok(("%s".to_string(), format!("\"{}\"", bool_str)))
-            }
-            Expr::Literal(Literal::String(_)) => Ok(("%s".to_string(), arg_code.to_string())),
-            Expr::Literal(Literal::Null) => Ok(("%s".to_string(), "\"null\"".to_string())),
-            Expr::Variable(name) => {
-                // Check the variable type from our tracking
-                if let Some(var_type) = self.variables.get(name) {
-                    match var_type.as_str() {
-                        "int" => Ok(("%d".to_string(), arg_code.to_string())),
-                        "float" | "double" => Ok(("%f".to_string(), arg_code.to_string())),
-                        "char*" => Ok(("%s".to_string(), arg_code.to_string())),
-                        _ => Ok(("%s".to_string(), arg_code.to_string())), // Default to string
-                    }
-                } else {
-                    // Variable not found in tracking, default to string
-                    Ok(("%s".to_string(), arg_code.to_string()))
-                }
-            }
-            Expr::Binary { .. } => {
-                // For expressions, assume they evaluate to integers for now
-                // This is a simplification - in a full implementation, we'd need type inference
-                Ok(("%d".to_string(), arg_code.to_string()))
-            }
-            _ => {
-                // Default to string representation
-                Ok(("%s".to_string(), arg_code.to_string()))
-            }
+        let plain = |format: &str| Ok((format.to_string(), arg_code.to_string()));
+        if let Expr::Literal { value: Literal::Null, .. } = expr {
+            return Ok(("%s".to_string(), "\"null\"".to_string()));
+        }
+        match self.types.type_of(expr) {
+            // Varargs aren't converted to the conversion's type, and
+            // literals and lengths are narrower than `long long`
+            Some(Type::Integer) => Ok(("%lld".to_string(), format!("(long long){}", arg_code))),
+            Some(Type::Float) => plain("%f"),
+            Some(Type::Boolean) => Ok(("%s".to_string(), format!("(({}) ? \"true\" : \"false\")", arg_code))),
+            Some(Type::String) => plain("%s"),
+            Some(_) => plain("%s"),
+            None => Err(CCodeGenError::Untyped),
         }
     }
     
+    /// The body of a C string literal holding the UTF-8 bytes of `s`. Bytes
+    /// other than printable ASCII are written as octal escapes, which unlike
+    /// hex ones can't run into a following digit; `?` is escaped so that no
+    /// trigraphs form.
     fn escape_c_string(&self, s: &str) -> String {
-        s.chars()
-            .map(|c| match c {
-                '"' => "\\\"".to_string(),
-                '\\' => "\\\\".to_string(),
-                '\n' => "\\n".to_string(),
-                '\r' => "\\r".to_string(),
-                '\t' => "\\t".to_string(),
-                c => c.to_string(),
+        s.bytes()
+            .map(|byte| match byte {
+                b'"' => "\\\"".to_string(),
+                b'\\' => "\\\\".to_string(),
+                b'?' => "\\?".to_string(),
+                b'\n' => "\\n".to_string(),
+                b'\r' => "\\r".to_string(),
+                b'\t' => "\\t".to_string(),
+                b' '..=b'~' => (byte as char).to_string(),
+                byte => format!("\\{:03o}", byte),
             })
             .collect()
     }
@@ -0,0 +1,165 @@
+//! Whole-tree checking without running or compiling anything
+//!
+//! Each file goes through the lexer, parser and semantic analysis, and every
+//! problem found along the way becomes a [`Diagnostic`]. Modules the checked
+//! files import are checked too, so errors in them are reported against
+//! their own source rather than only at the `import`.
+
+use crate::ast::{Program, Statement};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::lexer::tokenize;
+use crate::parser::parse;
+use crate::semantic::{self, resolve_module_path, SymbolIndex};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests;
+
+/// The outcome of checking one file.
+#[derive(Debug)]
+pub struct FileCheck {
+    pub path: PathBuf,
+    pub source: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl FileCheck {
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.severity == severity).count()
+    }
+}
+
+/// Checks the files in `inputs`, every `.nlang` file under the directories
+/// in it, and every module they import, in that order.
+///
+/// Files that declare `main` (or use `ASSIGN_MAIN`) are checked as
+/// programs; the rest, and everything reached through an import, as
+/// modules that don't need an entry point.
+pub fn check_paths(inputs: &[PathBuf]) -> std::io::Result<Vec<FileCheck>> {
+    let mut queue = expand_inputs(inputs)?;
+
+    let mut seen = HashSet::new();
+    let mut checks = Vec::new();
+    let mut next = 0;
+    let inputs_end = queue.len();
+    while next < queue.len() {
+        let path = queue[next].clone();
+        let is_input = next < inputs_end;
+        next += 1;
+        // Imports are resolved relative to the importing file, so the same
+        // module can be reached through differently spelled paths
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if !seen.insert(key) {
+            continue;
+        }
+
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            // A missing module is already reported at the import
+            Err(_) if !is_input => continue,
+            Err(error) => {
+                let diagnostic = Diagnostic::error(format!("Failed to read '{}': {}", path.display(), error));
+                checks.push(FileCheck { path, source: String::new(), diagnostics: vec![diagnostic] });
+                continue;
+            }
+        };
+        let SourceCheck { diagnostics, program, .. } = check_source(&source, &path, is_input);
+        if let Some(program) = program {
+            let current_dir = path.parent().unwrap_or_else(|| Path::new("."));
+            for module in imported_modules(&program.statements) {
+                queue.push(resolve_module_path(current_dir, module));
+            }
+        }
+        checks.push(FileCheck { path, source, diagnostics });
+    }
+    Ok(checks)
+}
+
+/// What checking the source of one file found.
+#[derive(Debug, Default)]
+pub struct SourceCheck {
+    pub diagnostics: Vec<Diagnostic>,
+    /// The parsed program, unless lexing failed.
+    pub program: Option<Program>,
+    /// The names declared and used in the program, as far as it parsed.
+    pub index: SymbolIndex,
+}
+
+/// The files in `inputs`, with each directory replaced by the `.nlang`
+/// files under it.
+pub fn expand_inputs(inputs: &[PathBuf]) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            collect_nlang_files(input, &mut files)?;
+        } else {
+            files.push(input.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Lexes, parses and analyzes one file.
+///
+/// With `as_program`, a file declaring an entry point must have a valid
+/// one; without it, the file is always checked as a module.
+pub fn check_source(source: &str, path: &Path, as_program: bool) -> SourceCheck {
+    let tokens = match tokenize(source) {
+        Ok(tokens) => tokens,
+        Err(error) => return SourceCheck { diagnostics: vec![Diagnostic::from(&error)], ..SourceCheck::default() },
+    };
+    let (program, errors) = parse(&tokens);
+    let analysis = if as_program && has_entry_point(&program) {
+        semantic::check(program.clone(), Some(path))
+    } else {
+        semantic::check_module(program.clone(), Some(path))
+    };
+    // Analyzing what did parse would mostly report fallout of the syntax
+    // errors, but still tells what the names in it are
+    let diagnostics = if errors.is_empty() {
+        analysis.errors.iter().map(Diagnostic::from)
+            .chain(analysis.warnings.iter().map(Diagnostic::from))
+            .collect()
+    } else {
+        errors.iter().map(Diagnostic::from).collect()
+    };
+    SourceCheck { diagnostics, program: Some(program), index: analysis.index }
+}
+
+fn has_entry_point(program: &Program) -> bool {
+    program.statements.iter().any(|statement| match statement {
+        Statement::FunctionDeclaration { name, .. } => name == "main",
+        Statement::AssignMain { .. } => true,
+        _ => false,
+    })
+}
+
+pub(crate) fn imported_modules(statements: &[Statement]) -> Vec<&str> {
+    let mut modules = Vec::new();
+    for statement in statements {
+        match statement {
+            Statement::Import { module, .. } | Statement::ImportFrom { module, .. } => modules.push(module.as_str()),
+            Statement::Block { statements, .. } => modules.extend(imported_modules(statements)),
+            _ => {}
+        }
+    }
+    modules
+}
+
+/// Adds every `.nlang` file under `dir` to `files`, sorted by path so the
+/// output doesn't depend on directory order.
+fn collect_nlang_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_nlang_files(&entry, files)?;
+        } else if entry.extension().is_some_and(|ext| ext == "nlang") {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
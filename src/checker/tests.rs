@@ -0,0 +1,50 @@
+#[cfg(test)]
+mod checker_tests {
+    use crate::checker::check_paths;
+    use crate::diagnostics::Severity;
+    use std::path::Path;
+
+    fn write(dir: &Path, name: &str, source: &str) {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, source).unwrap();
+    }
+
+    #[test]
+    fn test_directory_and_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app");
+        write(&app, "main.nlang", "import util;\ndef main() {\n    print(double(2));\n}\n");
+        write(&app, "util.nlang", "export def double(x: int): int {\n    return x * 2;\n}\n");
+        write(&app, "notes.txt", "not nlang");
+        // Only reached through an import from outside the checked directory
+        write(dir.path(), "broken.nlang", "export def f(): int {\n    return missing;\n}\n");
+        write(dir.path(), "other.nlang", "import broken;\nstore x = 1 +;\n");
+
+        let checks = check_paths(std::slice::from_ref(&app)).unwrap();
+        let paths: Vec<_> = checks.iter().map(|check| check.path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(paths, ["main.nlang", "util.nlang"]);
+        assert!(checks.iter().all(|check| check.count(Severity::Error) == 0));
+
+        let checks = check_paths(&[dir.path().join("other.nlang")]).unwrap();
+        assert_eq!(checks.len(), 2);
+        // Syntax errors in the file itself, and its imports still get checked
+        assert_eq!(checks[0].count(Severity::Error), 1);
+        assert_eq!(checks[0].diagnostics[0].code.as_deref(), Some("E0002"));
+        assert!(checks[1].path.ends_with("broken.nlang"));
+        let undefined = checks[1].diagnostics.iter().find(|d| d.message == "Undefined variable: missing").unwrap();
+        assert_eq!(undefined.primary_span().unwrap().line, 2);
+    }
+
+    #[test]
+    fn test_entry_points() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "lib.nlang", "export store answer = 42;\n");
+        write(dir.path(), "bad_main.nlang", "def main(x: int) {}\n");
+
+        let checks = check_paths(&[dir.path().to_path_buf()]).unwrap();
+        let errors: Vec<_> = checks.iter().map(|check| check.count(Severity::Error)).collect();
+        assert_eq!(errors, [1, 0]);
+        assert_eq!(checks[0].diagnostics[0].message, "Main function should not have parameters");
+    }
+}
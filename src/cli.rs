@@ -1,18 +1,59 @@
-use std::path::PathBuf;
-use crate::execution_engine::ExecutionEngine;
-use anyhow::bail;
+use std::path::{Path, PathBuf};
+use crate::checker::{check_paths, expand_inputs};
+use crate::diagnostics::{self, Renderer, Severity};
+use crate::doc::{self, DocFormat};
+use crate::execution_engine::{ExecutionEngine, ExecutionError};
+use crate::formatter::format_source;
+use crate::llvm_codegen::Target;
+use crate::lsp;
+use crate::repl::Repl;
+use crate::semantic::{SemanticError, SemanticErrors};
+use crate::test_runner::TestFile;
+use anyhow::{anyhow, bail};
 
 /// Validates that the input file has a .nlang extension.
-fn validate_nlang_file(input: &PathBuf) -> anyhow::Result<()> {
-    if input.extension().map_or(false, |ext| ext == "nlang") {
+fn validate_nlang_file(input: &Path) -> anyhow::Result<()> {
+    if input.extension().is_some_and(|ext| ext == "nlang") {
         Ok(())
     } else {
         bail!("Input file must have a .nlang extension, but got: {}", input.display());
     }
 }
 
-pub fn compile(input: PathBuf, output: Option<PathBuf>) -> anyhow::Result<()> {
+/// `count` followed by `noun`, pluralized unless the count is one.
+fn plural(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+/// `target`, or the host when none was given.
+fn target_or_host(target: Option<Target>) -> anyhow::Result<Target> {
+    match target.or_else(Target::host) {
+        Some(target) => Ok(target),
+        None => bail!("The host platform is not a supported target; choose one with --target"),
+    }
+}
+
+/// Prints `error` to stderr as rendered diagnostics pointing into `source`
+/// and returns a short summary error for the caller to propagate.
+fn report(error: ExecutionError, input: &Path, source: &str) -> anyhow::Error {
+    let diagnostics = diagnostics::from_execution_error(&error);
+    let renderer = Renderer::for_stderr();
+    let path = input.display().to_string();
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        if i > 0 {
+            eprintln!();
+        }
+        eprint!("{}", renderer.render(diagnostic, &path, source));
+    }
+    match diagnostics.len() {
+        1 => anyhow!("could not process `{}` due to the previous error", input.display()),
+        count => anyhow!("could not process `{}` due to {} previous errors", input.display(), count),
+    }
+}
+
+pub fn compile(input: PathBuf, output: Option<PathBuf>, target: Option<Target>) -> anyhow::Result<()> {
     validate_nlang_file(&input)?;
+    let target = target_or_host(target)?;
     println!("Compiling {}...", input.display());
     
     // Read the source code
@@ -29,19 +70,21 @@ pub fn compile(input: PathBuf, output: Option<PathBuf>) -> anyhow::Result<()> {
     // Determine output path
     let output_path = output.unwrap_or_else(|| {
         let mut path = input.clone();
-        path.set_extension("exe");
+        path.set_extension(target.executable_extension());
         path
     });
     
     // Compile to executable
-    engine.compile_to_executable(&source, module_name, &output_path)?;
+    engine.compile_to_executable(&source, module_name, &output_path, target)
+        .map_err(|e| report(e, &input, &source))?;
     
     println!("Compiled successfully to: {}", output_path.display());
     Ok(())
 }
 
-pub fn generate_ir(input: PathBuf, output: Option<PathBuf>) -> anyhow::Result<()> {
+pub fn generate_ir(input: PathBuf, output: Option<PathBuf>, target: Option<Target>) -> anyhow::Result<()> {
     validate_nlang_file(&input)?;
+    let target = target_or_host(target)?;
     println!("Generating LLVM IR for {}...", input.display());
     
     // Read the source code
@@ -56,7 +99,8 @@ pub fn generate_ir(input: PathBuf, output: Option<PathBuf>) -> anyhow::Result<()
         .unwrap_or("main");
     
     // Generate IR
-    let ir_code = engine.compile_to_ir(&source, module_name)?;
+    let ir_code = engine.compile_to_ir(&source, module_name, target)
+        .map_err(|e| report(e, &input, &source))?;
     
     // Determine output path
     let output_path = output.unwrap_or_else(|| {
@@ -88,7 +132,8 @@ pub fn generate_c(input: PathBuf, output: Option<PathBuf>) -> anyhow::Result<()>
         .unwrap_or("main");
     
     // Generate C code
-    let c_code = engine.compile_to_c(&source, module_name)?;
+    let c_code = engine.compile_to_c(&source, module_name)
+        .map_err(|e| report(e, &input, &source))?;
     
     // Determine output path
     let output_path = output.unwrap_or_else(|| {
@@ -104,7 +149,7 @@ pub fn generate_c(input: PathBuf, output: Option<PathBuf>) -> anyhow::Result<()>
     Ok(())
 }
 
-pub fn run(input: PathBuf) -> anyhow::Result<()> {
+pub fn run(input: PathBuf, vm: bool) -> anyhow::Result<()> {
     validate_nlang_file(&input)?;
     println!("Running {}...", input.display());
     
@@ -120,15 +165,159 @@ pub fn run(input: PathBuf) -> anyhow::Result<()> {
         .unwrap_or("main");
     
     // Execute the program with file path for proper module resolution
-    match engine.execute_source_with_file_path(&source, module_name, Some(&input)) {
+    let result = if vm {
+        engine.execute_source_on_vm(&source, module_name, Some(&input))
+    } else {
+        engine.execute_source_with_file_path(&source, module_name, Some(&input))
+    };
+    match result {
         Ok(exit_code) => {
             println!("Program executed successfully with exit code: {}", exit_code);
         }
         Err(e) => {
-            eprintln!("Execution error: {}", e);
-            return Err(e.into());
+            return Err(report(e, &input, &source));
         }
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+pub fn repl() -> anyhow::Result<()> {
+    Repl::new().run()
+}
+
+/// Checks every input file and the modules they import, printing all of
+/// their diagnostics. Fails if any of them has an error.
+pub fn check(inputs: Vec<PathBuf>) -> anyhow::Result<()> {
+    let checks = check_paths(&inputs)?;
+    let renderer = Renderer::for_stderr();
+    let mut printed = false;
+    for check in &checks {
+        let path = check.path.display().to_string();
+        for diagnostic in &check.diagnostics {
+            if printed {
+                eprintln!();
+            }
+            eprint!("{}", renderer.render(diagnostic, &path, &check.source));
+            printed = true;
+        }
+    }
+    
+    let errors: usize = checks.iter().map(|check| check.count(Severity::Error)).sum();
+    let warnings: usize = checks.iter().map(|check| check.count(Severity::Warning)).sum();
+    println!("Checked {}: {}, {}", plural(checks.len(), "file"), plural(errors, "error"), plural(warnings, "warning"));
+    if errors > 0 {
+        let failed = checks.iter().filter(|check| check.count(Severity::Error) > 0).count();
+        bail!("{} failed the check", plural(failed, "file"));
+    }
+    Ok(())
+}
+
+/// Runs every test in the inputs whose name contains `filter`, each in a
+/// fresh interpreter, and fails if any test fails or a file with tests
+/// doesn't compile.
+pub fn test(inputs: Vec<PathBuf>, filter: Option<String>) -> anyhow::Result<()> {
+    let start = std::time::Instant::now();
+    let renderer = Renderer::for_stderr();
+    let (mut passed, mut failed, mut filtered_out, mut broken_files) = (0, 0, 0, 0);
+    for input in expand_inputs(&inputs)? {
+        let source = std::fs::read_to_string(&input)?;
+        let file = match TestFile::load(&source, &input) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("{}", report(e, &input, &source));
+                broken_files += 1;
+                continue;
+            }
+        };
+        
+        let tests: Vec<_> = file.tests.iter()
+            .filter(|test| filter.as_ref().is_none_or(|filter| test.name.contains(filter.as_str())))
+            .collect();
+        filtered_out += file.tests.len() - tests.len();
+        if tests.is_empty() {
+            continue;
+        }
+        
+        println!("\nrunning {} in {}", plural(tests.len(), "test"), input.display());
+        let path = input.display().to_string();
+        for test in tests {
+            let result = file.run(&test.name);
+            match result.outcome {
+                Ok(()) => {
+                    println!("test {} ... ok ({:.2?})", result.name, result.duration);
+                    passed += 1;
+                }
+                Err(e) => {
+                    println!("test {} ... FAILED ({:.2?})", result.name, result.duration);
+                    for diagnostic in diagnostics::from_execution_error(&e.into()) {
+                        eprint!("{}", renderer.render(&diagnostic, &path, &source));
+                    }
+                    failed += 1;
+                }
+            }
+        }
+    }
+    
+    let status = if failed == 0 && broken_files == 0 { "ok" } else { "FAILED" };
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} filtered out; finished in {:.2?}",
+        status, passed, failed, filtered_out, start.elapsed()
+    );
+    if broken_files > 0 {
+        bail!("{} could not be compiled", plural(broken_files, "file"));
+    }
+    if failed > 0 {
+        bail!("{} failed", plural(failed, "test"));
+    }
+    Ok(())
+}
+
+/// Rewrites each input in canonical style. With `check`, files are left
+/// alone and the command fails if any of them would change.
+pub fn fmt(inputs: Vec<PathBuf>, check: bool) -> anyhow::Result<()> {
+    let mut unformatted = 0;
+    for input in &inputs {
+        validate_nlang_file(input)?;
+        let source = std::fs::read_to_string(input)?;
+        let formatted = format_source(&source).map_err(|e| report(e, input, &source))?;
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", input.display());
+            unformatted += 1;
+        } else {
+            std::fs::write(input, formatted)?;
+            println!("Formatted {}", input.display());
+        }
+    }
+    
+    if unformatted > 0 {
+        bail!("{} of {} files are not formatted", unformatted, inputs.len());
+    }
+    Ok(())
+}
+
+/// Documents the module `input` and the modules it imports, in each of
+/// `formats`, writing the pages under `output`.
+pub fn doc(input: PathBuf, output: PathBuf, formats: Vec<DocFormat>) -> anyhow::Result<()> {
+    validate_nlang_file(&input)?;
+    let modules = doc::collect(&input).map_err(|error| match error.downcast::<SemanticError>() {
+        // The error is in a module, which brings its own source
+        Ok(error) => report(ExecutionError::SemanticError(SemanticErrors(vec![error])), &input, ""),
+        Err(error) => error,
+    })?;
+    for format in &formats {
+        doc::write_pages(&doc::render(&modules, *format), &output)?;
+    }
+    let formats: Vec<String> = formats.iter().map(DocFormat::to_string).collect();
+    println!("Documented {} as {} in {}", plural(modules.len(), "module"), formats.join(" and "), output.display());
+    Ok(())
+}
+
+/// Serves the Language Server Protocol over stdin and stdout until the
+/// editor shuts the server down.
+pub fn lsp() -> anyhow::Result<()> {
+    lsp::run_stdio()
+}
@@ -0,0 +1,433 @@
+//! Compiler diagnostics and their rustc-style rendering
+//!
+//! Every phase reports failures with its own error type; this module turns
+//! them into a common [`Diagnostic`] that the CLI renders with the offending
+//! source line and a caret under the span.
+
+use crate::c_codegen::CCodeGenError;
+use crate::execution_engine::ExecutionError;
+use crate::interpreter::InterpreterError;
+use crate::bytecode::CompileError;
+use crate::lexer::LexerError;
+use crate::llvm_codegen::LLVMCodeGenError;
+use crate::parser::ParseError;
+use crate::semantic::{ModuleErrors, SemanticError, SemanticWarning};
+use crate::span::Span;
+use std::fmt::{self, Write};
+use std::rc::Rc;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A message attached to a region of source.
+///
+/// Primary labels mark where the problem is and are underlined with `^`;
+/// secondary labels add context and are underlined with `-`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+    /// The file the span is in, if not the one the diagnostic is rendered
+    /// against.
+    pub file: Option<Rc<SourceFile>>,
+}
+
+/// A file that labels can point into.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub path: String,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<String>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_primary_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: true, file: None });
+        self
+    }
+
+    pub fn with_secondary_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: false, file: None });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// The span of the first primary label, if any.
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|label| label.primary).map(|label| label.span)
+    }
+}
+
+// ANSI styles used by the colored renderer
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const GREEN: &str = "\x1b[1;32m";
+const CYAN: &str = "\x1b[1;36m";
+const BLUE: &str = "\x1b[1;34m";
+
+const TAB_WIDTH: usize = 4;
+
+/// Renders diagnostics in the same layout rustc uses.
+#[derive(Debug, Clone, Copy)]
+pub struct Renderer {
+    color: bool,
+}
+
+impl Renderer {
+    pub fn plain() -> Self {
+        Renderer { color: false }
+    }
+
+    pub fn colored() -> Self {
+        Renderer { color: true }
+    }
+
+    /// Picks colored output when stderr is a terminal and `NO_COLOR` is unset.
+    pub fn for_stderr() -> Self {
+        use std::io::IsTerminal;
+        let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        Renderer { color }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn severity_style(severity: Severity) -> &'static str {
+        match severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+            Severity::Note => GREEN,
+            Severity::Help => CYAN,
+        }
+    }
+
+    /// Renders `diagnostic` against the contents of the file at `path`.
+    pub fn render(&self, diagnostic: &Diagnostic, path: &str, source: &str) -> String {
+        let mut out = String::new();
+
+        // error[E0001]: message
+        let mut header = diagnostic.severity.to_string();
+        if let Some(code) = &diagnostic.code {
+            write!(header, "[{}]", code).unwrap();
+        }
+        let style = Self::severity_style(diagnostic.severity);
+        writeln!(out, "{}{}", self.paint(style, &header), self.paint(BOLD, &format!(": {}", diagnostic.message))).unwrap();
+
+        // Labels with no location (line 0) can't be drawn against the source
+        let labels: Vec<&Label> = diagnostic.labels.iter()
+            .filter(|label| label.span.line > 0)
+            .collect();
+
+        let gutter_width = labels.iter()
+            .map(|label| label.span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(gutter_width);
+        let bar = self.paint(BLUE, "|");
+
+        // The labels of each file in turn, starting with the primary label's
+        let mut files: Vec<(Option<&Rc<SourceFile>>, Vec<&Label>)> = Vec::new();
+        for label in &labels {
+            match files.iter_mut().find(|(file, _)| *file == label.file.as_ref()) {
+                Some((_, file_labels)) => file_labels.push(label),
+                None => files.push((label.file.as_ref(), vec![label])),
+            }
+        }
+        files.sort_by_key(|(_, file_labels)| !file_labels.iter().any(|label| label.primary));
+
+        if files.is_empty() {
+            writeln!(out, "{}{} {}", gutter, self.paint(BLUE, "-->"), path).unwrap();
+        }
+        for (i, (file, mut file_labels)) in files.into_iter().enumerate() {
+            let (path, source) = file.map_or((path, source), |file| (file.path.as_str(), file.source.as_str()));
+            file_labels.sort_by_key(|label| (label.span.line, label.span.column));
+            let span = file_labels.iter().find(|label| label.primary).unwrap_or(&file_labels[0]).span;
+            if i > 0 {
+                writeln!(out, "{} {}", gutter, bar).unwrap();
+            }
+            let arrow = if i == 0 { "-->" } else { ":::" };
+            writeln!(out, "{}{} {}:{}:{}", gutter, self.paint(BLUE, arrow), path, span.line, span.column).unwrap();
+            writeln!(out, "{} {}", gutter, bar).unwrap();
+            self.render_lines(&mut out, diagnostic.severity, &file_labels, source, gutter_width);
+        }
+
+        if !diagnostic.notes.is_empty() || !diagnostic.help.is_empty() {
+            if !labels.is_empty() {
+                writeln!(out, "{} {}", gutter, bar).unwrap();
+            }
+            for note in &diagnostic.notes {
+                writeln!(out, "{} {} {}: {}", gutter, self.paint(BLUE, "="), self.paint(BOLD, "note"), note).unwrap();
+            }
+            for help in &diagnostic.help {
+                writeln!(out, "{} {} {}: {}", gutter, self.paint(BLUE, "="), self.paint(BOLD, "help"), help).unwrap();
+            }
+        }
+
+        out
+    }
+
+    /// Writes each line of `source` that `labels` are on, with the labels
+    /// underlining their spans. `labels` are in source order.
+    fn render_lines(&self, out: &mut String, severity: Severity, labels: &[&Label], source: &str, gutter_width: usize) {
+        let gutter = " ".repeat(gutter_width);
+        let bar = self.paint(BLUE, "|");
+        let mut index = 0;
+        while index < labels.len() {
+            let line_number = labels[index].span.line;
+            let line_labels: Vec<&Label> = labels[index..].iter()
+                .take_while(|label| label.span.line == line_number)
+                .copied()
+                .collect();
+            index += line_labels.len();
+
+            let (line_start, line_text) = source_line(source, line_offset(source, line_labels[0]));
+            let line_number_text = format!("{:>width$}", line_number, width = gutter_width);
+            writeln!(out, "{} {} {}", self.paint(BLUE, &line_number_text), bar, expand_tabs(line_text).trim_end()).unwrap();
+
+            for label in line_labels {
+                let start = label.span.start.clamp(line_start, line_start + line_text.len());
+                let end = label.span.end.clamp(start, line_start + line_text.len());
+                let padding = display_width(&line_text[..start - line_start]);
+                let width = display_width(&line_text[start - line_start..end - line_start]).max(1);
+                let (marker, marker_style) = if label.primary {
+                    ("^", Self::severity_style(severity))
+                } else {
+                    ("-", BLUE)
+                };
+                let mut underline = marker.repeat(width);
+                if !label.message.is_empty() {
+                    underline.push(' ');
+                    underline.push_str(&label.message);
+                }
+                writeln!(out, "{} {} {}{}", gutter, bar, " ".repeat(padding), self.paint(marker_style, &underline)).unwrap();
+            }
+        }
+    }
+}
+
+/// Byte offset used to find the source line of `label`, falling back to the
+/// line number when the offset is out of range for this source.
+fn line_offset(source: &str, label: &Label) -> usize {
+    if label.span.start <= source.len() {
+        return label.span.start;
+    }
+    source.split_inclusive('\n')
+        .take(label.span.line - 1)
+        .map(str::len)
+        .sum::<usize>()
+        .min(source.len())
+}
+
+/// Returns the byte offset where the line containing `offset` begins and its
+/// text without the trailing newline.
+fn source_line(source: &str, offset: usize) -> (usize, &str) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let end = source[offset..].find('\n').map(|i| offset + i).unwrap_or(source.len());
+    (start, source[start..end].trim_end_matches('\r'))
+}
+
+fn display_width(text: &str) -> usize {
+    text.chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+impl From<&LexerError> for Diagnostic {
+    fn from(error: &LexerError) -> Self {
+        Diagnostic::error(error.message.clone())
+            .with_code("E0001")
+            .with_primary_label(error.span, "")
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        Diagnostic::error(error.message.clone())
+            .with_code("E0002")
+            .with_primary_label(error.span, "")
+    }
+}
+
+impl From<&SemanticError> for Diagnostic {
+    fn from(error: &SemanticError) -> Self {
+        let diagnostic = Diagnostic::error(error.message.clone()).with_code("E0003");
+        match error.span {
+            Some(span) => diagnostic.with_primary_label(span, ""),
+            None => diagnostic,
+        }
+    }
+}
+
+/// The diagnostics of `error`. A module that couldn't be imported gives one
+/// for each of its errors, drawn in its own source, with a secondary label
+/// on the import.
+pub fn from_semantic_error(error: &SemanticError) -> Vec<Diagnostic> {
+    let Some(module) = &error.module else {
+        return vec![error.into()];
+    };
+    let file = Rc::new(SourceFile { path: module.path.display().to_string(), source: module.source.clone() });
+    let diagnostics: Vec<Diagnostic> = match &module.errors {
+        ModuleErrors::Lexer(error) => vec![error.into()],
+        ModuleErrors::Parser(errors) => errors.0.iter().map(Diagnostic::from).collect(),
+        ModuleErrors::Semantic(errors) => errors.iter().flat_map(from_semantic_error).collect(),
+    };
+    diagnostics.into_iter()
+        .map(|mut diagnostic| {
+            if diagnostic.labels.is_empty() {
+                diagnostic = diagnostic.with_note(format!("in module '{}'", file.path));
+            }
+            for label in &mut diagnostic.labels {
+                label.file.get_or_insert_with(|| file.clone());
+            }
+            match error.span {
+                Some(span) => diagnostic.with_secondary_label(span, "imported here"),
+                None => diagnostic,
+            }
+        })
+        .collect()
+}
+
+impl From<&SemanticWarning> for Diagnostic {
+    fn from(warning: &SemanticWarning) -> Self {
+        let diagnostic = Diagnostic::warning(warning.message.clone()).with_primary_label(warning.span, "");
+        match &warning.help {
+            Some(help) => diagnostic.with_help(help.clone()),
+            None => diagnostic,
+        }
+    }
+}
+
+impl From<&InterpreterError> for Diagnostic {
+    fn from(error: &InterpreterError) -> Self {
+        match error {
+            InterpreterError::At { span, error } => {
+                Diagnostic::from(error.as_ref()).with_primary_label(*span, "")
+            }
+            error => Diagnostic::error(error.to_string())
+                .with_code("E0004")
+                .with_note("this error occurred while the program was running"),
+        }
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(error: &CompileError) -> Self {
+        let diagnostic = Diagnostic::error(error.message.clone()).with_code("E0005");
+        match error.span {
+            Some(span) => diagnostic.with_primary_label(span, ""),
+            None => diagnostic,
+        }
+    }
+}
+
+impl From<&CCodeGenError> for Diagnostic {
+    fn from(error: &CCodeGenError) -> Self {
+        match error {
+            CCodeGenError::At { span, error } => {
+                Diagnostic::from(error.as_ref()).with_primary_label(*span, "")
+            }
+            error => Diagnostic::error(error.to_string()).with_code("E0005"),
+        }
+    }
+}
+
+impl From<&LLVMCodeGenError> for Diagnostic {
+    fn from(error: &LLVMCodeGenError) -> Self {
+        let diagnostic = Diagnostic::error(error.message.clone()).with_code("E0005");
+        match error.span {
+            Some(span) => diagnostic.with_primary_label(span, ""),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Converts a pipeline failure into diagnostics, one per underlying error.
+pub fn from_execution_error(error: &ExecutionError) -> Vec<Diagnostic> {
+    match error {
+        ExecutionError::LexerError(e) => vec![e.into()],
+        ExecutionError::ParserError(errors) => errors.0.iter().map(Diagnostic::from).collect(),
+        ExecutionError::SemanticError(errors) => errors.0.iter().flat_map(from_semantic_error).collect(),
+        ExecutionError::InterpreterError(e) => vec![e.into()],
+        ExecutionError::BytecodeError(e) => vec![e.into()],
+        ExecutionError::LLVMCodeGenError(e) => vec![e.into()],
+        ExecutionError::CCodeGenError(e) => vec![e.into()],
+        ExecutionError::IoError(e) => vec![Diagnostic::error(e.to_string())],
+        ExecutionError::NotImplemented { message } => vec![Diagnostic::error(message.clone())],
+    }
+}
@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod diagnostics_tests {
+    use crate::diagnostics::{self, Diagnostic, Renderer, Severity};
+    use crate::execution_engine::ExecutionEngine;
+    use crate::span::Span;
+
+    #[test]
+    fn test_render_primary_label() {
+        let source = "def main() {\n    store x = 1;\n    print(y);\n}\n";
+        let diagnostic = Diagnostic::error("Undefined variable: y")
+            .with_code("E0003")
+            .with_primary_label(Span::new(40, 41, 3, 11), "not found in this scope");
+
+        let rendered = Renderer::plain().render(&diagnostic, "main.nlang", source);
+        assert_eq!(rendered, "\
+error[E0003]: Undefined variable: y
+ --> main.nlang:3:11
+  |
+3 |     print(y);
+  |           ^ not found in this scope
+");
+    }
+
+    #[test]
+    fn test_render_secondary_label_notes_and_help() {
+        let source = "store a = 1;\nstore a = 2;\n";
+        let diagnostic = Diagnostic::warning("Variable 'a' is redeclared")
+            .with_primary_label(Span::new(19, 20, 2, 7), "redeclared here")
+            .with_secondary_label(Span::new(6, 7, 1, 7), "first declared here")
+            .with_note("the earlier value is shadowed")
+            .with_help("rename one of the variables");
+
+        let rendered = Renderer::plain().render(&diagnostic, "a.nlang", source);
+        assert_eq!(rendered, "\
+warning: Variable 'a' is redeclared
+ --> a.nlang:2:7
+  |
+1 | store a = 1;
+  |       - first declared here
+2 | store a = 2;
+  |       ^ redeclared here
+  |
+  = note: the earlier value is shadowed
+  = help: rename one of the variables
+");
+    }
+
+    #[test]
+    fn test_render_without_location() {
+        let diagnostic = Diagnostic::new(Severity::Error, "No suitable compiler found");
+        let rendered = Renderer::plain().render(&diagnostic, "main.nlang", "");
+        assert_eq!(rendered, "error: No suitable compiler found\n --> main.nlang\n");
+    }
+
+    #[test]
+    fn test_colored_render() {
+        let source = "store x = ;";
+        let diagnostic = Diagnostic::error("Expected expression")
+            .with_primary_label(Span::new(10, 11, 1, 11), "");
+
+        let colored = Renderer::colored().render(&diagnostic, "x.nlang", source);
+        assert!(colored.contains("\x1b[1;31merror\x1b[0m"));
+        assert!(colored.contains("\x1b[1;31m^\x1b[0m"));
+
+        let plain = Renderer::plain().render(&diagnostic, "x.nlang", source);
+        assert!(!plain.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_execution_error_conversion() {
+        let mut engine = ExecutionEngine::new();
+        let source = "def main() {\n    store x = 10 / 0;\n}\n";
+        let error = engine.execute_source(source, "test_module").unwrap_err();
+
+        let diagnostics = diagnostics::from_execution_error(&error);
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.message, "Division by zero");
+        let span = diagnostic.primary_span().unwrap();
+        assert_eq!((span.line, span.column), (2, 15));
+        assert_eq!(&source[span.start..span.end], "10 / 0");
+    }
+
+    #[test]
+    fn test_module_errors_render_in_their_own_source() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("util.nlang"), "import math;\nexport def double(x: int): int {\n    return add(x, missing);\n}\n").unwrap();
+        std::fs::write(dir.path().join("math.nlang"), "store s = 1 +;\n").unwrap();
+        let main = dir.path().join("main.nlang");
+        let source = "from util import double;\ndef main() {\n    println(double(2));\n}\n";
+
+        let error = ExecutionEngine::new().execute_source_with_file_path(source, "main", Some(&main)).unwrap_err();
+        let diagnostics = diagnostics::from_execution_error(&error);
+        let rendered: Vec<String> = diagnostics.iter()
+            .map(|diagnostic| Renderer::plain().render(diagnostic, "main.nlang", source).replace(&format!("{}/", dir.path().display()), ""))
+            .collect();
+        assert_eq!(rendered, ["\
+error[E0002]: Expected expression, got Semicolon
+ --> math.nlang:1:14
+  |
+1 | store s = 1 +;
+  |              ^
+  |
+ ::: util.nlang:1:1
+  |
+1 | import math;
+  | ------------ imported here
+  |
+ ::: main.nlang:1:1
+  |
+1 | from util import double;
+  | ------------------------ imported here
+", "\
+error[E0003]: Undefined variable: missing
+ --> util.nlang:3:19
+  |
+3 |     return add(x, missing);
+  |                   ^^^^^^^
+  |
+ ::: main.nlang:1:1
+  |
+1 | from util import double;
+  | ------------------------ imported here
+"]);
+    }
+
+    #[test]
+    fn test_one_diagnostic_per_parse_error() {
+        let mut engine = ExecutionEngine::new();
+        let source = "def main() {\n    store = 1;\n    print(;\n}\n";
+        let error = engine.execute_source(source, "test_module").unwrap_err();
+
+        let diagnostics = diagnostics::from_execution_error(&error);
+        let lines: Vec<usize> = diagnostics.iter()
+            .map(|d| d.primary_span().unwrap().line)
+            .collect();
+        assert_eq!(lines, vec![2, 3]);
+    }
+}
@@ -0,0 +1,178 @@
+//! A static HTML site, browsable straight from the file system
+//!
+//! Every module gets a page, and its source a page with an anchor per line.
+//! The index page searches the items of every module with a script, so the
+//! site needs no server.
+
+use super::{code_spans, resolve, search_index, ModuleDoc, Page};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; line-height: 1.5; margin: 0; }
+nav { background: #f4f4f4; padding: 0.5em 2em; }
+main { max-width: 50em; padding: 0 2em 2em; }
+code, pre { font-family: monospace; }
+pre { background: #f4f4f4; padding: 0.5em; overflow-x: auto; }
+section { border-top: 1px solid #ddd; margin-top: 1.5em; }
+.source .line:target { background: #fff3b0; }
+.source .number { color: #999; display: inline-block; margin-right: 1em; text-align: right; width: 3em; }
+";
+
+const SEARCH: &str = "\
+const search = document.getElementById(\"search\");
+const results = document.getElementById(\"results\");
+search.addEventListener(\"input\", () => {
+    const query = search.value.trim().toLowerCase();
+    results.replaceChildren();
+    if (!query) {
+        return;
+    }
+    for (const entry of searchIndex) {
+        if (entry.name.toLowerCase().includes(query)) {
+            const link = document.createElement(\"a\");
+            link.href = entry.url;
+            link.textContent = entry.name;
+            const item = document.createElement(\"li\");
+            item.append(link, \" \", entry.summary);
+            results.append(item);
+        }
+    }
+});
+";
+
+pub(super) fn render(modules: &[ModuleDoc]) -> Vec<Page> {
+    let mut pages = vec![
+        Page { path: "index.html".into(), contents: index(modules) },
+        Page { path: "style.css".into(), contents: STYLE.to_string() },
+    ];
+    for module in modules {
+        pages.push(Page { path: format!("{}.html", module.name).into(), contents: module_page(modules, module) });
+        pages.push(Page { path: source_path(module).into(), contents: source_page(module) });
+    }
+    let index = search_index(modules, |module, item| format!("{}.html#{}", module.name, item.name));
+    pages.push(Page { path: "search-index.js".into(), contents: format!("const searchIndex = {};\n", index) });
+    pages
+}
+
+fn source_path(module: &ModuleDoc) -> String {
+    format!("src/{}.html", module.name)
+}
+
+/// A whole page; `root` leads from the page back to the top of the site.
+fn page(title: &str, root: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <link rel=\"stylesheet\" href=\"{}style.css\">\n</head>\n<body>\n\
+         <nav><a href=\"{}index.html\">Index</a></nav>\n<main>\n{}</main>\n</body>\n</html>\n",
+        escape(title), root, root, body
+    )
+}
+
+fn index(modules: &[ModuleDoc]) -> String {
+    let mut body = String::from("<h1>API documentation</h1>\n");
+    body.push_str("<input id=\"search\" type=\"search\" placeholder=\"Search\" autocomplete=\"off\">\n");
+    body.push_str("<ul id=\"results\"></ul>\n<h2>Modules</h2>\n<ul>\n");
+    for module in modules {
+        let name = escape(&module.name);
+        body.push_str(&format!("<li><a href=\"{}.html\"><code>{}</code></a></li>\n", name, name));
+    }
+    body.push_str("</ul>\n<script src=\"search-index.js\"></script>\n");
+    body.push_str(&format!("<script>\n{}</script>\n", SEARCH));
+    page("API documentation", "", &body)
+}
+
+fn module_page(modules: &[ModuleDoc], module: &ModuleDoc) -> String {
+    let source = source_path(module);
+    let mut body = format!("<h1>Module <code>{}</code></h1>\n<p><a href=\"{}\">Source</a></p>\n", escape(&module.name), source);
+    if !module.imports.is_empty() {
+        let imports: Vec<String> = module.imports.iter()
+            .map(|name| format!("<a href=\"{}.html\"><code>{}</code></a>", escape(name), escape(name)))
+            .collect();
+        body.push_str(&format!("<p>Imports {}</p>\n", imports.join(", ")));
+    }
+    if module.items.is_empty() {
+        body.push_str("<p>This module exports nothing.</p>\n");
+    } else {
+        body.push_str("<ul>\n");
+        for item in &module.items {
+            let name = escape(&item.name);
+            let summary = inline_html(modules, module, &item.summary());
+            body.push_str(&format!("<li><a href=\"#{}\"><code>{}</code></a> {}</li>\n", name, name, summary));
+        }
+        body.push_str("</ul>\n");
+    }
+    for item in &module.items {
+        body.push_str(&format!("<section id=\"{}\">\n<h2><code>{}</code></h2>\n", escape(&item.name), escape(&item.signature)));
+        if let Some(doc) = &item.doc {
+            body.push_str(&doc_html(modules, module, doc));
+        }
+        body.push_str(&format!("<p><a href=\"{}#L{}\">Source</a></p>\n</section>\n", source, item.line));
+    }
+    page(&format!("Module {}", module.name), "", &body)
+}
+
+fn source_page(module: &ModuleDoc) -> String {
+    let name = escape(&module.name);
+    let mut body = format!("<h1>Source of <a href=\"../{}.html\"><code>{}</code></a></h1>\n<pre class=\"source\">", name, name);
+    for (i, line) in module.source.lines().enumerate() {
+        let number = i + 1;
+        body.push_str(&format!(
+            "<span class=\"line\" id=\"L{}\"><a class=\"number\" href=\"#L{}\">{}</a>{}</span>\n",
+            number, number, number, escape(line)
+        ));
+    }
+    body.push_str("</pre>\n");
+    page(&format!("Source of {}", module.name), "../", &body)
+}
+
+/// A doc comment as paragraphs and code blocks, with each name in
+/// backticks that refers to a documented module or item linked to it.
+fn doc_html(modules: &[ModuleDoc], module: &ModuleDoc, doc: &str) -> String {
+    let mut out = String::new();
+    let mut paragraph = Vec::new();
+    let mut code_block: Option<Vec<&str>> = None;
+    for line in doc.lines() {
+        if line.trim_start().starts_with("```") {
+            match code_block.take() {
+                Some(lines) => out.push_str(&format!("<pre><code>{}</code></pre>\n", escape(&lines.join("\n")))),
+                None => {
+                    end_paragraph(&mut out, &mut paragraph);
+                    code_block = Some(Vec::new());
+                }
+            }
+        } else if let Some(lines) = &mut code_block {
+            lines.push(line);
+        } else if line.trim().is_empty() {
+            end_paragraph(&mut out, &mut paragraph);
+        } else {
+            paragraph.push(inline_html(modules, module, line));
+        }
+    }
+    // An unclosed code block runs to the end of the comment
+    if let Some(lines) = code_block {
+        out.push_str(&format!("<pre><code>{}</code></pre>\n", escape(&lines.join("\n"))));
+    }
+    end_paragraph(&mut out, &mut paragraph);
+    out
+}
+
+/// A line of text with its `code` spans marked up and linked.
+fn inline_html(modules: &[ModuleDoc], module: &ModuleDoc, line: &str) -> String {
+    code_spans(line).into_iter()
+        .map(|(is_code, text)| match is_code.then(|| resolve(modules, module, text)) {
+            None => escape(text),
+            Some(Some(link)) => format!("<a href=\"{}\"><code>{}</code></a>", escape(&link.href(module, "html")), escape(text)),
+            Some(None) => format!("<code>{}</code>", escape(text)),
+        })
+        .collect()
+}
+
+fn end_paragraph(out: &mut String, paragraph: &mut Vec<String>) {
+    if !paragraph.is_empty() {
+        out.push_str(&format!("<p>{}</p>\n", paragraph.join("\n")));
+        paragraph.clear();
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
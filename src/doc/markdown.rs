@@ -0,0 +1,77 @@
+//! Markdown pages, for browsing on a code host or feeding to a site generator
+
+use super::{code_spans, resolve, search_index, ModuleDoc, Page};
+
+pub(super) fn render(modules: &[ModuleDoc]) -> Vec<Page> {
+    let mut pages = vec![Page { path: "index.md".into(), contents: index(modules) }];
+    for module in modules {
+        pages.push(Page { path: format!("{}.md", module.name).into(), contents: module_page(modules, module) });
+        pages.push(Page { path: source_path(module).into(), contents: module.source.clone() });
+    }
+    let index = search_index(modules, |module, item| format!("{}.md#{}", module.name, item.name));
+    pages.push(Page { path: "search-index.json".into(), contents: index });
+    pages
+}
+
+fn source_path(module: &ModuleDoc) -> String {
+    format!("src/{}.nlang", module.name)
+}
+
+fn index(modules: &[ModuleDoc]) -> String {
+    let mut out = String::from("# API documentation\n\n");
+    for module in modules {
+        let items: Vec<String> = module.items.iter().map(|item| format!("`{}`", item.name)).collect();
+        out.push_str(&format!("- [`{}`]({}.md)", module.name, module.name));
+        if !items.is_empty() {
+            out.push_str(&format!(": {}", items.join(", ")));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn module_page(modules: &[ModuleDoc], module: &ModuleDoc) -> String {
+    let source = source_path(module);
+    let mut out = format!("# Module `{}`\n\n[Source]({})\n", module.name, source);
+    if !module.imports.is_empty() {
+        let imports: Vec<String> = module.imports.iter().map(|name| format!("[`{}`]({}.md)", name, name)).collect();
+        out.push_str(&format!("\nImports {}\n", imports.join(", ")));
+    }
+    if module.items.is_empty() {
+        out.push_str("\nThis module exports nothing.\n");
+    }
+    for item in &module.items {
+        out.push_str(&format!("\n<a id=\"{}\"></a>\n\n## `{}`\n\n", item.name, item.name));
+        out.push_str(&format!("```nlang\n{}\n```\n\n", item.signature));
+        if let Some(doc) = &item.doc {
+            out.push_str(&link_names(modules, module, doc));
+            out.push_str("\n\n");
+        }
+        out.push_str(&format!("[Source]({}#L{})\n", source, item.line));
+    }
+    out
+}
+
+/// `doc` with each name in backticks that refers to a documented module
+/// or item turned into a link to it. Code blocks are left alone.
+fn link_names(modules: &[ModuleDoc], module: &ModuleDoc, doc: &str) -> String {
+    let mut in_code_block = false;
+    let mut lines = Vec::new();
+    for line in doc.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        } else if !in_code_block {
+            let spans: Vec<String> = code_spans(line).into_iter()
+                .map(|(is_code, text)| match is_code.then(|| resolve(modules, module, text)) {
+                    None => text.to_string(),
+                    Some(Some(link)) => format!("[`{}`]({})", text, link.href(module, "md")),
+                    Some(None) => format!("`{}`", text),
+                })
+                .collect();
+            lines.push(spans.concat());
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,278 @@
+//! API documentation for modules
+//!
+//! `nlang doc` starts at one module and follows its imports, documenting
+//! what each module reached exports: the signature of every exported
+//! function and variable, the `///` comment above its declaration, and where
+//! it is declared. The result is a static site of HTML or Markdown pages,
+//! with the sources alongside so items can link to their declarations.
+
+use crate::ast::Type;
+use crate::checker::imported_modules;
+use crate::lexer::tokenize;
+use crate::parser::parse_strict;
+use crate::semantic::{self, resolve_module_path, Export, ExportKind};
+use anyhow::Context;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+mod html;
+mod markdown;
+
+#[cfg(test)]
+mod tests;
+
+/// What a module exports, with enough of its source to link to.
+#[derive(Debug, Clone)]
+pub struct ModuleDoc {
+    /// The name the first documented module would import it by, e.g.
+    /// `math.utils`; only the file name for modules outside its directory.
+    pub name: String,
+    pub path: PathBuf,
+    pub source: String,
+    /// The names of the modules it imports, which are documented too.
+    pub imports: Vec<String>,
+    pub items: Vec<ItemDoc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Function,
+    Variable,
+}
+
+impl ItemKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ItemKind::Function => "function",
+            ItemKind::Variable => "variable",
+        }
+    }
+}
+
+/// One exported function or variable.
+#[derive(Debug, Clone)]
+pub struct ItemDoc {
+    pub name: String,
+    pub kind: ItemKind,
+    /// The declaration without its body, e.g. `def double(x: int): int`.
+    pub signature: String,
+    pub doc: Option<String>,
+    /// The line the declaration starts on.
+    pub line: usize,
+}
+
+impl ItemDoc {
+    fn new(export: Export) -> ItemDoc {
+        let (kind, signature) = match &export.kind {
+            ExportKind::Function { parameters, return_type } => {
+                let parameters: Vec<String> = parameters.iter()
+                    .map(|param| format!("{}: {}", param.name, param.param_type))
+                    .collect();
+                let mut signature = format!("def {}({})", export.name, parameters.join(", "));
+                if *return_type != Type::Void {
+                    signature.push_str(&format!(": {}", return_type));
+                }
+                (ItemKind::Function, signature)
+            }
+            ExportKind::Variable { var_type } => (ItemKind::Variable, format!("store {}: {}", export.name, var_type)),
+        };
+        ItemDoc { name: export.name, kind, signature, doc: export.doc, line: export.span.line }
+    }
+
+    /// The first paragraph of the doc comment, for lists and search results.
+    pub fn summary(&self) -> String {
+        let doc = self.doc.as_deref().unwrap_or("");
+        doc.lines().take_while(|line| !line.trim().is_empty()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    Html,
+    Markdown,
+}
+
+impl fmt::Display for DocFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocFormat::Html => f.write_str("html"),
+            DocFormat::Markdown => f.write_str("markdown"),
+        }
+    }
+}
+
+impl FromStr for DocFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(DocFormat::Html),
+            "markdown" | "md" => Ok(DocFormat::Markdown),
+            _ => Err(format!("unknown format '{}'; expected html or markdown", s)),
+        }
+    }
+}
+
+/// A file of the generated documentation.
+#[derive(Debug, Clone)]
+pub struct Page {
+    /// Relative to the output directory.
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// Documents the module at `entry` and every module it imports, directly or
+/// not, in the order they are first reached. Fails if any of them doesn't
+/// analyze.
+pub fn collect(entry: &Path) -> anyhow::Result<Vec<ModuleDoc>> {
+    let root = entry.canonicalize()
+        .with_context(|| format!("Failed to read '{}'", entry.display()))?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    let mut queue = vec![entry.to_path_buf()];
+    let mut seen = HashSet::new();
+    let mut modules = Vec::new();
+    let mut next = 0;
+    while next < queue.len() {
+        let path = queue[next].clone();
+        next += 1;
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if !seen.insert(key) {
+            continue;
+        }
+
+        let exports = semantic::module_exports(&path)?;
+        let source = std::fs::read_to_string(&path)?;
+        // Analysis succeeded, so this lexes and parses
+        let program = parse_strict(&tokenize(&source)?)?;
+        let current_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut imports = Vec::new();
+        for module in imported_modules(&program.statements) {
+            let import = resolve_module_path(current_dir, module);
+            let name = module_name(&root, &import);
+            if !imports.contains(&name) {
+                imports.push(name);
+            }
+            queue.push(import);
+        }
+        modules.push(ModuleDoc {
+            name: module_name(&root, &path),
+            path,
+            source,
+            imports,
+            items: exports.into_iter().map(ItemDoc::new).collect(),
+        });
+    }
+    Ok(modules)
+}
+
+/// The dotted name of the module at `path` relative to `root`.
+fn module_name(root: &Path, path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    match path.strip_prefix(root) {
+        Ok(relative) => relative.with_extension("")
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("."),
+        Err(_) => path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+    }
+}
+
+/// The pages documenting `modules` in `format`.
+pub fn render(modules: &[ModuleDoc], format: DocFormat) -> Vec<Page> {
+    match format {
+        DocFormat::Html => html::render(modules),
+        DocFormat::Markdown => markdown::render(modules),
+    }
+}
+
+/// Writes `pages` under `dir`, creating the directories they need.
+pub fn write_pages(pages: &[Page], dir: &Path) -> std::io::Result<()> {
+    for page in pages {
+        let path = dir.join(&page.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, &page.contents)?;
+    }
+    Ok(())
+}
+
+/// What a name written in backticks in a doc comment refers to: a module,
+/// or an item of one.
+#[derive(Debug, PartialEq)]
+struct Link<'a> {
+    module: &'a str,
+    item: Option<&'a str>,
+}
+
+impl Link<'_> {
+    /// The address of the page or item from the page of `current`, whose
+    /// pages have the file `extension`.
+    fn href(&self, current: &ModuleDoc, extension: &str) -> String {
+        match self.item {
+            Some(item) if self.module == current.name => format!("#{}", item),
+            Some(item) => format!("{}.{}#{}", self.module, extension, item),
+            None => format!("{}.{}", self.module, extension),
+        }
+    }
+}
+
+/// Resolves `name` as written in the docs of `current`: an item of
+/// `current`, a documented module, or `module.item`.
+fn resolve<'a>(modules: &'a [ModuleDoc], current: &'a ModuleDoc, name: &str) -> Option<Link<'a>> {
+    let item_of = |module: &'a ModuleDoc, item: &str| {
+        module.items.iter()
+            .find(|candidate| candidate.name == item)
+            .map(|item| Link { module: &module.name, item: Some(&item.name) })
+    };
+    if let Some(link) = item_of(current, name) {
+        return Some(link);
+    }
+    if let Some(module) = modules.iter().find(|module| module.name == name) {
+        return Some(Link { module: &module.name, item: None });
+    }
+    let (module, item) = name.rsplit_once('.')?;
+    item_of(modules.iter().find(|candidate| candidate.name == module)?, item)
+}
+
+/// Splits a line of a doc comment into text and `code` spans, the latter
+/// without their backticks. An unclosed backtick is text.
+fn code_spans(line: &str) -> Vec<(bool, &str)> {
+    let mut spans = Vec::new();
+    let mut rest = line;
+    while let Some(open) = rest.find('`') {
+        let Some(close) = rest[open + 1..].find('`') else {
+            break;
+        };
+        if open > 0 {
+            spans.push((false, &rest[..open]));
+        }
+        spans.push((true, &rest[open + 1..open + 1 + close]));
+        rest = &rest[open + close + 2..];
+    }
+    if !rest.is_empty() {
+        spans.push((false, rest));
+    }
+    spans
+}
+
+/// The search index entries of every item, as JSON.
+fn search_index(modules: &[ModuleDoc], url: impl Fn(&ModuleDoc, &ItemDoc) -> String) -> String {
+    let entries: Vec<serde_json::Value> = modules.iter()
+        .flat_map(|module| module.items.iter().map(move |item| (module, item)))
+        .map(|(module, item)| serde_json::json!({
+            "name": format!("{}.{}", module.name, item.name),
+            "kind": item.kind.name(),
+            "signature": item.signature,
+            "summary": item.summary(),
+            "url": url(module, item),
+        }))
+        .collect();
+    serde_json::to_string_pretty(&entries).unwrap_or_default()
+}
@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod doc_tests {
+    use crate::doc::{collect, render, DocFormat, ItemKind, ModuleDoc, Page};
+    use std::path::Path;
+
+    fn write(dir: &Path, name: &str, source: &str) {
+        std::fs::write(dir.join(name), source).unwrap();
+    }
+
+    /// A program importing two modules, one of them twice.
+    fn project(dir: &Path) -> Vec<ModuleDoc> {
+        write(dir, "main.nlang", "import util;\nimport math;\ndef main() {\n    print(double(ten));\n}\n");
+        write(dir, "util.nlang", "\
+from math import add;
+/// Doubles `x` with `math.add`.
+///
+/// ```
+/// double(<2>);
+/// ```
+export def double(x: int): int {
+    return add(x, x);
+}
+/// Ten, twice `half`.
+export store ten = 10;
+export store half = 5;
+def hidden() {}
+");
+        write(dir, "math.nlang", "/// Adds two numbers.\nexport def add(a: int, b: int): int {\n    return a + b;\n}\nexport def shout(s: string) {\n    println(s);\n}\n");
+        collect(&dir.join("main.nlang")).unwrap()
+    }
+
+    fn page<'a>(pages: &'a [Page], path: &str) -> &'a str {
+        &pages.iter().find(|page| page.path == Path::new(path)).unwrap().contents
+    }
+
+    #[test]
+    fn test_collect() {
+        let dir = tempfile::tempdir().unwrap();
+        let modules = project(dir.path());
+
+        let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+        assert_eq!(names, ["main", "util", "math"]);
+        assert!(modules[0].items.is_empty());
+        assert_eq!(modules[0].imports, ["util", "math"]);
+        assert_eq!(modules[1].imports, ["math"]);
+
+        let util = &modules[1];
+        let items: Vec<(&str, ItemKind, &str, usize)> = util.items.iter()
+            .map(|item| (item.name.as_str(), item.kind, item.signature.as_str(), item.line))
+            .collect();
+        assert_eq!(items, [
+            ("double", ItemKind::Function, "def double(x: int): int", 7),
+            ("ten", ItemKind::Variable, "store ten: int", 11),
+            ("half", ItemKind::Variable, "store half: int", 12),
+        ]);
+        assert_eq!(util.items[0].doc.as_deref(), Some("Doubles `x` with `math.add`.\n\n```\ndouble(<2>);\n```"));
+        assert_eq!(util.items[0].summary(), "Doubles `x` with `math.add`.");
+        assert_eq!(modules[2].items[1].signature, "def shout(s: string)");
+
+        write(dir.path(), "math.nlang", "export def add(a: int, b: int): int {\n    return missing;\n}\n");
+        let error = collect(&dir.path().join("main.nlang")).unwrap_err().to_string();
+        assert!(error.contains("Undefined variable: missing"), "{}", error);
+    }
+
+    #[test]
+    fn test_markdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = render(&project(dir.path()), DocFormat::Markdown);
+
+        assert_eq!(page(&pages, "index.md"), "\
+# API documentation
+
+- [`main`](main.md)
+- [`util`](util.md): `double`, `ten`, `half`
+- [`math`](math.md): `add`, `shout`
+");
+        let util = page(&pages, "util.md");
+        assert!(util.starts_with("# Module `util`\n\n[Source](src/util.nlang)\n\nImports [`math`](math.md)\n"));
+        assert!(util.contains("\
+<a id=\"double\"></a>
+
+## `double`
+
+```nlang
+def double(x: int): int
+```
+
+Doubles `x` with [`math.add`](math.md#add).
+
+```
+double(<2>);
+```
+
+[Source](src/util.nlang#L7)
+"));
+        assert!(util.contains("Ten, twice [`half`](#half)."));
+        assert!(page(&pages, "main.md").contains("This module exports nothing."));
+        assert!(page(&pages, "src/math.nlang").starts_with("/// Adds two numbers."));
+
+        let index: serde_json::Value = serde_json::from_str(page(&pages, "search-index.json")).unwrap();
+        assert_eq!(index.as_array().unwrap().len(), 5);
+        assert_eq!(index[0]["name"], "util.double");
+        assert_eq!(index[0]["url"], "util.md#double");
+        assert_eq!(index[4]["summary"], "");
+    }
+
+    #[test]
+    fn test_html() {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = render(&project(dir.path()), DocFormat::Html);
+
+        let util = page(&pages, "util.html");
+        assert!(util.contains("<p>Imports <a href=\"math.html\"><code>math</code></a></p>"));
+        assert!(util.contains("\
+<section id=\"double\">
+<h2><code>def double(x: int): int</code></h2>
+<p>Doubles <code>x</code> with <a href=\"math.html#add\"><code>math.add</code></a>.</p>
+<pre><code>double(&lt;2&gt;);</code></pre>
+<p><a href=\"src/util.html#L7\">Source</a></p>
+</section>
+"));
+        assert!(util.contains("<li><a href=\"#ten\"><code>ten</code></a> Ten, twice <a href=\"#half\"><code>half</code></a>.</li>"));
+
+        let source = page(&pages, "src/util.html");
+        assert!(source.contains("<link rel=\"stylesheet\" href=\"../style.css\">"));
+        assert!(source.contains("<span class=\"line\" id=\"L5\"><a class=\"number\" href=\"#L5\">5</a>/// double(&lt;2&gt;);</span>\n"));
+
+        let index = page(&pages, "index.html");
+        assert!(index.contains("<li><a href=\"math.html\"><code>math</code></a></li>"));
+        assert!(index.contains("<script src=\"search-index.js\"></script>"));
+        let script = page(&pages, "search-index.js");
+        let json = script.strip_prefix("const searchIndex = ").unwrap().trim_end().strip_suffix(';').unwrap();
+        let entries: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(entries[3]["url"], "math.html#add");
+        assert_eq!(entries[3]["signature"], "def add(a: int, b: int): int");
+    }
+
+    #[test]
+    fn test_format_names() {
+        assert_eq!("html".parse(), Ok(DocFormat::Html));
+        assert_eq!("md".parse(), Ok(DocFormat::Markdown));
+        assert_eq!("markdown".parse(), Ok(DocFormat::Markdown));
+        assert_eq!("pdf".parse::<DocFormat>(), Err("unknown format 'pdf'; expected html or markdown".to_string()));
+    }
+}
@@ -1,8 +1,9 @@
 use crate::lexer::tokenize;
-use crate::parser::parse;
-use crate::semantic::{analyze, analyze_with_file_path, SemanticError};
+use crate::parser::parse_strict;
+use crate::semantic::{analyze, analyze_with_file_path, SemanticErrors};
 use crate::interpreter::{Interpreter, InterpreterError};
-use crate::llvm_codegen::{LLVMCodeGenerator, LLVMCodeGenError};
+use crate::bytecode::{self, CompileError, Vm};
+use crate::llvm_codegen::{LLVMCodeGenerator, LLVMCodeGenError, Target};
 use crate::c_codegen::{CCodeGenerator, CCodeGenError};
 use std::path::Path;
 use std::process::Command;
@@ -13,11 +14,13 @@ pub enum ExecutionError {
     #[error("Lexer error: {0}")]
     LexerError(#[from] crate::lexer::LexerError),
     #[error("Parser error: {0}")]
-    ParserError(#[from] crate::parser::ParseError),
+    ParserError(#[from] crate::parser::ParseErrors),
     #[error("Semantic error: {0}")]
-    SemanticError(#[from] SemanticError),
+    SemanticError(#[from] SemanticErrors),
     #[error("Interpreter error: {0}")]
     InterpreterError(#[from] InterpreterError),
+    #[error("Bytecode error: {0}")]
+    BytecodeError(#[from] CompileError),
     #[error("LLVM CodeGen error: {0}")]
     LLVMCodeGenError(#[from] LLVMCodeGenError),
     #[error("C CodeGen error: {0}")]
@@ -50,7 +53,7 @@ impl ExecutionEngine {
         let tokens = tokenize(source)?;
         
         // Parse
-        let program = parse(&tokens)?;
+        let program = parse_strict(&tokens)?;
         
         // Semantic analysis with file path for proper module resolution
         let analyzed_program = if let Some(path) = file_path {
@@ -68,15 +71,32 @@ impl ExecutionEngine {
         Ok(result)
     }
     
-    /// Compile a nlang program to an executable binary
+    /// Execute a nlang program on the bytecode VM instead of the interpreter
+    pub fn execute_source_on_vm(&mut self, source: &str, _module_name: &str, file_path: Option<&Path>) -> Result<i32, ExecutionError> {
+        // Tokenize
+        let tokens = tokenize(source)?;
+        
+        // Parse
+        let program = parse_strict(&tokens)?;
+        
+        // Semantic analysis with file path for proper module resolution
+        let analyzed_program = analyze_with_file_path(program, file_path)?;
+        
+        // Compile to bytecode and run it
+        let module = bytecode::compile(&analyzed_program.program, file_path)?;
+        Ok(Vm::new().run(&module)?)
+    }
+    
+    /// Compile a nlang program to an executable binary for `target`
     pub fn compile_to_executable(
         &self,
         source: &str,
         module_name: &str,
         output_path: &Path,
+        target: Target,
     ) -> Result<(), ExecutionError> {
         // Generate LLVM IR
-        let ir_code = self.compile_to_ir(source, module_name)?;
+        let ir_code = self.compile_to_ir(source, module_name, target)?;
         
         // Create temporary IR file
         let temp_dir = std::env::temp_dir();
@@ -85,8 +105,8 @@ impl ExecutionEngine {
         
         let mut errors = Vec::new();
         
-        // Try LLVM tools first (llc + lld-link)
-        match self.compile_with_llvm_tools(&ir_file, output_path, module_name) {
+        // Try LLVM tools first (llc + the target's linker)
+        match self.compile_with_llvm_tools(&ir_file, output_path, module_name, target) {
             Ok(()) => {
                 let _ = std::fs::remove_file(&ir_file);
                 return Ok(());
@@ -97,7 +117,7 @@ impl ExecutionEngine {
         }
         
         // Try clang as fallback
-        match self.compile_with_clang_from_ir(&ir_file, output_path) {
+        match self.compile_with_clang_from_ir(&ir_file, output_path, target) {
             Ok(()) => {
                 let _ = std::fs::remove_file(&ir_file);
                 return Ok(());
@@ -107,14 +127,17 @@ impl ExecutionEngine {
             }
         }
         
-        // Try GCC as final fallback (compile to C first)
-        match self.compile_with_gcc(source, module_name, output_path) {
-            Ok(()) => {
-                let _ = std::fs::remove_file(&ir_file);
-                return Ok(());
-            }
-            Err(e) => {
-                errors.push(format!("GCC (from C): {}", e));
+        // Try GCC as final fallback (compile to C first), which can only
+        // build for the host
+        if Target::host() == Some(target) {
+            match self.compile_with_gcc(source, module_name, output_path) {
+                Ok(()) => {
+                    let _ = std::fs::remove_file(&ir_file);
+                    return Ok(());
+                }
+                Err(e) => {
+                    errors.push(format!("GCC (from C): {}", e));
+                }
             }
         }
         
@@ -125,19 +148,21 @@ impl ExecutionEngine {
         })
     }
     
-    /// Try compilation with LLVM tools (llc + lld-link)
+    /// Try compilation with LLVM tools (llc + the target's linker)
     fn compile_with_llvm_tools(
         &self,
         ir_file: &Path,
         output_path: &Path,
         module_name: &str,
+        target: Target,
     ) -> Result<(), ExecutionError> {
         let temp_dir = std::env::temp_dir();
-        let obj_file = temp_dir.join(format!("{}.obj", module_name));
+        let obj_file = temp_dir.join(format!("{}.{}", module_name, target.object_extension()));
         
         // Compile IR to object file using llc
         let llc_output = Command::new("llc")
             .arg("-filetype=obj")
+            .args(target.llc_args())
             .arg("-o")
             .arg(&obj_file)
             .arg(ir_file)
@@ -154,23 +179,8 @@ impl ExecutionEngine {
         }
         
         // Link object file to executable
-        let link_output = if cfg!(windows) {
-            Command::new("lld-link")
-                .arg("/entry:main")
-                .arg("/subsystem:console")
-                .arg(format!("/out:{}", output_path.display()))
-                .arg(&obj_file)
-                .arg("msvcrt.lib")
-                .arg("legacy_stdio_definitions.lib")
-                .output()
-        } else {
-            Command::new("ld")
-                .arg("-o")
-                .arg(output_path)
-                .arg(&obj_file)
-                .arg("-lc")
-                .output()
-        };
+        let mut link_command = target.link_command(&obj_file, output_path);
+        let link_output = link_command.output();
         
         match link_output {
             Ok(output) => {
@@ -183,7 +193,7 @@ impl ExecutionEngine {
             }
             Err(_) => {
                 return Err(ExecutionError::NotImplemented {
-                    message: "Linker not found".to_string(),
+                    message: format!("Linker not found: {}", link_command.get_program().to_string_lossy()),
                 });
             }
         }
@@ -198,8 +208,10 @@ impl ExecutionEngine {
         &self,
         ir_file: &Path,
         output_path: &Path,
+        target: Target,
     ) -> Result<(), ExecutionError> {
         let clang_output = Command::new("clang")
+            .arg(format!("--target={}", target.triple()))
             .arg("-o")
             .arg(output_path)
             .arg(ir_file)
@@ -262,7 +274,7 @@ impl ExecutionEngine {
         let tokens = tokenize(source)?;
         
         // Parse
-        let program = parse(&tokens)?;
+        let program = parse_strict(&tokens)?;
         
         // Semantic analysis
         let analyzed_program = analyze(program)?;
@@ -272,19 +284,19 @@ impl ExecutionEngine {
         Ok(c_generator.generate_program(&analyzed_program)?)
     }
 
-    /// Generate LLVM IR representation
-    pub fn compile_to_ir(&self, source: &str, module_name: &str) -> Result<String, ExecutionError> {
+    /// Generate LLVM IR representation for `target`
+    pub fn compile_to_ir(&self, source: &str, module_name: &str, target: Target) -> Result<String, ExecutionError> {
         // Tokenize
         let tokens = tokenize(source)?;
         
         // Parse
-        let program = parse(&tokens)?;
+        let program = parse_strict(&tokens)?;
         
         // Semantic analysis
         let analyzed_program = analyze(program)?;
         
         // Generate actual LLVM IR
-        let mut llvm_gen = LLVMCodeGenerator::new(module_name);
+        let mut llvm_gen = LLVMCodeGenerator::new(module_name, target);
         let ir = llvm_gen.generate_program(&analyzed_program)?;
         
         Ok(ir)
@@ -331,11 +343,413 @@ mod tests {
             }
         "#;
         
-        let ir = engine.compile_to_ir(source, "test_module");
+        let ir = engine.compile_to_ir(source, "test_module", Target::X86_64Linux);
         assert!(ir.is_ok());
         let ir_code = ir.unwrap();
         // Check for LLVM IR content instead of "Pseudo-IR"
         assert!(ir_code.contains("ModuleID"));
         assert!(ir_code.contains("define"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_ir_targets() {
+        let engine = ExecutionEngine::new();
+        let source = "def main() {\n    println(1);\n}\n";
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Linux).unwrap();
+        assert!(ir_code.contains("target triple = \"x86_64-unknown-linux-gnu\""));
+        assert!(ir_code.contains("target datalayout = \"e-m:e-"));
+        assert!(ir_code.contains("define ccc void @nl_main()"));
+        assert!(ir_code.contains("define ccc i32 @main() {\nentry:\n  call void @nl_main()\n  ret i32 0\n}"));
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::Aarch64Linux).unwrap();
+        assert!(ir_code.contains("target triple = \"aarch64-unknown-linux-gnu\""));
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Windows).unwrap();
+        assert!(ir_code.contains("target triple = \"x86_64-pc-windows-msvc\""));
+        
+        for target in Target::ALL {
+            assert_eq!(target.name().parse::<Target>(), Ok(target));
+            assert_eq!(target.triple().parse::<Target>(), Ok(target));
+        }
+        assert!("x86_64-apple-darwin".parse::<Target>().unwrap_err().contains("expected one of x86_64-linux-gnu"));
+    }
+    
+    /// The body of the function `name` in `ir`.
+    fn function<'a>(ir: &'a str, name: &str) -> &'a str {
+        let start = ir.find(&format!(" @{}(", name)).unwrap_or_else(|| panic!("no function {} in:\n{}", name, ir));
+        let body = &ir[start..];
+        &body[..body.find("\n}").unwrap()]
+    }
+    
+    /// The value the first instruction in `body` that starts with
+    /// `instruction` defines, e.g. `%3` for `%3 = fmul double %1, %2`.
+    fn result_of<'a>(body: &'a str, instruction: &str) -> &'a str {
+        body.lines()
+            .filter_map(|line| line.trim().split_once(" = "))
+            .find(|(_, rhs)| rhs.starts_with(instruction))
+            .map(|(value, _)| value)
+            .unwrap_or_else(|| panic!("no `{}` in:\n{}", instruction, body))
+    }
+    
+    /// The instruction in `body` that defines `value`.
+    fn definition<'a>(body: &'a str, value: &str) -> &'a str {
+        body.lines()
+            .filter_map(|line| line.trim().split_once(" = "))
+            .find(|(name, _)| *name == value)
+            .map(|(_, rhs)| rhs)
+            .unwrap_or_else(|| panic!("{} is not defined in:\n{}", value, body))
+    }
+    
+    /// The value the first line of `body` containing `instruction` has as
+    /// its `operand`th operand after it, e.g. `%2` as operand 1 of
+    /// `fmul double` in `%3 = fmul double %0, %2`.
+    fn operand<'a>(body: &'a str, instruction: &str, operand: usize) -> &'a str {
+        let line = body.lines().find(|line| line.contains(instruction)).unwrap_or_else(|| panic!("no `{}` in:\n{}", instruction, body));
+        let operands = &line[line.find(instruction).unwrap() + instruction.len()..];
+        operands.split(',').nth(operand).unwrap().split_whitespace().next().unwrap().trim_end_matches(')')
+    }
+    
+    #[test]
+    fn test_typed_ir_generation() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            def scale(x: float, factor: int): float {
+                return x * factor;
+            }
+            
+            def main() {
+                store big = scale(1.5, 2) > 2.0;
+                store name = "n" + "lang";
+                println(int(10 / 4));
+                println(big == true);
+                println(name);
+            }
+        "#;
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Linux).unwrap();
+        assert!(ir_code.contains("define ccc double @scale(double %x, i64 %factor) {"));
+        let scale = function(&ir_code, "scale");
+        let x_slot = result_of(scale, "alloca double");
+        assert!(scale.contains(&format!("store double %x, double* {}, align 8", x_slot)));
+        let x = result_of(scale, &format!("load double, double* {}", x_slot));
+        let factor = result_of(scale, "load i64");
+        let widened = result_of(scale, &format!("sitofp i64 {} to double", factor));
+        let product = result_of(scale, &format!("fmul double {}, {}", x, widened));
+        assert!(scale.contains(&format!("ret double {}", product)));
+        
+        let main = function(&ir_code, "nl_main");
+        let scaled = result_of(main, "call double @scale(double 0x3FF8000000000000, i64 2)");
+        let big = result_of(main, &format!("fcmp ogt double {}, 0x4000000000000000", scaled));
+        let big_slot = result_of(main, "alloca i1, align 8");
+        assert!(main.contains(&format!("store i1 {}, i1* {}, align 8", big, big_slot)));
+        result_of(main, "call i8* @nl_string_concat(i8* getelementptr inbounds");
+        let quotient = operand(main, "fptosi double ", 0);
+        assert!(definition(main, quotient).starts_with("fdiv double %"));
+        let loaded = result_of(main, &format!("load i1, i1* {}", big_slot));
+        result_of(main, &format!("icmp eq i1 {}, true", loaded));
+    }
+    
+    #[test]
+    fn test_typed_ir_comparisons_and_conversions() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            def ratio(a: int, b: int): float {
+                return a / b;
+            }
+            def truncate(x: float): int {
+                return int(x * 2);
+            }
+            def half(x: float): float {
+                return x / 2.0;
+            }
+            def main() {
+                store a = "ab";
+                println(a == "a" + "b");
+                println(a != "ab");
+                println(true);
+                store flag = 1 > 2;
+                println(flag);
+                store n = 3;
+                println(half(n * 1.0));
+                println(truncate(ratio(n, 4)));
+            }
+        "#;
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Linux).unwrap();
+        
+        // An int divided by an int is a float, converted before it's returned
+        let ratio = function(&ir_code, "ratio");
+        let quotient = operand(ratio, "ret double", 0);
+        assert!(definition(ratio, quotient).starts_with("fdiv double %"));
+        for divided in [operand(ratio, "fdiv double", 0), operand(ratio, "fdiv double", 1)] {
+            assert!(definition(ratio, divided).starts_with("sitofp i64 %"));
+        }
+        let truncate = function(&ir_code, "truncate");
+        let truncated = operand(truncate, "ret i64", 0);
+        let doubled = operand(truncate, &format!("{} = fptosi double", truncated), 0);
+        assert!(definition(truncate, doubled).starts_with("fmul double %"));
+        
+        // Strings compare by their contents
+        let main = function(&ir_code, "nl_main");
+        let equal = operand(main, "icmp eq i32", 0);
+        assert!(definition(main, equal).starts_with("call i32 @strcmp(i8* %"));
+        let not_equal = operand(main, "icmp ne i32", 0);
+        assert!(definition(main, not_equal).starts_with("call i32 @strcmp(i8* %"));
+        
+        // Booleans print as words, whether constant or computed
+        for condition in ["true", result_of(main, "load i1, i1* %")] {
+            let word = result_of(main, &format!("select i1 {}, i8* getelementptr inbounds ([5 x i8], [5 x i8]* @.str.bool_true", condition));
+            assert!(main.contains(&format!("i8* {})", word)));
+        }
+        
+        // Arguments are converted to the parameter's type by the expression computing them
+        let argument = operand(main, "call double @half(double", 0);
+        let widened = operand(main, &format!("{} = fmul double", argument), 0);
+        assert!(definition(main, widened).starts_with("sitofp i64 %"));
+        let argument = operand(main, "call i64 @truncate(double", 0);
+        assert!(definition(main, argument).starts_with("call double @ratio(i64 %"));
+    }
+    
+    #[test]
+    fn test_c_code_uses_analyzed_types() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            def ratio(a: int, b: int): float {
+                return a / b;
+            }
+            def main() {
+                store ready = ratio(1, 2) > 0.25;
+                println(ready);
+                println(ratio(3, 4) * 2.0);
+                println(str(ratio(1, 4)) + "!");
+            }
+        "#;
+        
+        let c_code = engine.compile_to_c(source, "test_module").unwrap();
+        assert!(c_code.contains("printf(\"%s\\n\", ((ready) ? \"true\" : \"false\"))"));
+        assert!(c_code.contains("return ((double)a / b);"));
+        assert!(c_code.contains("printf(\"%f\\n\", (ratio(3, 4) * 2.0))"));
+        assert!(c_code.contains("nl_str_concat(float_to_str(ratio(1, 4)), str_const_0)"));
+    }
+    
+    #[test]
+    fn test_string_literal_encoding() {
+        let engine = ExecutionEngine::new();
+        let source = "def main() {\n    println(\"tab\\t\\\"q\\\" \\\\ ??\\u{e9}\");\n}\n";
+        
+        let c_code = engine.compile_to_c(source, "test_module").unwrap();
+        assert!(c_code.contains(r#"static const char str_const_0[] = "tab\t\"q\" \\ \?\?\303\251";"#));
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Linux).unwrap();
+        assert!(ir_code.contains(r#"constant [15 x i8] c"tab\09\22q\22 \5C ??\C3\A9\00""#));
+    }
+    
+    #[test]
+    fn test_wide_integer_code_generation() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            def main() {
+                store big = 5000000000;
+                println(big);
+                println(-9223372036854775808);
+                println(len("abc"));
+            }
+        "#;
+        
+        // Ints are 64-bit like in every other backend
+        let c_code = engine.compile_to_c(source, "test_module").unwrap();
+        assert!(c_code.contains("long long big = 5000000000;"));
+        assert!(c_code.contains(r#"printf("%lld\n", (long long)big);"#));
+        assert!(c_code.contains(r#"printf("%lld\n", (long long)(-9223372036854775807LL - 1));"#));
+        assert!(c_code.contains(r#"printf("%lld\n", (long long)((long long)strlen(str_const_0)));"#));
+    }
+    
+    #[test]
+    fn test_global_code_generation() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            store counter = 0;
+            store label: string;
+            def bump(by: int) {
+                counter = counter + by;
+            }
+            def main() {
+                bump(3);
+                println(counter);
+            }
+        "#;
+        
+        // Globals are declared before the functions using them and
+        // initialized in order when main starts
+        let c_code = engine.compile_to_c(source, "test_module").unwrap();
+        assert!(c_code.contains("static long long counter;\nstatic const char* label;\n"));
+        assert!(c_code.contains("int main(void) {\n    counter = 0;\n    bump(3);"));
+        assert!(c_code.contains("(counter = (counter + by));"));
+    }
+        
+    #[test]
+    fn test_format_string_code_generation() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            def main() {
+                store x = 42;
+                store ratio = 0.5;
+                println(f"Total: {x} items ({ratio:.2}) {ratio:<6} 100%");
+            }
+        "#;
+        
+        let c_code = engine.compile_to_c(source, "test_module").unwrap();
+        assert!(c_code.contains(r#"nl_format("Total: %lld items (%.2f) %-6s 100%%", (long long)x, ratio, float_to_str(ratio))"#));
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Linux).unwrap();
+        assert!(ir_code.contains(r#"c"Total: %lld items (%.2f) %-6s 100%%\00""#));
+        assert!(ir_code.contains("@snprintf(i8* null, i64 0, i8* getelementptr inbounds"));
+        assert!(ir_code.contains("call i8* @nl_float_to_string(double %"));
+    }
+    
+    #[test]
+    fn test_for_loop_code_generation() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            def main() {
+                for (i in 1..4) {
+                    print(i);
+                }
+            }
+        "#;
+        
+        let c_code = engine.compile_to_c(source, "test_module").unwrap();
+        assert!(c_code.contains("for (long long i = 1, __for_end_0 = 4; i < __for_end_0; i++)"));
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Linux).unwrap();
+        assert!(ir_code.contains("icmp slt i64"));
+    }
+    
+    #[test]
+    fn test_loop_and_block_scoping_ir() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            def main() {
+                store i = 10;
+                for (i in 0..2) {
+                    for (j in 0..2) {
+                        store k = j;
+                    }
+                }
+                {
+                    store i = 7;
+                }
+                println(i);
+            }
+        "#;
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Linux).unwrap();
+        let main = &ir_code[ir_code.find("define ccc void @nl_main()").unwrap()..];
+        let main = &main[..main.find("\n}").unwrap()];
+        let lines: Vec<&str> = main.lines().skip(2).collect();
+        // Every slot is allocated once, up front, however deep the loop
+        let allocas = lines.iter().take_while(|line| line.contains(" = alloca ")).count();
+        assert_eq!(allocas, 5);
+        assert!(lines[allocas..].iter().all(|line| !line.contains(" = alloca ")));
+        // The loop variable and the block's `i` get slots of their own, and
+        // the outer `i` is the one printed
+        let slots: Vec<&str> = lines[..allocas].iter().map(|line| line.trim().split(' ').next().unwrap()).collect();
+        let outer = slots[0];
+        assert!(outer.starts_with("%i.addr"));
+        assert_eq!(slots.iter().filter(|slot| slot.starts_with("%i.addr")).count(), 3);
+        let printed = lines.iter().rposition(|line| line.contains("@printf")).unwrap();
+        assert!(lines[printed - 1].ends_with(&format!("load i64, i64* {}, align 8", outer)));
+    }
+    
+    #[test]
+    fn test_array_code_generation() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            def main() {
+                store xs = [1, 2];
+                store names: array[string] = [];
+                push(names, "a");
+                println(xs[len(names)]);
+            }
+        "#;
+        
+        let c_code = engine.compile_to_c(source, "test_module").unwrap();
+        assert!(c_code.contains("nl_array_int* xs = nl_array_int_new(2, (long long[]){1, 2});"));
+        assert!(c_code.contains("nl_array_str* names = nl_array_str_new(0, NULL);"));
+        assert!(c_code.contains("(*nl_array_int_at(xs, (names)->len, 6, 25))"));
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Linux).unwrap();
+        assert!(ir_code.contains("call void @nl_array_push"));
+        assert!(ir_code.contains("call i64* @nl_array_at"));
+    }
+    
+    #[test]
+    fn test_nested_array_code_generation() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            struct Board { cells: array[array[int]] }
+            def main() {
+                store grid = [[1, 2], [3, 4]];
+                grid[1][0] = 30;
+                println(grid);
+                store words: array[array[array[string]]] = [[["a"]]];
+                println(Board { cells: grid }.cells[1][0]);
+            }
+        "#;
+        
+        let c_code = engine.compile_to_c(source, "test_module").unwrap();
+        let rows = c_code.find("NL_DEFINE_ARRAY(nl_array_nl_array_int, nl_array_int*, nl_array_int_print)").unwrap();
+        let cube = c_code.find("NL_DEFINE_ARRAY(nl_array_nl_array_nl_array_str, nl_array_nl_array_str*, nl_array_nl_array_str_print)").unwrap();
+        assert!(c_code.find("NL_DEFINE_ARRAY(nl_array_nl_array_str, nl_array_str*, nl_array_str_print)").unwrap() < cube);
+        assert!(rows < c_code.find("struct Board {\n    nl_array_nl_array_int* cells;\n};").unwrap());
+        assert!(c_code.contains("nl_array_nl_array_int* grid = nl_array_nl_array_int_new(2, (nl_array_int*[]){nl_array_int_new(2, (long long[]){1, 2}), nl_array_int_new(2, (long long[]){3, 4})});"));
+        assert!(c_code.contains("((*nl_array_int_at((*nl_array_nl_array_int_at(grid, 1, "));
+        assert!(c_code.contains("(nl_array_nl_array_int_print(grid), printf(\"\\n\"))"));
+    }
+    
+    #[test]
+    fn test_struct_code_generation() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            struct Point { x: int, y: float }
+            def main() {
+                store p = Point { y: 2.5, x: 1 };
+                p.x = p.x + 1;
+                println(p.y);
+            }
+        "#;
+        
+        let c_code = engine.compile_to_c(source, "test_module").unwrap();
+        assert!(c_code.contains("struct Point {\n    long long x;\n    double y;\n};"));
+        assert!(c_code.contains("Point* p = Point_new(1, 2.5);"));
+        assert!(c_code.contains("((p)->x = ((p)->x + 1));"));
+        assert!(c_code.contains("printf(\"%f\\n\", (p)->y);"));
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Linux).unwrap();
+        assert!(ir_code.contains("%struct.Point = type { i64, i64 }"));
+        assert!(ir_code.contains("getelementptr %struct.Point, %struct.Point* %"));
+    }
+    
+    #[test]
+    fn test_enum_code_generation() {
+        let engine = ExecutionEngine::new();
+        let source = r#"
+            enum Opt { Some(int), None }
+            def main() {
+                store o = Opt::Some(4);
+                store v = match (o) { Opt::Some(n) => n, Opt::None => 0 };
+                println(v);
+            }
+        "#;
+        
+        let c_code = engine.compile_to_c(source, "test_module").unwrap();
+        assert!(c_code.contains("struct Opt {\n    int tag;\n    long long Some_0;\n};"));
+        assert!(c_code.contains("Opt* o = Opt_Some(4);"));
+        assert!(c_code.contains("if (__match_0->tag == Opt_Some_tag) {\n    long long n = __match_0->Some_0;"));
+        
+        let ir_code = engine.compile_to_ir(source, "test_module", Target::X86_64Linux).unwrap();
+        assert!(ir_code.contains("%enum.Opt = type { i64, i64 }"));
+        assert!(ir_code.contains("getelementptr %enum.Opt, %enum.Opt* %"));
+        assert!(ir_code.contains("%match_result.0 = alloca i64, align 8"));
+    }
+}
@@ -0,0 +1,555 @@
+//! Canonical source formatting
+//!
+//! A file is parsed and its syntax tree printed back in one fixed style:
+//! four-space indentation, spaces around binary operators, one statement per
+//! line and at most one blank line between statements. The parser has no use
+//! for comments, so they are lexed alongside the tokens and put back before,
+//! or at the end of the line of, the item they were written next to.
+
+use crate::ast::*;
+use crate::execution_engine::ExecutionError;
+use crate::lexer::{tokenize_with_comments, TokenType};
+use crate::parser::parse_strict;
+use crate::span::Span;
+
+#[cfg(test)]
+mod tests;
+
+const INDENT: &str = "    ";
+
+/// Formats a whole file. Fails if it does not lex or parse.
+pub fn format_source(source: &str) -> Result<String, ExecutionError> {
+    let mut tokens = Vec::new();
+    let mut comments = Vec::new();
+    for token in tokenize_with_comments(source)? {
+        match token.token_type {
+            TokenType::Comment(_) | TokenType::BlockComment(_) => comments.push(Comment { span: token.span }),
+            _ => tokens.push(token),
+        }
+    }
+    let program = parse_strict(&tokens)?;
+
+    let mut formatter = Formatter { source, comments, next_comment: 0, out: String::new(), indent: 0 };
+    formatter.statements(&program.statements, source.len());
+    Ok(formatter.out)
+}
+
+/// A `//` or `/* */` comment, written back exactly as it is in the source.
+struct Comment {
+    span: Span,
+}
+
+/// Binding strength of an expression, used to decide where parentheses are
+/// needed. Higher binds tighter.
+mod precedence {
+    pub const LOWEST: u8 = 0;
+    pub const ASSIGNMENT: u8 = 1;
+    pub const OR: u8 = 2;
+    pub const AND: u8 = 3;
+    pub const EQUALITY: u8 = 4;
+    pub const COMPARISON: u8 = 5;
+    pub const TERM: u8 = 6;
+    pub const FACTOR: u8 = 7;
+    pub const UNARY: u8 = 8;
+    pub const POSTFIX: u8 = 9;
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    comments: Vec<Comment>,
+    // Index of the first comment not yet written
+    next_comment: usize,
+    out: String,
+    indent: usize,
+}
+
+impl<'a> Formatter<'a> {
+    /// Writes `statements` one per line, followed by any comments before
+    /// `end`, the offset where the enclosing block or file ends.
+    fn statements(&mut self, statements: &[Statement], end: usize) {
+        let mut previous = None;
+        for statement in statements {
+            let span = statement.span();
+            self.item_start(span.start, &mut previous);
+            self.statement(statement);
+            self.item_end(span.end, &mut previous);
+        }
+        self.comments_before(end, &mut previous);
+    }
+
+    /// Starts a line for an item (statement, field, variant or match arm)
+    /// beginning at `start`, after the comments written above it.
+    /// `previous` is the end of the item before it in the same list.
+    fn item_start(&mut self, start: usize, previous: &mut Option<usize>) {
+        self.comments_before(start, previous);
+        self.blank_line_between(*previous, start);
+        self.out.push_str(&INDENT.repeat(self.indent));
+    }
+
+    fn item_end(&mut self, end: usize, previous: &mut Option<usize>) {
+        self.trailing_comments(end);
+        self.out.push('\n');
+        *previous = Some(end);
+    }
+
+    /// Writes the comments starting before `offset` on lines of their own.
+    fn comments_before(&mut self, offset: usize, previous: &mut Option<usize>) {
+        while let Some(comment) = self.comments.get(self.next_comment)
+            && comment.span.start < offset
+        {
+            let span = comment.span;
+            let line = format!("{}{}\n", INDENT.repeat(self.indent), self.text(comment));
+            self.blank_line_between(*previous, span.start);
+            self.out.push_str(&line);
+            self.next_comment += 1;
+            *previous = Some(span.end);
+        }
+    }
+
+    /// Writes the comments left inside the item ending at `end`, such as
+    /// ones between the operands of an expression, then the one after it on
+    /// the same line. The first goes at the end of the line if it fits on
+    /// one; the others on lines of their own below.
+    fn trailing_comments(&mut self, end: usize) {
+        let mut same_line = true;
+        while let Some(comment) = self.comments.get(self.next_comment)
+            && (comment.span.start < end || !self.source[end..comment.span.start].contains('\n'))
+        {
+            let text = self.text(comment);
+            if same_line && !text.contains('\n') {
+                self.out.push(' ');
+            } else {
+                self.out.push('\n');
+                self.out.push_str(&INDENT.repeat(self.indent));
+            }
+            self.out.push_str(text);
+            same_line = false;
+            self.next_comment += 1;
+        }
+    }
+
+    fn text(&self, comment: &Comment) -> &'a str {
+        self.source[comment.span.start..comment.span.end].trim_end()
+    }
+
+    /// Keeps a single blank line where the source had one or more. The text
+    /// between two items can hold more than line breaks, like the `else {`
+    /// between a comment and the statement after it, so only lines with
+    /// nothing on them count.
+    fn blank_line_between(&mut self, previous: Option<usize>, start: usize) {
+        if let Some(between) = previous.and_then(|previous| self.source.get(previous..start)) {
+            let lines: Vec<&str> = between.split('\n').collect();
+            if lines.len() > 2 && lines[1..lines.len() - 1].iter().any(|line| line.trim().is_empty()) {
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn has_comments_before(&self, offset: usize) -> bool {
+        self.comments.get(self.next_comment).is_some_and(|comment| comment.span.start < offset)
+    }
+
+    /// Writes `{ ... }` for a block ending at `end`.
+    fn block(&mut self, statements: &[Statement], end: usize) {
+        if statements.is_empty() && !self.has_comments_before(end) {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{\n");
+        self.indent += 1;
+        self.statements(statements, end);
+        self.indent -= 1;
+        self.out.push_str(&INDENT.repeat(self.indent));
+        self.out.push('}');
+    }
+
+    /// Writes `{`, one line per item with `write_item`, then `}`, keeping
+    /// the comments between the items.
+    fn braced_items<T>(&mut self, items: &[T], end: usize, span_of: impl Fn(&T) -> Span, mut write_item: impl FnMut(&mut Self, &T)) {
+        if items.is_empty() && !self.has_comments_before(end) {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{\n");
+        self.indent += 1;
+        let mut previous = None;
+        for item in items {
+            let span = span_of(item);
+            self.item_start(span.start, &mut previous);
+            write_item(self, item);
+            self.item_end(span.end, &mut previous);
+        }
+        self.comments_before(end, &mut previous);
+        self.indent -= 1;
+        self.out.push_str(&INDENT.repeat(self.indent));
+        self.out.push('}');
+    }
+
+    /// Writes a statement, starting at the current position in the line.
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expr) => {
+                self.expr(expr, precedence::LOWEST);
+                self.out.push(';');
+            }
+            Statement::LetDeclaration { name, var_type, initializer, is_exported, .. } => {
+                if *is_exported {
+                    self.out.push_str("export ");
+                }
+                self.out.push_str("store ");
+                self.out.push_str(name);
+                if let Some(var_type) = var_type {
+                    self.out.push_str(&format!(": {}", var_type));
+                }
+                if let Some(initializer) = initializer {
+                    self.out.push_str(" = ");
+                    self.expr(initializer, precedence::LOWEST);
+                }
+                self.out.push(';');
+            }
+            Statement::FunctionDeclaration { name, parameters, body, return_type, is_exported, is_test, span, .. } => {
+                if *is_exported {
+                    self.out.push_str("export ");
+                }
+                if *is_test {
+                    self.out.push_str("test ");
+                }
+                self.out.push_str("def ");
+                self.out.push_str(name);
+                self.signature(parameters, return_type);
+                self.out.push(' ');
+                self.block(body, span.end);
+            }
+            Statement::StructDeclaration { name, fields, span } => {
+                self.out.push_str(&format!("struct {} ", name));
+                self.braced_items(fields, span.end, |field| field.span, |formatter, field| {
+                    formatter.out.push_str(&format!("{}: {},", field.name, field.field_type));
+                });
+            }
+            Statement::EnumDeclaration { name, variants, span } => {
+                self.out.push_str(&format!("enum {} ", name));
+                self.braced_items(variants, span.end, |variant| variant.span, |formatter, variant| {
+                    formatter.out.push_str(&variant.name);
+                    if !variant.fields.is_empty() {
+                        let fields: Vec<String> = variant.fields.iter().map(|field| field.to_string()).collect();
+                        formatter.out.push_str(&format!("({})", fields.join(", ")));
+                    }
+                    formatter.out.push(',');
+                });
+            }
+            Statement::Block { statements, span } => self.block(statements, span.end),
+            Statement::If { condition, then_branch, else_branch, .. } => {
+                self.out.push_str("if (");
+                self.expr(condition, precedence::LOWEST);
+                self.out.push_str(") ");
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    let start = else_branch.span().start;
+                    if self.has_comments_before(start) {
+                        // Comments written before `else` stay there, which
+                        // puts `else` on a line of its own
+                        let end = then_branch.span().end;
+                        let mut previous = Some(end);
+                        self.trailing_comments(end);
+                        self.out.push('\n');
+                        self.comments_before(start, &mut previous);
+                        self.out.push_str(&INDENT.repeat(self.indent));
+                        self.out.push_str("else ");
+                    } else {
+                        self.out.push_str(" else ");
+                    }
+                    self.statement(else_branch);
+                }
+            }
+            Statement::While { condition, body, .. } => {
+                self.out.push_str("while (");
+                self.expr(condition, precedence::LOWEST);
+                self.out.push_str(") ");
+                self.statement(body);
+            }
+            Statement::For { variable, iterable, body, .. } => {
+                self.out.push_str(&format!("for ({} in ", variable));
+                self.expr(iterable, precedence::LOWEST);
+                self.out.push_str(") ");
+                self.statement(body);
+            }
+            Statement::Match { scrutinee, arms, span } => {
+                self.out.push_str("match (");
+                self.expr(scrutinee, precedence::LOWEST);
+                self.out.push_str(") ");
+                self.braced_items(arms, span.end, |arm| arm.span, |formatter, arm| {
+                    formatter.pattern(&arm.pattern);
+                    formatter.out.push_str(" => ");
+                    match &arm.body {
+                        Statement::Expression(expr) => {
+                            formatter.expr(expr, precedence::LOWEST);
+                            formatter.out.push(',');
+                        }
+                        body => formatter.statement(body),
+                    }
+                });
+            }
+            Statement::Return { value, .. } => {
+                self.out.push_str("return");
+                if let Some(value) = value {
+                    self.out.push(' ');
+                    self.expr(value, precedence::LOWEST);
+                }
+                self.out.push(';');
+            }
+            Statement::Break { .. } => self.out.push_str("break;"),
+            Statement::Continue { .. } => self.out.push_str("continue;"),
+            Statement::Import { module, alias, .. } => {
+                self.out.push_str(&format!("import {}", module));
+                if let Some(alias) = alias {
+                    self.out.push_str(&format!(" as {}", alias));
+                }
+                self.out.push(';');
+            }
+            Statement::ImportFrom { module, items, .. } => {
+                let items: Vec<String> = items
+                    .iter()
+                    .map(|(item, alias)| match alias {
+                        Some(alias) => format!("{} as {}", item, alias),
+                        None => item.clone(),
+                    })
+                    .collect();
+                self.out.push_str(&format!("from {} import {};", module, items.join(", ")));
+            }
+            Statement::AssignMain { function_name, .. } => {
+                self.out.push_str(&format!("ASSIGN_MAIN -> \"{}\";", function_name));
+            }
+        }
+    }
+
+    /// Writes `(parameters)` and the return type of a function or lambda.
+    fn signature(&mut self, parameters: &[Parameter], return_type: &Option<Type>) {
+        let parameters: Vec<String> = parameters
+            .iter()
+            .map(|parameter| {
+                if parameter.param_type == Type::Unknown {
+                    parameter.name.clone()
+                } else {
+                    format!("{}: {}", parameter.name, parameter.param_type)
+                }
+            })
+            .collect();
+        self.out.push_str(&format!("({})", parameters.join(", ")));
+        if let Some(return_type) = return_type {
+            self.out.push_str(&format!(": {}", return_type));
+        }
+    }
+
+    /// Writes `expr`, in parentheses if it binds more loosely than `min`.
+    fn expr(&mut self, expr: &Expr, min: u8) {
+        let parenthesize = expr_precedence(expr) < min;
+        if parenthesize {
+            self.out.push('(');
+        }
+        self.expr_unparenthesized(expr);
+        if parenthesize {
+            self.out.push(')');
+        }
+    }
+
+    fn expr_unparenthesized(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { span, .. } | Expr::FormatString { span, .. } => self.out.push_str(&self.source[span.start..span.end]),
+            Expr::Variable { name, .. } => self.out.push_str(name),
+            Expr::Binary { left, operator, right, .. } => {
+                let precedence = binary_precedence(operator);
+                self.expr(left, precedence);
+                self.out.push_str(&format!(" {} ", binary_symbol(operator)));
+                self.expr(right, precedence + 1);
+            }
+            Expr::Unary { operator, operand, .. } => {
+                self.out.push_str(match operator {
+                    UnaryOperator::Negate => "-",
+                    UnaryOperator::Not => "!",
+                });
+                self.expr(operand, precedence::UNARY);
+            }
+            Expr::Call { callee, arguments, .. } => {
+                self.expr(callee, precedence::POSTFIX);
+                self.out.push('(');
+                self.comma_separated(arguments);
+                self.out.push(')');
+            }
+            Expr::Function { parameters, body, return_type, span } => {
+                self.out.push_str("def");
+                self.signature(parameters, return_type);
+                match arrow_body(body) {
+                    Some(value) => {
+                        self.out.push_str(" => ");
+                        self.expr(value, precedence::ASSIGNMENT);
+                    }
+                    None => {
+                        self.out.push(' ');
+                        self.block(body, span.end);
+                    }
+                }
+            }
+            Expr::Get { object, name, .. } => {
+                self.expr(object, precedence::POSTFIX);
+                self.out.push('.');
+                self.out.push_str(name);
+            }
+            Expr::Set { object, name, value, .. } => {
+                self.expr(object, precedence::POSTFIX);
+                self.out.push_str(&format!(".{} = ", name));
+                self.expr(value, precedence::ASSIGNMENT);
+            }
+            Expr::Index { sequence, index, .. } => {
+                self.expr(sequence, precedence::POSTFIX);
+                self.out.push('[');
+                self.expr(index, precedence::LOWEST);
+                self.out.push(']');
+            }
+            Expr::SetIndex { sequence, index, value, .. } => {
+                self.expr(sequence, precedence::POSTFIX);
+                self.out.push('[');
+                self.expr(index, precedence::LOWEST);
+                self.out.push_str("] = ");
+                self.expr(value, precedence::ASSIGNMENT);
+            }
+            Expr::StructLiteral { name, fields, .. } => {
+                self.out.push_str(name);
+                if fields.is_empty() {
+                    self.out.push_str(" {}");
+                    return;
+                }
+                self.out.push_str(" { ");
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.out.push_str(&format!("{}: ", field));
+                    self.expr(value, precedence::LOWEST);
+                }
+                self.out.push_str(" }");
+            }
+            Expr::EnumVariant { enum_name, variant, arguments, .. } => {
+                self.out.push_str(&format!("{}::{}", enum_name, variant));
+                if !arguments.is_empty() {
+                    self.out.push('(');
+                    self.comma_separated(arguments);
+                    self.out.push(')');
+                }
+            }
+            Expr::Match { scrutinee, arms, span } => {
+                self.out.push_str("match (");
+                self.expr(scrutinee, precedence::LOWEST);
+                self.out.push_str(") ");
+                self.braced_items(arms, span.end, |arm| arm.span, |formatter, arm| {
+                    formatter.pattern(&arm.pattern);
+                    formatter.out.push_str(" => ");
+                    formatter.expr(&arm.body, precedence::LOWEST);
+                    formatter.out.push(',');
+                });
+            }
+            Expr::Array { elements, .. } => {
+                self.out.push('[');
+                self.comma_separated(elements);
+                self.out.push(']');
+            }
+            Expr::Assign { name, value, .. } => {
+                self.out.push_str(&format!("{} = ", name));
+                self.expr(value, precedence::ASSIGNMENT);
+            }
+            Expr::Range { start, end, .. } => {
+                self.expr(start, precedence::LOWEST);
+                self.out.push_str("..");
+                self.expr(end, precedence::LOWEST);
+            }
+        }
+    }
+
+    fn comma_separated(&mut self, exprs: &[Expr]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(expr, precedence::LOWEST);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard { .. } => self.out.push('_'),
+            Pattern::Binding { name, .. } => self.out.push_str(name),
+            Pattern::Literal { span, .. } => {
+                // Negative numbers may have been written as `- 1`
+                let text = &self.source[span.start..span.end];
+                match text.strip_prefix('-') {
+                    Some(number) => self.out.push_str(&format!("-{}", number.trim_start())),
+                    None => self.out.push_str(text),
+                }
+            }
+            Pattern::Variant { enum_name, variant, fields, .. } => {
+                self.out.push_str(&format!("{}::{}", enum_name, variant));
+                if !fields.is_empty() {
+                    self.out.push('(');
+                    for (i, field) in fields.iter().enumerate() {
+                        if i > 0 {
+                            self.out.push_str(", ");
+                        }
+                        self.pattern(field);
+                    }
+                    self.out.push(')');
+                }
+            }
+        }
+    }
+}
+
+/// The value of a lambda written `def(...) => value`, which the parser turns
+/// into a body holding a single return spanning just that value.
+fn arrow_body(body: &[Statement]) -> Option<&Expr> {
+    match body {
+        [Statement::Return { value: Some(value), span }] if *span == value.span() => Some(value),
+        _ => None,
+    }
+}
+
+fn expr_precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Assign { .. } | Expr::Set { .. } | Expr::SetIndex { .. } => precedence::ASSIGNMENT,
+        // The body of an arrow lambda extends as far right as it can
+        Expr::Function { body, .. } if arrow_body(body).is_some() => precedence::ASSIGNMENT,
+        Expr::Binary { operator, .. } => binary_precedence(operator),
+        Expr::Unary { .. } => precedence::UNARY,
+        Expr::Range { .. } => precedence::LOWEST,
+        _ => precedence::POSTFIX,
+    }
+}
+
+fn binary_precedence(operator: &BinaryOperator) -> u8 {
+    match operator {
+        BinaryOperator::Or => precedence::OR,
+        BinaryOperator::And => precedence::AND,
+        BinaryOperator::EqualEqual | BinaryOperator::NotEqual => precedence::EQUALITY,
+        BinaryOperator::Less | BinaryOperator::LessEqual | BinaryOperator::Greater | BinaryOperator::GreaterEqual => {
+            precedence::COMPARISON
+        }
+        BinaryOperator::Plus | BinaryOperator::Minus => precedence::TERM,
+        BinaryOperator::Star | BinaryOperator::Slash | BinaryOperator::Percent => precedence::FACTOR,
+    }
+}
+
+fn binary_symbol(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Plus => "+",
+        BinaryOperator::Minus => "-",
+        BinaryOperator::Star => "*",
+        BinaryOperator::Slash => "/",
+        BinaryOperator::Percent => "%",
+        BinaryOperator::EqualEqual => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::Less => "<",
+        BinaryOperator::LessEqual => "<=",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::GreaterEqual => ">=",
+        BinaryOperator::And => "&&",
+        BinaryOperator::Or => "||",
+    }
+}
@@ -0,0 +1,270 @@
+#[cfg(test)]
+mod formatter_tests {
+    use crate::formatter::format_source;
+
+    /// Formats `source`, checking that formatting the result changes nothing.
+    fn format(source: &str) -> String {
+        let formatted = format_source(source).unwrap();
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+        formatted
+    }
+
+    #[test]
+    fn test_layout() {
+        let source = "
+            struct Point{x:int,y:int}
+            enum Shape { Square(int), Empty }
+            def area(s:Shape):int{
+            match(s){Shape::Square(side)=>{return side*side;}
+            Shape::Empty=>return 0;}
+            }
+
+
+
+            def main():int{store p=Point{x:1,y:2};if(p.x>0){p.x=p.x-1;}else{return 1;}
+            for(i in 0..3){}
+            return area(Shape::Square(p.y));}
+        ";
+        let expected = "\
+struct Point {
+    x: int,
+    y: int,
+}
+enum Shape {
+    Square(int),
+    Empty,
+}
+def area(s: Shape): int {
+    match (s) {
+        Shape::Square(side) => {
+            return side * side;
+        }
+        Shape::Empty => return 0;
+    }
+}
+
+def main(): int {
+    store p = Point { x: 1, y: 2 };
+    if (p.x > 0) {
+        p.x = p.x - 1;
+    } else {
+        return 1;
+    }
+    for (i in 0..3) {}
+    return area(Shape::Square(p.y));
+}
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn test_expressions() {
+        let source = "store a = (1 + 2) * -(3 - 4) - (5 - 6);\n\
+                      store b = !(x == 1 || y) && (f)(def(n) => n + 1, def(): int { return 2.50; });\n\
+                      store c = (def() => 1)()[0] + match (a) { 1 => \"one\", - 2 => 2, _ => 3 };\n";
+        let expected = "\
+store a = (1 + 2) * -(3 - 4) - (5 - 6);
+store b = !(x == 1 || y) && f(def(n) => n + 1, def(): int {
+    return 2.50;
+});
+store c = (def() => 1)()[0] + match (a) {
+    1 => \"one\",
+    -2 => 2,
+    _ => 3,
+};
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "\
+// Leading comment
+store x = 1;   // trailing
+
+
+// Before main
+def main(): int {   // after brace
+    store y = x;
+    // before return
+
+    return y;
+    // end of body
+}
+struct S {
+    // first field
+    a: int, // the a
+}
+// end of file
+";
+        let expected = "\
+// Leading comment
+store x = 1; // trailing
+
+// Before main
+def main(): int {
+    // after brace
+    store y = x;
+    // before return
+
+    return y;
+    // end of body
+}
+struct S {
+    // first field
+    a: int, // the a
+}
+// end of file
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn test_comments_inside_expressions() {
+        let source = "\
+store a = 1 + // why
+    5;
+store b = /* c */ 5;
+foo(1, // c
+    2);
+def main() {
+    store c = f(/* first */ 1, /* multi
+    line */ 2); // after
+    return c;
+}
+";
+        let expected = "\
+store a = 1 + 5; // why
+store b = 5; /* c */
+foo(1, 2); // c
+def main() {
+    store c = f(1, 2); /* first */
+    /* multi
+    line */
+    // after
+    return c;
+}
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn test_block_and_doc_comments() {
+        let source = "\
+/// Adds one.
+///   Keeps indentation.
+def inc(x: int): int { /* the
+    result */
+    return x + 1;   /* trailing */
+}
+/* before /* nested */ x */
+store x = inc(1);
+";
+        let expected = "\
+/// Adds one.
+///   Keeps indentation.
+def inc(x: int): int {
+    /* the
+    result */
+    return x + 1; /* trailing */
+}
+/* before /* nested */ x */
+store x = inc(1);
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn test_comments_before_else() {
+        let source = "\
+def sign(x: int): int {
+    if (x > 0) {
+        return 1;
+    }
+    // zero or negative
+    else {
+        return 0;
+    }
+}
+def main() {
+    if (true) {
+        print(1);
+    } // done
+    else if (false) print(2);
+
+    /* neither */
+    else print(3);
+}
+";
+        let expected = "\
+def sign(x: int): int {
+    if (x > 0) {
+        return 1;
+    }
+    // zero or negative
+    else {
+        return 0;
+    }
+}
+def main() {
+    if (true) {
+        print(1);
+    } // done
+    else if (false) print(2);
+
+    /* neither */
+    else print(3);
+}
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        // Only lines with nothing on them are kept, whatever else is between
+        // two items, and a comment after a `}` stays after it
+        let source = "\
+store a = 1;
+store b = 2;
+
+
+def f() {
+    print(a);
+} // end of f
+def g() // about g
+{
+    print(b);
+}
+
+// about main
+def main() {
+    f();
+    g();
+}
+";
+        let expected = "\
+store a = 1;
+store b = 2;
+
+def f() {
+    print(a);
+} // end of f
+def g() {
+    // about g
+    print(b);
+}
+
+// about main
+def main() {
+    f();
+    g();
+}
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        assert!(format_source("store x = ;").is_err());
+    }
+}
@@ -1,10 +1,20 @@
-use crate::ast::{Program, Statement, Expr, Type, BinaryOperator, UnaryOperator, Literal, Parameter};
+use crate::ast::{Statement, Expr, Type, BinaryOperator, UnaryOperator, Literal, Parameter, MatchArm, Pattern, FormatPart};
 use crate::lexer::Lexer;
-use crate::parser::Parser;
+use crate::parser::parse_strict;
+use crate::semantic::{self, DefinitionKind, SymbolIndex, TypedProgram};
+use crate::span::Span;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use thiserror::Error;
 
+mod value;
+
+pub use value::{write_separated, RuntimeValue, Scalar};
+
 #[derive(Error, Debug)]
 pub enum InterpreterError {
     #[error("Variable '{name}' not found")]
@@ -17,12 +27,56 @@ pub enum InterpreterError {
     DivisionByZero,
     #[error("Invalid operation: {message}")]
     InvalidOperation { message: String },
+    #[error("Index out of bounds: the length is {length} but the index is {index}")]
+    IndexOutOfBounds { index: i64, length: usize },
+    #[error("Assertion failed: {message}")]
+    AssertionFailed { message: String },
+    #[error("Stack overflow: more than {depth} nested calls")]
+    StackOverflow { depth: usize },
+    /// Analysis recorded nothing for a name the running code uses, so it
+    /// and the interpreter disagree about the program.
+    #[error("Internal error: analysis did not resolve '{name}'")]
+    Unresolved { name: String },
     #[error("Return statement executed")]
     ReturnValue(Value),
     #[error("Break statement executed")]
     Break,
     #[error("Continue statement executed")]
     Continue,
+    #[error("{error} (line {}, column {})", span.line, span.column)]
+    At { span: Span, error: Box<InterpreterError> },
+}
+
+impl InterpreterError {
+    /// Attaches a source location, leaving control-flow signals and
+    /// already-located errors untouched.
+    pub fn with_span(self, span: Span) -> Self {
+        match self {
+            InterpreterError::ReturnValue(_)
+            | InterpreterError::Break
+            | InterpreterError::Continue
+            | InterpreterError::At { .. } => self,
+            error => InterpreterError::At { span, error: Box::new(error) },
+        }
+    }
+    
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            InterpreterError::At { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+    
+    /// The failure of `assert_eq` (when `expect_equal`) or `assert_ne`,
+    /// showing the values it compared.
+    pub fn failed_comparison(expect_equal: bool, left: &str, right: &str) -> Self {
+        let message = if expect_equal {
+            format!("left == right (left: {}, right: {})", left, right)
+        } else {
+            format!("left != right (both are {})", left)
+        };
+        InterpreterError::AssertionFailed { message }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -31,6 +85,32 @@ pub enum Value {
     Float(f64),
     Boolean(bool),
     String(String),
+    Array(Array),
+    Struct(Rc<RefCell<StructValue>>),
+    Enum(Rc<EnumValue>),
+    Function(Rc<Closure>),
+}
+
+/// Arrays are shared by reference, so a `push` through one variable is
+/// visible through every other variable holding the same array.
+pub type Array = Rc<RefCell<Vec<Value>>>;
+
+/// An instance of a user-defined struct. Like arrays, structs are shared by
+/// reference, so assigning one to another variable doesn't copy its fields.
+#[derive(Debug, PartialEq)]
+pub struct StructValue {
+    pub name: String,
+    /// Field values in declaration order.
+    pub fields: Vec<(String, Value)>,
+}
+
+/// A value of a user-defined enum. Enum values are immutable, so they
+/// compare by their contents.
+#[derive(Debug, PartialEq)]
+pub struct EnumValue {
+    pub enum_name: String,
+    pub variant: String,
+    pub fields: Vec<Value>,
 }
 
 #[derive(Debug, Clone)]
@@ -39,157 +119,277 @@ pub struct Function {
     pub parameters: Vec<Parameter>,
     pub body: Vec<Statement>,
     pub return_type: Option<Type>,
+    /// What the names in the body resolve to.
+    pub symbols: Rc<SymbolIndex>,
+    /// The imported module the function is declared in; `None` for the
+    /// program being run.
+    pub module: Option<PathBuf>,
+}
+
+/// Which declared function a call runs. A module is analyzed once, so its
+/// functions are told apart by where they are declared; the program's by
+/// name, since every REPL input is analyzed on its own, with spans that
+/// start over, and can declare a function again.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum FunctionKey {
+    Program(String),
+    Module(PathBuf, Span),
+}
+
+/// What a name in the running code refers to, as analysis resolved it.
+enum Resolved {
+    Builtin,
+    Function(FunctionKey),
+    /// A variable or parameter, found in the environment.
+    Variable,
+}
+
+/// A function used as a value: a declared function or a lambda. A lambda
+/// captures the environment it was created in, so its body can still read
+/// the variables around it after that scope has returned.
+pub struct Closure {
+    pub function: Rc<Function>,
+    /// `None` for declared functions, which run in the global environment.
+    pub captured: Option<Environment>,
+}
+
+impl PartialEq for Closure {
+    /// Function values are only equal to themselves.
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Closure({})", self.function.name)
+    }
 }
 
 impl Value {
-    pub fn type_name(&self) -> &'static str {
+    pub fn array(elements: Vec<Value>) -> Self {
+        Value::Array(Rc::new(RefCell::new(elements)))
+    }
+    
+    pub fn structure(name: String, fields: Vec<(String, Value)>) -> Self {
+        Value::Struct(Rc::new(RefCell::new(StructValue { name, fields })))
+    }
+    
+    pub fn enumeration(enum_name: String, variant: String, fields: Vec<Value>) -> Self {
+        Value::Enum(Rc::new(EnumValue { enum_name, variant, fields }))
+    }
+}
+
+impl RuntimeValue for Value {
+    fn type_name(&self) -> &'static str {
         match self {
             Value::Integer(_) => "int",
             Value::Float(_) => "float",
             Value::Boolean(_) => "bool",
             Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Struct(_) => "struct",
+            Value::Enum(_) => "enum",
+            Value::Function(_) => "function",
         }
     }
     
-    pub fn to_int(&self) -> Result<i64, InterpreterError> {
+    fn scalar(&self) -> Option<Scalar<'_>> {
         match self {
-            Value::Integer(i) => Ok(*i),
-            Value::Float(f) => Ok(*f as i64),
-            Value::Boolean(b) => Ok(if *b { 1 } else { 0 }),
-            _ => Err(InterpreterError::TypeMismatch {
-                expected: "int".to_string(),
-                actual: self.type_name().to_string(),
-            }),
+            Value::Integer(i) => Some(Scalar::Integer(*i)),
+            Value::Float(f) => Some(Scalar::Float(*f)),
+            Value::Boolean(b) => Some(Scalar::Boolean(*b)),
+            Value::String(s) => Some(Scalar::String(s)),
+            _ => None,
         }
     }
-    
-    pub fn to_float(&self) -> Result<f64, InterpreterError> {
-        match self {
-            Value::Integer(i) => Ok(*i as f64),
-            Value::Float(f) => Ok(*f),
-            _ => Err(InterpreterError::TypeMismatch {
-                expected: "float".to_string(),
-                actual: self.type_name().to_string(),
-            }),
-        }
-    }
-    
-    pub fn to_bool(&self) -> Result<bool, InterpreterError> {
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Value::Boolean(b) => Ok(*b),
-            Value::Integer(i) => Ok(*i != 0),
-            Value::Float(f) => Ok(*f != 0.0),
-            _ => Err(InterpreterError::TypeMismatch {
-                expected: "bool".to_string(),
-                actual: self.type_name().to_string(),
-            }),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                write_separated(f, elements.borrow().iter(), |f, element| write!(f, "{}", element))?;
+                write!(f, "]")
+            }
+            Value::Struct(instance) => {
+                let instance = instance.borrow();
+                write!(f, "{} {{ ", instance.name)?;
+                write_separated(f, &instance.fields, |f, (name, value)| write!(f, "{}: {}", name, value))?;
+                write!(f, " }}")
+            }
+            Value::Enum(value) => {
+                write!(f, "{}::{}", value.enum_name, value.variant)?;
+                if !value.fields.is_empty() {
+                    write!(f, "(")?;
+                    write_separated(f, &value.fields, |f, field| write!(f, "{}", field))?;
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Value::Function(closure) => match closure.captured {
+                Some(_) => write!(f, "<lambda>"),
+                None => write!(f, "<function {}>", closure.function.name),
+            },
         }
     }
 }
 
+/// A chain of lexical scopes, innermost first.
+///
+/// Cloning an environment is cheap and the clone shares the same scopes, so
+/// a closure sees later assignments to the variables it captured.
 #[derive(Clone)]
 pub struct Environment {
-    variables: HashMap<String, Value>,
-    functions: HashMap<String, Function>,
+    scope: Rc<Scope>,
+}
+
+struct Scope {
+    variables: RefCell<HashMap<String, Value>>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
+    /// Creates a global scope holding the built-in variables.
     pub fn new() -> Self {
-        let mut env = Environment {
-            variables: HashMap::new(),
-            functions: HashMap::new(),
+        let env = Environment {
+            scope: Rc::new(Scope { variables: RefCell::new(HashMap::new()), parent: None }),
         };
         
         // Add built-in variables
-        env.variables.insert("PI".to_string(), Value::Float(std::f64::consts::PI));
+        env.define_variable("PI".to_string(), Value::Float(std::f64::consts::PI));
         
         env
     }
     
-    pub fn define_variable(&mut self, name: String, value: Value) {
-        self.variables.insert(name, value);
+    /// Creates a new innermost scope. Variables defined in it shadow outer
+    /// ones and go away with it.
+    pub fn child(&self) -> Self {
+        Environment {
+            scope: Rc::new(Scope { variables: RefCell::new(HashMap::new()), parent: Some(self.scope.clone()) }),
+        }
     }
     
-    pub fn get_variable(&self, name: &str) -> Result<Value, InterpreterError> {
-        self.variables.get(name)
-            .cloned()
-            .ok_or_else(|| InterpreterError::VariableNotFound { name: name.to_string() })
+    pub fn define_variable(&self, name: String, value: Value) {
+        self.scope.variables.borrow_mut().insert(name, value);
     }
     
-    pub fn set_variable(&mut self, name: String, value: Value) -> Result<(), InterpreterError> {
-        if self.variables.contains_key(&name) {
-            self.variables.insert(name, value);
-            Ok(())
-        } else {
-            Err(InterpreterError::VariableNotFound { name })
+    pub fn get_variable(&self, name: &str) -> Result<Value, InterpreterError> {
+        let mut scope = Some(&self.scope);
+        while let Some(current) = scope {
+            if let Some(value) = current.variables.borrow().get(name) {
+                return Ok(value.clone());
+            }
+            scope = current.parent.as_ref();
         }
+        Err(InterpreterError::VariableNotFound { name: name.to_string() })
     }
     
-    pub fn define_function(&mut self, func: Function) {
-        self.functions.insert(func.name.clone(), func);
-    }
-    
-    pub fn get_function(&self, name: &str) -> Result<&Function, InterpreterError> {
-        self.functions.get(name)
-            .ok_or_else(|| InterpreterError::FunctionNotFound { name: name.to_string() })
+    /// Assigns to the innermost variable called `name`.
+    pub fn set_variable(&self, name: String, value: Value) -> Result<(), InterpreterError> {
+        let mut scope = Some(&self.scope);
+        while let Some(current) = scope {
+            if let Some(slot) = current.variables.borrow_mut().get_mut(&name) {
+                *slot = value;
+                return Ok(());
+            }
+            scope = current.parent.as_ref();
+        }
+        Err(InterpreterError::VariableNotFound { name })
     }
 }
 
 pub struct Interpreter {
     global_env: Environment,
+    // Declared functions of the program and of the modules it imports
+    functions: HashMap<FunctionKey, Rc<Function>>,
+    // Field names of each declared struct, in declaration order
+    structs: HashMap<String, Vec<String>>,
+    // Imported modules by path, each analyzed and loaded once
+    modules: HashMap<PathBuf, Rc<TypedProgram>>,
+    // What the names in the code running now resolve to, and the module
+    // that code is in
+    symbols: Rc<SymbolIndex>,
+    module: Option<PathBuf>,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         Interpreter {
             global_env: Environment::new(),
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            modules: HashMap::new(),
+            symbols: Rc::default(),
+            module: None,
         }
     }
     
-    pub fn execute_program(&mut self, program: &Program) -> Result<i32, InterpreterError> {
+    pub fn execute_program(&mut self, program: &TypedProgram) -> Result<i32, InterpreterError> {
         self.execute_program_with_path(program, None)
     }
     
-    pub fn execute_program_with_path(&mut self, program: &Program, file_path: Option<&str>) -> Result<i32, InterpreterError> {
+    pub fn execute_program_with_path(&mut self, typed: &TypedProgram, file_path: Option<&str>) -> Result<i32, InterpreterError> {
+        let program = &typed.program;
+        self.symbols = typed.symbols.clone();
+        self.module = None;
+        
         // First pass: handle imports
+        let dir = semantic::module_dir(file_path.map(Path::new));
         for statement in &program.statements {
-            match statement {
-                Statement::Import { module, alias } => {
-                    self.load_module(module, alias.as_deref(), file_path)?;
-                }
-                Statement::ImportFrom { module, items } => {
-                    self.load_module_items(module, items, file_path)?;
-                }
-                _ => {}
-            }
+            self.import(statement, &dir)?;
         }
         
-        // Second pass: collect all function declarations
+        // Second pass: collect all struct and function declarations
         for statement in &program.statements {
+            if let Statement::StructDeclaration { name, fields, .. } = statement {
+                let field_names = fields.iter().map(|field| field.name.clone()).collect();
+                self.structs.insert(name.clone(), field_names);
+            }
             if let Statement::FunctionDeclaration { name, parameters, body, return_type, .. } = statement {
                 let func = Function {
                     name: name.clone(),
                     parameters: parameters.clone(),
                     body: body.clone(),
                     return_type: return_type.clone(),
+                    symbols: typed.symbols.clone(),
+                    module: None,
                 };
-                self.global_env.define_function(func);
+                self.define_function(FunctionKey::Program(name.clone()), func);
             }
         }
         
         // Execute main function if it exists
-        if let Ok(main_func) = self.global_env.get_function("main") {
-            let main_func = main_func.clone();
+        if let Ok(main_func) = self.get_function(&FunctionKey::Program("main".to_string()), "main") {
+            // Globals are initialized before main runs, in declaration order
+            let env = self.global_env.clone();
+            for statement in &program.statements {
+                if let Statement::LetDeclaration { .. } = statement {
+                    self.execute_statement(statement, &env)?;
+                }
+            }
+            
             match self.execute_function(&main_func, &[]) {
                 Ok(value) => Ok(value.to_int().unwrap_or(0) as i32),
                 Err(InterpreterError::ReturnValue(value)) => Ok(value.to_int().unwrap_or(0) as i32),
                 Err(e) => Err(e),
             }
         } else {
-            // Execute statements in order
-            let mut env = self.global_env.clone();
+            // Execute statements in order, directly in the global scope
+            let env = self.global_env.clone();
             for statement in &program.statements {
-                match self.execute_statement(statement, &mut env) {
+                match self.execute_statement(statement, &env) {
                     Ok(_) => {}
                     Err(InterpreterError::ReturnValue(value)) => {
                         return Ok(value.to_int().unwrap_or(0) as i32);
@@ -201,127 +401,284 @@ impl Interpreter {
         }
     }
     
-    fn load_module(&mut self, module_path: &str, alias: Option<&str>, importing_file: Option<&str>) -> Result<(), InterpreterError> {
-        let module_program = self.parse_module(module_path, importing_file)?;
-        let namespace = alias.unwrap_or(module_path);
-        
-        // Load exported functions from the module with qualified names
-        for statement in &module_program.statements {
-            if let Statement::FunctionDeclaration { name, parameters, body, return_type, is_exported } = statement {
-                if *is_exported {
-                    let qualified_name = format!("{}.{}", namespace, name);
-                    let func = Function {
-                        name: qualified_name.clone(),
+    /// Runs one piece of a program on top of the state left by the pieces
+    /// run before it, as the REPL does: its declarations are added to the
+    /// existing ones and its other statements run in the global scope.
+    /// Returns the value of the last statement if it is an expression.
+    pub fn execute_incremental(&mut self, typed: &TypedProgram, file_path: Option<&str>) -> Result<Option<Value>, InterpreterError> {
+        let program = &typed.program;
+        self.symbols = typed.symbols.clone();
+        self.module = None;
+        let dir = semantic::module_dir(file_path.map(Path::new));
+        for statement in &program.statements {
+            match statement {
+                Statement::Import { .. } | Statement::ImportFrom { .. } => self.import(statement, &dir)?,
+                Statement::StructDeclaration { name, fields, .. } => {
+                    let field_names = fields.iter().map(|field| field.name.clone()).collect();
+                    self.structs.insert(name.clone(), field_names);
+                }
+                Statement::FunctionDeclaration { name, parameters, body, return_type, .. } => {
+                    self.define_function(FunctionKey::Program(name.clone()), Function {
+                        name: name.clone(),
                         parameters: parameters.clone(),
                         body: body.clone(),
                         return_type: return_type.clone(),
-                    };
-                    self.global_env.define_function(func);
+                        symbols: typed.symbols.clone(),
+                        module: None,
+                    });
                 }
+                _ => {}
             }
         }
-        
-        // Load exported constants from the module with qualified names
-        for statement in &module_program.statements {
-            if let Statement::LetDeclaration { name, initializer, is_exported } = statement {
-                if *is_exported {
-                    if let Some(init_expr) = initializer {
-                        let mut temp_env = self.global_env.clone();
-                        let value = self.evaluate_expression(init_expr, &mut temp_env)?;
-                        let qualified_name = format!("{}.{}", namespace, name);
-                        self.global_env.define_variable(qualified_name, value);
-                    }
-                }
-            }
+
+        let env = self.global_env.clone();
+        let mut last = None;
+        for statement in &program.statements {
+            last = match statement {
+                Statement::Expression(expr) => Some(self.evaluate_expression(expr, &env)?),
+                statement => match self.execute_statement(statement, &env) {
+                    Ok(()) => None,
+                    Err(InterpreterError::ReturnValue(value)) => return Ok(Some(value)),
+                    Err(e) => return Err(e),
+                },
+            };
         }
-        
-        Ok(())
+        Ok(last)
+    }
+
+    /// Calls a declared function, as `nlang test` does to run each test
+    /// after loading its file with [`Self::execute_incremental`].
+    pub fn call_function(&mut self, name: &str, args: &[Value]) -> Result<Value, InterpreterError> {
+        let func = self.get_function(&FunctionKey::Program(name.to_string()), name)?;
+        self.execute_function(&func, args)
     }
     
-    fn load_module_items(&mut self, module_path: &str, imports: &[(String, Option<String>)], importing_file: Option<&str>) -> Result<(), InterpreterError> {
-        let module_program = self.parse_module(module_path, importing_file)?;
-        
-        for (item_name, alias) in imports {
-            let local_name = alias.as_ref().unwrap_or(item_name);
-            
-            // Look for exported functions
-            for statement in &module_program.statements {
-                if let Statement::FunctionDeclaration { name, parameters, body, return_type, is_exported } = statement {
-                    if name == item_name && *is_exported {
-                        let func = Function {
-                            name: local_name.clone(),
-                            parameters: parameters.clone(),
-                            body: body.clone(),
-                            return_type: return_type.clone(),
-                        };
-                        self.global_env.define_function(func);
-                        break;
-                    }
-                }
-            }
-            
-            // Look for exported constants
-            for statement in &module_program.statements {
-                if let Statement::LetDeclaration { name, initializer, is_exported } = statement {
-                    if name == item_name && *is_exported {
-                        if let Some(init_expr) = initializer {
-                            let mut temp_env = self.global_env.clone();
-                            let value = self.evaluate_expression(init_expr, &mut temp_env)?;
-                            self.global_env.define_variable(local_name.clone(), value);
-                        }
-                        break;
-                    }
-                }
+    fn define_function(&mut self, key: FunctionKey, func: Function) {
+        self.functions.insert(key, Rc::new(func));
+    }
+    
+    fn get_function(&self, key: &FunctionKey, name: &str) -> Result<Rc<Function>, InterpreterError> {
+        self.functions.get(key)
+            .cloned()
+            .ok_or_else(|| InterpreterError::FunctionNotFound { name: name.to_string() })
+    }
+    
+    /// What the name `name` used at `span` refers to in the running code.
+    fn resolve(&self, name: &str, span: Span) -> Result<Resolved, InterpreterError> {
+        let reference = self.symbols.reference(span)
+            .ok_or_else(|| InterpreterError::Unresolved { name: name.to_string() })?;
+        Ok(match self.symbols.definition_of(reference) {
+            None => Resolved::Builtin,
+            Some(definition) if definition.kind == DefinitionKind::Function => {
+                Resolved::Function(match (&definition.module, &self.module) {
+                    (Some(module), _) | (None, Some(module)) => FunctionKey::Module(module.clone(), definition.span),
+                    (None, None) => FunctionKey::Program(definition.name.clone()),
+                })
             }
-        }
+            Some(_) => Resolved::Variable,
+        })
+    }
+    
+    /// Runs `run` as code of `module` whose names resolve through `symbols`.
+    fn running<T>(&mut self, symbols: Rc<SymbolIndex>, module: Option<PathBuf>, run: impl FnOnce(&mut Self) -> T) -> T {
+        let outer_symbols = std::mem::replace(&mut self.symbols, symbols);
+        let outer_module = std::mem::replace(&mut self.module, module);
+        let result = run(self);
+        self.symbols = outer_symbols;
+        self.module = outer_module;
+        result
+    }
+    
+    /// Loads the module an import statement names, resolving it in `dir`,
+    /// and defines the constants it imports under the names the importing
+    /// code uses for them. Other statements are left alone.
+    fn import(&mut self, statement: &Statement, dir: &Path) -> Result<(), InterpreterError> {
+        let (module_name, span) = match statement {
+            Statement::Import { module, span, .. } | Statement::ImportFrom { module, span, .. } => (module, *span),
+            _ => return Ok(()),
+        };
+        let path = semantic::resolve_module_path(dir, module_name);
+        let module = self.load_module(&path).map_err(|e| e.with_span(span))?;
         
+        for declaration in &module.program.statements {
+            let Statement::LetDeclaration { name, initializer: Some(init_expr), is_exported: true, .. } = declaration else {
+                continue;
+            };
+            let local_name = match statement {
+                Statement::Import { alias: Some(alias), .. } => format!("{}.{}", alias, name),
+                Statement::Import { alias: None, .. } => name.clone(),
+                Statement::ImportFrom { items, .. } => match items.iter().find(|(item, _)| item == name) {
+                    Some((item, alias)) => alias.clone().unwrap_or_else(|| item.clone()),
+                    None => continue,
+                },
+                _ => unreachable!(),
+            };
+            let env = self.global_env.clone();
+            let value = self.running(module.symbols.clone(), Some(path.clone()), |this| this.evaluate_expression(init_expr, &env))?;
+            self.global_env.define_variable(local_name, value);
+        }
         Ok(())
     }
     
-    fn parse_module(&self, module_path: &str, importing_file: Option<&str>) -> Result<Program, InterpreterError> {
-        let file_path = if let Some(importing_file) = importing_file {
-            // Resolve relative to the importing file's directory
-            let importing_dir = std::path::Path::new(importing_file)
-                .parent()
-                .unwrap_or(std::path::Path::new("."));
-            importing_dir.join(format!("{}.nlang", module_path))
-                .to_string_lossy()
-                .to_string()
-        } else {
-            format!("{}.nlang", module_path)
-        };
+    /// Analyzes the module at `path` and declares its functions, along with
+    /// those of the modules it imports, unless it was loaded before.
+    fn load_module(&mut self, path: &Path) -> Result<Rc<TypedProgram>, InterpreterError> {
+        if let Some(module) = self.modules.get(path) {
+            return Ok(module.clone());
+        }
         
-        let content = fs::read_to_string(&file_path)
+        let content = fs::read_to_string(path)
             .map_err(|_| InterpreterError::InvalidOperation { 
-                message: format!("Could not read module file: {}", file_path) 
+                message: format!("Could not read module file: {}", path.display()) 
             })?;
         
         let mut lexer = Lexer::new(&content);
         let tokens = lexer.tokenize()
             .map_err(|e| InterpreterError::InvalidOperation { 
-                message: format!("Lexer error in module {}: {:?}", module_path, e) 
+                message: format!("Lexer error in module {}: {:?}", path.display(), e) 
             })?;
         
-        let mut parser = Parser::new(&tokens);
-        let statements = parser.parse_program()
+        let program = parse_strict(&tokens)
             .map_err(|e| InterpreterError::InvalidOperation { 
-                message: format!("Parser error in module {}: {:?}", module_path, e) 
+                message: format!("Parser error in module {}: {}", path.display(), e) 
             })?;
         
-        Ok(Program { statements })
+        let analysis = semantic::check_module(program, Some(path));
+        if let Some(error) = analysis.errors.first() {
+            return Err(InterpreterError::InvalidOperation {
+                message: format!("Semantic error in module {}: {}", path.display(), error.message),
+            });
+        }
+        let module = Rc::new(analysis.into_typed());
+        self.modules.insert(path.to_path_buf(), module.clone());
+        
+        let dir = semantic::module_dir(Some(path));
+        for statement in &module.program.statements {
+            if let Statement::FunctionDeclaration { name, parameters, body, return_type, span, .. } = statement {
+                self.define_function(FunctionKey::Module(path.to_path_buf(), *span), Function {
+                    name: name.clone(),
+                    parameters: parameters.clone(),
+                    body: body.clone(),
+                    return_type: return_type.clone(),
+                    symbols: module.symbols.clone(),
+                    module: Some(path.to_path_buf()),
+                });
+            }
+            self.import(statement, &dir)?;
+        }
+        Ok(module)
+    }
+    
+    /// Evaluates `object`, which must produce a struct instance.
+    fn evaluate_struct(&mut self, object: &Expr, env: &Environment) -> Result<Rc<RefCell<StructValue>>, InterpreterError> {
+        match self.evaluate_expression(object, env)? {
+            Value::Struct(instance) => Ok(instance),
+            other => Err(InterpreterError::TypeMismatch {
+                expected: "struct".to_string(),
+                actual: other.type_name().to_string(),
+            }),
+        }
+    }
+    
+    fn field<'a>(instance: &'a StructValue, name: &str) -> Result<&'a Value, InterpreterError> {
+        instance.fields.iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| InterpreterError::InvalidOperation {
+                message: format!("Struct '{}' has no field named '{}'", instance.name, name),
+            })
+    }
+    
+    fn field_mut<'a>(instance: &'a mut StructValue, name: &str) -> Result<&'a mut Value, InterpreterError> {
+        let struct_name = instance.name.clone();
+        instance.fields.iter_mut()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| InterpreterError::InvalidOperation {
+                message: format!("Struct '{}' has no field named '{}'", struct_name, name),
+            })
+    }
+    
+    /// Finds the first arm whose pattern matches `value` and defines the
+    /// variables it binds.
+    fn matching_arm<'a, B>(arms: &'a [MatchArm<B>], value: &Value, env: &Environment) -> Result<&'a MatchArm<B>, InterpreterError> {
+        for arm in arms {
+            let mut bindings = Vec::new();
+            if Self::pattern_matches(&arm.pattern, value, &mut bindings) {
+                for (name, value) in bindings {
+                    env.define_variable(name, value);
+                }
+                return Ok(arm);
+            }
+        }
+        Err(InterpreterError::InvalidOperation {
+            message: format!("No match arm matches the value {}", value),
+        })
+    }
+    
+    fn pattern_matches(pattern: &Pattern, value: &Value, bindings: &mut Vec<(String, Value)>) -> bool {
+        match pattern {
+            Pattern::Wildcard { .. } => true,
+            Pattern::Binding { name, .. } => {
+                bindings.push((name.clone(), value.clone()));
+                true
+            }
+            Pattern::Literal { value: literal, .. } => match (literal, value) {
+                (Literal::Integer(a), Value::Integer(b)) => a == b,
+                (Literal::Float(a), Value::Float(b)) => a == b,
+                (Literal::Boolean(a), Value::Boolean(b)) => a == b,
+                (Literal::String(a), Value::String(b)) => a == b,
+                _ => false,
+            },
+            Pattern::Variant { enum_name, variant, fields, .. } => match value {
+                Value::Enum(value) => {
+                    value.enum_name == *enum_name
+                        && value.variant == *variant
+                        && fields.len() == value.fields.len()
+                        && fields.iter().zip(&value.fields).all(|(field, value)| Self::pattern_matches(field, value, bindings))
+                }
+                _ => false,
+            },
+        }
     }
     
     fn execute_function(&mut self, func: &Function, args: &[Value]) -> Result<Value, InterpreterError> {
-        let mut local_env = self.global_env.clone();
+        let parent = self.global_env.clone();
+        self.execute_function_in(func, args, &parent)
+    }
+    
+    /// Calls a function value, running a lambda in the environment it captured.
+    fn execute_closure(&mut self, closure: &Closure, args: &[Value]) -> Result<Value, InterpreterError> {
+        if args.len() != closure.function.parameters.len() {
+            return Err(InterpreterError::InvalidOperation {
+                message: format!(
+                    "Function '{}' expects {} arguments, but {} were provided",
+                    closure.function.name,
+                    closure.function.parameters.len(),
+                    args.len()
+                ),
+            });
+        }
+        let parent = closure.captured.clone().unwrap_or_else(|| self.global_env.clone());
+        self.execute_function_in(&closure.function, args, &parent)
+    }
+    
+    /// Runs `func` in a new scope inside `parent`, which holds the variables
+    /// the function body can see besides its parameters.
+    fn execute_function_in(&mut self, func: &Function, args: &[Value], parent: &Environment) -> Result<Value, InterpreterError> {
+        let local_env = parent.child();
         
         // Bind parameters
         for (param, arg) in func.parameters.iter().zip(args.iter()) {
             local_env.define_variable(param.name.clone(), arg.clone());
         }
         
-        // Execute function body
-        for statement in &func.body {
-            match self.execute_statement(statement, &mut local_env) {
+        self.running(func.symbols.clone(), func.module.clone(), |this| this.execute_body(&func.body, &local_env))
+    }
+    
+    fn execute_body(&mut self, body: &[Statement], env: &Environment) -> Result<Value, InterpreterError> {
+        for statement in body {
+            match self.execute_statement(statement, env) {
                 Ok(_) => {}
                 Err(InterpreterError::ReturnValue(value)) => return Ok(value),
                 Err(e) => return Err(e),
@@ -332,7 +689,7 @@ impl Interpreter {
         Ok(Value::Integer(0))
     }
     
-    fn execute_statement(&mut self, stmt: &Statement, env: &mut Environment) -> Result<(), InterpreterError> {
+    fn execute_statement(&mut self, stmt: &Statement, env: &Environment) -> Result<(), InterpreterError> {
         match stmt {
             Statement::LetDeclaration { name, initializer, .. } => {
                 if let Some(init_expr) = initializer {
@@ -344,7 +701,7 @@ impl Interpreter {
                 }
                 Ok(())
             }
-            Statement::Return { value } => {
+            Statement::Return { value, .. } => {
                 if let Some(ret_expr) = value {
                     let val = self.evaluate_expression(ret_expr, env)?;
                     Err(InterpreterError::ReturnValue(val))
@@ -352,7 +709,7 @@ impl Interpreter {
                     Err(InterpreterError::ReturnValue(Value::Integer(0)))
                 }
             }
-            Statement::If { condition, then_branch, else_branch } => {
+            Statement::If { condition, then_branch, else_branch, .. } => {
                 let cond_val = self.evaluate_expression(condition, env)?;
                 if cond_val.to_bool()? {
                     self.execute_statement(then_branch, env)?;
@@ -361,7 +718,7 @@ impl Interpreter {
                 }
                 Ok(())
             }
-            Statement::While { condition, body } => {
+            Statement::While { condition, body, .. } => {
                 loop {
                     let cond_val = self.evaluate_expression(condition, env)?;
                     if !cond_val.to_bool()? {
@@ -376,6 +733,39 @@ impl Interpreter {
                 }
                 Ok(())
             }
+            Statement::For { variable, iterable, body, .. } => {
+                if let Expr::Range { start, end, .. } = iterable.as_ref() {
+                    // Bounds are evaluated once, before the first iteration
+                    let start = self.evaluate_expression(start, env)?.to_int()?;
+                    let end = self.evaluate_expression(end, env)?.to_int()?;
+                    for i in start..end {
+                        // Each iteration gets its own loop variable, so
+                        // closures created in the body keep the value they saw
+                        let iteration_env = env.child();
+                        iteration_env.define_variable(variable.clone(), Value::Integer(i));
+                        if !self.execute_loop_body(body, &iteration_env)? {
+                            break;
+                        }
+                    }
+                    return Ok(());
+                }
+                
+                let elements = match self.evaluate_expression(iterable, env)? {
+                    // Iterate over a snapshot so the body can modify the array
+                    Value::Array(elements) => elements.borrow().clone(),
+                    other => return Err(InterpreterError::InvalidOperation {
+                        message: format!("Cannot iterate over a value of type {}", other.type_name()),
+                    }.with_span(iterable.span())),
+                };
+                for element in elements {
+                    let iteration_env = env.child();
+                    iteration_env.define_variable(variable.clone(), element);
+                    if !self.execute_loop_body(body, &iteration_env)? {
+                        break;
+                    }
+                }
+                Ok(())
+            }
             Statement::FunctionDeclaration { .. } => {
                 // Already handled in first pass
                 Ok(())
@@ -384,9 +774,11 @@ impl Interpreter {
                 self.evaluate_expression(expr, env)?;
                 Ok(())
             }
-            Statement::Block { statements } => {
+            Statement::Block { statements, .. } => {
+                // Variables declared in a block are only visible inside it
+                let block_env = env.child();
                 for stmt in statements {
-                    match self.execute_statement(stmt, env) {
+                    match self.execute_statement(stmt, &block_env) {
                         Ok(()) => {},
                         Err(InterpreterError::Break) => return Err(InterpreterError::Break),
                         Err(InterpreterError::Continue) => return Err(InterpreterError::Continue),
@@ -395,10 +787,16 @@ impl Interpreter {
                 }
                 Ok(())
             }
-            Statement::Break => {
+            Statement::Match { scrutinee, arms, span } => {
+                let value = self.evaluate_expression(scrutinee, env)?;
+                let arm_env = env.child();
+                let arm = Self::matching_arm(arms, &value, &arm_env).map_err(|e| e.with_span(*span))?;
+                self.execute_statement(&arm.body, &arm_env)
+            }
+            Statement::Break { .. } => {
                 Err(InterpreterError::Break)
             }
-            Statement::Continue => {
+            Statement::Continue { .. } => {
                 Err(InterpreterError::Continue)
             }
             _ => {
@@ -408,9 +806,43 @@ impl Interpreter {
         }
     }
     
-    fn evaluate_expression(&mut self, expr: &Expr, env: &mut Environment) -> Result<Value, InterpreterError> {
+    /// Runs one iteration of a loop body, returning `false` once it breaks
+    /// out of the loop.
+    fn execute_loop_body(&mut self, body: &Statement, env: &Environment) -> Result<bool, InterpreterError> {
+        match self.execute_statement(body, env) {
+            Ok(()) | Err(InterpreterError::Continue) => Ok(true),
+            Err(InterpreterError::Break) => Ok(false),
+            Err(other) => Err(other),
+        }
+    }
+    
+    /// Evaluates `sequence[index]` down to the array and a bounds-checked
+    /// position in it.
+    fn evaluate_element(&mut self, sequence: &Expr, index: &Expr, env: &Environment) -> Result<(Array, usize), InterpreterError> {
+        let elements = match self.evaluate_expression(sequence, env)? {
+            Value::Array(elements) => elements,
+            other => return Err(InterpreterError::TypeMismatch {
+                expected: "array".to_string(),
+                actual: other.type_name().to_string(),
+            }.with_span(sequence.span())),
+        };
+        let index = self.evaluate_expression(index, env)?.to_int()?;
+        
+        let length = elements.borrow().len();
+        match usize::try_from(index) {
+            Ok(position) if position < length => Ok((elements, position)),
+            _ => Err(InterpreterError::IndexOutOfBounds { index, length }),
+        }
+    }
+    
+    fn evaluate_expression(&mut self, expr: &Expr, env: &Environment) -> Result<Value, InterpreterError> {
+        self.evaluate_expression_kind(expr, env)
+            .map_err(|e| e.with_span(expr.span()))
+    }
+    
+    fn evaluate_expression_kind(&mut self, expr: &Expr, env: &Environment) -> Result<Value, InterpreterError> {
         match expr {
-            Expr::Literal(literal) => {
+            Expr::Literal { value: literal, .. } => {
                 match literal {
                     Literal::Integer(i) => Ok(Value::Integer(*i)),
                     Literal::Float(f) => Ok(Value::Float(*f)),
@@ -419,36 +851,65 @@ impl Interpreter {
                     Literal::Null => Ok(Value::Integer(0)), // Default null to 0
                 }
             }
-            Expr::Variable(name) => {
-                env.get_variable(name)
+            Expr::Variable { name, span } => match self.resolve(name, *span)? {
+                // A declared function used as a value
+                Resolved::Function(key) => Ok(Value::Function(Rc::new(Closure {
+                    function: self.get_function(&key, name)?,
+                    captured: None,
+                }))),
+                Resolved::Builtin | Resolved::Variable => env.get_variable(name),
+            },
+            Expr::Function { parameters, body, return_type, .. } => {
+                Ok(Value::Function(Rc::new(Closure {
+                    function: Rc::new(Function {
+                        name: "lambda".to_string(),
+                        parameters: parameters.clone(),
+                        body: body.clone(),
+                        return_type: return_type.clone(),
+                        symbols: self.symbols.clone(),
+                        module: self.module.clone(),
+                    }),
+                    captured: Some(env.clone()),
+                })))
             }
-            Expr::Binary { left, operator, right } => {
+            Expr::Binary { left, operator, right, .. } => {
                 let left_val = self.evaluate_expression(left, env)?;
                 let right_val = self.evaluate_expression(right, env)?;
                 self.evaluate_binary_op(&left_val, operator, &right_val)
             }
-            Expr::Unary { operator, operand } => {
+            Expr::Unary { operator, operand, .. } => {
                 let val = self.evaluate_expression(operand, env)?;
                 self.evaluate_unary_op(operator, &val)
             }
-            Expr::Call { callee, arguments } => {
-                // Handle different types of function calls
-                let func_name = match callee.as_ref() {
-                    Expr::Variable(name) => name.clone(),
-                    Expr::Get { object, name } => {
-                        // Handle module-qualified function calls (e.g., math.add())
-                        if let Expr::Variable(namespace_name) = object.as_ref() {
-                            format!("{}.{}", namespace_name, name)
-                        } else {
-                            return Err(InterpreterError::InvalidOperation {
-                                message: "Complex function calls not yet supported".to_string(),
-                            });
+            Expr::Call { callee, arguments, .. } => {
+                let resolved = match callee.as_ref() {
+                    Expr::Variable { name, span } => self.resolve(name, *span)?,
+                    _ => Resolved::Variable,
+                };
+                let func_name = match (resolved, callee.as_ref()) {
+                    (Resolved::Builtin, Expr::Variable { name, .. }) => name.clone(),
+                    (Resolved::Function(key), Expr::Variable { name, .. }) => {
+                        let func = self.get_function(&key, name)?;
+                        let mut args = Vec::with_capacity(arguments.len());
+                        for arg_expr in arguments {
+                            args.push(self.evaluate_expression(arg_expr, env)?);
                         }
-                    },
+                        return self.execute_function(&func, &args);
+                    }
+                    // Anything else evaluates to the function to call
                     _ => {
-                        return Err(InterpreterError::InvalidOperation {
-                            message: "Complex function calls not yet supported".to_string(),
-                        });
+                        let closure = match self.evaluate_expression(callee, env)? {
+                            Value::Function(closure) => closure,
+                            other => return Err(InterpreterError::TypeMismatch {
+                                expected: "function".to_string(),
+                                actual: other.type_name().to_string(),
+                            }.with_span(callee.span())),
+                        };
+                        let mut args = Vec::with_capacity(arguments.len());
+                        for arg_expr in arguments {
+                            args.push(self.evaluate_expression(arg_expr, env)?);
+                        }
+                        return self.execute_closure(&closure, &args);
                     }
                 };
 
@@ -461,12 +922,7 @@ impl Interpreter {
                                 });
                             }
                             let arg = self.evaluate_expression(&arguments[0], env)?;
-                            match arg {
-                                Value::String(s) => print!("{}", s),
-                                Value::Integer(i) => print!("{}", i),
-                                Value::Float(f) => print!("{}", f),
-                                Value::Boolean(b) => print!("{}", b),
-                            }
+                            print!("{}", arg);
                             use std::io::{self, Write};
                             io::stdout().flush().unwrap();
                             Ok(Value::Integer(0)) // Return null/void equivalent
@@ -478,12 +934,7 @@ impl Interpreter {
                                 });
                             }
                             let arg = self.evaluate_expression(&arguments[0], env)?;
-                            match arg {
-                                Value::String(s) => println!("{}", s),
-                                Value::Integer(i) => println!("{}", i),
-                                Value::Float(f) => println!("{}", f),
-                                Value::Boolean(b) => println!("{}", b),
-                            }
+                            println!("{}", arg);
                             Ok(Value::Integer(0)) // Return null/void equivalent
                         }
                         "add" => {
@@ -518,6 +969,42 @@ impl Interpreter {
                                 Value::Float(f) => Ok(Value::String(f.to_string())),
                                 Value::Boolean(b) => Ok(Value::String(b.to_string())),
                                 Value::String(s) => Ok(Value::String(s)), // Already a string
+                                value @ (Value::Array(_) | Value::Struct(_) | Value::Enum(_) | Value::Function(_)) => Ok(Value::String(value.to_string())),
+                            }
+                        }
+                        "len" => {
+                            if arguments.len() != 1 {
+                                return Err(InterpreterError::InvalidOperation {
+                                    message: "len function requires 1 argument".to_string(),
+                                });
+                            }
+                            let arg = self.evaluate_expression(&arguments[0], env)?;
+                            match arg {
+                                Value::String(s) => Ok(Value::Integer(s.len() as i64)),
+                                Value::Array(elements) => Ok(Value::Integer(elements.borrow().len() as i64)),
+                                _ => Err(InterpreterError::TypeMismatch {
+                                    expected: "string or array".to_string(),
+                                    actual: arg.type_name().to_string(),
+                                }),
+                            }
+                        }
+                        "push" => {
+                            if arguments.len() != 2 {
+                                return Err(InterpreterError::InvalidOperation {
+                                    message: "push function requires 2 arguments".to_string(),
+                                });
+                            }
+                            let array = self.evaluate_expression(&arguments[0], env)?;
+                            let value = self.evaluate_expression(&arguments[1], env)?;
+                            match array {
+                                Value::Array(elements) => {
+                                    elements.borrow_mut().push(value);
+                                    Ok(Value::Integer(0)) // Return null/void equivalent
+                                }
+                                _ => Err(InterpreterError::TypeMismatch {
+                                    expected: "array".to_string(),
+                                    actual: array.type_name().to_string(),
+                                }),
                             }
                         }
                         "int" => {
@@ -539,6 +1026,10 @@ impl Interpreter {
                                         }),
                                     }
                                 }
+                                Value::Array(_) | Value::Struct(_) | Value::Enum(_) | Value::Function(_) => Err(InterpreterError::TypeMismatch {
+                                    expected: "int".to_string(),
+                                    actual: arg.type_name().to_string(),
+                                }),
                             }
                         }
                         "float" => {
@@ -560,6 +1051,10 @@ impl Interpreter {
                                         }),
                                     }
                                 }
+                                Value::Array(_) | Value::Struct(_) | Value::Enum(_) | Value::Function(_) => Err(InterpreterError::TypeMismatch {
+                                    expected: "float".to_string(),
+                                    actual: arg.type_name().to_string(),
+                                }),
                             }
                         }
                         "abs" => {
@@ -577,6 +1072,31 @@ impl Interpreter {
                                 }),
                             }
                         }
+                        "assert" => {
+                            if arguments.len() != 1 {
+                                return Err(InterpreterError::InvalidOperation {
+                                    message: "assert function requires 1 argument".to_string(),
+                                });
+                            }
+                            if !self.evaluate_expression(&arguments[0], env)?.to_bool()? {
+                                return Err(InterpreterError::AssertionFailed { message: "condition is false".to_string() });
+                            }
+                            Ok(Value::Integer(0)) // Return null/void equivalent
+                        }
+                        "assert_eq" | "assert_ne" => {
+                            if arguments.len() != 2 {
+                                return Err(InterpreterError::InvalidOperation {
+                                    message: format!("{} function requires 2 arguments", func_name),
+                                });
+                            }
+                            let left = self.evaluate_expression(&arguments[0], env)?;
+                            let right = self.evaluate_expression(&arguments[1], env)?;
+                            let expect_equal = func_name == "assert_eq";
+                            if (left == right) != expect_equal {
+                                return Err(InterpreterError::failed_comparison(expect_equal, &left.repr(), &right.repr()));
+                            }
+                            Ok(Value::Integer(0)) // Return null/void equivalent
+                        }
                         "abs_float" => {
                             if arguments.len() != 1 {
                                 return Err(InterpreterError::InvalidOperation {
@@ -593,61 +1113,87 @@ impl Interpreter {
                                 }),
                             }
                         }
-                        _ => {
-                            // User-defined function
-                            
-                            // First try to find the function as-is
-                            if let Ok(func) = env.get_function(&func_name) {
-                                let func = func.clone();
-                                let mut args = Vec::new();
-                                for arg_expr in arguments {
-                                    args.push(self.evaluate_expression(arg_expr, env)?);
-                                }
-                                return self.execute_function(&func, &args);
-                            }
-                            
-                            // If not found, try to find it in the math namespace (for recursive calls)
-                            let qualified_name = format!("math.{}", func_name);
-                            if let Ok(func) = env.get_function(&qualified_name) {
-                                let func = func.clone();
-                                let mut args = Vec::new();
-                                for arg_expr in arguments {
-                                    args.push(self.evaluate_expression(arg_expr, env)?);
-                                }
-                                return self.execute_function(&func, &args);
-                            }
-                            
-                            // If still not found, return error
-                            Err(InterpreterError::FunctionNotFound { name: func_name })
-                        }
+                        _ => Err(InterpreterError::FunctionNotFound { name: func_name }),
                     }
             }
-            Expr::Get { object, name } => {
-                // Handle namespace access like math.PI
-                if let Expr::Variable(obj_name) = object.as_ref() {
-                    if obj_name == "math" {
-                        match name.as_str() {
-                            "PI" => Ok(Value::Float(std::f64::consts::PI)),
-                            _ => Err(InterpreterError::InvalidOperation {
-                                message: format!("Unknown math property: {}", name),
-                            }),
-                        }
-                    } else {
-                        Err(InterpreterError::InvalidOperation {
-                            message: format!("Unknown namespace: {}", obj_name),
-                        })
-                    }
-                } else {
-                    Err(InterpreterError::InvalidOperation {
-                        message: "Complex object access not yet supported".to_string(),
-                    })
+            Expr::Get { object, name, .. } => {
+                // Names in modules were resolved to variables by analysis,
+                // so this is a struct field
+                let instance = self.evaluate_struct(object, env)?;
+                let instance = instance.borrow();
+                Self::field(&instance, name).cloned()
+            }
+            Expr::Set { object, name, value, .. } => {
+                let instance = self.evaluate_struct(object, env)?;
+                let value = self.evaluate_expression(value, env)?;
+                let mut instance = instance.borrow_mut();
+                *Self::field_mut(&mut instance, name)? = value.clone();
+                Ok(value)
+            }
+            Expr::StructLiteral { name, fields, .. } => {
+                // Fields are evaluated in the order they are written, then
+                // stored in declaration order
+                let mut values = Vec::with_capacity(fields.len());
+                for (field_name, value) in fields {
+                    values.push((field_name.clone(), self.evaluate_expression(value, env)?));
+                }
+                if let Some(declared) = self.structs.get(name) {
+                    values.sort_by_key(|(field_name, _)| declared.iter().position(|declared| declared == field_name));
                 }
+                Ok(Value::structure(name.clone(), values))
             }
-            Expr::Assign { name, value } => {
+            Expr::EnumVariant { enum_name, variant, arguments, .. } => {
+                let mut fields = Vec::with_capacity(arguments.len());
+                for argument in arguments {
+                    fields.push(self.evaluate_expression(argument, env)?);
+                }
+                Ok(Value::enumeration(enum_name.clone(), variant.clone(), fields))
+            }
+            Expr::Match { scrutinee, arms, span } => {
+                let value = self.evaluate_expression(scrutinee, env)?;
+                let arm_env = env.child();
+                let arm = Self::matching_arm(arms, &value, &arm_env).map_err(|e| e.with_span(*span))?;
+                self.evaluate_expression(&arm.body, &arm_env)
+            }
+            Expr::Assign { name, value, .. } => {
                 let val = self.evaluate_expression(value, env)?;
                 env.set_variable(name.clone(), val.clone())?;
                 Ok(val)
             }
+            Expr::Array { elements, .. } => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.evaluate_expression(element, env)?);
+                }
+                Ok(Value::array(values))
+            }
+            Expr::Index { sequence, index, .. } => {
+                let (elements, index) = self.evaluate_element(sequence, index, env)?;
+                let value = elements.borrow()[index].clone();
+                Ok(value)
+            }
+            Expr::SetIndex { sequence, index, value, .. } => {
+                let (elements, index) = self.evaluate_element(sequence, index, env)?;
+                let value = self.evaluate_expression(value, env)?;
+                elements.borrow_mut()[index] = value.clone();
+                Ok(value)
+            }
+            Expr::FormatString { parts, .. } => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        FormatPart::Literal(literal) => text.push_str(literal),
+                        FormatPart::Expr { expr, spec } => {
+                            let written = match (self.evaluate_expression(expr, env)?, spec.precision) {
+                                (Value::Float(f), Some(precision)) => format!("{:.*}", precision, f),
+                                (value, _) => value.to_string(),
+                            };
+                            text.push_str(&spec.pad(&written));
+                        }
+                    }
+                }
+                Ok(Value::String(text))
+            }
             _ => {
                 Err(InterpreterError::InvalidOperation {
                     message: "Expression type not yet supported".to_string(),
@@ -747,10 +1293,10 @@ impl Interpreter {
                 }
             }
             BinaryOperator::EqualEqual => {
-                Ok(Value::Boolean(left == right))
+                Ok(Value::Boolean(left.equals(right)))
             }
             BinaryOperator::NotEqual => {
-                Ok(Value::Boolean(left != right))
+                Ok(Value::Boolean(!left.equals(right)))
             }
             BinaryOperator::Less => {
                 match (left, right) {
@@ -839,6 +1385,14 @@ mod tests {
     use super::*;
     use crate::ast::*;
     
+    /// Analyzes a program that may run statements outside of a `main`
+    /// function, as the REPL does.
+    fn analyzed(program: Program) -> TypedProgram {
+        let analysis = crate::semantic::check_module(program, None);
+        assert!(analysis.errors.is_empty(), "{:?}", analysis.errors);
+        analysis.into_typed()
+    }
+    
     #[test]
     fn test_simple_arithmetic() {
         let mut interpreter = Interpreter::new();
@@ -846,17 +1400,232 @@ mod tests {
             statements: vec![
                 Statement::LetDeclaration {
                     name: "x".to_string(),
-                    initializer: Some(Expr::Literal(Literal::Integer(5))),
+                    var_type: None,
+                    initializer: Some(Expr::Literal { value: Literal::Integer(5), span: Span::default() }),
                     is_exported: false,
+                    doc: None,
+                    span: Span::default(),
                 },
                 Statement::Return {
-                    value: Some(Box::new(Expr::Variable("x".to_string()))),
+                    value: Some(Box::new(Expr::Variable { name: "x".to_string(), span: Span::default() })),
+                    span: Span::default(),
                 },
             ],
         };
         
-        let result = interpreter.execute_program(&program);
+        let result = interpreter.execute_program(&analyzed(program));
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), 5);
     }
+    
+    #[test]
+    fn test_for_loop_with_break_and_continue() {
+        let source = "
+            store total = 0;
+            for (i in 0..10) {
+                if (i == 2) { continue; }
+                if (i == 5) { break; }
+                total = total + i;
+            }
+            return total;
+        ";
+        let tokens = crate::lexer::tokenize(source).unwrap();
+        let program = crate::parser::parse_strict(&tokens).unwrap();
+        
+        // 0 + 1 + 3 + 4
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.execute_program(&analyzed(program)).unwrap(), 8);
+    }
+    
+    #[test]
+    fn test_arrays() {
+        let source = "
+            store xs = [1, 2];
+            store alias = xs;
+            push(alias, 3);
+            xs[0] = 10;
+            store total = 0;
+            for (x in xs) {
+                total = total + x;
+            }
+            return total * 10 + len(xs);
+        ";
+        let tokens = crate::lexer::tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        
+        // Arrays are shared, so the push through `alias` is seen through `xs`
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.execute_program(&analyzed(program)).unwrap(), 153);
+    }
+    
+    #[test]
+    fn test_structs() {
+        let source = "
+            struct Point { x: int, y: int }
+            struct Line { start: Point, end: Point }
+            store start = Point { y: 2, x: 1 };
+            store line = Line { start: start, end: Point { x: 4, y: 6 } };
+            start.x = 3;
+            line.end.y = line.end.y + 1;
+            return line.start.x * 100 + line.end.x * 10 + line.end.y;
+        ";
+        let tokens = crate::lexer::tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        
+        // Structs are shared, so the write through `start` is seen through `line`
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.execute_program(&analyzed(program)).unwrap(), 347);
+        
+        let point = Value::structure("Point".to_string(), vec![
+            ("x".to_string(), Value::Integer(1)),
+            ("tags".to_string(), Value::array(vec![Value::String("a".to_string())])),
+        ]);
+        assert_eq!(point.to_string(), "Point { x: 1, tags: [a] }");
+    }
+    
+    #[test]
+    fn test_enums_and_match() {
+        let source = "
+            enum Shape { Square(int), Rect(int, int), Empty }
+            store shapes = [Shape::Square(3), Shape::Rect(2, 5), Shape::Empty, Shape::Square(0)];
+            store total = 0;
+            for (shape in shapes) {
+                total = total + match (shape) {
+                    Shape::Square(0) => 1000,
+                    Shape::Square(side) => side * side,
+                    Shape::Rect(w, h) => w * h,
+                    Shape::Empty => 0,
+                };
+            }
+            match (shapes[1] == Shape::Rect(2, 5)) {
+                true => total = total * 2,
+                false => {}
+            }
+            return total;
+        ";
+        let tokens = crate::lexer::tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.execute_program(&analyzed(program)).unwrap(), 2038);
+        
+        let shape = Value::enumeration("Shape".to_string(), "Rect".to_string(), vec![Value::Integer(2), Value::Integer(5)]);
+        assert_eq!(shape.to_string(), "Shape::Rect(2, 5)");
+    }
+    
+    #[test]
+    fn test_closures() {
+        let source = "
+            def make_counter(start: int): def(): int {
+                store counts = [start];
+                return def() {
+                    counts[0] = counts[0] + 1;
+                    return counts[0];
+                };
+            }
+            def twice(f: def(int): int, x: int): int {
+                return f(f(x));
+            }
+            def triple(x: int): int {
+                return x * 3;
+            }
+            def main(): int {
+                store counter = make_counter(10);
+                counter();
+                store offset = 5;
+                store shift = def(x: int) => x + offset;
+                return counter() * 100 + twice(shift, 0) + twice(triple, 1);
+            }
+        ";
+        let tokens = crate::lexer::tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.execute_program(&analyzed(program)).unwrap(), 1219);
+    }
+    
+    #[test]
+    fn test_lexical_scopes() {
+        let source = "
+            store scale = 10;
+            def make_counter(): def(): int {
+                store count = 0;
+                return def() {
+                    count = count + 1;
+                    return count;
+                };
+            }
+            def main(): int {
+                store x = 1;
+                if (true) {
+                    store x = 2;
+                    x = x + 1;
+                }
+                store getters: array[def(): int] = [];
+                for (i in 0..3) {
+                    push(getters, def() => i);
+                }
+                store counter = make_counter();
+                counter();
+                counter();
+                return x * scale + getters[1]() * 100 + counter() * 1000;
+            }
+        ";
+        let tokens = crate::lexer::tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.execute_program(&analyzed(program)).unwrap(), 3110);
+    }
+    
+    #[test]
+    fn test_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("twice.nlang"), "export def twice(x: int): int {\n    return x * 2;\n}\n").unwrap();
+        std::fs::write(dir.path().join("shapes.nlang"), "\
+import twice;
+export store UNIT = 3;
+def helper(x: int): int {
+    return x * 10;
+}
+export def scaled(x: int): int {
+    return helper(x) + twice(x);
+}
+").unwrap();
+        let main = dir.path().join("main.nlang");
+        let source = "
+            import shapes;
+            import shapes as s;
+            from shapes import scaled as sc;
+            def main(): int {
+                store f = s.scaled;
+                return scaled(1) * 1000 + sc(2) * 10 + f(UNIT) - s.UNIT;
+            }
+        ";
+        let tokens = crate::lexer::tokenize(source).unwrap();
+        let program = crate::semantic::check_module(parse_strict(&tokens).unwrap(), Some(&main)).into_typed();
+        
+        // Calls in a module reach its private functions and the modules it imports
+        let result = Interpreter::new().execute_program_with_path(&program, main.to_str());
+        assert_eq!(result.unwrap(), 12000 + 240 + 36 - 3);
+    }
+    
+    #[test]
+    fn test_unresolved_name() {
+        let tokens = crate::lexer::tokenize("store x = 1;\nreturn x;").unwrap();
+        let program = TypedProgram { program: parse_strict(&tokens).unwrap(), types: Default::default(), symbols: Default::default() };
+        
+        let error = Interpreter::new().execute_program(&program).unwrap_err();
+        assert_eq!(error.to_string(), "Internal error: analysis did not resolve 'x' (line 2, column 8)");
+    }
+    
+    #[test]
+    fn test_index_out_of_bounds() {
+        let tokens = crate::lexer::tokenize("store xs = [1, 2];\nreturn xs[2];").unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        
+        let error = Interpreter::new().execute_program(&analyzed(program)).unwrap_err();
+        assert_eq!(error.span().map(|span| (span.line, span.column)), Some((2, 8)));
+        assert_eq!(error.to_string(), "Index out of bounds: the length is 2 but the index is 2 (line 2, column 8)");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,100 @@
+//! What values mean, for both the interpreter and the VM
+//!
+//! The two represent values differently: the VM resolves struct fields to
+//! layouts and keeps captured variables in cells. But `run` and `run --vm`
+//! must agree on what a value means, so both implement [`RuntimeValue`],
+//! which defines comparison, conversion and printing in terms of the few
+//! things each representation can tell about its values.
+
+use super::InterpreterError;
+use std::fmt;
+
+/// A value that is a number, a bool or a string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scalar<'a> {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(&'a str),
+}
+
+pub trait RuntimeValue: PartialEq + fmt::Display {
+    /// The name of the value's type, as runtime errors show it.
+    fn type_name(&self) -> &'static str;
+
+    /// The value if it is a scalar; `None` for arrays, structs, enums and
+    /// functions.
+    fn scalar(&self) -> Option<Scalar<'_>>;
+
+    /// What `==` means: like the compiled backends, an int and a float are
+    /// compared as floats. Other values are equal when [`PartialEq`] says
+    /// so, which compares arrays, structs and enums by their contents and
+    /// functions by identity.
+    fn equals(&self, other: &Self) -> bool {
+        match (self.scalar(), other.scalar()) {
+            (Some(Scalar::Integer(a)), Some(Scalar::Float(b))) | (Some(Scalar::Float(b)), Some(Scalar::Integer(a))) => {
+                a as f64 == b
+            }
+            _ => self == other,
+        }
+    }
+
+    /// The value as it would be written in source, so strings are quoted.
+    fn repr(&self) -> String {
+        match self.scalar() {
+            Some(Scalar::String(s)) => format!("{:?}", s),
+            _ => self.to_string(),
+        }
+    }
+
+    fn to_int(&self) -> Result<i64, InterpreterError> {
+        match self.scalar() {
+            Some(Scalar::Integer(i)) => Ok(i),
+            Some(Scalar::Float(f)) => Ok(f as i64),
+            Some(Scalar::Boolean(b)) => Ok(if b { 1 } else { 0 }),
+            _ => Err(InterpreterError::TypeMismatch {
+                expected: "int".to_string(),
+                actual: self.type_name().to_string(),
+            }),
+        }
+    }
+
+    fn to_float(&self) -> Result<f64, InterpreterError> {
+        match self.scalar() {
+            Some(Scalar::Integer(i)) => Ok(i as f64),
+            Some(Scalar::Float(f)) => Ok(f),
+            _ => Err(InterpreterError::TypeMismatch {
+                expected: "float".to_string(),
+                actual: self.type_name().to_string(),
+            }),
+        }
+    }
+
+    fn to_bool(&self) -> Result<bool, InterpreterError> {
+        match self.scalar() {
+            Some(Scalar::Boolean(b)) => Ok(b),
+            Some(Scalar::Integer(i)) => Ok(i != 0),
+            Some(Scalar::Float(f)) => Ok(f != 0.0),
+            _ => Err(InterpreterError::TypeMismatch {
+                expected: "bool".to_string(),
+                actual: self.type_name().to_string(),
+            }),
+        }
+    }
+}
+
+/// Writes `items` separated by commas, as the elements of an array and the
+/// fields of a struct or enum value are printed.
+pub fn write_separated<T>(
+    f: &mut fmt::Formatter<'_>,
+    items: impl IntoIterator<Item = T>,
+    mut write: impl FnMut(&mut fmt::Formatter<'_>, T) -> fmt::Result,
+) -> fmt::Result {
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write(f, item)?;
+    }
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use std::fmt;
+use crate::span::Span;
 
 #[cfg(test)]
 mod tests;
@@ -8,10 +9,14 @@ pub enum TokenType {
     // Keywords
     Store,
     Def,
+    Struct,
+    Enum,
+    Match,
     If,
     Else,
     While,
     For,
+    In,
     Return,
     Break,
     Continue,
@@ -27,7 +32,12 @@ pub enum TokenType {
     // Identifiers and literals
     Identifier(String),
     String(String),
+    /// An `f"..."` string, split into its text and interpolated expressions.
+    FormatString(Vec<FormatStringPart>),
     Integer(i64),
+    /// `9223372036854775808` right after a negating `-`. It is no int on its
+    /// own; the parser folds it with the minus into the smallest int.
+    IntMinMagnitude,
     Float(f64),
     
     // Operators
@@ -55,22 +65,44 @@ pub enum TokenType {
     LeftBracket,
     RightBracket,
     Colon,
+    ColonColon,
     Semicolon,
     Comma,
     Dot,
+    DotDot,
+    FatArrow,
     
     // Assignment
     Assign,
     
     // Other
+    /// A `//` comment, holding the text after the slashes. Only produced by
+    /// [`tokenize_with_comments`].
+    Comment(String),
+    /// A `/* */` comment, holding the text between its delimiters. Only
+    /// produced by [`tokenize_with_comments`].
+    BlockComment(String),
     Eof,
 }
 
-#[derive(Debug, Clone)]
+/// A piece of an `f"..."` string.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatStringPart {
+    /// Text, with escapes and doubled braces decoded.
+    Literal(String),
+    /// The tokens of a `{...}`, ending with `Eof`, and the text and span of
+    /// the format spec after its colon, if any.
+    Expr { tokens: Vec<Token>, spec: Option<(String, Span)> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
-    pub line: usize,
+    pub span: Span,
+    /// The `///` doc comment lines written before this token, without their
+    /// slashes and joined by newlines.
+    pub doc: Option<String>,
 }
 
 impl fmt::Display for Token {
@@ -82,12 +114,12 @@ impl fmt::Display for Token {
 #[derive(Debug)]
 pub struct LexerError {
     pub message: String,
-    pub line: usize,
+    pub span: Span,
 }
 
 impl fmt::Display for LexerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Lexer error on line {}: {}", self.line, self.message)
+        write!(f, "Lexer error on line {}, column {}: {}", self.span.line, self.span.column, self.message)
     }
 }
 
@@ -98,12 +130,28 @@ pub fn tokenize(source: &str) -> Result<Vec<Token>, LexerError> {
     lexer.tokenize()
 }
 
+/// Like [`tokenize`], but keeps comments as [`TokenType::Comment`] tokens for
+/// tools that need to reproduce the source, such as the formatter.
+pub fn tokenize_with_comments(source: &str) -> Result<Vec<Token>, LexerError> {
+    let mut lexer = Lexer::new(source);
+    lexer.keep_comments = true;
+    lexer.tokenize()
+}
+
 pub struct Lexer {
     source: String,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
+    // Byte offset of the first character on the current line
+    line_start: usize,
+    // Position of the token currently being scanned
+    start_line: usize,
+    start_column: usize,
+    keep_comments: bool,
+    // Lines of the doc comment for the next token
+    doc_lines: Vec<String>,
 }
 
 impl Lexer {
@@ -114,6 +162,11 @@ impl Lexer {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
+            keep_comments: false,
+            doc_lines: Vec::new(),
         }
     }
     
@@ -126,13 +179,17 @@ impl Lexer {
             }
             
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column_at(self.start);
             self.scan_token()?;
         }
         
+        let end = self.source.len();
         self.tokens.push(Token {
             token_type: TokenType::Eof,
             lexeme: "".to_string(),
-            line: self.line,
+            span: Span::new(end, end, self.line, self.column_at(end)),
+            doc: None,
         });
         
         Ok(self.tokens.clone())
@@ -145,8 +202,8 @@ impl Lexer {
                     self.advance();
                 }
                 '\n' => {
-                    self.line += 1;
                     self.advance();
+                    self.new_line();
                 }
                 _ => break,
             }
@@ -163,9 +220,23 @@ impl Lexer {
             '[' => self.add_token(TokenType::LeftBracket),
             ']' => self.add_token(TokenType::RightBracket),
             ';' => self.add_token(TokenType::Semicolon),
-            ':' => self.add_token(TokenType::Colon),
+            ':' => {
+                let token_type = if self.match_char(':') {
+                    TokenType::ColonColon
+                } else {
+                    TokenType::Colon
+                };
+                self.add_token(token_type);
+            }
             ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
+            '.' => {
+                let token_type = if self.match_char('.') {
+                    TokenType::DotDot
+                } else {
+                    TokenType::Dot
+                };
+                self.add_token(token_type);
+            }
             '-' => self.add_token(TokenType::Minus),
             '+' => self.add_token(TokenType::Plus),
             '*' => self.add_token(TokenType::Star),
@@ -180,6 +251,8 @@ impl Lexer {
             '=' => {
                 let token_type = if self.match_char('=') {
                     TokenType::EqualEqual
+                } else if self.match_char('>') {
+                    TokenType::FatArrow
                 } else {
                     TokenType::Assign
                 };
@@ -207,6 +280,16 @@ impl Lexer {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    let text = self.source[(self.start + 2)..self.current].to_string();
+                    // Three slashes start a doc comment, but four or more don't
+                    if let Some(doc) = text.strip_prefix('/') && !doc.starts_with('/') {
+                        self.doc_lines.push(doc.strip_prefix(' ').unwrap_or(doc).trim_end().to_string());
+                    }
+                    if self.keep_comments {
+                        self.add_token(TokenType::Comment(text));
+                    }
+                } else if self.match_char('*') {
+                    self.block_comment()?;
                 } else {
                     self.add_token(TokenType::Slash);
                 }
@@ -216,30 +299,23 @@ impl Lexer {
                 if self.match_char('&') {
                     self.add_token(TokenType::And);
                 } else {
-                    return Err(LexerError {
-                        message: "Unexpected character: &".to_string(),
-                        line: self.line,
-                    });
+                    return Err(self.error("Unexpected character: &".to_string()));
                 }
             }
             '|' => {
                 if self.match_char('|') {
                     self.add_token(TokenType::Or);
                 } else {
-                    return Err(LexerError {
-                        message: "Unexpected character: |".to_string(),
-                        line: self.line,
-                    });
+                    return Err(self.error("Unexpected character: |".to_string()));
                 }
             }
-            '"' => self.string()?,
-            '0'..='9' => self.number(),
+            '"' => self.string(false)?,
+            'r' if self.peek() == '"' || self.peek() == '#' => self.string(true)?,
+            'f' if self.peek() == '"' => self.format_string()?,
+            '0'..='9' => self.number()?,
             'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
             _ => {
-                return Err(LexerError {
-                    message: format!("Unexpected character: {}", c),
-                    line: self.line,
-                });
+                return Err(self.error(format!("Unexpected character: {}", c)));
             }
         }
         
@@ -255,10 +331,14 @@ impl Lexer {
         let token_type = match text.as_str() {
             "store" => TokenType::Store,
             "def" => TokenType::Def,
+            "struct" => TokenType::Struct,
+            "enum" => TokenType::Enum,
+            "match" => TokenType::Match,
             "if" => TokenType::If,
             "else" => TokenType::Else,
             "while" => TokenType::While,
             "for" => TokenType::For,
+            "in" => TokenType::In,
             "return" => TokenType::Return,
             "break" => TokenType::Break,
             "continue" => TokenType::Continue,
@@ -276,86 +356,465 @@ impl Lexer {
         self.add_token(token_type);
     }
     
-    fn number(&mut self) {
-        while self.peek().is_ascii_digit() {
+    /// Scans a `/* */` comment whose `/*` has been consumed. Block comments
+    /// nest, so code that already contains them can be commented out.
+    fn block_comment(&mut self) -> Result<(), LexerError> {
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                return Err(LexerError {
+                    message: "Unterminated block comment".to_string(),
+                    span: self.span_between(self.start, self.start + 2),
+                });
+            }
+            if self.peek() == '/' && self.peek_next() == '*' {
+                self.current += 2;
+                depth += 1;
+            } else if self.peek() == '*' && self.peek_next() == '/' {
+                self.current += 2;
+                depth -= 1;
+            } else if self.advance() == '\n' {
+                self.new_line();
+            }
+        }
+        if self.keep_comments {
+            let text = self.source[(self.start + 2)..(self.current - 2)].to_string();
+            self.add_token(TokenType::BlockComment(text));
+        }
+        Ok(())
+    }
+    
+    /// Scans a number literal: decimal, possibly with a fraction and an
+    /// exponent, or an integer in hex (`0x`), octal (`0o`) or binary (`0b`).
+    /// `_` may separate digits, and a suffix may give the literal a type:
+    /// `i8` to `i64` and `u8` to `u32` check that an integer fits that type,
+    /// and `f32` and `f64` make it a float, rounded to single precision for
+    /// `f32`.
+    fn number(&mut self) -> Result<(), LexerError> {
+        let radix = match (&self.source[self.start..self.current], self.peek()) {
+            ("0", 'x' | 'X') => 16,
+            ("0", 'o' | 'O') => 8,
+            ("0", 'b' | 'B') => 2,
+            _ => 10,
+        };
+        let mut digits_start = self.start;
+        let mut is_float = false;
+        if radix == 10 {
+            self.digits(10);
+            // Look for a fractional part
+            if self.peek() == '.' && self.peek_next().is_ascii_digit() {
+                self.advance();
+                self.digits(10);
+                is_float = true;
+            }
+            if self.peek() == 'e' || self.peek() == 'E' {
+                self.advance();
+                if self.peek() == '+' || self.peek() == '-' {
+                    self.advance();
+                }
+                if !self.peek().is_ascii_digit() {
+                    return Err(self.error("Expected digits in the exponent of a float literal".to_string()));
+                }
+                self.digits(10);
+                is_float = true;
+            }
+        } else {
+            // The prefix letter
             self.advance();
+            digits_start = self.current;
+            // Every hex digit, so a wrong one is reported as such rather than
+            // as the start of a suffix
+            self.digits(16);
         }
+        let digits_end = self.current;
+        while self.peek().is_ascii_alphanumeric() || self.peek() == '_' {
+            self.advance();
+        }
+        let digits = &self.source[digits_start..digits_end];
+        let suffix = &self.source[digits_end..self.current];
+        self.check_digits(digits, digits_start, radix)?;
+        let text = digits.replace('_', "");
         
-        // Look for a fractional part
-        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
-            // Consume the "."
+        let token_type = match suffix {
+            "f32" | "f64" if radix == 10 => {
+                let value = if suffix == "f32" {
+                    text.parse::<f32>().map(f64::from).unwrap_or(f64::INFINITY)
+                } else {
+                    text.parse::<f64>().unwrap_or(f64::INFINITY)
+                };
+                TokenType::Float(self.finite(value, suffix)?)
+            }
+            "" if is_float => TokenType::Float(self.finite(text.parse().unwrap_or(f64::INFINITY), "float")?),
+            "" | "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" if !is_float => {
+                // A literal right after a negating `-` may reach the negative
+                // end of its range, so `-9223372036854775808` and `-128i8` fit
+                let negated = self.after_negation();
+                let magnitude = u64::from_str_radix(&text, radix).ok()
+                    .filter(|&magnitude| magnitude <= i64::MAX as u64 + negated as u64)
+                    .ok_or_else(|| {
+                        self.error(format!("Integer literal is too large for int, whose largest value is {}", i64::MAX))
+                    })?;
+                let (min, max) = match suffix {
+                    "i8" => (i8::MIN as i64, i8::MAX as i64),
+                    "i16" => (i16::MIN as i64, i16::MAX as i64),
+                    "i32" => (i32::MIN as i64, i32::MAX as i64),
+                    "u8" => (0, u8::MAX as i64),
+                    "u16" => (0, u16::MAX as i64),
+                    "u32" => (0, u32::MAX as i64),
+                    _ => (i64::MIN, i64::MAX),
+                };
+                let limit = if negated { (max as u64).max(min.unsigned_abs()) } else { max as u64 };
+                if magnitude > limit {
+                    return Err(self.error(format!("Integer literal is out of range for {} ({} to {})", suffix, min, max)));
+                }
+                if magnitude > i64::MAX as u64 {
+                    TokenType::IntMinMagnitude
+                } else {
+                    TokenType::Integer(magnitude as i64)
+                }
+            }
+            "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" => {
+                return Err(self.error(format!("Integer suffix '{}' on a float literal", suffix)));
+            }
+            _ => {
+                return Err(self.error(format!(
+                    "Invalid suffix '{}' on a number literal; expected one of i8, i16, i32, i64, u8, u16, u32, f32, f64",
+                    suffix
+                )));
+            }
+        };
+        self.add_token(token_type);
+        Ok(())
+    }
+    
+    /// Whether the last token is a `-` negating what follows rather than
+    /// subtracting it, judged by whether the token before it ends an operand.
+    fn after_negation(&self) -> bool {
+        let mut previous = self.tokens.iter().rev()
+            .map(|token| &token.token_type)
+            .filter(|token_type| !matches!(token_type, TokenType::Comment(_) | TokenType::BlockComment(_)));
+        matches!(previous.next(), Some(TokenType::Minus)) && !matches!(
+            previous.next(),
+            Some(TokenType::Identifier(_) | TokenType::String(_) | TokenType::FormatString(_) | TokenType::Integer(_)
+                | TokenType::IntMinMagnitude | TokenType::Float(_) | TokenType::True | TokenType::False | TokenType::Null
+                | TokenType::RightParen | TokenType::RightBracket)
+        )
+    }
+    
+    /// Consumes digits of `radix` and `_` separators.
+    fn digits(&mut self, radix: u32) {
+        while self.peek().is_digit(radix) || self.peek() == '_' {
             self.advance();
-            
-            while self.peek().is_ascii_digit() {
-                self.advance();
+        }
+    }
+    
+    /// Checks the digits of a number literal, which start at byte `offset`:
+    /// that there are some, that each is a digit of `radix`, and that every
+    /// `_` is between two digits.
+    fn check_digits(&self, digits: &str, offset: usize, radix: u32) -> Result<(), LexerError> {
+        let base = match radix {
+            16 => "hex",
+            8 => "octal",
+            2 => "binary",
+            _ => "decimal",
+        };
+        if digits.is_empty() {
+            return Err(self.error(format!("Expected {} digits after '{}'", base, &self.source[self.start..offset])));
+        }
+        let bytes = digits.as_bytes();
+        for (i, &byte) in bytes.iter().enumerate() {
+            let at = |message: String| LexerError { message, span: self.span_between(offset + i, offset + i + 1) };
+            let is_digit = |position: Option<usize>| {
+                position.and_then(|p| bytes.get(p)).is_some_and(|b| (*b as char).is_digit(radix))
+            };
+            match byte {
+                b'_' if !is_digit(i.checked_sub(1)) || !is_digit(Some(i + 1)) => {
+                    return Err(at("Digit separator '_' must be between two digits".to_string()));
+                }
+                b'_' => {}
+                // Only decimal literals have fractions and exponents
+                b'.' | b'e' | b'E' | b'+' | b'-' if radix == 10 => {}
+                byte if !(byte as char).is_digit(radix) => {
+                    return Err(at(format!("Invalid digit '{}' in a {} literal", byte as char, base)));
+                }
+                _ => {}
             }
         }
+        Ok(())
+    }
+    
+    /// `value`, unless it overflowed to infinity when parsed as `ty`.
+    fn finite(&self, value: f64, ty: &str) -> Result<f64, LexerError> {
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(self.error(format!("Float literal is out of range for {}", ty)))
+        }
+    }
+    
+    /// Scans a string literal whose opening `"` (or `r`, for a raw string)
+    /// has been consumed. Raw strings may put `#`s between the `r` and the
+    /// quote, and must then close with as many after the closing quote, so
+    /// that they can contain `"`. Three quotes open a multi-line string,
+    /// which loses the line breaks right after its opening and before its
+    /// closing quotes, and the indentation its lines have in common.
+    fn string(&mut self, raw: bool) -> Result<(), LexerError> {
+        let mut hashes = 0;
+        if raw {
+            while self.match_char('#') {
+                hashes += 1;
+            }
+            if !self.match_char('"') {
+                return Err(self.error("Expected '\"' to open a raw string".to_string()));
+            }
+        }
+        let multi_line = self.source[self.current..].starts_with("\"\"");
+        if multi_line {
+            self.current += 2;
+        }
+        let closing = format!("{}{}", if multi_line { "\"\"\"" } else { "\"" }, "#".repeat(hashes));
         
-        // Look for scientific notation (e or E)
-        if self.peek() == 'e' || self.peek() == 'E' {
-            let saved_current = self.current; // Save position before consuming 'e'/'E'
-            self.advance(); // consume 'e' or 'E'
-            
-            // Optional + or - after e/E
-            if self.peek() == '+' || self.peek() == '-' {
+        let content_start = self.current;
+        while !self.source[self.current..].starts_with(&closing) {
+            if self.is_at_end() {
+                return Err(self.error("Unterminated string".to_string()));
+            }
+            // An escaped quote doesn't close the string
+            if !raw && self.peek() == '\\' {
                 self.advance();
             }
-            
-            // Must have at least one digit after e/E
-            if !self.peek().is_ascii_digit() {
-                // Invalid scientific notation, backtrack to before 'e'/'E'
-                self.current = saved_current;
-            } else {
-                while self.peek().is_ascii_digit() {
-                    self.advance();
-                }
+            if self.advance() == '\n' {
+                self.new_line();
             }
         }
+        let content_end = self.current;
+        self.current += closing.len();
         
-        let text = &self.source[self.start..self.current];
+        let content = &self.source[content_start..content_end];
+        let lines = if multi_line {
+            dedent(content, content_start)
+        } else {
+            vec![(content_start, content)]
+        };
+        let mut value = String::new();
+        for (i, (offset, line)) in lines.into_iter().enumerate() {
+            if i > 0 {
+                value.push('\n');
+            }
+            if raw {
+                value.push_str(line);
+            } else {
+                value.push_str(&self.unescape(line, offset)?);
+            }
+        }
+        self.add_token(TokenType::String(value));
         
-        if text.contains('.') || text.contains('e') || text.contains('E') {
-            match text.parse::<f64>() {
-                Ok(value) => self.add_token(TokenType::Float(value)),
-                Err(e) => {
-                    eprintln!("Failed to parse float '{}': {}", text, e);
-                    panic!("Invalid float literal: {}", text);
-                }
+        Ok(())
+    }
+    
+    /// Scans an `f"..."` string whose `f` has been consumed. Text between
+    /// `{` and `}` is tokenized as an expression, and anything after a colon
+    /// that isn't part of `::` or inside brackets is its format spec.
+    fn format_string(&mut self) -> Result<(), LexerError> {
+        self.advance();
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut text_start = self.current;
+        loop {
+            if self.is_at_end() {
+                return Err(self.error("Unterminated string".to_string()));
             }
-        } else {
-            match text.parse::<i64>() {
-                Ok(value) => self.add_token(TokenType::Integer(value)),
-                Err(e) => {
-                    eprintln!("Failed to parse integer '{}': {}", text, e);
-                    panic!("Invalid integer literal: {}", text);
+            let brace = self.current;
+            match self.peek() {
+                '"' => break,
+                '\\' => {
+                    self.advance();
+                    // The braces of a `\u{...}` escape don't interpolate;
+                    // `unescape` reports it if it isn't closed
+                    if self.peek() == 'u' && self.peek_next() == '{' {
+                        while !self.is_at_end() && !matches!(self.peek(), '}' | '"' | '\n') {
+                            self.advance();
+                        }
+                        if self.peek() != '}' {
+                            continue;
+                        }
+                    }
+                    if self.advance() == '\n' {
+                        self.new_line();
+                    }
+                }
+                '{' | '}' if self.peek_next() == self.peek() => {
+                    text.push_str(&self.unescape(&self.source[text_start..brace], text_start)?);
+                    text.push(self.advance());
+                    self.advance();
+                    text_start = self.current;
+                }
+                '}' => {
+                    return Err(LexerError {
+                        message: "Unmatched '}' in a format string; write '}}' for a literal brace".to_string(),
+                        span: self.span_between(brace, brace + 1),
+                    });
+                }
+                '{' => {
+                    text.push_str(&self.unescape(&self.source[text_start..brace], text_start)?);
+                    if !text.is_empty() {
+                        parts.push(FormatStringPart::Literal(std::mem::take(&mut text)));
+                    }
+                    parts.push(self.interpolation()?);
+                    text_start = self.current;
+                }
+                _ => {
+                    if self.advance() == '\n' {
+                        self.new_line();
+                    }
                 }
             }
         }
+        text.push_str(&self.unescape(&self.source[text_start..self.current], text_start)?);
+        if !text.is_empty() {
+            parts.push(FormatStringPart::Literal(text));
+        }
+        // The closing "
+        self.advance();
+        self.add_token(TokenType::FormatString(parts));
+        
+        Ok(())
     }
     
-    fn string(&mut self) -> Result<(), LexerError> {
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+    /// Scans a `{expr}` or `{expr:spec}` of a format string, starting at its
+    /// `{`.
+    fn interpolation(&mut self) -> Result<FormatStringPart, LexerError> {
+        let open = self.current;
+        self.advance();
+        let expr_start = self.current;
+        let mut depth = 0;
+        let mut colon = None;
+        loop {
+            if self.is_at_end() || self.peek() == '\n' {
+                return Err(LexerError {
+                    message: "Unterminated '{' in a format string".to_string(),
+                    span: self.span_between(open, open + 1),
+                });
+            }
+            match self.advance() {
+                ':' if self.peek() == ':' => {
+                    self.advance();
+                }
+                ':' if depth == 0 && colon.is_none() => colon = Some(self.current - 1),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' => depth -= 1,
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                '"' => {
+                    // A string inside the expression, which may contain any of the above
+                    while !self.is_at_end() && self.peek() != '"' && self.peek() != '\n' {
+                        if self.advance() == '\\' {
+                            self.advance();
+                        }
+                    }
+                    self.advance();
+                }
+                _ => {}
             }
-            self.advance();
         }
+        let close = self.current - 1;
+        let expr_end = colon.unwrap_or(close);
+        let spec = colon.map(|colon| {
+            (self.source[colon + 1..close].to_string(), self.span_between(colon + 1, close))
+        });
         
-        if self.is_at_end() {
+        let mut tokens = Lexer::new(&self.source[expr_start..expr_end]).tokenize()
+            .map_err(|err| LexerError { message: err.message, span: self.shift(err.span, expr_start) })?;
+        if tokens.len() == 1 {
             return Err(LexerError {
-                message: "Unterminated string".to_string(),
-                line: self.line,
+                message: "Expected an expression between '{' and '}' in a format string".to_string(),
+                span: self.span_between(open, close + 1),
             });
         }
-        
-        // The closing ".
-        self.advance();
-        
-        // Trim the surrounding quotes.
-        let value = self.source[(self.start + 1)..(self.current - 1)].to_string();
-        self.add_token(TokenType::String(value));
-        
-        Ok(())
+        for token in &mut tokens {
+            token.span = self.shift(token.span, expr_start);
+        }
+        Ok(FormatStringPart::Expr { tokens, spec })
+    }
+    
+    /// Moves a span of a separately lexed piece of the source to where that
+    /// piece starts, at byte `offset`.
+    fn shift(&self, span: Span, offset: usize) -> Span {
+        let base = self.span_between(offset, offset);
+        let column = if span.line == 1 { span.column + base.column - 1 } else { span.column };
+        Span::new(span.start + offset, span.end + offset, span.line + base.line - 1, column)
+    }
+    
+    /// Decodes the escape sequences in `text`, which starts at byte `offset`
+    /// of the source.
+    fn unescape(&self, text: &str, offset: usize) -> Result<String, LexerError> {
+        let mut value = String::new();
+        let mut chars = text.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            let invalid = |end: usize, message: String| LexerError {
+                message,
+                span: self.span_between(offset + start, offset + end),
+            };
+            let escape = match chars.next() {
+                Some((_, escape)) if escape != '\n' && escape != '\r' => escape,
+                _ => return Err(invalid(start + 1, "Escape sequence at the end of a line".to_string())),
+            };
+            let decoded = match escape {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '0' => '\0',
+                '\\' => '\\',
+                '"' => '"',
+                '\'' => '\'',
+                'x' => {
+                    let digits: String = text[start + 2..].chars().take(2).collect();
+                    let end = start + 2 + digits.len();
+                    match u8::from_str_radix(&digits, 16) {
+                        Ok(byte) if digits.len() == 2 && is_hex(&digits) && byte.is_ascii() => {
+                            chars.next();
+                            chars.next();
+                            byte as char
+                        }
+                        _ => return Err(invalid(end, format!(
+                            "Invalid escape sequence '\\x{}': expected two hex digits for a character up to \\x7F", digits
+                        ))),
+                    }
+                }
+                'u' => {
+                    let rest = &text[start + 2..];
+                    let Some(close) = rest.strip_prefix('{').and_then(|rest| rest.find('}')) else {
+                        return Err(invalid(start + 2, "Invalid escape sequence '\\u': expected '{' and '}' around the hex code of a character".to_string()));
+                    };
+                    let digits = &rest[1..close + 1];
+                    let end = start + 2 + close + 2;
+                    let code = u32::from_str_radix(digits, 16).ok()
+                        .filter(|_| digits.len() <= 6 && is_hex(digits))
+                        .and_then(char::from_u32);
+                    let Some(code) = code else {
+                        return Err(invalid(end, format!("Invalid unicode escape '\\u{{{}}}'", digits)));
+                    };
+                    while chars.next_if(|(i, _)| *i < end).is_some() {}
+                    code
+                }
+                other => {
+                    let end = start + 1 + other.len_utf8();
+                    return Err(invalid(end, format!("Invalid escape sequence '\\{}'", other)));
+                }
+            };
+            // The C and LLVM backends end strings with a NUL, so one inside
+            // would cut the string short there but not in the interpreter
+            if decoded == '\0' {
+                let end = chars.peek().map_or(text.len(), |(i, _)| *i);
+                return Err(invalid(end, "Strings can't contain a NUL character".to_string()));
+            }
+            value.push(decoded);
+        }
+        Ok(value)
     }
     
     fn match_char(&mut self, expected: char) -> bool {
@@ -405,10 +864,87 @@ impl Lexer {
     
     fn add_token(&mut self, token_type: TokenType) {
         let lexeme = self.source[self.start..self.current].to_string();
+        let span = self.token_span();
+        let doc = match token_type {
+            TokenType::Comment(_) | TokenType::BlockComment(_) => None,
+            _ if self.doc_lines.is_empty() => None,
+            _ => Some(std::mem::take(&mut self.doc_lines).join("\n")),
+        };
         self.tokens.push(Token {
             token_type,
             lexeme,
-            line: self.line,
+            span,
+            doc,
         });
     }
-}
\ No newline at end of file
+    
+    /// Records that a '\n' has just been consumed.
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+    
+    /// 1-based character column of a byte offset on the current line.
+    fn column_at(&self, offset: usize) -> usize {
+        self.source[self.line_start..offset].chars().count() + 1
+    }
+    
+    fn token_span(&self) -> Span {
+        Span::new(self.start, self.current, self.start_line, self.start_column)
+    }
+    
+    /// Span of the source bytes `start..end`, which may lie on an earlier
+    /// line than the current one.
+    fn span_between(&self, start: usize, end: usize) -> Span {
+        let before = &self.source[..start];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let line = before.matches('\n').count() + 1;
+        Span::new(start, end, line, before[line_start..].chars().count() + 1)
+    }
+    
+    fn error(&self, message: String) -> LexerError {
+        LexerError {
+            message,
+            span: self.token_span(),
+        }
+    }
+}
+// `from_str_radix` also takes a leading sign, which escapes don't
+fn is_hex(digits: &str) -> bool {
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Splits the content of a multi-line string into lines paired with their
+/// byte offsets in the source, dropping a blank first and last line and the
+/// indentation all the lines share. A blank last line holds the closing
+/// quotes, so their indentation counts too.
+fn dedent(content: &str, offset: usize) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+    let mut line_offset = offset;
+    for line in content.split('\n') {
+        lines.push((line_offset, line.strip_suffix('\r').unwrap_or(line)));
+        line_offset += line.len() + 1;
+    }
+    let is_blank = |line: &str| line.chars().all(|c| c == ' ' || c == '\t');
+    let indentation = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
+    
+    if lines.len() > 1 && is_blank(lines[0].1) {
+        lines.remove(0);
+    }
+    let mut common = lines.iter()
+        .filter(|(_, line)| !is_blank(line))
+        .map(|(_, line)| indentation(line))
+        .min();
+    if lines.len() > 1 && lines.last().is_some_and(|(_, line)| is_blank(line)) {
+        let (_, closing) = lines.pop().unwrap();
+        common = Some(common.map_or(closing.len(), |common| common.min(closing.len())));
+    }
+    let common = common.unwrap_or(0);
+    
+    lines.into_iter()
+        .map(|(offset, line)| {
+            let strip = common.min(indentation(line));
+            (offset + strip, &line[strip..])
+        })
+        .collect()
+}
@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod lexer_tests {
-    use crate::lexer::{tokenize, TokenType};
+    use crate::lexer::{tokenize, tokenize_with_comments, FormatStringPart, TokenType};
+    use crate::span::Span;
     
     #[test]
     fn test_basic_tokenization() {
@@ -27,6 +28,34 @@ mod lexer_tests {
         assert_eq!(tokens[5].token_type, TokenType::Identifier("y".to_string()));
     }
     
+    #[test]
+    fn test_for_loop_header() {
+        let tokens = tokenize("for (i in 0..10)").unwrap();
+        let types: Vec<TokenType> = tokens.into_iter().map(|t| t.token_type).collect();
+        
+        assert_eq!(types, vec![
+            TokenType::For,
+            TokenType::LeftParen,
+            TokenType::Identifier("i".to_string()),
+            TokenType::In,
+            TokenType::Integer(0),
+            TokenType::DotDot,
+            TokenType::Integer(10),
+            TokenType::RightParen,
+            TokenType::Eof,
+        ]);
+    }
+    
+    #[test]
+    fn test_match_arm() {
+        let tokens = tokenize("match (x) { Shape::Circle(r) => r }").unwrap();
+        let types: Vec<TokenType> = tokens.into_iter().map(|t| t.token_type).collect();
+        
+        assert_eq!(types[0], TokenType::Match);
+        assert_eq!(types[6], TokenType::ColonColon);
+        assert_eq!(types[11], TokenType::FatArrow);
+    }
+    
     #[test]
     fn test_string_literal() {
         let source = r#"store msg = "Hello, World!";"#;
@@ -35,6 +64,114 @@ mod lexer_tests {
         assert_eq!(tokens[3].token_type, TokenType::String("Hello, World!".to_string()));
     }
     
+    #[test]
+    fn test_number_literals() {
+        let value = |source: &str| tokenize(source).unwrap().remove(0).token_type;
+        
+        assert_eq!(value("0xFF"), TokenType::Integer(255));
+        assert_eq!(value("0o755"), TokenType::Integer(493));
+        assert_eq!(value("0b1010_0101"), TokenType::Integer(165));
+        assert_eq!(value("1_000_000"), TokenType::Integer(1_000_000));
+        assert_eq!(value("9223372036854775807"), TokenType::Integer(i64::MAX));
+        assert_eq!(value("10i32"), TokenType::Integer(10));
+        assert_eq!(value("0xFFu8"), TokenType::Integer(255));
+        assert_eq!(value("10f64"), TokenType::Float(10.0));
+        assert_eq!(value("2.5e1_0"), TokenType::Float(2.5e10));
+        assert_eq!(value("0.1f32"), TokenType::Float(0.1f32 as f64));
+        
+        // Under a negating minus a literal may reach the bottom of its range
+        let negated = |source: &str| tokenize(source).unwrap().remove(1).token_type;
+        assert_eq!(negated("-9223372036854775808"), TokenType::IntMinMagnitude);
+        assert_eq!(negated("-128i8"), TokenType::Integer(128));
+        assert_eq!(tokenize("f(-0x8000_0000i32)").unwrap()[3].token_type, TokenType::Integer(1 << 31));
+        
+        let error = |source: &str| {
+            let err = tokenize(source).unwrap_err();
+            (err.message, err.span.column)
+        };
+        assert_eq!(error("x = 9223372036854775808;"), ("Integer literal is too large for int, whose largest value is 9223372036854775807".to_string(), 5));
+        assert_eq!(error("x = 128i8;"), ("Integer literal is out of range for i8 (-128 to 127)".to_string(), 5));
+        assert_eq!(error("x = y - 128i8;").0, "Integer literal is out of range for i8 (-128 to 127)");
+        assert_eq!(error("x = y - 9223372036854775808;").0, "Integer literal is too large for int, whose largest value is 9223372036854775807");
+        assert_eq!(error("x = -9223372036854775809;").0, "Integer literal is too large for int, whose largest value is 9223372036854775807");
+        assert_eq!(error("x = 1__000;"), ("Digit separator '_' must be between two digits".to_string(), 6));
+        assert_eq!(error("x = 0b0121;"), ("Invalid digit '2' in a binary literal".to_string(), 9));
+        assert_eq!(error("x = 0o;").0, "Expected octal digits after '0o'");
+        assert_eq!(error("x = 1e+;").0, "Expected digits in the exponent of a float literal");
+        assert_eq!(error("x = 1.5u16;").0, "Integer suffix 'u16' on a float literal");
+        assert_eq!(error("x = 3px;").0, "Invalid suffix 'px' on a number literal; expected one of i8, i16, i32, i64, u8, u16, u32, f32, f64");
+        assert_eq!(error("x = 1e309;").0, "Float literal is out of range for float");
+        assert_eq!(error("x = 1e39f32;").0, "Float literal is out of range for f32");
+    }
+    
+    #[test]
+    fn test_string_escapes() {
+        let string = |source: &str| match tokenize(source).unwrap().remove(0).token_type {
+            TokenType::String(value) => value,
+            other => panic!("expected a string, got {:?}", other),
+        };
+        
+        assert_eq!(string(r#""a\tb\n\"c\" \\ \x41\u{1F600}""#), "a\tb\n\"c\" \\ A\u{1F600}");
+        assert_eq!(string(r#"r"C:\new\n""#), "C:\\new\\n");
+        assert_eq!(string(r###"r#"say "hi""#"###), "say \"hi\"");
+        assert_eq!(string("\"\"\"\n    one\n      two\\t\n\n    three\n    \"\"\""), "one\n  two\t\n\nthree");
+        assert_eq!(string("\"\"\"a \"quoted\" word\"\"\""), "a \"quoted\" word");
+        assert_eq!(string("r\"\"\"\n  \\d+\n  \"\"\""), "\\d+");
+        assert_eq!(string("\"\""), "");
+        
+        let err = tokenize("store s = 1;\nstore t = \"ok \\q\";").unwrap_err();
+        assert_eq!(err.message, "Invalid escape sequence '\\q'");
+        assert_eq!(err.span, Span::new(27, 29, 2, 15));
+        let err = tokenize("\"\"\"\n  fine\n  \\u{D800}\n  \"\"\"").unwrap_err();
+        assert_eq!(err.message, "Invalid unicode escape '\\u{D800}'");
+        assert_eq!((err.span.line, err.span.column), (3, 3));
+        assert!(tokenize(r#""\x80""#).unwrap_err().message.starts_with("Invalid escape sequence '\\x80'"));
+        assert!(tokenize(r#""\u41""#).unwrap_err().message.starts_with("Invalid escape sequence '\\u'"));
+        assert_eq!(tokenize(r#"r#"open"#).unwrap_err().message, "Unterminated string");
+        for escape in [r"\0", r"\x00", r"\u{0}", r"\u{000000}"] {
+            let err = tokenize(&format!("\"a{}b\"", escape)).unwrap_err();
+            assert_eq!(err.message, "Strings can't contain a NUL character");
+            assert_eq!(err.span, Span::new(2, 2 + escape.len(), 1, 3));
+        }
+        assert!(tokenize(r#"f"{1}\0""#).is_err());
+    }
+    
+    #[test]
+    fn test_format_string() {
+        let source = "store s = 1;\nf\"{{n}} = {n + 1:>4}\\t{Shape::Unit}!\"";
+        let tokens = tokenize(source).unwrap();
+        let TokenType::FormatString(parts) = &tokens[5].token_type else {
+            panic!("expected a format string, got {:?}", tokens[5].token_type);
+        };
+        assert_eq!(tokens[5].span, Span::new(13, 50, 2, 1));
+        assert_eq!(parts.len(), 5);
+        assert_eq!(parts[0], FormatStringPart::Literal("{n} = ".to_string()));
+        let FormatStringPart::Expr { tokens: expr, spec } = &parts[1] else {
+            panic!("expected an interpolation");
+        };
+        let types: Vec<&TokenType> = expr.iter().map(|t| &t.token_type).collect();
+        assert_eq!(types, [&TokenType::Identifier("n".to_string()), &TokenType::Plus, &TokenType::Integer(1), &TokenType::Eof]);
+        assert_eq!(expr[2].span, Span::new(28, 29, 2, 16));
+        assert_eq!(spec.as_ref().map(|(text, span)| (text.as_str(), span.column)), Some((">4", 18)));
+        assert_eq!(parts[2], FormatStringPart::Literal("\t".to_string()));
+        assert!(matches!(&parts[3], FormatStringPart::Expr { tokens, spec: None } if tokens[1].token_type == TokenType::ColonColon));
+        assert_eq!(parts[4], FormatStringPart::Literal("!".to_string()));
+        
+        // The braces of a Unicode escape aren't an interpolation
+        let tokens = tokenize("f\"smile \\u{1F600} {n}\"").unwrap();
+        let TokenType::FormatString(parts) = &tokens[0].token_type else {
+            panic!("expected a format string, got {:?}", tokens[0].token_type);
+        };
+        assert_eq!(parts[0], FormatStringPart::Literal("smile \u{1F600} ".to_string()));
+        assert!(matches!(&parts[1], FormatStringPart::Expr { spec: None, .. }));
+        assert!(tokenize("f\"\\u{1F600\"").unwrap_err().message.starts_with("Invalid escape sequence"));
+        
+        assert!(tokenize("f\"a } b\"").unwrap_err().message.starts_with("Unmatched '}'"));
+        assert!(tokenize("f\"a {} b\"").unwrap_err().message.starts_with("Expected an expression"));
+        let err = tokenize("f\"a {x @ 1} b\"").unwrap_err();
+        assert_eq!((err.message.as_str(), err.span.column), ("Unexpected character: @", 8));
+    }
+    
     #[test]
     fn test_operators() {
         let source = "x + y - z * w / v % u";
@@ -46,4 +183,66 @@ mod lexer_tests {
         assert_eq!(tokens[7].token_type, TokenType::Slash);
         assert_eq!(tokens[9].token_type, TokenType::Percent);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_token_spans() {
+        let source = "store x = 42;\n  print(x);";
+        let tokens = tokenize(source).unwrap();
+        
+        assert_eq!(tokens[3].span, Span::new(10, 12, 1, 11));
+        assert_eq!(&source[tokens[3].span.start..tokens[3].span.end], "42");
+        assert_eq!(tokens[5].span, Span::new(16, 21, 2, 3));
+        assert_eq!(tokens.last().unwrap().token_type, TokenType::Eof);
+    }
+    
+    #[test]
+    fn test_error_location() {
+        let err = tokenize("store s = 1;\nstore t = @;").unwrap_err();
+        assert_eq!(err.span.line, 2);
+        assert_eq!(err.span.column, 11);
+    }
+    
+    #[test]
+    fn test_comments() {
+        let source = "store x = 1; // one\n// two\nx;";
+        let types = |tokens: Vec<crate::lexer::Token>| tokens.into_iter().map(|t| t.token_type).collect::<Vec<_>>();
+        
+        assert!(!types(tokenize(source).unwrap()).iter().any(|t| matches!(t, TokenType::Comment(_))));
+        
+        let tokens = tokenize_with_comments(source).unwrap();
+        assert_eq!(tokens[5].token_type, TokenType::Comment(" one".to_string()));
+        assert_eq!(tokens[5].span, Span::new(13, 19, 1, 14));
+        assert_eq!(tokens[6].token_type, TokenType::Comment(" two".to_string()));
+        assert_eq!(tokens[6].span.line, 2);
+    }
+    
+    #[test]
+    fn test_block_comments() {
+        let source = "store /* a /* nested */ comment\n */ x = 1;";
+        let tokens = tokenize(source).unwrap();
+        assert_eq!(tokens[1].token_type, TokenType::Identifier("x".to_string()));
+        assert_eq!(tokens[1].span.line, 2);
+        
+        let tokens = tokenize_with_comments(source).unwrap();
+        assert_eq!(tokens[1].token_type, TokenType::BlockComment(" a /* nested */ comment\n ".to_string()));
+        assert_eq!(tokens[1].span, Span::new(6, 35, 1, 7));
+        
+        let err = tokenize("x;\n  /* open /* closed */").unwrap_err();
+        assert_eq!(err.message, "Unterminated block comment");
+        assert_eq!(err.span, Span::new(5, 7, 2, 3));
+    }
+    
+    #[test]
+    fn test_doc_comments() {
+        let source = "/// Adds one.\n///\n///   Indented\n//// not a doc\ndef inc() {}\n// plain\nstore x = 1;";
+        let tokens = tokenize(source).unwrap();
+        assert_eq!(tokens[0].token_type, TokenType::Def);
+        assert_eq!(tokens[0].doc.as_deref(), Some("Adds one.\n\n  Indented"));
+        assert!(tokens[1..].iter().all(|token| token.doc.is_none()));
+        
+        let tokens = tokenize_with_comments(source).unwrap();
+        assert_eq!(tokens[0].token_type, TokenType::Comment("/ Adds one.".to_string()));
+        assert_eq!(tokens[0].doc, None);
+        assert_eq!(tokens[4].doc.as_deref(), Some("Adds one.\n\n  Indented"));
+    }
+}
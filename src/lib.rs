@@ -1,3 +1,5 @@
+pub mod span;
+pub mod diagnostics;
 pub mod lexer;
 pub mod parser;
 pub mod ast;
@@ -5,6 +7,13 @@ pub mod semantic;
 pub mod llvm_codegen;
 pub mod c_codegen;
 pub mod interpreter;
+pub mod bytecode;
 pub mod execution_engine;
 pub mod std_lib;
+pub mod checker;
+pub mod formatter;
+pub mod doc;
+pub mod lsp;
+pub mod test_runner;
+pub mod repl;
 pub mod cli;
\ No newline at end of file
@@ -1,33 +1,347 @@
-use crate::ast::{Program, Statement, Expr, Literal, BinaryOperator, UnaryOperator, Type};
+use crate::ast::{Program, Statement, Expr, Literal, BinaryOperator, UnaryOperator, Type, MatchArm, Pattern, FormatPart, FormatSpec};
+use crate::semantic::{TypeTable, TypedProgram};
+use crate::span::Span;
 use std::collections::HashMap;
 
+mod target;
+
+pub use target::Target;
+
 #[derive(Debug)]
 pub struct LLVMCodeGenError {
     pub message: String,
+    pub span: Option<Span>,
+}
+
+impl LLVMCodeGenError {
+    /// Attaches a location to an error that doesn't already have one.
+    pub fn with_span(mut self, span: Span) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
 }
 
 impl std::fmt::Display for LLVMCodeGenError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "LLVM CodeGen error: {}", self.message)
+        match self.span {
+            Some(span) => write!(f, "LLVM CodeGen error on line {}, column {}: {}", span.line, span.column, self.message),
+            None => write!(f, "LLVM CodeGen error: {}", self.message),
+        }
     }
 }
 
 impl std::error::Error for LLVMCodeGenError {}
 
+/// Growable arrays of i64 slots. An array is a heap-allocated
+/// `{ data, len, cap }` passed around as an i64 handle. Elements that
+/// aren't integers are converted to and from their slot's bits, and
+/// `nl_print_slot` is told which kind of value a slot holds.
+const ARRAY_RUNTIME: &str = r#"; Array runtime
+%nl_array = type { i64*, i64, i64 }
+
+declare i8* @malloc(i64)
+declare i8* @realloc(i8*, i64)
+declare void @exit(i32)
+declare i32 @dprintf(i32, i8*, ...)
+
+@.str.index_error = private unnamed_addr constant [88 x i8] c"Index out of bounds: the length is %lld but the index is %lld (line %lld, column %lld)\0A\00", align 1
+@.str.array_open = private unnamed_addr constant [2 x i8] c"[\00", align 1
+@.str.array_separator = private unnamed_addr constant [3 x i8] c", \00", align 1
+@.str.array_element = private unnamed_addr constant [5 x i8] c"%lld\00", align 1
+@.str.array_close = private unnamed_addr constant [2 x i8] c"]\00", align 1
+@.str.array_close_line = private unnamed_addr constant [3 x i8] c"]\0A\00", align 1
+@.str.array_string = private unnamed_addr constant [3 x i8] c"%s\00", align 1
+
+define i64 @nl_array_new() {
+entry:
+  %raw = call i8* @malloc(i64 24)
+  %array = bitcast i8* %raw to %nl_array*
+  %data_raw = call i8* @malloc(i64 32)
+  %data = bitcast i8* %data_raw to i64*
+  %data_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 0
+  store i64* %data, i64** %data_ptr, align 8
+  %len_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 1
+  store i64 0, i64* %len_ptr, align 8
+  %cap_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 2
+  store i64 4, i64* %cap_ptr, align 8
+  %handle = ptrtoint %nl_array* %array to i64
+  ret i64 %handle
+}
+
+define void @nl_array_push(i64 %handle, i64 %value) {
+entry:
+  %array = inttoptr i64 %handle to %nl_array*
+  %data_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 0
+  %len_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 1
+  %cap_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 2
+  %len = load i64, i64* %len_ptr, align 8
+  %cap = load i64, i64* %cap_ptr, align 8
+  %full = icmp eq i64 %len, %cap
+  br i1 %full, label %grow, label %append
+grow:
+  %new_cap = mul i64 %cap, 2
+  store i64 %new_cap, i64* %cap_ptr, align 8
+  %old_data = load i64*, i64** %data_ptr, align 8
+  %old_raw = bitcast i64* %old_data to i8*
+  %bytes = mul i64 %new_cap, 8
+  %new_raw = call i8* @realloc(i8* %old_raw, i64 %bytes)
+  %new_data = bitcast i8* %new_raw to i64*
+  store i64* %new_data, i64** %data_ptr, align 8
+  br label %append
+append:
+  %data = load i64*, i64** %data_ptr, align 8
+  %slot = getelementptr i64, i64* %data, i64 %len
+  store i64 %value, i64* %slot, align 8
+  %new_len = add i64 %len, 1
+  store i64 %new_len, i64* %len_ptr, align 8
+  ret void
+}
+
+define i64 @nl_array_len(i64 %handle) {
+entry:
+  %array = inttoptr i64 %handle to %nl_array*
+  %len_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 1
+  %len = load i64, i64* %len_ptr, align 8
+  ret i64 %len
+}
+
+define i64* @nl_array_at(i64 %handle, i64 %index, i64 %line, i64 %column) {
+entry:
+  %array = inttoptr i64 %handle to %nl_array*
+  %len_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 1
+  %len = load i64, i64* %len_ptr, align 8
+  %negative = icmp slt i64 %index, 0
+  %past_end = icmp sge i64 %index, %len
+  %out_of_bounds = or i1 %negative, %past_end
+  br i1 %out_of_bounds, label %fail, label %in_bounds
+fail:
+  call i32 (i32, i8*, ...) @dprintf(i32 2, i8* getelementptr inbounds ([88 x i8], [88 x i8]* @.str.index_error, i32 0, i32 0), i64 %len, i64 %index, i64 %line, i64 %column)
+  call void @exit(i32 1)
+  unreachable
+in_bounds:
+  %data_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 0
+  %data = load i64*, i64** %data_ptr, align 8
+  %slot = getelementptr i64, i64* %data, i64 %index
+  ret i64* %slot
+}
+
+define void @nl_print_slot(i64 %bits, i64 %kind) {
+entry:
+  switch i64 %kind, label %integer [ i64 1, label %float
+                                     i64 2, label %boolean
+                                     i64 3, label %string ]
+integer:
+  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([5 x i8], [5 x i8]* @.str.array_element, i32 0, i32 0), i64 %bits)
+  ret void
+float:
+  %double = bitcast i64 %bits to double
+  %float_text = call i8* @nl_float_to_string(double %double)
+  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @.str.array_string, i32 0, i32 0), i8* %float_text)
+  ret void
+boolean:
+  %true = icmp ne i64 %bits, 0
+  %bool_text = select i1 %true, i8* getelementptr inbounds ([5 x i8], [5 x i8]* @.str.bool_true, i32 0, i32 0), i8* getelementptr inbounds ([6 x i8], [6 x i8]* @.str.bool_false, i32 0, i32 0)
+  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @.str.array_string, i32 0, i32 0), i8* %bool_text)
+  ret void
+string:
+  %text = inttoptr i64 %bits to i8*
+  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @.str.array_string, i32 0, i32 0), i8* %text)
+  ret void
+}
+
+define void @nl_array_print(i64 %handle, i8* %close, i64 %kind) {
+entry:
+  %array = inttoptr i64 %handle to %nl_array*
+  %data_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 0
+  %data = load i64*, i64** %data_ptr, align 8
+  %len_ptr = getelementptr %nl_array, %nl_array* %array, i32 0, i32 1
+  %len = load i64, i64* %len_ptr, align 8
+  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([2 x i8], [2 x i8]* @.str.array_open, i32 0, i32 0))
+  br label %loop
+loop:
+  %i = phi i64 [ 0, %entry ], [ %next, %element ]
+  %done = icmp sge i64 %i, %len
+  br i1 %done, label %exit, label %body
+body:
+  %first = icmp eq i64 %i, 0
+  br i1 %first, label %element, label %separator
+separator:
+  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @.str.array_separator, i32 0, i32 0))
+  br label %element
+element:
+  %slot = getelementptr i64, i64* %data, i64 %i
+  %value = load i64, i64* %slot, align 8
+  call void @nl_print_slot(i64 %value, i64 %kind)
+  %next = add i64 %i, 1
+  br label %loop
+exit:
+  call i32 (i8*, ...) @printf(i8* %close)
+  ret void
+}
+
+"#;
+
+/// Strings are NUL-terminated and passed around as i8* pointers. Literals
+/// point at constants; concatenation and conversions allocate new strings.
+const STRING_RUNTIME: &str = r#"; String runtime
+declare i64 @strlen(i8*)
+declare i32 @strcmp(i8*, i8*)
+declare i32 @snprintf(i8*, i64, i8*, ...)
+declare double @strtod(i8*, i8**)
+declare i64 @strtoll(i8*, i8**, i32)
+
+@.str.int_format = private unnamed_addr constant [5 x i8] c"%lld\00", align 1
+@.str.float_format = private unnamed_addr constant [5 x i8] c"%.*g\00", align 1
+
+define i8* @nl_string_concat(i8* %left, i8* %right) {
+entry:
+  %left_len = call i64 @strlen(i8* %left)
+  %right_len = call i64 @strlen(i8* %right)
+  %right_size = add i64 %right_len, 1
+  %size = add i64 %left_len, %right_size
+  %result = call i8* @malloc(i64 %size)
+  call void @llvm.memcpy.p0i8.p0i8.i64(i8* %result, i8* %left, i64 %left_len, i1 false)
+  %tail = getelementptr i8, i8* %result, i64 %left_len
+  call void @llvm.memcpy.p0i8.p0i8.i64(i8* %tail, i8* %right, i64 %right_size, i1 false)
+  ret i8* %result
+}
+
+define i8* @nl_int_to_string(i64 %value) {
+entry:
+  %buffer = call i8* @malloc(i64 21)
+  call i32 (i8*, i64, i8*, ...) @snprintf(i8* %buffer, i64 21, i8* getelementptr inbounds ([5 x i8], [5 x i8]* @.str.int_format, i32 0, i32 0), i64 %value)
+  ret i8* %buffer
+}
+
+; Like the interpreter, uses the fewest digits that read back as the same value
+define i8* @nl_float_to_string(double %value) {
+entry:
+  %buffer = call i8* @malloc(i64 32)
+  br label %attempt
+attempt:
+  %precision = phi i32 [ 15, %entry ], [ %next, %retry ]
+  call i32 (i8*, i64, i8*, ...) @snprintf(i8* %buffer, i64 32, i8* getelementptr inbounds ([5 x i8], [5 x i8]* @.str.float_format, i32 0, i32 0), i32 %precision, double %value)
+  %parsed = call double @strtod(i8* %buffer, i8** null)
+  %exact = fcmp oeq double %parsed, %value
+  %last = icmp sge i32 %precision, 17
+  %done = or i1 %exact, %last
+  br i1 %done, label %exit, label %retry
+retry:
+  %next = add i32 %precision, 1
+  br label %attempt
+exit:
+  ret i8* %buffer
+}
+
+"#;
+
+/// The LLVM type values of an nlang type are lowered to. Arrays, structs
+/// and enums are i64 handles.
+fn llvm_type(ty: &Type) -> &'static str {
+    match ty {
+        Type::Integer => "i64",
+        Type::Float => "double",
+        Type::Boolean => "i1",
+        Type::String => "i8*",
+        Type::Void => "void",
+        _ => "i64",
+    }
+}
+
+/// How `nl_print_slot` prints an array element of type `ty`, for the
+/// element types it can print.
+fn slot_kind(ty: &Type) -> Option<i64> {
+    match ty {
+        Type::Integer => Some(0),
+        Type::Float => Some(1),
+        Type::Boolean => Some(2),
+        Type::String => Some(3),
+        _ => None,
+    }
+}
+
+/// The value a variable of type `ty` holds before anything is assigned to it.
+fn zero_value(ty: &Type) -> &'static str {
+    match ty {
+        Type::Float => "0.0",
+        Type::Boolean => "false",
+        Type::String => "null",
+        _ => "0",
+    }
+}
+
+/// The body of an LLVM `c"..."` constant holding the UTF-8 bytes of `s`,
+/// with everything but printable ASCII written as a `\XX` hex escape.
+fn escape_llvm_string(s: &str) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b' '..=b'~' if byte != b'"' && byte != b'\\' => (byte as char).to_string(),
+            byte => format!("\\{:02X}", byte),
+        })
+        .collect()
+}
+
+/// The `printf` conversion an interpolated value of type `ty` is written
+/// with: ints and floats with a precision as numbers, so the spec's zero
+/// padding and precision apply, and everything else as the text `str()`
+/// gives it.
+fn printf_conversion(ty: Option<&Type>, spec: &FormatSpec) -> &'static str {
+    match ty {
+        Some(Type::Integer) => "lld",
+        Some(Type::Float) if spec.precision.is_some() => "f",
+        _ => "s",
+    }
+}
+
+/// A generated value together with its nlang type.
+#[derive(Debug, Clone)]
+struct Operand {
+    value: String,
+    ty: Type,
+}
+
+impl Operand {
+    fn new(value: impl Into<String>, ty: Type) -> Self {
+        Operand { value: value.into(), ty }
+    }
+
+    /// The result of an expression that produces no value, like a call to
+    /// a void function.
+    fn void() -> Self {
+        Operand::new("0", Type::Void)
+    }
+}
+
 pub struct LLVMCodeGenerator {
     module_name: String,
-    #[allow(dead_code)]
-    functions: Vec<String>,
-    #[allow(dead_code)]
-    current_function: String,
+    target: Target,
+    // Parameter and return types of every function in the program
+    functions: HashMap<String, (Vec<Type>, Type)>,
+    // Return type of the function being generated
+    return_type: Type,
     basic_blocks: Vec<String>,
+    // Stack slot of each variable in scope
     variables: HashMap<String, String>,
+    variable_types: HashMap<String, Type>,
+    // The `alloca`s of the function being generated. They all go in its
+    // entry block, so a loop doesn't allocate again on every iteration
+    allocas: String,
+    // Fields of each declared struct, in declaration order
+    structs: HashMap<String, Vec<(String, Type)>>,
+    // Variants of each declared enum and their payload types; a variant's
+    // tag is its index
+    enums: HashMap<String, Vec<(String, Vec<Type>)>>,
     string_constants: HashMap<String, String>,
     temp_counter: usize,
     label_counter: usize,
+    slot_counter: usize,
     string_counter: usize,
     // Loop context for break/continue
     loop_stack: Vec<LoopContext>,
+    // What analysis found each expression's type to be
+    types: TypeTable,
 }
 
 #[derive(Clone)]
@@ -37,28 +351,37 @@ struct LoopContext {
 }
 
 impl LLVMCodeGenerator {
-    pub fn new(module_name: &str) -> Self {
+    pub fn new(module_name: &str, target: Target) -> Self {
         Self {
             module_name: module_name.to_string(),
-            functions: Vec::new(),
-            current_function: String::new(),
+            target,
+            functions: HashMap::new(),
+            return_type: Type::Void,
             basic_blocks: Vec::new(),
             variables: HashMap::new(),
+            variable_types: HashMap::new(),
+            allocas: String::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
             string_constants: HashMap::new(),
             temp_counter: 0,
             label_counter: 0,
+            slot_counter: 0,
             string_counter: 0,
             loop_stack: Vec::new(),
+            types: TypeTable::default(),
         }
     }
 
-    pub fn generate_program(&mut self, program: &Program) -> Result<String, LLVMCodeGenError> {
+    pub fn generate_program(&mut self, typed: &TypedProgram) -> Result<String, LLVMCodeGenError> {
+        let program = &typed.program;
+        self.types = typed.types.clone();
         let mut output = String::new();
         
         // Module header
         output.push_str(&format!("; ModuleID = '{}'\n", self.module_name));
-        output.push_str("target datalayout = \"e-m:w-p270:32:32-p271:32:32-p272:64:64-i64:64-f80:128-n8:16:32:64-S128\"\n");
-        output.push_str("target triple = \"x86_64-pc-windows-msvc\"\n\n");
+        output.push_str(&format!("target datalayout = \"{}\"\n", self.target.datalayout()));
+        output.push_str(&format!("target triple = \"{}\"\n\n", self.target.triple()));
 
         // First pass: collect all string literals
         self.collect_string_literals(program);
@@ -68,23 +391,24 @@ impl LLVMCodeGenerator {
         output.push_str("declare i32 @printf(i8*, ...)\n");
         output.push_str("declare i32 @puts(i8*)\n");
         output.push_str("declare void @llvm.memcpy.p0i8.p0i8.i64(i8*, i8*, i64, i1)\n\n");
+        output.push_str(ARRAY_RUNTIME);
+        output.push_str(STRING_RUNTIME);
+        output.push_str(&self.generate_struct_types(program));
+        self.collect_function_signatures(program);
 
         // String constants for print functions
         output.push_str("; String constants\n");
         output.push_str("@.str = private unnamed_addr constant [4 x i8] c\"%s\\0A\\00\", align 1\n");
         output.push_str("@.str.1 = private unnamed_addr constant [3 x i8] c\"%s\\00\", align 1\n");
-        output.push_str("@.str.2 = private unnamed_addr constant [4 x i8] c\"%d\\0A\\00\", align 1\n");
-        output.push_str("@.str.3 = private unnamed_addr constant [3 x i8] c\"%d\\00\", align 1\n");
-        output.push_str("@.str.4 = private unnamed_addr constant [4 x i8] c\"%f\\0A\\00\", align 1\n");
-        output.push_str("@.str.5 = private unnamed_addr constant [3 x i8] c\"%f\\00\", align 1\n");
+        output.push_str("@.str.2 = private unnamed_addr constant [6 x i8] c\"%lld\\0A\\00\", align 1\n");
+        output.push_str("@.str.3 = private unnamed_addr constant [5 x i8] c\"%lld\\00\", align 1\n");
         output.push_str("@.str.bool_true = private unnamed_addr constant [5 x i8] c\"true\\00\", align 1\n");
         output.push_str("@.str.bool_false = private unnamed_addr constant [6 x i8] c\"false\\00\", align 1\n");
 
         // Generate string constants
         for (content, name) in &self.string_constants {
-            let escaped_content = content.replace("\\", "\\\\").replace("\"", "\\22");
             output.push_str(&format!("{} = private unnamed_addr constant [{} x i8] c\"{}\\00\", align 1\n", 
-                name, content.len() + 1, escaped_content));
+                name, content.len() + 1, escape_llvm_string(content)));
         }
         output.push('\n');
 
@@ -97,9 +421,61 @@ impl LLVMCodeGenerator {
             }
         }
 
+        // The C runtime calls `main` and exits with the status it returns
+        let has_main = program.statements.iter()
+            .any(|stmt| matches!(stmt, Statement::FunctionDeclaration { name, .. } if name == "main"));
+        if has_main {
+            output.push_str("; Entry point\n");
+            output.push_str(&format!("define {} i32 @main() {{\n", self.target.calling_convention()));
+            output.push_str("entry:\n");
+            output.push_str("  call void @nl_main()\n");
+            output.push_str("  ret i32 0\n");
+            output.push_str("}\n");
+        }
+
         Ok(output)
     }
 
+    /// Declares a named struct type for every struct and enum declaration.
+    /// Every field is an i64 like any other value, and struct values are
+    /// handles to heap-allocated instances. An enum holds its variant's tag
+    /// followed by enough slots for the payload of its largest variant.
+    fn generate_struct_types(&mut self, program: &Program) -> String {
+        let mut output = String::new();
+        for stmt in &program.statements {
+            match stmt {
+                Statement::StructDeclaration { name, fields, .. } => {
+                    let field_types = vec!["i64"; fields.len()];
+                    output.push_str(&format!("%struct.{} = type {{ {} }}\n", name, field_types.join(", ")));
+                    let fields = fields.iter().map(|field| (field.name.clone(), field.field_type.clone())).collect();
+                    self.structs.insert(name.clone(), fields);
+                }
+                Statement::EnumDeclaration { name, variants, .. } => {
+                    let slots = 1 + variants.iter().map(|variant| variant.fields.len()).max().unwrap_or(0);
+                    output.push_str(&format!("%enum.{} = type {{ {} }}\n", name, vec!["i64"; slots].join(", ")));
+                    let variants = variants.iter().map(|variant| (variant.name.clone(), variant.fields.clone())).collect();
+                    self.enums.insert(name.clone(), variants);
+                }
+                _ => {}
+            }
+        }
+        if !output.is_empty() {
+            output.insert_str(0, "; Struct and enum types\n");
+            output.push('\n');
+        }
+        output
+    }
+
+    fn collect_function_signatures(&mut self, program: &Program) {
+        for stmt in &program.statements {
+            if let Statement::FunctionDeclaration { name, parameters, return_type, .. } = stmt {
+                let parameter_types = parameters.iter().map(|param| param.param_type.clone()).collect();
+                let return_type = return_type.clone().unwrap_or(Type::Void);
+                self.functions.insert(name.clone(), (parameter_types, return_type));
+            }
+        }
+    }
+
     fn collect_string_literals(&mut self, program: &Program) {
         for stmt in &program.statements {
             self.collect_strings_from_statement(stmt);
@@ -116,33 +492,39 @@ impl LLVMCodeGenerator {
             Statement::Expression(expr) => {
                 self.collect_strings_from_expression(expr);
             }
-            Statement::Return { value } => {
-                if let Some(expr) = value {
-                    self.collect_strings_from_expression(expr);
-                }
+            Statement::Return { value: Some(expr), .. } => {
+                self.collect_strings_from_expression(expr);
             }
-            Statement::LetDeclaration { initializer, .. } => {
-                if let Some(expr) = initializer {
-                    self.collect_strings_from_expression(expr);
-                }
+            Statement::LetDeclaration { initializer: Some(expr), .. } => {
+                self.collect_strings_from_expression(expr);
             }
-            Statement::While { condition, body } => {
+            Statement::While { condition, body, .. } => {
                 self.collect_strings_from_expression(condition);
                 self.collect_strings_from_statement(body);
             }
-            Statement::If { condition, then_branch, else_branch } => {
+            Statement::For { iterable, body, .. } => {
+                self.collect_strings_from_expression(iterable);
+                self.collect_strings_from_statement(body);
+            }
+            Statement::If { condition, then_branch, else_branch, .. } => {
                 self.collect_strings_from_expression(condition);
                 self.collect_strings_from_statement(then_branch);
                 if let Some(else_stmt) = else_branch {
                     self.collect_strings_from_statement(else_stmt);
                 }
             }
-            Statement::Block { statements } => {
+            Statement::Block { statements, .. } => {
                 for statement in statements {
                     self.collect_strings_from_statement(statement);
                 }
             }
-            Statement::Break | Statement::Continue => {
+            Statement::Match { scrutinee, arms, .. } => {
+                self.collect_strings_from_expression(scrutinee);
+                for arm in arms {
+                    self.collect_strings_from_statement(&arm.body);
+                }
+            }
+            Statement::Break { .. } | Statement::Continue { .. } => {
                 // No strings to collect from break/continue
             }
             _ => {}
@@ -151,83 +533,143 @@ impl LLVMCodeGenerator {
 
     fn collect_strings_from_expression(&mut self, expr: &Expr) {
         match expr {
-            Expr::Literal(Literal::String(s)) => {
-                if !self.string_constants.contains_key(s) {
-                    let name = format!("@.str.{}", self.string_counter + 4); // +4 to avoid conflicts with printf format strings
-                    self.string_constants.insert(s.clone(), name);
-                    self.string_counter += 1;
-                }
+            Expr::Literal { value: Literal::String(s), .. } if !self.string_constants.contains_key(s) => {
+                let name = format!("@.str.{}", self.string_counter + 6); // +6 to stay clear of the printf format strings @.str.1 to @.str.3
+                self.string_constants.insert(s.clone(), name);
+                self.string_counter += 1;
             }
             Expr::Binary { left, right, .. } => {
                 self.collect_strings_from_expression(left);
                 self.collect_strings_from_expression(right);
             }
+            Expr::Unary { operand, .. } => {
+                self.collect_strings_from_expression(operand);
+            }
+            Expr::Assign { value, .. } => {
+                self.collect_strings_from_expression(value);
+            }
             Expr::Call { arguments, .. } => {
                 for arg in arguments {
                     self.collect_strings_from_expression(arg);
                 }
             }
+            Expr::Range { start, end, .. } => {
+                self.collect_strings_from_expression(start);
+                self.collect_strings_from_expression(end);
+            }
+            Expr::Array { elements, .. } => {
+                for element in elements {
+                    self.collect_strings_from_expression(element);
+                }
+            }
+            Expr::Index { sequence, index, .. } => {
+                self.collect_strings_from_expression(sequence);
+                self.collect_strings_from_expression(index);
+            }
+            Expr::SetIndex { sequence, index, value, .. } => {
+                self.collect_strings_from_expression(sequence);
+                self.collect_strings_from_expression(index);
+                self.collect_strings_from_expression(value);
+            }
+            Expr::StructLiteral { fields, .. } => {
+                for (_, value) in fields {
+                    self.collect_strings_from_expression(value);
+                }
+            }
+            Expr::Get { object, .. } => {
+                self.collect_strings_from_expression(object);
+            }
+            Expr::Set { object, value, .. } => {
+                self.collect_strings_from_expression(object);
+                self.collect_strings_from_expression(value);
+            }
+            Expr::EnumVariant { arguments, .. } => {
+                for arg in arguments {
+                    self.collect_strings_from_expression(arg);
+                }
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                self.collect_strings_from_expression(scrutinee);
+                for arm in arms {
+                    self.collect_strings_from_expression(&arm.body);
+                }
+            }
+            Expr::FormatString { parts, .. } => {
+                let format = self.printf_format(parts);
+                if !self.string_constants.contains_key(&format) {
+                    let name = format!("@.str.{}", self.string_counter + 6);
+                    self.string_constants.insert(format, name);
+                    self.string_counter += 1;
+                }
+                for part in parts {
+                    if let FormatPart::Expr { expr, .. } = part {
+                        self.collect_strings_from_expression(expr);
+                    }
+                }
+            }
             _ => {}
         }
 }
 
+    /// The `snprintf` format an `f"..."` string is written with.
+    fn printf_format(&self, parts: &[FormatPart]) -> String {
+        let mut format = String::new();
+        for part in parts {
+            match part {
+                FormatPart::Literal(text) => format.push_str(&text.replace('%', "%%")),
+                FormatPart::Expr { expr, spec } => {
+                    let conversion = printf_conversion(self.types.type_of(expr), spec);
+                    format.push_str(&format!("%{}{}", spec.printf_flags(), conversion));
+                }
+            }
+        }
+        format
+    }
+
     fn generate_function(&mut self, stmt: &Statement) -> Result<String, LLVMCodeGenError> {
         if let Statement::FunctionDeclaration { name, parameters, body, return_type, .. } = stmt {
             let mut func_ir = String::new();
             
             // Reset state for new function
             self.variables.clear();
+            self.variable_types.clear();
+            self.allocas.clear();
             self.temp_counter = 0;
             self.label_counter = 0;
+            self.slot_counter = 0;
             self.basic_blocks.clear();
+            self.return_type = return_type.clone().unwrap_or(Type::Void);
             
-            // Function signature
-            let ret_type = match return_type.as_ref().unwrap_or(&Type::Void) {
-                Type::Integer => "i64",
-                Type::Float => "double",
-                Type::Boolean => "i1",
-                Type::String => "i8*",
-                Type::Void => "void",
-                _ => "i64", // Default
-            };
-
-            func_ir.push_str(&format!("define {} @{}(", ret_type, name));
+            // Function signature; `main` is wrapped by the entry point, which
+            // returns the exit status
+            let symbol = if name == "main" { "nl_main" } else { name.as_str() };
+            let params: Vec<String> = parameters.iter()
+                .map(|param| format!("{} %{}", llvm_type(&param.param_type), param.name))
+                .collect();
+            func_ir.push_str(&format!("define {} {} @{}({}) {{\n",
+                self.target.calling_convention(), llvm_type(&self.return_type), symbol, params.join(", ")));
+            func_ir.push_str("entry:\n");
             
-            // Parameters
-            for (i, param) in parameters.iter().enumerate() {
-                if i > 0 {
-                    func_ir.push_str(", ");
-                }
-                let param_type = match param.param_type {
-                    Type::Integer => "i64",
-                    Type::Float => "double",
-                    Type::Boolean => "i1",
-                    Type::String => "i8*",
-                    _ => "i64",
-                };
-                func_ir.push_str(&format!("{} %{}", param_type, param.name));
-                self.variables.insert(param.name.clone(), format!("%{}", param.name));
+            // Parameters are assignable, so each gets a stack slot like any other variable
+            let mut body_ir = String::new();
+            for param in parameters {
+                let value = format!("%{}", param.name);
+                body_ir.push_str(&self.define_local(&param.name, param.param_type.clone(), &value));
             }
-            
-            func_ir.push_str(") {\n");
-            func_ir.push_str("entry:\n");
 
             // Generate function body
             for stmt in body {
                 let stmt_ir = self.generate_statement(stmt)?;
-                func_ir.push_str(&stmt_ir);
+                body_ir.push_str(&stmt_ir);
             }
+            func_ir.push_str(&self.allocas);
+            func_ir.push_str(&body_ir);
 
-            // Add default return if needed
-            if !body.iter().any(|s| matches!(s, Statement::Return { .. })) {
-                match return_type.as_ref().unwrap_or(&Type::Void) {
-                    Type::Void => func_ir.push_str("  ret void\n"),
-                    Type::Integer => func_ir.push_str("  ret i64 0\n"),
-                    Type::Float => func_ir.push_str("  ret double 0.0\n"),
-                    Type::Boolean => func_ir.push_str("  ret i1 false\n"),
-                    Type::String => func_ir.push_str("  ret i8* null\n"),
-                    _ => func_ir.push_str("  ret i64 0\n"),
-                }
+            // Add a default return; after an explicit one it lands in an
+            // unreachable block, which still needs a terminator
+            match &self.return_type {
+                Type::Void => func_ir.push_str("  ret void\n"),
+                return_type => func_ir.push_str(&format!("  ret {} {}\n", llvm_type(return_type), zero_value(return_type))),
             }
 
             func_ir.push_str("}\n");
@@ -235,33 +677,34 @@ impl LLVMCodeGenerator {
         } else {
             Err(LLVMCodeGenError {
                 message: "Expected function declaration".to_string(),
+                span: None,
             })
         }
     }
 
     fn generate_statement(&mut self, stmt: &Statement) -> Result<String, LLVMCodeGenError> {
+        self.generate_statement_kind(stmt)
+            .map_err(|e| e.with_span(stmt.span()))
+    }
+
+    fn generate_statement_kind(&mut self, stmt: &Statement) -> Result<String, LLVMCodeGenError> {
         match stmt {
-            Statement::LetDeclaration { name, initializer, .. } => {
+            Statement::LetDeclaration { name, var_type, initializer, .. } => {
                 let mut stmt_ir = String::new();
                 
-                if let Some(init_expr) = initializer {
-                    let (expr_ir, expr_result) = self.generate_expression(init_expr)?;
+                let (value, ty) = if let Some(init_expr) = initializer {
+                    let (expr_ir, operand) = self.generate_expression(init_expr)?;
                     stmt_ir.push_str(&expr_ir);
-                    
-                    // Allocate space for the variable
-                    let var_name = format!("%{}", name);
-                    stmt_ir.push_str(&format!("  {} = alloca i64, align 8\n", var_name));
-                    stmt_ir.push_str(&format!("  store i64 {}, i64* {}, align 8\n", expr_result, var_name));
-                    
-                    self.variables.insert(name.clone(), var_name);
+                    // An annotation wins over the initializer's type
+                    let ty = var_type.clone().unwrap_or_else(|| operand.ty.clone());
+                    let (convert_ir, value) = self.convert(&operand, &ty);
+                    stmt_ir.push_str(&convert_ir);
+                    (value, ty)
                 } else {
-                    // Default initialization
-                    let var_name = format!("%{}", name);
-                    stmt_ir.push_str(&format!("  {} = alloca i64, align 8\n", var_name));
-                    stmt_ir.push_str(&format!("  store i64 0, i64* {}, align 8\n", var_name));
-                    
-                    self.variables.insert(name.clone(), var_name);
-                }
+                    let ty = var_type.clone().unwrap_or(Type::Integer);
+                    (zero_value(&ty).to_string(), ty)
+                };
+                stmt_ir.push_str(&self.define_local(name, ty, &value));
                 
                 Ok(stmt_ir)
             }
@@ -269,20 +712,30 @@ impl LLVMCodeGenerator {
                 let (expr_ir, _) = self.generate_expression(expr)?;
                 Ok(expr_ir)
             }
-            Statement::Return { value } => {
+            Statement::Return { value, .. } => {
                 let mut stmt_ir = String::new();
                 
-                if let Some(expr) = value {
-                    let (expr_ir, expr_result) = self.generate_expression(expr)?;
-                    stmt_ir.push_str(&expr_ir);
-                    stmt_ir.push_str(&format!("  ret i64 {}\n", expr_result));
-                } else {
-                    stmt_ir.push_str("  ret void\n");
+                match value {
+                    Some(expr) if self.return_type != Type::Void => {
+                        let (expr_ir, operand) = self.generate_expression(expr)?;
+                        stmt_ir.push_str(&expr_ir);
+                        let return_type = self.return_type.clone();
+                        let (convert_ir, result) = self.convert(&operand, &return_type);
+                        stmt_ir.push_str(&convert_ir);
+                        stmt_ir.push_str(&format!("  ret {} {}\n", llvm_type(&return_type), result));
+                    }
+                    Some(expr) => {
+                        let (expr_ir, _) = self.generate_expression(expr)?;
+                        stmt_ir.push_str(&expr_ir);
+                        stmt_ir.push_str("  ret void\n");
+                    }
+                    None => stmt_ir.push_str("  ret void\n"),
                 }
+                stmt_ir.push_str(&self.unreachable_block());
                 
                 Ok(stmt_ir)
             }
-            Statement::While { condition, body } => {
+            Statement::While { condition, body, .. } => {
                 let mut stmt_ir = String::new();
                 
                 // Generate unique labels for the loop
@@ -302,6 +755,7 @@ impl LLVMCodeGenerator {
                 // Loop start: check condition
                 stmt_ir.push_str(&format!("{}:\n", loop_start));
                 let (cond_ir, cond_result) = self.generate_expression(condition)?;
+                let cond_result = cond_result.value;
                 stmt_ir.push_str(&cond_ir);
                 stmt_ir.push_str(&format!("  br i1 {}, label %{}, label %{}\n", cond_result, loop_body, loop_end));
                 
@@ -319,7 +773,89 @@ impl LLVMCodeGenerator {
                 
                 Ok(stmt_ir)
             }
-            Statement::If { condition, then_branch, else_branch } => {
+            Statement::For { variable, iterable, body, span } => self.scoped(|this| {
+                let mut stmt_ir = String::new();
+                
+                let loop_header = this.next_label();
+                let loop_body = this.next_label();
+                let loop_latch = this.next_label();
+                let loop_end = this.next_label();
+                
+                // Range loops count with the loop variable itself; array loops
+                // count with a hidden index and load each element into it.
+                // Either way the bounds are evaluated once, before the loop.
+                let (counter, end_result, array) = if let Expr::Range { start, end, .. } = iterable.as_ref() {
+                    let (start_ir, start_result) = this.generate_expression(start)?;
+                    stmt_ir.push_str(&start_ir);
+                    let (end_ir, end_result) = this.generate_expression(end)?;
+                    stmt_ir.push_str(&end_ir);
+                    stmt_ir.push_str(&this.define_local(variable, Type::Integer, &start_result.value));
+                    (this.variables[variable].clone(), end_result.value, None)
+                } else {
+                    let (array_ir, handle) = this.generate_expression(iterable)?;
+                    stmt_ir.push_str(&array_ir);
+                    let len = this.next_temp();
+                    stmt_ir.push_str(&format!("  {} = call i64 @nl_array_len(i64 {})\n", len, handle.value));
+                    let counter = format!("%{}.index", loop_header);
+                    this.alloca(&counter, "i64");
+                    stmt_ir.push_str(&format!("  store i64 0, i64* {}, align 8\n", counter));
+                    let element_type = match handle.ty {
+                        Type::Array(ref element_type) => (**element_type).clone(),
+                        _ => Type::Integer,
+                    };
+                    stmt_ir.push_str(&this.define_local(variable, element_type.clone(), zero_value(&element_type)));
+                    (counter, len, Some((handle.value, element_type)))
+                };
+                
+                // `continue` still has to increment the counter
+                this.loop_stack.push(LoopContext {
+                    continue_label: loop_latch.clone(),
+                    break_label: loop_end.clone(),
+                });
+                
+                // Header: compare the counter against the end bound
+                stmt_ir.push_str(&format!("  br label %{}\n", loop_header));
+                stmt_ir.push_str(&format!("{}:\n", loop_header));
+                let current = this.next_temp();
+                stmt_ir.push_str(&format!("  {} = load i64, i64* {}, align 8\n", current, counter));
+                let in_range = this.next_temp();
+                stmt_ir.push_str(&format!("  {} = icmp slt i64 {}, {}\n", in_range, current, end_result));
+                stmt_ir.push_str(&format!("  br i1 {}, label %{}, label %{}\n", in_range, loop_body, loop_end));
+                
+                // Loop body
+                stmt_ir.push_str(&format!("{}:\n", loop_body));
+                if let Some((handle, element_type)) = array {
+                    let index = this.next_temp();
+                    stmt_ir.push_str(&format!("  {} = load i64, i64* {}, align 8\n", index, counter));
+                    let slot = this.next_temp();
+                    stmt_ir.push_str(&format!("  {} = call i64* @nl_array_at(i64 {}, i64 {}, i64 {}, i64 {})\n",
+                        slot, handle, index, span.line, span.column));
+                    let bits = this.next_temp();
+                    stmt_ir.push_str(&format!("  {} = load i64, i64* {}, align 8\n", bits, slot));
+                    let (element_ir, element) = this.slot_value(&bits, &element_type);
+                    stmt_ir.push_str(&element_ir);
+                    stmt_ir.push_str(&format!("  store {} {}, {}* {}, align 8\n",
+                        llvm_type(&element_type), element.value, llvm_type(&element_type), this.variables[variable]));
+                }
+                let body_ir = this.generate_statement(body)?;
+                stmt_ir.push_str(&body_ir);
+                stmt_ir.push_str(&format!("  br label %{}\n", loop_latch));
+                
+                // Latch: increment and go back to the header
+                stmt_ir.push_str(&format!("{}:\n", loop_latch));
+                let value = this.next_temp();
+                stmt_ir.push_str(&format!("  {} = load i64, i64* {}, align 8\n", value, counter));
+                let next = this.next_temp();
+                stmt_ir.push_str(&format!("  {} = add i64 {}, 1\n", next, value));
+                stmt_ir.push_str(&format!("  store i64 {}, i64* {}, align 8\n", next, counter));
+                stmt_ir.push_str(&format!("  br label %{}\n", loop_header));
+                
+                stmt_ir.push_str(&format!("{}:\n", loop_end));
+                this.loop_stack.pop();
+                
+                Ok(stmt_ir)
+            }),
+            Statement::If { condition, then_branch, else_branch, .. } => {
                 let mut stmt_ir = String::new();
                 
                 let then_label = self.next_label();
@@ -328,6 +864,7 @@ impl LLVMCodeGenerator {
                 
                 // Generate condition
                 let (cond_ir, cond_result) = self.generate_expression(condition)?;
+                let cond_result = cond_result.value;
                 stmt_ir.push_str(&cond_ir);
                 
                 if else_branch.is_some() {
@@ -355,258 +892,804 @@ impl LLVMCodeGenerator {
                 
                 Ok(stmt_ir)
             }
-            Statement::Block { statements } => {
+            Statement::Block { statements, .. } => self.scoped(|this| {
                 let mut stmt_ir = String::new();
                 for statement in statements {
-                    let sub_ir = self.generate_statement(statement)?;
+                    let sub_ir = this.generate_statement(statement)?;
                     stmt_ir.push_str(&sub_ir);
                 }
                 Ok(stmt_ir)
-            }
-            Statement::Break => {
+            }),
+            Statement::Break { .. } => {
                 if let Some(loop_ctx) = self.loop_stack.last() {
-                    Ok(format!("  br label %{}\n", loop_ctx.break_label))
+                    let br = format!("  br label %{}\n", loop_ctx.break_label);
+                    Ok(br + &self.unreachable_block())
                 } else {
                     Err(LLVMCodeGenError {
                         message: "Break statement outside of loop".to_string(),
+                        span: None,
                     })
                 }
             }
-            Statement::Continue => {
+            Statement::Continue { .. } => {
                 if let Some(loop_ctx) = self.loop_stack.last() {
-                    Ok(format!("  br label %{}\n", loop_ctx.continue_label))
+                    let br = format!("  br label %{}\n", loop_ctx.continue_label);
+                    Ok(br + &self.unreachable_block())
                 } else {
                     Err(LLVMCodeGenError {
                         message: "Continue statement outside of loop".to_string(),
+                        span: None,
                     })
                 }
             }
+            Statement::Match { scrutinee, arms, .. } => {
+                self.generate_match(scrutinee, arms, |this, body| this.generate_statement(body))
+            }
             _ => Ok(String::new()), // Skip other statements for now
         }
     }
 
-    fn generate_expression(&mut self, expr: &Expr) -> Result<(String, String), LLVMCodeGenError> {
+    fn generate_expression(&mut self, expr: &Expr) -> Result<(String, Operand), LLVMCodeGenError> {
+        self.generate_expression_kind(expr)
+            .map_err(|e| e.with_span(expr.span()))
+    }
+
+    fn generate_expression_kind(&mut self, expr: &Expr) -> Result<(String, Operand), LLVMCodeGenError> {
         match expr {
-            Expr::Literal(literal) => {
-                match literal {
-                    Literal::Integer(value) => Ok((String::new(), value.to_string())),
-                    Literal::Float(value) => Ok((String::new(), value.to_string())),
-                    Literal::Boolean(value) => Ok((String::new(), if *value { "1" } else { "0" }.to_string())),
-                    Literal::String(value) => {
-                        // Get the string constant name
-                        if let Some(str_name) = self.string_constants.get(value) {
-                            Ok((String::new(), str_name.clone()))
-                        } else {
-                            Err(LLVMCodeGenError {
-                                message: format!("String constant not found: {}", value),
-                            })
-                        }
-                    }
-                    Literal::Null => Ok((String::new(), "null".to_string())),
-                }
+            Expr::Literal { value: literal, .. } => {
+                let operand = match literal {
+                    Literal::Integer(value) => Operand::new(value.to_string(), Type::Integer),
+                    // Hexadecimal is the one notation LLVM takes for any double
+                    Literal::Float(value) => Operand::new(format!("0x{:016X}", value.to_bits()), Type::Float),
+                    Literal::Boolean(value) => Operand::new(value.to_string(), Type::Boolean),
+                    Literal::String(value) => Operand::new(self.string_constant(value)?, Type::String),
+                    Literal::Null => Operand::new("null", Type::Void),
+                };
+                Ok((String::new(), operand))
             }
-            Expr::Variable(name) => {
-                if let Some(var_ref) = self.variables.get(name).cloned() {
-                    let temp_name = self.next_temp();
-                    let load_ir = format!("  {} = load i64, i64* {}, align 8\n", temp_name, var_ref);
-                    Ok((load_ir, temp_name))
-                } else {
-                    Err(LLVMCodeGenError {
-                        message: format!("Undefined variable: {}", name),
-                    })
-                }
+            Expr::Variable { name, .. } => {
+                self.load_variable(name).ok_or_else(|| LLVMCodeGenError {
+                    message: format!("Undefined variable: {}", name),
+                    span: None,
+                })
             }
-            Expr::Binary { left, operator, right } => {
-                let (left_ir, left_result) = self.generate_expression(left)?;
-                let (right_ir, right_result) = self.generate_expression(right)?;
-                
-                let temp_name = self.next_temp();
-                let mut expr_ir = String::new();
-                expr_ir.push_str(&left_ir);
-                expr_ir.push_str(&right_ir);
-                
-                let op_instr = match operator {
-                    BinaryOperator::Plus => "add",
-                    BinaryOperator::Minus => "sub",
-                    BinaryOperator::Star => "mul",
-                    BinaryOperator::Slash => "sdiv",
-                    BinaryOperator::Percent => "srem",
-                    BinaryOperator::EqualEqual => "icmp eq",
-                    BinaryOperator::NotEqual => "icmp ne",
-                    BinaryOperator::Less => "icmp slt",
-                    BinaryOperator::LessEqual => "icmp sle",
-                    BinaryOperator::Greater => "icmp sgt",
-                    BinaryOperator::GreaterEqual => "icmp sge",
-                    BinaryOperator::And => "and",
-                    BinaryOperator::Or => "or",
-                };
-                
-                expr_ir.push_str(&format!("  {} = {} i64 {}, {}\n", temp_name, op_instr, left_result, right_result));
-                Ok((expr_ir, temp_name))
+            Expr::Binary { left, operator, right, .. } => {
+                let (left_ir, left) = self.generate_expression(left)?;
+                let (right_ir, right) = self.generate_expression(right)?;
+                let (operator_ir, result) = self.generate_binary(operator, &left, &right)?;
+                Ok((left_ir + &right_ir + &operator_ir, result))
             }
-            Expr::Unary { operator, operand } => {
-                let (operand_ir, operand_result) = self.generate_expression(operand)?;
+            Expr::Unary { operator, operand, .. } => {
+                let (mut expr_ir, operand) = self.generate_expression(operand)?;
                 let temp_name = self.next_temp();
-                let mut expr_ir = String::new();
-                expr_ir.push_str(&operand_ir);
                 
                 match operator {
+                    UnaryOperator::Negate if operand.ty == Type::Float => {
+                        expr_ir.push_str(&format!("  {} = fneg double {}\n", temp_name, operand.value));
+                    }
                     UnaryOperator::Negate => {
-                        expr_ir.push_str(&format!("  {} = sub i64 0, {}\n", temp_name, operand_result));
+                        expr_ir.push_str(&format!("  {} = sub i64 0, {}\n", temp_name, operand.value));
                     }
                     UnaryOperator::Not => {
-                        expr_ir.push_str(&format!("  {} = xor i1 {}, true\n", temp_name, operand_result));
+                        expr_ir.push_str(&format!("  {} = xor i1 {}, true\n", temp_name, operand.value));
                     }
                 }
                 
-                Ok((expr_ir, temp_name))
+                Ok((expr_ir, Operand::new(temp_name, operand.ty)))
             }
-            Expr::Call { callee, arguments } => {
-                if let Expr::Variable(func_name) = callee.as_ref() {
+            Expr::Call { callee, arguments, .. } => {
+                if let Expr::Variable { name: func_name, .. } = callee.as_ref()
+                    && !self.variables.contains_key(func_name) {
                     match func_name.as_str() {
                         "print" | "println" => {
                             if arguments.len() != 1 {
                                 return Err(LLVMCodeGenError {
                                     message: format!("{} expects exactly 1 argument", func_name),
+                                    span: None,
                                 });
                             }
                             
-                            let (arg_ir, arg_result) = self.generate_expression(&arguments[0])?;
-                            let mut call_ir = String::new();
-                            call_ir.push_str(&arg_ir);
+                            let (mut call_ir, argument) = self.generate_expression(&arguments[0])?;
+                            let line = func_name == "println";
                             
-                            match &arguments[0] {
-                                // String literals
-                                Expr::Literal(Literal::String(s)) => {
-                                    if let Some(str_name) = self.string_constants.get(s) {
-                                        let format_str = if func_name == "println" { "@.str" } else { "@.str.1" };
-                                        call_ir.push_str(&format!("  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([4 x i8], [4 x i8]* {}, i32 0, i32 0), i8* getelementptr inbounds ([{} x i8], [{} x i8]* {}, i32 0, i32 0))\n", 
-                                            format_str, s.len() + 1, s.len() + 1, str_name));
+                            match &argument.ty {
+                                Type::Named(name) => {
+                                    let message = if self.enums.contains_key(name) {
+                                        format!("Printing a whole '{}' enum is not supported by the LLVM backend; match on it instead", name)
                                     } else {
+                                        format!("Printing a whole '{}' struct is not supported by the LLVM backend; print its fields instead", name)
+                                    };
+                                    return Err(LLVMCodeGenError { message, span: None });
+                                }
+                                Type::Array(element_type) => {
+                                    let Some(kind) = slot_kind(element_type) else {
                                         return Err(LLVMCodeGenError {
-                                            message: format!("String constant not found: {}", s),
+                                            message: format!("Printing an array of {} is not supported by the LLVM backend", element_type),
+                                            span: None,
                                         });
-                                    }
-                                }
-                                // Integer literals
-                                Expr::Literal(Literal::Integer(_)) => {
-                                    let format_str = if func_name == "println" { "@.str.2" } else { "@.str.3" };
-                                    call_ir.push_str(&format!("  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([4 x i8], [4 x i8]* {}, i32 0, i32 0), i64 {})\n", 
-                                        format_str, arg_result));
-                                }
-                                // Float literals
-                                Expr::Literal(Literal::Float(_)) => {
-                                    let format_str = if func_name == "println" { "@.str.4" } else { "@.str.5" };
-                                    call_ir.push_str(&format!("  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([4 x i8], [4 x i8]* {}, i32 0, i32 0), double {})\n", 
-                                        format_str, arg_result));
+                                    };
+                                    let (close, size) = if line { ("@.str.array_close_line", 3) } else { ("@.str.array_close", 2) };
+                                    call_ir.push_str(&format!("  call void @nl_array_print(i64 {}, i8* getelementptr inbounds ([{} x i8], [{} x i8]* {}, i32 0, i32 0), i64 {})\n", 
+                                        argument.value, size, size, close, kind));
                                 }
-                                // Boolean literals
-                                Expr::Literal(Literal::Boolean(b)) => {
-                                    let bool_str = if *b { "@.str.bool_true" } else { "@.str.bool_false" };
-                                    let format_str = if func_name == "println" { "@.str" } else { "@.str.1" };
-                                    call_ir.push_str(&format!("  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([4 x i8], [4 x i8]* {}, i32 0, i32 0), i8* getelementptr inbounds ([{} x i8], [{} x i8]* {}, i32 0, i32 0))\n", 
-                                        format_str, if *b { 5 } else { 6 }, if *b { 5 } else { 6 }, bool_str));
+                                Type::Integer => {
+                                    // printf's result has to be named, or it silently takes the next temp number
+                                    let printf_result = self.next_temp();
+                                    let (format_str, size) = if line { ("@.str.2", 6) } else { ("@.str.3", 5) };
+                                    call_ir.push_str(&format!("  {} = call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([{} x i8], [{} x i8]* {}, i32 0, i32 0), i64 {})\n", 
+                                        printf_result, size, size, format_str, argument.value));
                                 }
-                                // Variables and expressions - assume integer for now (needs type system for proper handling)
                                 _ => {
-                                    let format_str = if func_name == "println" { "@.str.2" } else { "@.str.3" };
-                                    call_ir.push_str(&format!("  call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([4 x i8], [4 x i8]* {}, i32 0, i32 0), i64 {})\n", 
-                                        format_str, arg_result));
+                                    let (text_ir, text) = self.text_of(&argument);
+                                    call_ir.push_str(&text_ir);
+                                    let printf_result = self.next_temp();
+                                    let (format_str, size) = if line { ("@.str", 4) } else { ("@.str.1", 3) };
+                                    call_ir.push_str(&format!("  {} = call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([{} x i8], [{} x i8]* {}, i32 0, i32 0), i8* {})\n", 
+                                        printf_result, size, size, format_str, text));
                                 }
                             }
                             
-                            Ok((call_ir, "0".to_string())) // print/println return void, represented as 0
+                            Ok((call_ir, Operand::void()))
+                        }
+                        "len" if arguments.len() == 1 => {
+                            let (arg_ir, argument) = self.generate_expression(&arguments[0])?;
+                            let temp_name = self.next_temp();
+                            let function = if argument.ty == Type::String { "@strlen(i8*" } else { "@nl_array_len(i64" };
+                            let call_ir = format!("{}  {} = call i64 {} {})\n", arg_ir, temp_name, function, argument.value);
+                            Ok((call_ir, Operand::new(temp_name, Type::Integer)))
+                        }
+                        "push" if arguments.len() == 2 => {
+                            let (array_ir, array) = self.generate_expression(&arguments[0])?;
+                            let (value_ir, value) = self.generate_expression(&arguments[1])?;
+                            let (slot_ir, bits) = self.slot_bits(&value);
+                            let call_ir = format!("{}{}{}  call void @nl_array_push(i64 {}, i64 {})\n", array_ir, value_ir, slot_ir, array.value, bits);
+                            Ok((call_ir, Operand::void()))
+                        }
+                        "str" if arguments.len() == 1 => {
+                            let (mut call_ir, argument) = self.generate_expression(&arguments[0])?;
+                            let (text_ir, text) = self.text_of(&argument);
+                            call_ir.push_str(&text_ir);
+                            Ok((call_ir, Operand::new(text, Type::String)))
+                        }
+                        "int" | "float" | "bool" if arguments.len() == 1 => {
+                            let (mut call_ir, argument) = self.generate_expression(&arguments[0])?;
+                            let ty = match func_name.as_str() {
+                                "int" => Type::Integer,
+                                "float" => Type::Float,
+                                _ => Type::Boolean,
+                            };
+                            let (convert_ir, value) = self.convert(&argument, &ty);
+                            call_ir.push_str(&convert_ir);
+                            Ok((call_ir, Operand::new(value, ty)))
+                        }
+                        "abs" | "abs_float" if arguments.len() == 1 => {
+                            let (mut call_ir, argument) = self.generate_expression(&arguments[0])?;
+                            let negated = self.next_temp();
+                            let negative = self.next_temp();
+                            if argument.ty == Type::Float {
+                                call_ir.push_str(&format!("  {} = fneg double {}\n", negated, argument.value));
+                                call_ir.push_str(&format!("  {} = fcmp olt double {}, 0.0\n", negative, argument.value));
+                            } else {
+                                call_ir.push_str(&format!("  {} = sub i64 0, {}\n", negated, argument.value));
+                                call_ir.push_str(&format!("  {} = icmp slt i64 {}, 0\n", negative, argument.value));
+                            }
+                            let result = self.next_temp();
+                            let ty = llvm_type(&argument.ty);
+                            call_ir.push_str(&format!("  {} = select i1 {}, {} {}, {} {}\n", result, negative, ty, negated, ty, argument.value));
+                            Ok((call_ir, Operand::new(result, argument.ty)))
+                        }
+                        "max" | "min" if arguments.len() == 2 => {
+                            let (left_ir, left) = self.generate_expression(&arguments[0])?;
+                            let (right_ir, right) = self.generate_expression(&arguments[1])?;
+                            let predicate = if func_name == "max" { "sgt" } else { "slt" };
+                            let left_wins = self.next_temp();
+                            let result = self.next_temp();
+                            let call_ir = format!("{}{}  {} = icmp {} i64 {}, {}\n  {} = select i1 {}, i64 {}, i64 {}\n",
+                                left_ir, right_ir, left_wins, predicate, left.value, right.value, result, left_wins, left.value, right.value);
+                            Ok((call_ir, Operand::new(result, Type::Integer)))
                         }
                         _ => {
                             // Regular function call
                             let mut call_ir = String::new();
-                            let mut arg_results = Vec::new();
+                            let mut operands = Vec::new();
                             
                             for arg in arguments {
-                                let (arg_ir, arg_result) = self.generate_expression(arg)?;
+                                let (arg_ir, operand) = self.generate_expression(arg)?;
                                 call_ir.push_str(&arg_ir);
-                                arg_results.push(arg_result);
+                                operands.push(operand);
                             }
                             
-                            let temp_name = self.next_temp();
-                            call_ir.push_str(&format!("  {} = call i64 @{}(", temp_name, func_name));
-                            
-                            for (i, arg_result) in arg_results.iter().enumerate() {
-                                if i > 0 {
-                                    call_ir.push_str(", ");
-                                }
-                                call_ir.push_str(&format!("i64 {}", arg_result));
+                            // A function the program doesn't declare takes its arguments as they are
+                            let (parameter_types, return_type) = self.functions.get(func_name).cloned()
+                                .unwrap_or_else(|| {
+                                    let return_type = self.types.type_of(expr).cloned().unwrap_or(Type::Integer);
+                                    (operands.iter().map(|operand| operand.ty.clone()).collect(), return_type)
+                                });
+                            let mut args = Vec::new();
+                            for (operand, parameter_type) in operands.iter().zip(&parameter_types) {
+                                let (convert_ir, value) = self.convert(operand, parameter_type);
+                                call_ir.push_str(&convert_ir);
+                                args.push(format!("{} {}", llvm_type(parameter_type), value));
                             }
                             
-                            call_ir.push_str(")\n");
-                            Ok((call_ir, temp_name))
+                            let symbol = if func_name == "main" { "nl_main" } else { func_name.as_str() };
+                            if return_type == Type::Void {
+                                call_ir.push_str(&format!("  call void @{}({})\n", symbol, args.join(", ")));
+                                return Ok((call_ir, Operand::void()));
+                            }
+                            let temp_name = self.next_temp();
+                            call_ir.push_str(&format!("  {} = call {} @{}({})\n", temp_name, llvm_type(&return_type), symbol, args.join(", ")));
+                            Ok((call_ir, Operand::new(temp_name, return_type)))
                         }
                     }
                 } else {
                     Err(LLVMCodeGenError {
-                        message: "Complex function calls not supported yet".to_string(),
+                        message: "Calling a function value is not supported by the LLVM backend".to_string(),
+                        span: None,
                     })
                 }
             }
-            Expr::Assign { name, value } => {
+            Expr::Assign { name, value, .. } => {
                 // Generate code for the value expression
-                let (value_ir, value_result) = self.generate_expression(value)?;
+                let (mut assign_ir, operand) = self.generate_expression(value)?;
                 
-                // Check if variable exists, if not create it
-                let var_ref = if let Some(existing_var) = self.variables.get(name).cloned() {
-                    existing_var
-                } else {
-                    // Create new variable
-                    let var_name = format!("%{}", name);
-                    self.variables.insert(name.clone(), var_name.clone());
-                    // Add alloca to the beginning of current function (this is a simplification)
-                    // In a real implementation, we'd need to track function entry blocks
-                    var_name
+                let Some(slot) = self.variables.get(name).cloned() else {
+                    assign_ir.push_str(&self.define_local(name, operand.ty.clone(), &operand.value));
+                    return Ok((assign_ir, operand));
                 };
-                
-                // Generate store instruction
-                let mut assign_ir = String::new();
-                assign_ir.push_str(&value_ir);
-                assign_ir.push_str(&format!("  store i64 {}, i64* {}, align 8\n", value_result, var_ref));
+                let ty = self.variable_types[name].clone();
+                let (convert_ir, value) = self.convert(&operand, &ty);
+                assign_ir.push_str(&convert_ir);
+                assign_ir.push_str(&format!("  store {} {}, {}* {}, align 8\n", llvm_type(&ty), value, llvm_type(&ty), slot));
                 
                 // Assignment returns the assigned value
-                Ok((assign_ir, value_result))
+                Ok((assign_ir, Operand::new(value, ty)))
             }
-            Expr::Get { object, name } => {
+            Expr::Array { elements, .. } => {
+                let mut array_ir = String::new();
+                let handle = self.next_temp();
+                array_ir.push_str(&format!("  {} = call i64 @nl_array_new()\n", handle));
+                let mut element_type = None;
+                for element in elements {
+                    let (element_ir, operand) = self.generate_expression(element)?;
+                    array_ir.push_str(&element_ir);
+                    let (slot_ir, bits) = self.slot_bits(&operand);
+                    array_ir.push_str(&slot_ir);
+                    array_ir.push_str(&format!("  call void @nl_array_push(i64 {}, i64 {})\n", handle, bits));
+                    element_type.get_or_insert(operand.ty);
+                }
+                // An empty array takes its type from the annotation it is assigned to
+                let array_type = match (self.types.type_of(expr), element_type) {
+                    (Some(array_type @ Type::Array(_)), _) => array_type.clone(),
+                    (_, element_type) => Type::Array(Box::new(element_type.unwrap_or(Type::Integer))),
+                };
+                Ok((array_ir, Operand::new(handle, array_type)))
+            }
+            Expr::Index { sequence, index, span } => {
+                let (mut element_ir, slot, element_type) = self.generate_element(sequence, index, *span)?;
+                let bits = self.next_temp();
+                element_ir.push_str(&format!("  {} = load i64, i64* {}, align 8\n", bits, slot));
+                let (value_ir, value) = self.slot_value(&bits, &element_type);
+                element_ir.push_str(&value_ir);
+                Ok((element_ir, value))
+            }
+            Expr::SetIndex { sequence, index, value, span } => {
+                let (mut element_ir, slot, _) = self.generate_element(sequence, index, *span)?;
+                let (value_ir, value) = self.generate_expression(value)?;
+                element_ir.push_str(&value_ir);
+                let (slot_ir, bits) = self.slot_bits(&value);
+                element_ir.push_str(&slot_ir);
+                element_ir.push_str(&format!("  store i64 {}, i64* {}, align 8\n", bits, slot));
+                Ok((element_ir, value))
+            }
+            Expr::Get { object, name, .. } => {
                 // Handle module-qualified access like math.PI
-                if let Expr::Variable(module_name) = object.as_ref() {
+                if let Expr::Variable { name: module_name, .. } = object.as_ref()
+                    && !self.variables.contains_key(module_name) {
                     let qualified_name = format!("{}.{}", module_name, name);
-                    if let Some(var_ref) = self.variables.get(&qualified_name).cloned() {
-                        let temp_name = self.next_temp();
-                        let load_ir = format!("  {} = load i64, i64* {}, align 8\n", temp_name, var_ref);
-                        Ok((load_ir, temp_name))
-                    } else {
-                        Err(LLVMCodeGenError {
-                            message: format!("Undefined variable: {}", qualified_name),
-                        })
-                    }
-                } else {
-                    Err(LLVMCodeGenError {
-                        message: "Complex object access not yet supported".to_string(),
+                    self.load_variable(&qualified_name).ok_or_else(|| LLVMCodeGenError {
+                        message: format!("Undefined variable: {}", qualified_name),
+                        span: None,
                     })
+                } else {
+                    let (mut field_ir, slot, field_type) = self.generate_field(object, name)?;
+                    let bits = self.next_temp();
+                    field_ir.push_str(&format!("  {} = load i64, i64* {}, align 8\n", bits, slot));
+                    let (value_ir, value) = self.slot_value(&bits, &field_type);
+                    field_ir.push_str(&value_ir);
+                    Ok((field_ir, value))
+                }
+            }
+            Expr::Set { object, name, value, .. } => {
+                let (mut field_ir, slot, _) = self.generate_field(object, name)?;
+                let (value_ir, value) = self.generate_expression(value)?;
+                field_ir.push_str(&value_ir);
+                let (slot_ir, bits) = self.slot_bits(&value);
+                field_ir.push_str(&slot_ir);
+                field_ir.push_str(&format!("  store i64 {}, i64* {}, align 8\n", bits, slot));
+                Ok((field_ir, value))
+            }
+            Expr::StructLiteral { name, fields, .. } => {
+                let Some(declared) = self.structs.get(name).cloned() else {
+                    return Err(LLVMCodeGenError {
+                        message: format!("Unknown struct: {}", name),
+                        span: None,
+                    });
+                };
+                
+                // Fields are evaluated in the order they are written
+                let mut literal_ir = String::new();
+                let mut values = HashMap::new();
+                for (field, value) in fields {
+                    let (value_ir, operand) = self.generate_expression(value)?;
+                    literal_ir.push_str(&value_ir);
+                    values.insert(field.as_str(), operand);
+                }
+                
+                let struct_type = format!("%struct.{}", name);
+                let raw = self.next_temp();
+                literal_ir.push_str(&format!("  {} = call i8* @malloc(i64 {})\n", raw, 8 * declared.len().max(1)));
+                let instance = self.next_temp();
+                literal_ir.push_str(&format!("  {} = bitcast i8* {} to {}*\n", instance, raw, struct_type));
+                for (index, (field, _)) in declared.iter().enumerate() {
+                    let Some(value) = values.get(field.as_str()) else {
+                        return Err(LLVMCodeGenError {
+                            message: format!("Missing field '{}' in initializer of '{}'", field, name),
+                            span: None,
+                        });
+                    };
+                    let (slot_ir, bits) = self.slot_bits(value);
+                    literal_ir.push_str(&slot_ir);
+                    let slot = self.next_temp();
+                    literal_ir.push_str(&format!("  {} = getelementptr {}, {}* {}, i32 0, i32 {}\n",
+                        slot, struct_type, struct_type, instance, index));
+                    literal_ir.push_str(&format!("  store i64 {}, i64* {}, align 8\n", bits, slot));
                 }
+                let handle = self.next_temp();
+                literal_ir.push_str(&format!("  {} = ptrtoint {}* {} to i64\n", handle, struct_type, instance));
+                Ok((literal_ir, Operand::new(handle, Type::Named(name.clone()))))
+            }
+            Expr::EnumVariant { enum_name, variant, arguments, .. } => {
+                let Some(tag) = self.enums.get(enum_name).and_then(|variants| variants.iter().position(|(name, _)| name == variant)) else {
+                    return Err(LLVMCodeGenError {
+                        message: format!("Unknown enum variant: {}::{}", enum_name, variant),
+                        span: None,
+                    });
+                };
+                let slots = 1 + self.enums[enum_name].iter().map(|(_, fields)| fields.len()).max().unwrap_or(0);
+                
+                let mut variant_ir = String::new();
+                let mut values = vec![Operand::new(tag.to_string(), Type::Integer)];
+                for argument in arguments {
+                    let (argument_ir, operand) = self.generate_expression(argument)?;
+                    variant_ir.push_str(&argument_ir);
+                    values.push(operand);
+                }
+                
+                let enum_type = format!("%enum.{}", enum_name);
+                let raw = self.next_temp();
+                variant_ir.push_str(&format!("  {} = call i8* @malloc(i64 {})\n", raw, 8 * slots));
+                let instance = self.next_temp();
+                variant_ir.push_str(&format!("  {} = bitcast i8* {} to {}*\n", instance, raw, enum_type));
+                for (index, value) in values.iter().enumerate() {
+                    let (slot_ir, bits) = self.slot_bits(value);
+                    variant_ir.push_str(&slot_ir);
+                    let slot = self.next_temp();
+                    variant_ir.push_str(&format!("  {} = getelementptr {}, {}* {}, i32 0, i32 {}\n",
+                        slot, enum_type, enum_type, instance, index));
+                    variant_ir.push_str(&format!("  store i64 {}, i64* {}, align 8\n", bits, slot));
+                }
+                let handle = self.next_temp();
+                variant_ir.push_str(&format!("  {} = ptrtoint {}* {} to i64\n", handle, enum_type, instance));
+                Ok((variant_ir, Operand::new(handle, Type::Named(enum_name.clone()))))
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                // Each arm stores its value into a slot that is read once the arms rejoin
+                let result_slot = format!("%match_result.{}", self.label_counter);
+                self.alloca(&result_slot, "i64");
+                let mut match_ir = String::new();
+                let result_type = self.types.type_of(expr).cloned().unwrap_or(Type::Void);
+                match_ir.push_str(&self.generate_match(scrutinee, arms, |this, body| {
+                    let (mut body_ir, body) = this.generate_expression(body)?;
+                    let (convert_ir, value) = this.convert(&body, &result_type);
+                    body_ir.push_str(&convert_ir);
+                    let (slot_ir, bits) = this.slot_bits(&Operand::new(value, result_type.clone()));
+                    body_ir.push_str(&slot_ir);
+                    Ok(format!("{}  store i64 {}, i64* {}, align 8\n", body_ir, bits, result_slot))
+                })?);
+                let bits = self.next_temp();
+                match_ir.push_str(&format!("  {} = load i64, i64* {}, align 8\n", bits, result_slot));
+                let (value_ir, value) = self.slot_value(&bits, &result_type);
+                match_ir.push_str(&value_ir);
+                Ok((match_ir, value))
+            }
+            Expr::Function { span, .. } => Err(LLVMCodeGenError {
+                message: "Lambdas are not supported by the LLVM backend".to_string(),
+                span: Some(*span),
+            }),
+            Expr::FormatString { parts, .. } => {
+                // snprintf measures the text first, then writes it into a
+                // buffer of that size
+                let mut format_ir = String::new();
+                let mut arguments = String::new();
+                for part in parts {
+                    let FormatPart::Expr { expr, spec } = part else {
+                        continue;
+                    };
+                    let (expr_ir, operand) = self.generate_expression(expr)?;
+                    format_ir.push_str(&expr_ir);
+                    let (conversion_ir, argument) = match printf_conversion(self.types.type_of(expr), spec) {
+                        "lld" => {
+                            let (ir, value) = self.convert(&operand, &Type::Integer);
+                            (ir, format!("i64 {}", value))
+                        }
+                        "f" => {
+                            let (ir, value) = self.convert(&operand, &Type::Float);
+                            (ir, format!("double {}", value))
+                        }
+                        _ => {
+                            let (ir, text) = self.text_of(&operand);
+                            (ir, format!("i8* {}", text))
+                        }
+                    };
+                    format_ir.push_str(&conversion_ir);
+                    arguments.push_str(&format!(", {}", argument));
+                }
+                let format = self.string_constant(&self.printf_format(parts))?;
+                let length = self.next_temp();
+                let wide_length = self.next_temp();
+                let size = self.next_temp();
+                let buffer = self.next_temp();
+                let written = self.next_temp();
+                format_ir.push_str(&format!(
+                    "  {} = call i32 (i8*, i64, i8*, ...) @snprintf(i8* null, i64 0, i8* {}{})\n",
+                    length, format, arguments
+                ));
+                format_ir.push_str(&format!("  {} = sext i32 {} to i64\n", wide_length, length));
+                format_ir.push_str(&format!("  {} = add i64 {}, 1\n", size, wide_length));
+                format_ir.push_str(&format!("  {} = call i8* @malloc(i64 {})\n", buffer, size));
+                format_ir.push_str(&format!(
+                    "  {} = call i32 (i8*, i64, i8*, ...) @snprintf(i8* {}, i64 {}, i8* {}{})\n",
+                    written, buffer, size, format, arguments
+                ));
+                Ok((format_ir, Operand::new(buffer, Type::String)))
             }
             _ => Err(LLVMCodeGenError {
                 message: format!("Expression type not implemented: {:?}", expr),
+                span: None,
+            }),
+        }
+    }
+
+    /// Generates `left operator right` for operands of the types semantic
+    /// analysis allows. An int mixed with a float is converted to a float,
+    /// and division always produces a float.
+    fn generate_binary(&mut self, operator: &BinaryOperator, left: &Operand, right: &Operand) -> Result<(String, Operand), LLVMCodeGenError> {
+        if left.ty == Type::String && right.ty == Type::String {
+            return match operator {
+                BinaryOperator::Plus => {
+                    let result = self.next_temp();
+                    let concat_ir = format!("  {} = call i8* @nl_string_concat(i8* {}, i8* {})\n", result, left.value, right.value);
+                    Ok((concat_ir, Operand::new(result, Type::String)))
+                }
+                BinaryOperator::EqualEqual | BinaryOperator::NotEqual => {
+                    let order = self.next_temp();
+                    let result = self.next_temp();
+                    let predicate = if matches!(operator, BinaryOperator::EqualEqual) { "eq" } else { "ne" };
+                    let compare_ir = format!("  {} = call i32 @strcmp(i8* {}, i8* {})\n  {} = icmp {} i32 {}, 0\n",
+                        order, left.value, right.value, result, predicate, order);
+                    Ok((compare_ir, Operand::new(result, Type::Boolean)))
+                }
+                _ => Err(LLVMCodeGenError {
+                    message: format!("Operator {:?} is not supported on strings", operator),
+                    span: None,
+                }),
+            };
+        }
+        
+        let numeric = |ty: &Type| matches!(ty, Type::Integer | Type::Float);
+        let floating = numeric(&left.ty) && numeric(&right.ty)
+            && (left.ty == Type::Float || right.ty == Type::Float || matches!(operator, BinaryOperator::Slash));
+        let mut binary_ir = String::new();
+        let (operand_type, left_value, right_value) = if floating {
+            let (left_ir, left_value) = self.convert(left, &Type::Float);
+            binary_ir.push_str(&left_ir);
+            let (right_ir, right_value) = self.convert(right, &Type::Float);
+            binary_ir.push_str(&right_ir);
+            ("double", left_value, right_value)
+        } else {
+            (llvm_type(&left.ty), left.value.clone(), right.value.clone())
+        };
+        
+        let (op_instr, result_type) = match (operator, floating) {
+            (BinaryOperator::Plus, true) => ("fadd", Type::Float),
+            (BinaryOperator::Minus, true) => ("fsub", Type::Float),
+            (BinaryOperator::Star, true) => ("fmul", Type::Float),
+            (BinaryOperator::Slash, _) => ("fdiv", Type::Float),
+            (BinaryOperator::Percent, true) => ("frem", Type::Float),
+            (BinaryOperator::EqualEqual, true) => ("fcmp oeq", Type::Boolean),
+            (BinaryOperator::NotEqual, true) => ("fcmp une", Type::Boolean),
+            (BinaryOperator::Less, true) => ("fcmp olt", Type::Boolean),
+            (BinaryOperator::LessEqual, true) => ("fcmp ole", Type::Boolean),
+            (BinaryOperator::Greater, true) => ("fcmp ogt", Type::Boolean),
+            (BinaryOperator::GreaterEqual, true) => ("fcmp oge", Type::Boolean),
+            (BinaryOperator::Plus, false) => ("add", Type::Integer),
+            (BinaryOperator::Minus, false) => ("sub", Type::Integer),
+            (BinaryOperator::Star, false) => ("mul", Type::Integer),
+            (BinaryOperator::Percent, false) => ("srem", Type::Integer),
+            (BinaryOperator::EqualEqual, false) => ("icmp eq", Type::Boolean),
+            (BinaryOperator::NotEqual, false) => ("icmp ne", Type::Boolean),
+            (BinaryOperator::Less, false) => ("icmp slt", Type::Boolean),
+            (BinaryOperator::LessEqual, false) => ("icmp sle", Type::Boolean),
+            (BinaryOperator::Greater, false) => ("icmp sgt", Type::Boolean),
+            (BinaryOperator::GreaterEqual, false) => ("icmp sge", Type::Boolean),
+            (BinaryOperator::And, _) => ("and", Type::Boolean),
+            (BinaryOperator::Or, _) => ("or", Type::Boolean),
+        };
+        
+        let temp_name = self.next_temp();
+        binary_ir.push_str(&format!("  {} = {} {} {}, {}\n", temp_name, op_instr, operand_type, left_value, right_value));
+        Ok((binary_ir, Operand::new(temp_name, result_type)))
+    }
+
+    /// Generates a match as a chain of arms, each testing its pattern and
+    /// falling through to the next arm when a test fails. `generate_body`
+    /// generates the code for an arm's body once its bindings are defined.
+    fn generate_match<B>(
+        &mut self,
+        scrutinee: &Expr,
+        arms: &[MatchArm<B>],
+        mut generate_body: impl FnMut(&mut Self, &B) -> Result<String, LLVMCodeGenError>,
+    ) -> Result<String, LLVMCodeGenError> {
+        let (mut match_ir, value) = self.generate_expression(scrutinee)?;
+        let end_label = self.next_label();
+        let mut arm_label = self.next_label();
+        match_ir.push_str(&format!("  br label %{}\n", arm_label));
+        
+        for arm in arms {
+            let next_arm_label = self.next_label();
+            match_ir.push_str(&format!("{}:\n", arm_label));
+            // The arm's bindings are only in scope in its body
+            match_ir.push_str(&self.scoped(|this| {
+                let pattern_ir = this.generate_pattern(&arm.pattern, &value, &next_arm_label)?;
+                Ok(pattern_ir + &generate_body(this, &arm.body)?)
+            })?);
+            match_ir.push_str(&format!("  br label %{}\n", end_label));
+            arm_label = next_arm_label;
+        }
+        
+        // Semantic analysis guarantees some arm matches
+        match_ir.push_str(&format!("{}:\n  unreachable\n", arm_label));
+        match_ir.push_str(&format!("{}:\n", end_label));
+        Ok(match_ir)
+    }
+
+    /// Generates the tests of `pattern` against `value`, branching to
+    /// `fail_label` if it doesn't match, and defines the variables it binds.
+    fn generate_pattern(&mut self, pattern: &Pattern, value: &Operand, fail_label: &str) -> Result<String, LLVMCodeGenError> {
+        match pattern {
+            Pattern::Wildcard { .. } => Ok(String::new()),
+            Pattern::Binding { name, .. } => Ok(self.define_local(name, value.ty.clone(), &value.value)),
+            Pattern::Literal { value: literal @ (Literal::Integer(_) | Literal::Boolean(_)), .. } => {
+                let (_, expected) = self.generate_expression(&Expr::Literal { value: literal.clone(), span: pattern.span() })?;
+                let matches = self.next_temp();
+                let matched_label = self.next_label();
+                Ok(format!(
+                    "  {} = icmp eq {} {}, {}\n  br i1 {}, label %{}, label %{}\n{}:\n",
+                    matches, llvm_type(&expected.ty), value.value, expected.value, matches, matched_label, fail_label, matched_label
+                ))
+            }
+            Pattern::Literal { .. } => Err(LLVMCodeGenError {
+                message: "Only integer and boolean literal patterns are supported by the LLVM backend".to_string(),
+                span: Some(pattern.span()),
             }),
+            Pattern::Variant { enum_name, variant, fields, span } => {
+                let Some((tag, field_types)) = self.enums.get(enum_name)
+                    .and_then(|variants| variants.iter().enumerate().find(|(_, (name, _))| name == variant))
+                    .map(|(tag, (_, field_types))| (tag, field_types.clone())) else {
+                    return Err(LLVMCodeGenError {
+                        message: format!("Unknown enum variant: {}::{}", enum_name, variant),
+                        span: Some(*span),
+                    });
+                };
+                
+                let enum_type = format!("%enum.{}", enum_name);
+                let instance = self.next_temp();
+                let mut pattern_ir = format!("  {} = inttoptr i64 {} to {}*\n", instance, value.value, enum_type);
+                let tag_slot = self.next_temp();
+                pattern_ir.push_str(&format!("  {} = getelementptr {}, {}* {}, i32 0, i32 0\n", tag_slot, enum_type, enum_type, instance));
+                let actual_tag = self.next_temp();
+                pattern_ir.push_str(&format!("  {} = load i64, i64* {}, align 8\n", actual_tag, tag_slot));
+                let matches = self.next_temp();
+                let matched_label = self.next_label();
+                pattern_ir.push_str(&format!(
+                    "  {} = icmp eq i64 {}, {}\n  br i1 {}, label %{}, label %{}\n{}:\n",
+                    matches, actual_tag, tag, matches, matched_label, fail_label, matched_label
+                ));
+                
+                for (index, (field, field_type)) in fields.iter().zip(field_types).enumerate() {
+                    let slot = self.next_temp();
+                    pattern_ir.push_str(&format!("  {} = getelementptr {}, {}* {}, i32 0, i32 {}\n",
+                        slot, enum_type, enum_type, instance, index + 1));
+                    let bits = self.next_temp();
+                    pattern_ir.push_str(&format!("  {} = load i64, i64* {}, align 8\n", bits, slot));
+                    let (field_ir, field_value) = self.slot_value(&bits, &field_type);
+                    pattern_ir.push_str(&field_ir);
+                    pattern_ir.push_str(&self.generate_pattern(field, &field_value, fail_label)?);
+                }
+                Ok(pattern_ir)
+            }
         }
     }
 
+    /// Generates a pointer to the `field` slot of the struct `object` refers
+    /// to, along with the field's type.
+    fn generate_field(&mut self, object: &Expr, field: &str) -> Result<(String, String, Type), LLVMCodeGenError> {
+        let (mut field_ir, handle) = self.generate_expression(object)?;
+        let Type::Named(struct_name) = &handle.ty else {
+            return Err(LLVMCodeGenError {
+                message: format!("Cannot determine the struct type for field access '.{}'", field),
+                span: None,
+            });
+        };
+        let (index, field_type) = self.structs.get(struct_name)
+            .and_then(|fields| fields.iter().enumerate().find(|(_, (name, _))| name == field))
+            .map(|(index, (_, field_type))| (index, field_type.clone()))
+            .ok_or_else(|| LLVMCodeGenError {
+                message: format!("Struct '{}' has no field named '{}'", struct_name, field),
+                span: None,
+            })?;
+        
+        let struct_type = format!("%struct.{}", struct_name);
+        let instance = self.next_temp();
+        field_ir.push_str(&format!("  {} = inttoptr i64 {} to {}*\n", instance, handle.value, struct_type));
+        let slot = self.next_temp();
+        field_ir.push_str(&format!("  {} = getelementptr {}, {}* {}, i32 0, i32 {}\n",
+            slot, struct_type, struct_type, instance, index));
+        Ok((field_ir, slot, field_type))
+    }
+
+    /// Generates a bounds-checked pointer to `sequence[index]`, along with
+    /// the element type.
+    fn generate_element(&mut self, sequence: &Expr, index: &Expr, span: Span) -> Result<(String, String, Type), LLVMCodeGenError> {
+        let (sequence_ir, sequence) = self.generate_expression(sequence)?;
+        let (index_ir, index) = self.generate_expression(index)?;
+        let slot = self.next_temp();
+        let element_ir = format!("{}{}  {} = call i64* @nl_array_at(i64 {}, i64 {}, i64 {}, i64 {})\n",
+            sequence_ir, index_ir, slot, sequence.value, index.value, span.line, span.column);
+        let element_type = match sequence.ty {
+            Type::Array(element_type) => *element_type,
+            _ => Type::Integer,
+        };
+        Ok((element_ir, slot, element_type))
+    }
+
+    /// Allocates a stack slot for the variable `name` and stores `value` of
+    /// type `ty` in it. Every declaration gets a slot of its own, so a
+    /// shadowing declaration or match arms binding the same name don't clash.
+    fn define_local(&mut self, name: &str, ty: Type, value: &str) -> String {
+        let slot = format!("%{}.addr{}", name, self.slot_counter);
+        self.slot_counter += 1;
+        let llvm_type = llvm_type(&ty);
+        self.variables.insert(name.to_string(), slot.clone());
+        self.variable_types.insert(name.to_string(), ty);
+        self.alloca(&slot, llvm_type);
+        format!("  store {} {}, {}* {}, align 8\n", llvm_type, value, llvm_type, slot)
+    }
+
+    /// Declares the stack slot `slot` in the entry block of the function.
+    fn alloca(&mut self, slot: &str, llvm_type: &str) {
+        self.allocas.push_str(&format!("  {} = alloca {}, align 8\n", slot, llvm_type));
+    }
+
+    /// Generates code with `generate` in a scope of its own: the variables it
+    /// declares are forgotten afterwards, and the ones they shadowed are
+    /// visible again.
+    fn scoped(&mut self, generate: impl FnOnce(&mut Self) -> Result<String, LLVMCodeGenError>) -> Result<String, LLVMCodeGenError> {
+        let variables = self.variables.clone();
+        let variable_types = self.variable_types.clone();
+        let result = generate(self);
+        self.variables = variables;
+        self.variable_types = variable_types;
+        result
+    }
+
+    /// Loads the current value of the variable `name`, if it is defined.
+    fn load_variable(&mut self, name: &str) -> Option<(String, Operand)> {
+        let slot = self.variables.get(name)?.clone();
+        let ty = self.variable_types[name].clone();
+        let value = self.next_temp();
+        let load_ir = format!("  {} = load {}, {}* {}, align 8\n", value, llvm_type(&ty), llvm_type(&ty), slot);
+        Some((load_ir, Operand::new(value, ty)))
+    }
+
+    /// A pointer to the first character of the constant for the string literal `value`.
+    fn string_constant(&self, value: &str) -> Result<String, LLVMCodeGenError> {
+        let Some(name) = self.string_constants.get(value) else {
+            return Err(LLVMCodeGenError {
+                message: format!("String constant not found: {}", value),
+                span: None,
+            });
+        };
+        let size = value.len() + 1;
+        Ok(format!("getelementptr inbounds ([{} x i8], [{} x i8]* {}, i32 0, i32 0)", size, size, name))
+    }
+
+    /// Converts `operand` to `ty`: between ints and floats with `sitofp` and
+    /// `fptosi`, to and from booleans, and from strings by parsing them.
+    /// An operand that already has the type is used as it is.
+    fn convert(&mut self, operand: &Operand, ty: &Type) -> (String, String) {
+        let value = &operand.value;
+        let instruction = match (&operand.ty, ty) {
+            (Type::Integer, Type::Float) => format!("sitofp i64 {} to double", value),
+            (Type::Float, Type::Integer) => format!("fptosi double {} to i64", value),
+            (Type::Boolean, Type::Integer) => format!("zext i1 {} to i64", value),
+            (Type::Boolean, Type::Float) => format!("uitofp i1 {} to double", value),
+            (Type::Integer, Type::Boolean) => format!("icmp ne i64 {}, 0", value),
+            (Type::Float, Type::Boolean) => format!("fcmp une double {}, 0.0", value),
+            (Type::String, Type::Integer) => format!("call i64 @strtoll(i8* {}, i8** null, i32 10)", value),
+            (Type::String, Type::Float) => format!("call double @strtod(i8* {}, i8** null)", value),
+            _ => return (String::new(), value.clone()),
+        };
+        let result = self.next_temp();
+        (format!("  {} = {}\n", result, instruction), result)
+    }
+
+    /// Converts a scalar to the string `str()` and printing produce for it.
+    fn text_of(&mut self, operand: &Operand) -> (String, String) {
+        let call = match operand.ty {
+            Type::String => return (String::new(), operand.value.clone()),
+            Type::Boolean => format!(
+                "select i1 {}, i8* getelementptr inbounds ([5 x i8], [5 x i8]* @.str.bool_true, i32 0, i32 0), i8* getelementptr inbounds ([6 x i8], [6 x i8]* @.str.bool_false, i32 0, i32 0)",
+                operand.value
+            ),
+            Type::Float => format!("call i8* @nl_float_to_string(double {})", operand.value),
+            _ => format!("call i8* @nl_int_to_string(i64 {})", operand.value),
+        };
+        let text = self.next_temp();
+        (format!("  {} = {}\n", text, call), text)
+    }
+
+    /// Converts `operand` to the i64 it is stored as in an array element,
+    /// struct field or enum payload slot.
+    fn slot_bits(&mut self, operand: &Operand) -> (String, String) {
+        let instruction = match operand.ty {
+            Type::Float => "bitcast double",
+            Type::Boolean => "zext i1",
+            Type::String => "ptrtoint i8*",
+            _ => return (String::new(), operand.value.clone()),
+        };
+        let bits = self.next_temp();
+        (format!("  {} = {} {} to i64\n", bits, instruction, operand.value), bits)
+    }
+
+    /// Converts the i64 `bits` read from a slot back to a value of type `ty`.
+    fn slot_value(&mut self, bits: &str, ty: &Type) -> (String, Operand) {
+        let instruction = match ty {
+            Type::Float => "bitcast",
+            Type::Boolean => "trunc",
+            Type::String => "inttoptr",
+            _ => return (String::new(), Operand::new(bits, ty.clone())),
+        };
+        let value = self.next_temp();
+        (format!("  {} = {} i64 {} to {}\n", value, instruction, bits, llvm_type(ty)), Operand::new(value, ty.clone()))
+    }
+
     fn next_temp(&mut self) -> String {
         let temp = format!("%{}", self.temp_counter);
         self.temp_counter += 1;
         temp
     }
 
-    #[allow(dead_code)]
     fn next_label(&mut self) -> String {
         let label = format!("label{}", self.label_counter);
         self.label_counter += 1;
         label
     }
+
+    /// Starts a fresh block for the code following a terminator, which LLVM
+    /// would otherwise put in an implicitly numbered block.
+    fn unreachable_block(&mut self) -> String {
+        format!("{}:\n", self.next_label())
+    }
 }
\ No newline at end of file
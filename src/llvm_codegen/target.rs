@@ -0,0 +1,139 @@
+//! Platforms the LLVM backend can generate code for
+//!
+//! A target decides what the module header declares, how functions are
+//! called, and how the object file `llc` produces is linked into an
+//! executable.
+
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    X86_64Linux,
+    Aarch64Linux,
+    X86_64Windows,
+}
+
+impl Target {
+    pub const ALL: [Target; 3] = [Target::X86_64Linux, Target::Aarch64Linux, Target::X86_64Windows];
+
+    /// The target of the machine the compiler runs on, if it is supported.
+    pub fn host() -> Option<Target> {
+        if cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            Some(Target::X86_64Linux)
+        } else if cfg!(all(target_arch = "aarch64", target_os = "linux")) {
+            Some(Target::Aarch64Linux)
+        } else if cfg!(all(target_arch = "x86_64", target_os = "windows")) {
+            Some(Target::X86_64Windows)
+        } else {
+            None
+        }
+    }
+
+    /// The short name `--target` takes, e.g. `x86_64-linux-gnu`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Target::X86_64Linux => "x86_64-linux-gnu",
+            Target::Aarch64Linux => "aarch64-linux-gnu",
+            Target::X86_64Windows => "x86_64-windows-msvc",
+        }
+    }
+
+    pub fn triple(&self) -> &'static str {
+        match self {
+            Target::X86_64Linux => "x86_64-unknown-linux-gnu",
+            Target::Aarch64Linux => "aarch64-unknown-linux-gnu",
+            Target::X86_64Windows => "x86_64-pc-windows-msvc",
+        }
+    }
+
+    pub fn datalayout(&self) -> &'static str {
+        match self {
+            Target::X86_64Linux => "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-f80:128-n8:16:32:64-S128",
+            Target::Aarch64Linux => "e-m:e-i8:8:32-i16:16:32-i64:64-i128:128-n32:64-S128",
+            Target::X86_64Windows => "e-m:w-p270:32:32-p271:32:32-p272:64:64-i64:64-f80:128-n8:16:32:64-S128",
+        }
+    }
+
+    /// The LLVM calling convention of generated functions. It is the C one
+    /// everywhere, so the runtime and the C library can call them.
+    pub fn calling_convention(&self) -> &'static str {
+        "ccc"
+    }
+
+    /// Extra `llc` arguments. Linux toolchains link position-independent
+    /// executables by default, so the code has to be position-independent too.
+    pub fn llc_args(&self) -> &'static [&'static str] {
+        match self {
+            Target::X86_64Linux | Target::Aarch64Linux => &["-relocation-model=pic"],
+            Target::X86_64Windows => &[],
+        }
+    }
+
+    pub fn object_extension(&self) -> &'static str {
+        match self {
+            Target::X86_64Linux | Target::Aarch64Linux => "o",
+            Target::X86_64Windows => "obj",
+        }
+    }
+
+    /// The extension of executables, empty where they don't have one.
+    pub fn executable_extension(&self) -> &'static str {
+        match self {
+            Target::X86_64Linux | Target::Aarch64Linux => "",
+            Target::X86_64Windows => "exe",
+        }
+    }
+
+    /// The command that links `object` and the C library into `output`.
+    /// Linux objects are linked by the C compiler driver, which knows where
+    /// the startup files are; a cross-compiling one when the target isn't
+    /// the host.
+    pub fn link_command(&self, object: &Path, output: &Path) -> Command {
+        match self {
+            Target::X86_64Linux | Target::Aarch64Linux => {
+                let driver = if Target::host() == Some(*self) {
+                    "cc".to_string()
+                } else {
+                    format!("{}-gcc", self.name())
+                };
+                let mut command = Command::new(driver);
+                command.arg("-o").arg(output).arg(object);
+                command
+            }
+            Target::X86_64Windows => {
+                let mut command = Command::new("lld-link");
+                command
+                    .arg("/entry:main")
+                    .arg("/subsystem:console")
+                    .arg(format!("/out:{}", output.display()))
+                    .arg(object)
+                    .arg("msvcrt.lib")
+                    .arg("legacy_stdio_definitions.lib");
+                command
+            }
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    /// Accepts a target's short name or its full LLVM triple.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Target::ALL.into_iter()
+            .find(|target| target.name() == s || target.triple() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Target::ALL.iter().map(Target::name).collect();
+                format!("unknown target '{}'; expected one of {}", s, names.join(", "))
+            })
+    }
+}
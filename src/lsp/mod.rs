@@ -0,0 +1,392 @@
+//! Language server for editor integration
+//!
+//! Speaks the Language Server Protocol over a [`Connection`], usually stdio.
+//! Every open document is checked again whenever it changes: its
+//! diagnostics are published, and the symbol index semantic analysis built
+//! is kept to answer hover, go-to-definition and completion requests.
+
+use crate::ast::Type;
+use crate::checker::{check_source, SourceCheck};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::semantic::{Definition, DefinitionKind, SymbolIndex};
+use crate::span::Span;
+use crate::std_lib::StdLib;
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DiagnosticSeverity, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, NumberOrString, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[cfg(test)]
+mod tests;
+
+/// An open document and what checking it last found.
+struct Document {
+    source: String,
+    index: SymbolIndex,
+}
+
+pub struct Server {
+    connection: Connection,
+    documents: HashMap<Uri, Document>,
+    builtins: Vec<CompletionItem>,
+}
+
+impl Server {
+    pub fn new(connection: Connection) -> Self {
+        Server { connection, documents: HashMap::new(), builtins: builtin_completions() }
+    }
+
+    /// Answers the initialization handshake, then handles messages until
+    /// the client shuts the server down.
+    pub fn run(mut self) -> anyhow::Result<()> {
+        let capabilities = ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            definition_provider: Some(OneOf::Left(true)),
+            completion_provider: Some(CompletionOptions {
+                trigger_characters: Some(vec![".".to_string()]),
+                ..CompletionOptions::default()
+            }),
+            ..ServerCapabilities::default()
+        };
+        self.connection.initialize(serde_json::to_value(capabilities)?)?;
+
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: Request) -> anyhow::Result<()> {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(request.params)?;
+                serde_json::to_value(self.hover(params))?
+            }
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(request.params)?;
+                serde_json::to_value(self.definition(params))?
+            }
+            Completion::METHOD => {
+                let params: CompletionParams = serde_json::from_value(request.params)?;
+                serde_json::to_value(self.completion(params))?
+            }
+            method => {
+                let message = format!("Unsupported request: {}", method);
+                return self.respond(Response::new_err(id, lsp_server::ErrorCode::MethodNotFound as i32, message));
+            }
+        };
+        self.respond(Response::new_ok(id, result))
+    }
+
+    fn respond(&self, response: Response) -> anyhow::Result<()> {
+        self.connection.sender.send(Message::Response(response))?;
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> anyhow::Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+                self.update(params.text_document.uri, params.text_document.text)?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+                // Only whole documents are synced, so the last change is all of it
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.update(params.text_document.uri, change.text)?;
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+                self.publish(params.text_document.uri, Vec::new())?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Checks the new text of a document and publishes its diagnostics.
+    fn update(&mut self, uri: Uri, source: String) -> anyhow::Result<()> {
+        let SourceCheck { diagnostics, program, index } = check_source(&source, &path_of(&uri), true);
+        let diagnostics = diagnostics.iter().map(|diagnostic| to_lsp_diagnostic(diagnostic, &source)).collect();
+
+        // Text that doesn't lex says nothing about names; keep the last index
+        // so completion still works while a string literal is being typed
+        let index = match (program, self.documents.remove(&uri)) {
+            (None, Some(previous)) => previous.index,
+            _ => index,
+        };
+        self.documents.insert(uri.clone(), Document { source, index });
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&self, uri: Uri, diagnostics: Vec<lsp_types::Diagnostic>) -> anyhow::Result<()> {
+        let params = PublishDiagnosticsParams { uri, diagnostics, version: None };
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.connection.sender.send(Message::Notification(notification))?;
+        Ok(())
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let offset = offset_at(&document.source, position.position);
+
+        // A use of a name, or else the name where it is declared
+        let (definition, signature, span) = match document.index.reference_at(offset) {
+            Some(reference) => {
+                let definition = document.index.definition_of(reference);
+                let signature = match definition {
+                    Some(definition) => describe(definition),
+                    None => describe_function(&reference.name, &reference.ty),
+                };
+                (definition, signature, reference.span)
+            }
+            None => {
+                let (definition, span) = document.index.declaration_at(&document.source, offset)?;
+                (Some(definition), describe(definition), span)
+            }
+        };
+        let mut value = format!("```nlang\n{}\n```", signature);
+        if let Some(doc) = definition.and_then(|definition| definition.doc.as_ref()) {
+            value.push_str("\n\n");
+            value.push_str(doc);
+        }
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(range_of(&document.source, span)),
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let reference = document.index.reference_at(offset_at(&document.source, position.position))?;
+        let definition = document.index.definition_of(reference)?;
+
+        let location = match &definition.module {
+            Some(module) => {
+                let uri = uri_of(module)?;
+                let range = match self.documents.get(&uri) {
+                    Some(open) => range_of(&open.source, definition.span),
+                    None => range_of(&std::fs::read_to_string(module).ok()?, definition.span),
+                };
+                Location::new(uri, range)
+            }
+            None => Location::new(position.text_document.uri, range_of(&document.source, definition.span)),
+        };
+        Some(GotoDefinitionResponse::Scalar(location))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let offset = offset_at(&document.source, position.position);
+        let visible = document.index.visible_at(offset);
+
+        // After `alias.`, offer what the module aliased as `alias` exports
+        let word = word_before(&document.source, offset);
+        let items = match word.rsplit_once('.') {
+            Some((qualifier, _)) => {
+                let prefix = format!("{}.", qualifier);
+                visible.into_iter()
+                    .filter_map(|definition| {
+                        let member = definition.name.strip_prefix(&prefix)?;
+                        Some(completion_item(member, definition))
+                    })
+                    .collect()
+            }
+            None => visible.into_iter()
+                .filter(|definition| !definition.name.contains('.'))
+                .map(|definition| completion_item(&definition.name, definition))
+                .chain(self.builtins.iter().cloned())
+                .collect(),
+        };
+        Some(CompletionResponse::Array(items))
+    }
+}
+
+/// Runs a server over stdin and stdout until the client shuts it down.
+pub fn run_stdio() -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    Server::new(connection).run()?;
+    io_threads.join()?;
+    Ok(())
+}
+
+fn to_lsp_diagnostic(diagnostic: &Diagnostic, source: &str) -> lsp_types::Diagnostic {
+    // Problems with the whole program, like a missing `main`, go at its start
+    let range = diagnostic.primary_span().map_or_else(Range::default, |span| range_of(source, span));
+    let severity = match diagnostic.severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Note => DiagnosticSeverity::INFORMATION,
+        Severity::Help => DiagnosticSeverity::HINT,
+    };
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message.push_str(&format!("\nnote: {}", note));
+    }
+    for help in &diagnostic.help {
+        message.push_str(&format!("\nhelp: {}", help));
+    }
+    lsp_types::Diagnostic {
+        range,
+        severity: Some(severity),
+        code: diagnostic.code.clone().map(NumberOrString::String),
+        source: Some("nlang".to_string()),
+        message,
+        ..lsp_types::Diagnostic::default()
+    }
+}
+
+fn describe(definition: &Definition) -> String {
+    match definition.kind {
+        DefinitionKind::Function => describe_function(&definition.name, &definition.ty),
+        DefinitionKind::Module => format!("module {}", definition.name),
+        DefinitionKind::Variable | DefinitionKind::Parameter => format!("{}: {}", definition.name, definition.ty),
+    }
+}
+
+/// Spells a function's type as a declaration, e.g. `def add(int, int): int`.
+fn describe_function(name: &str, ty: &Type) -> String {
+    let ty = ty.to_string();
+    match ty.strip_prefix("def") {
+        Some(signature) => format!("def {}{}", name, signature),
+        None => format!("{}: {}", name, ty),
+    }
+}
+
+fn completion_item(label: &str, definition: &Definition) -> CompletionItem {
+    let kind = match definition.kind {
+        DefinitionKind::Function => CompletionItemKind::FUNCTION,
+        DefinitionKind::Variable | DefinitionKind::Parameter => CompletionItemKind::VARIABLE,
+        DefinitionKind::Module => CompletionItemKind::MODULE,
+    };
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(kind),
+        detail: Some(describe(definition)),
+        ..CompletionItem::default()
+    }
+}
+
+/// One item per built-in function; overloads share an item.
+fn builtin_completions() -> Vec<CompletionItem> {
+    let mut items: Vec<CompletionItem> = Vec::new();
+    for function in StdLib::new().functions {
+        if items.iter().any(|item| item.label == function.name) {
+            continue;
+        }
+        let ty = Type::Function { params: function.parameters, return_type: Box::new(function.return_type) };
+        items.push(CompletionItem {
+            detail: Some(describe_function(&function.name, &ty)),
+            label: function.name,
+            kind: Some(CompletionItemKind::FUNCTION),
+            ..CompletionItem::default()
+        });
+    }
+    items
+}
+
+/// The identifier, possibly qualified with dots, that ends at `offset`.
+fn word_before(source: &str, offset: usize) -> &str {
+    let before = &source[..offset];
+    let start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+        .map_or(0, |i| i + before[i..].chars().next().map_or(1, char::len_utf8));
+    &before[start..]
+}
+
+/// The byte offset of an LSP position, whose column counts UTF-16 code units.
+fn offset_at(source: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match source[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return source.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in source[line_start..].char_indices() {
+        if units >= position.character as usize || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    source.len()
+}
+
+fn position_at(source: &str, offset: usize) -> Position {
+    let offset = offset.min(source.len());
+    let before = &source[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    Position::new(line as u32, character as u32)
+}
+
+fn range_of(source: &str, span: Span) -> Range {
+    Range::new(position_at(source, span.start), position_at(source, span.end))
+}
+
+/// The file a `file:` URI names. Other URIs are used as paths as they are,
+/// which leaves their imports unresolved.
+fn path_of(uri: &Uri) -> PathBuf {
+    let bytes = uri.path().as_str().as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+fn uri_of(path: &Path) -> Option<Uri> {
+    let mut uri = String::from("file://");
+    for byte in path.to_str()?.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    Uri::from_str(&uri).ok()
+}
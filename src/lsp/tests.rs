@@ -0,0 +1,191 @@
+#[cfg(test)]
+mod lsp_tests {
+    use crate::lsp::Server;
+    use lsp_server::{Connection, Message, Notification, Request, RequestId};
+    use serde_json::{json, Value};
+    use std::path::Path;
+    use std::thread::JoinHandle;
+
+    /// The editor's side of a conversation with a server on another thread.
+    struct Client {
+        connection: Connection,
+        server: JoinHandle<anyhow::Result<()>>,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn start() -> Client {
+            let (server, connection) = Connection::memory();
+            let server = std::thread::spawn(move || Server::new(server).run());
+            let mut client = Client { connection, server, next_id: 0 };
+            let capabilities = client.request("initialize", json!({ "capabilities": {} }));
+            assert_eq!(capabilities["capabilities"]["hoverProvider"], json!(true));
+            client.notify("initialized", json!({}));
+            client
+        }
+
+        fn request(&mut self, method: &str, params: Value) -> Value {
+            self.next_id += 1;
+            let id = RequestId::from(self.next_id);
+            let request = Request::new(id.clone(), method.to_string(), params);
+            self.connection.sender.send(Message::Request(request)).unwrap();
+            loop {
+                match self.connection.receiver.recv().unwrap() {
+                    Message::Response(response) if response.id == id => return response.result.unwrap(),
+                    _ => {}
+                }
+            }
+        }
+
+        fn notify(&self, method: &str, params: Value) {
+            let notification = Notification::new(method.to_string(), params);
+            self.connection.sender.send(Message::Notification(notification)).unwrap();
+        }
+
+        /// Waits for the diagnostics the server publishes next.
+        fn diagnostics(&self) -> Vec<Value> {
+            loop {
+                if let Message::Notification(notification) = self.connection.receiver.recv().unwrap()
+                    && notification.method == "textDocument/publishDiagnostics" {
+                    return serde_json::from_value(notification.params["diagnostics"].clone()).unwrap();
+                }
+            }
+        }
+
+        fn open(&self, uri: &str, text: &str) -> Vec<Value> {
+            let document = json!({ "uri": uri, "languageId": "nlang", "version": 1, "text": text });
+            self.notify("textDocument/didOpen", json!({ "textDocument": document }));
+            self.diagnostics()
+        }
+
+        fn at(&mut self, method: &str, uri: &str, line: u32, character: u32) -> Value {
+            let params = json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+            });
+            self.request(method, params)
+        }
+
+        fn shut_down(mut self) {
+            self.request("shutdown", Value::Null);
+            self.notify("exit", Value::Null);
+            self.server.join().unwrap().unwrap();
+        }
+    }
+
+    fn file_uri(path: &Path) -> String {
+        format!("file://{}", path.display())
+    }
+
+    fn labels(completions: &Value) -> Vec<&str> {
+        completions.as_array().unwrap().iter().map(|item| item["label"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut client = Client::start();
+        let uri = "file:///project/main.nlang";
+
+        let diagnostics = client.open(uri, "def main() {\n    store x: int = \"one\";\n    print(x);\n}\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["code"], "E0003");
+        assert_eq!(diagnostics[0]["severity"], 1);
+        assert_eq!(diagnostics[0]["range"]["start"], json!({ "line": 1, "character": 4 }));
+        assert!(diagnostics[0]["message"].as_str().unwrap().starts_with("Type mismatch in declaration of 'x'"));
+
+        let change = json!({
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": "def main() {\n    store x: int = 1;\n    print(x);\n}\n" }],
+        });
+        client.notify("textDocument/didChange", change);
+        assert!(client.diagnostics().is_empty());
+
+        let diagnostics = client.open("file:///project/lib.nlang", "store s = \"unterminated;\n");
+        assert_eq!(diagnostics[0]["code"], "E0001");
+        let hover = client.at("textDocument/hover", uri, 2, 10);
+        assert_eq!(hover["contents"]["value"], "```nlang\nx: int\n```");
+        client.shut_down();
+    }
+
+    #[test]
+    fn test_navigation() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("util.nlang"), "// Helpers\n/// Twice `x`.\nexport def double(x: int): int {\n    return x * 2;\n}\n").unwrap();
+        let main = dir.path().join("main.nlang");
+        let uri = file_uri(&main);
+        let source = "\
+import util;
+import util as u;
+store limit = 10;
+def main() {
+    store total = double(limit);
+    print(u.double(total));
+}
+";
+        let mut client = Client::start();
+        assert!(client.open(&uri, source).is_empty());
+
+        // Hover on a local, a user function, an imported function and a builtin
+        let hover = |client: &mut Client, line, character| {
+            let hover = client.at("textDocument/hover", &uri, line, character);
+            hover["contents"]["value"].as_str().unwrap().to_string()
+        };
+        assert_eq!(hover(&mut client, 5, 20), "```nlang\ntotal: int\n```");
+        assert_eq!(hover(&mut client, 4, 20), "```nlang\ndef double(int): int\n```\n\nTwice `x`.");
+        assert_eq!(hover(&mut client, 5, 13), "```nlang\ndef u.double(int): int\n```\n\nTwice `x`.");
+        assert!(hover(&mut client, 5, 5).starts_with("```nlang\ndef print("));
+        let range = client.at("textDocument/hover", &uri, 4, 26)["range"].clone();
+        assert_eq!(range, json!({ "start": { "line": 4, "character": 25 }, "end": { "line": 4, "character": 30 } }));
+
+        // Hover on names where they are declared
+        assert_eq!(hover(&mut client, 2, 8), "```nlang\nlimit: int\n```");
+        assert_eq!(hover(&mut client, 4, 12), "```nlang\ntotal: int\n```");
+        assert_eq!(hover(&mut client, 3, 6), "```nlang\ndef main()\n```");
+        let range = client.at("textDocument/hover", &uri, 2, 8)["range"].clone();
+        assert_eq!(range, json!({ "start": { "line": 2, "character": 6 }, "end": { "line": 2, "character": 11 } }));
+        assert!(client.at("textDocument/hover", &uri, 2, 2).is_null());
+
+        // Definitions in the same file and in an imported module
+        let definition = client.at("textDocument/definition", &uri, 5, 22);
+        assert_eq!(definition["uri"], uri);
+        assert_eq!(definition["range"]["start"], json!({ "line": 4, "character": 4 }));
+        let definition = client.at("textDocument/definition", &uri, 4, 27);
+        assert_eq!(definition["range"]["start"], json!({ "line": 2, "character": 0 }));
+        let definition = client.at("textDocument/definition", &uri, 5, 12);
+        assert_eq!(definition["uri"], file_uri(&dir.path().join("util.nlang")));
+        assert_eq!(definition["range"]["start"], json!({ "line": 2, "character": 0 }));
+        assert!(client.at("textDocument/definition", &uri, 5, 6).is_null());
+        client.shut_down();
+    }
+
+    #[test]
+    fn test_completion() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("util.nlang"), "export def double(x: int): int {\n    return x * 2;\n}\nexport store ten = 10;\n").unwrap();
+        let uri = file_uri(&dir.path().join("main.nlang"));
+        let mut client = Client::start();
+        client.open(&uri, "import util as u;\ndef main() {\n    store total = 1;\n    \n}\nstore later = 2;\n");
+
+        let completions = client.at("textDocument/completion", &uri, 3, 4);
+        let names = labels(&completions);
+        for name in ["total", "main", "u", "println", "len"] {
+            assert!(names.contains(&name), "missing {} in {:?}", name, names);
+        }
+        assert!(!names.contains(&"later") && !names.contains(&"u.double"));
+        assert_eq!(names.iter().filter(|name| **name == "str").count(), 1);
+
+        let change = json!({
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": "import util as u;\ndef main() {\n    store total = 1;\n    u.\n}\n" }],
+        });
+        client.notify("textDocument/didChange", change);
+        assert!(!client.diagnostics().is_empty());
+        let completions = client.at("textDocument/completion", &uri, 3, 6);
+        let mut names = labels(&completions);
+        names.sort();
+        assert_eq!(names, ["double", "ten"]);
+        let double = completions.as_array().unwrap().iter().find(|item| item["label"] == "double").unwrap();
+        assert_eq!(double["detail"], "def u.double(int): int");
+        client.shut_down();
+    }
+}
@@ -1,4 +1,6 @@
 use clap::Parser;
+use nlang::doc::DocFormat;
+use nlang::llvm_codegen::Target;
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -20,6 +22,10 @@ enum Commands {
         /// Output file name (optional)
         #[arg(short, long)]
         output: Option<PathBuf>,
+        
+        /// Platform to compile for, e.g. aarch64-linux-gnu (defaults to the host)
+        #[arg(long)]
+        target: Option<Target>,
     },
     
     /// Run an Nlang file directly
@@ -27,8 +33,61 @@ enum Commands {
     Run {
         /// Input file to run
         input: PathBuf,
+        
+        /// Run on the bytecode VM instead of the tree-walking interpreter
+        #[arg(long)]
+        vm: bool,
     },
     
+    /// Start an interactive session
+    Repl,
+    
+    /// Check Nlang files for errors without running or compiling them
+    Check {
+        /// Files, or directories to search for .nlang files
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    
+    /// Run the `test def` functions in Nlang files
+    Test {
+        /// Files, or directories to search for .nlang files
+        #[arg(default_value = ".")]
+        inputs: Vec<PathBuf>,
+        
+        /// Only run tests whose name contains this text
+        #[arg(short, long)]
+        filter: Option<String>,
+    },
+    
+    /// Format Nlang files in place
+    Fmt {
+        /// Files to format
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        
+        /// Only report files that are not formatted, without changing them
+        #[arg(long)]
+        check: bool,
+    },
+    
+    /// Generate API documentation for a module and the modules it imports
+    Doc {
+        /// The module to document
+        input: PathBuf,
+        
+        /// Directory to write the documentation to
+        #[arg(short, long, default_value = "doc")]
+        output: PathBuf,
+        
+        /// Format to generate, html or markdown; repeat it for both
+        #[arg(long = "format", default_value = "html")]
+        formats: Vec<DocFormat>,
+    },
+    
+    /// Start a language server on stdin and stdout for editors
+    Lsp,
+    
     /// Generate LLVM IR from an Nlang file
     #[command(alias = "ir")]
     GenerateIr {
@@ -38,6 +97,10 @@ enum Commands {
         /// Output IR file name (optional)
         #[arg(short, long)]
         output: Option<PathBuf>,
+        
+        /// Platform to generate IR for, e.g. aarch64-linux-gnu (defaults to the host)
+        #[arg(long)]
+        target: Option<Target>,
     },
     
     /// Generate C code from an Nlang file
@@ -56,14 +119,32 @@ fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Compile { input, output } => {
-            nlang::cli::compile(input, output)?;
+        Commands::Compile { input, output, target } => {
+            nlang::cli::compile(input, output, target)?;
+        }
+        Commands::Run { input, vm } => {
+            nlang::cli::run(input, vm)?;
+        }
+        Commands::Repl => {
+            nlang::cli::repl()?;
+        }
+        Commands::Check { inputs } => {
+            nlang::cli::check(inputs)?;
+        }
+        Commands::Test { inputs, filter } => {
+            nlang::cli::test(inputs, filter)?;
+        }
+        Commands::Fmt { inputs, check } => {
+            nlang::cli::fmt(inputs, check)?;
+        }
+        Commands::Doc { input, output, formats } => {
+            nlang::cli::doc(input, output, formats)?;
         }
-        Commands::Run { input } => {
-            nlang::cli::run(input)?;
+        Commands::Lsp => {
+            nlang::cli::lsp()?;
         }
-        Commands::GenerateIr { input, output } => {
-            nlang::cli::generate_ir(input, output)?;
+        Commands::GenerateIr { input, output, target } => {
+            nlang::cli::generate_ir(input, output, target)?;
         }
         Commands::GenerateC { input, output } => {
             nlang::cli::generate_c(input, output)?;
@@ -1,5 +1,6 @@
-use crate::lexer::{Token, TokenType};
+use crate::lexer::{FormatStringPart, Token, TokenType};
 use crate::ast::*;
+use crate::span::Span;
 
 #[cfg(test)]
 mod tests;
@@ -7,54 +8,179 @@ mod tests;
 #[derive(Debug)]
 pub struct ParseError {
     pub message: String,
-    pub line: usize,
+    pub span: Span,
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Parse error on line {}: {}", self.line, self.message)
+        write!(f, "Parse error on line {}, column {}: {}", self.span.line, self.span.column, self.message)
     }
 }
 
 impl std::error::Error for ParseError {}
 
-pub fn parse(tokens: &[Token]) -> Result<Program, ParseError> {
+/// Every syntax error found in a file, in source order.
+#[derive(Debug)]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl std::fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
+/// Parses a whole file, recovering from syntax errors.
+///
+/// The returned program holds every statement that parsed cleanly, so it is
+/// only safe to run or compile when the error list is empty.
+pub fn parse(tokens: &[Token]) -> (Program, Vec<ParseError>) {
     let mut parser = Parser::new(tokens);
-    let statements = parser.parse_program()?;
-    Ok(Program { statements })
+    let (statements, errors) = parser.parse_program();
+    (Program { statements }, errors)
+}
+
+/// Like [`parse`], but fails unless the whole file parsed cleanly.
+pub fn parse_strict(tokens: &[Token]) -> Result<Program, ParseErrors> {
+    match parse(tokens) {
+        (program, errors) if errors.is_empty() => Ok(program),
+        (_, errors) => Err(ParseErrors(errors)),
+    }
 }
 
 pub struct Parser<'a> {
     tokens: &'a [Token],
     current: usize,
+    errors: Vec<ParseError>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a [Token]) -> Self {
-        Self { tokens, current: 0 }
+        Self { tokens, current: 0, errors: Vec::new() }
     }
     
-    pub fn parse_program(&mut self) -> Result<Vec<Statement>, ParseError> {
+    pub fn parse_program(&mut self) -> (Vec<Statement>, Vec<ParseError>) {
         let mut statements = Vec::new();
         
         while !self.is_at_end() {
-            statements.push(self.declaration()?);
+            if let Some(statement) = self.recovering_declaration() {
+                statements.push(statement);
+            }
         }
         
-        Ok(statements)
+        (statements, std::mem::take(&mut self.errors))
+    }
+    
+    /// Parses a declaration, and on failure records the error and skips ahead
+    /// to the next likely statement boundary so parsing can continue.
+    fn recovering_declaration(&mut self) -> Option<Statement> {
+        let before = self.current;
+        match self.declaration() {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                self.errors.push(error);
+                // Always make progress, even when the offending token is a
+                // boundary itself (e.g. a stray '}' at the top level)
+                if self.current == before {
+                    self.advance();
+                }
+                self.synchronize();
+                None
+            }
+        }
+    }
+    
+    /// Panic-mode recovery: discards tokens until just after a `;`, just after
+    /// a balanced `{ ... }` group, or just before a keyword that starts a new
+    /// statement or a `}` closing the enclosing block.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while !self.is_at_end() {
+            match self.peek().token_type {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 0 => return,
+                TokenType::RightBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.advance();
+                        return;
+                    }
+                }
+                TokenType::Semicolon if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                TokenType::Def
+                | TokenType::Struct
+                | TokenType::Enum
+                | TokenType::Match
+                | TokenType::Store
+                | TokenType::Export
+                | TokenType::Import
+                | TokenType::From
+                | TokenType::AssignMain
+                | TokenType::If
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue if depth == 0 => return,
+                _ => {}
+            }
+            self.advance();
+        }
     }
     
     fn declaration(&mut self) -> Result<Statement, ParseError> {
+        // A doc comment documents the declaration whose first token it precedes
+        let doc = self.peek().doc.clone();
+        let mut stmt = self.undocumented_declaration()?;
+        if let Statement::LetDeclaration { doc: slot, .. } | Statement::FunctionDeclaration { doc: slot, .. } = &mut stmt {
+            *slot = doc;
+        }
+        Ok(stmt)
+    }
+    
+    fn undocumented_declaration(&mut self) -> Result<Statement, ParseError> {
+        let start = self.peek().span;
+        
         if self.match_token(&TokenType::Export) {
-            return self.export_declaration();
+            return self.export_declaration(start);
         }
         
         if self.match_token(&TokenType::Store) {
-            return self.let_declaration();
+            return self.let_declaration(start);
         }
         
         if self.match_token(&TokenType::Def) {
-            return self.function_declaration();
+            return self.function_declaration(start);
+        }
+        
+        // `test` is only a keyword in front of `def`, so it stays usable as a name
+        if matches!(&self.peek().token_type, TokenType::Identifier(name) if name == "test")
+            && matches!(self.tokens.get(self.current + 1).map(|token| &token.token_type), Some(TokenType::Def))
+        {
+            self.current += 2;
+            let mut stmt = self.function_declaration(start)?;
+            if let Statement::FunctionDeclaration { ref mut is_test, .. } = stmt {
+                *is_test = true;
+            }
+            return Ok(stmt);
+        }
+        
+        if self.match_token(&TokenType::Struct) {
+            return self.struct_declaration(start);
+        }
+        
+        if self.match_token(&TokenType::Enum) {
+            return self.enum_declaration(start);
         }
         
         if self.check(&TokenType::Import) {
@@ -62,28 +188,34 @@ impl<'a> Parser<'a> {
         }
         
         if self.check(&TokenType::From) {
-            return self.from_import_declaration();
+            return self.import_from_declaration();
         }
         
         if self.match_token(&TokenType::AssignMain) {
-            return self.assign_main_declaration();
+            return self.assign_main_declaration(start);
         }
         
         self.statement()
     }
     
-    fn let_declaration(&mut self) -> Result<Statement, ParseError> {
+    fn let_declaration(&mut self, start: Span) -> Result<Statement, ParseError> {
         let name = if let TokenType::Identifier(name) = &self.peek().token_type {
             name.clone()
         } else {
             return Err(ParseError {
                 message: "Expected variable name".to_string(),
-                line: self.peek().line,
+                span: self.peek().span,
             });
         };
         
         self.consume(&TokenType::Identifier(name.clone()), "Expected variable name")?;
         
+        let var_type = if self.match_token(&TokenType::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        
         let mut initializer = None;
         if self.match_token(&TokenType::Assign) {
             initializer = Some(self.expression()?);
@@ -93,20 +225,23 @@ impl<'a> Parser<'a> {
         
         Ok(Statement::LetDeclaration { 
             name, 
+            var_type,
             initializer, 
-            is_exported: false 
+            is_exported: false,
+            doc: None,
+            span: self.span_from(start),
         })
     }
     
-    fn export_declaration(&mut self) -> Result<Statement, ParseError> {
+    fn export_declaration(&mut self, start: Span) -> Result<Statement, ParseError> {
         if self.match_token(&TokenType::Store) {
-            let mut stmt = self.let_declaration()?;
+            let mut stmt = self.let_declaration(start)?;
             if let Statement::LetDeclaration { ref mut is_exported, .. } = stmt {
                 *is_exported = true;
             }
             Ok(stmt)
         } else if self.match_token(&TokenType::Def) {
-            let mut stmt = self.function_declaration()?;
+            let mut stmt = self.function_declaration(start)?;
             if let Statement::FunctionDeclaration { ref mut is_exported, .. } = stmt {
                 *is_exported = true;
             }
@@ -114,12 +249,12 @@ impl<'a> Parser<'a> {
         } else {
             Err(ParseError {
                 message: "Expected 'store' or 'def' after 'export'".to_string(),
-                line: self.peek().line,
+                span: self.peek().span,
             })
         }
     }
     
-    fn assign_main_declaration(&mut self) -> Result<Statement, ParseError> {
+    fn assign_main_declaration(&mut self, start: Span) -> Result<Statement, ParseError> {
         self.consume(&TokenType::Minus, "Expected '-' after 'ASSIGN_MAIN'")?;
         self.consume(&TokenType::Greater, "Expected '>' after '-'")?;
         
@@ -128,38 +263,70 @@ impl<'a> Parser<'a> {
         } else {
             return Err(ParseError {
                 message: "Expected string literal for function name".to_string(),
-                line: self.peek().line,
+                span: self.peek().span,
             });
         };
         
         self.advance(); // consume the string
         self.consume(&TokenType::Semicolon, "Expected ';' after ASSIGN_MAIN declaration")?;
         
-        Ok(Statement::AssignMain { function_name })
+        Ok(Statement::AssignMain { function_name, span: self.span_from(start) })
     }
     
-    fn function_declaration(&mut self) -> Result<Statement, ParseError> {
+    fn function_declaration(&mut self, start: Span) -> Result<Statement, ParseError> {
         let name = if let TokenType::Identifier(name) = &self.peek().token_type {
             name.clone()
         } else {
             return Err(ParseError {
                 message: "Expected function name".to_string(),
-                line: self.peek().line,
+                span: self.peek().span,
             });
         };
         
         self.consume(&TokenType::Identifier(name.clone()), "Expected function name")?;
         self.consume(&TokenType::LeftParen, "Expected '(' after function name")?;
+        let parameters = self.parameters()?;
+        
+        // For now, we'll assume return type is void unless specified
+        let return_type = if self.match_token(&TokenType::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        
+        let body = if self.check(&TokenType::LeftBrace) {
+            self.block()?
+        } else {
+            return Err(ParseError {
+                message: "Expected function body".to_string(),
+                span: self.peek().span,
+            });
+        };
         
+        Ok(Statement::FunctionDeclaration {
+            name,
+            parameters,
+            body,
+            return_type,
+            is_exported: false,
+            is_test: false,
+            doc: None,
+            span: self.span_from(start),
+        })
+    }
+    
+    /// Parses a parameter list up to and including the closing `)`.
+    fn parameters(&mut self) -> Result<Vec<Parameter>, ParseError> {
         let mut parameters = Vec::new();
         if !self.check(&TokenType::RightParen) {
             loop {
+                let param_start = self.peek().span;
                 let param_name = if let TokenType::Identifier(name) = &self.peek().token_type {
                     name.clone()
                 } else {
                     return Err(ParseError {
                         message: "Expected parameter name".to_string(),
-                        line: self.peek().line,
+                        span: self.peek().span,
                     });
                 };
                 
@@ -168,13 +335,13 @@ impl<'a> Parser<'a> {
                 let param_type = if self.match_token(&TokenType::Colon) {
                     self.parse_type()?
                 } else {
-                    // For now, default to Integer if not specified
-                    Type::Integer
+                    Type::Unknown
                 };
 
                 parameters.push(Parameter {
                     name: param_name,
                     param_type,
+                    span: self.span_from(param_start),
                 });
                 
                 if !self.match_token(&TokenType::Comma) {
@@ -184,30 +351,145 @@ impl<'a> Parser<'a> {
         }
         
         self.consume(&TokenType::RightParen, "Expected ')' after parameters")?;
+        Ok(parameters)
+    }
+    
+    /// Parses an anonymous function after its `def`: either
+    /// `def(x: int): int { ... }` or the shorthand `def(x: int) => x * 2`.
+    fn lambda(&mut self, start: Span) -> Result<Expr, ParseError> {
+        self.consume(&TokenType::LeftParen, "Expected '(' after 'def'")?;
+        let parameters = self.parameters()?;
         
-        // For now, we'll assume return type is void unless specified
         let return_type = if self.match_token(&TokenType::Colon) {
-            // For now, just consume the type - we'll implement proper type parsing later
             Some(self.parse_type()?)
         } else {
             None
         };
         
-        let body = if self.check(&TokenType::LeftBrace) {
+        let body = if self.match_token(&TokenType::FatArrow) {
+            let value = self.expression()?;
+            let span = value.span();
+            vec![Statement::Return { value: Some(Box::new(value)), span }]
+        } else if self.check(&TokenType::LeftBrace) {
             self.block()?
         } else {
             return Err(ParseError {
-                message: "Expected function body".to_string(),
-                line: self.peek().line,
+                message: "Expected '{' or '=>' after lambda parameters".to_string(),
+                span: self.peek().span,
             });
         };
         
-        Ok(Statement::FunctionDeclaration {
-            name,
+        Ok(Expr::Function {
             parameters,
             body,
             return_type,
-            is_exported: false,
+            span: self.span_from(start),
+        })
+    }
+    
+    fn struct_declaration(&mut self, start: Span) -> Result<Statement, ParseError> {
+        let name = if let TokenType::Identifier(name) = &self.peek().token_type {
+            name.clone()
+        } else {
+            return Err(ParseError {
+                message: "Expected struct name".to_string(),
+                span: self.peek().span,
+            });
+        };
+        
+        self.advance();
+        self.consume(&TokenType::LeftBrace, "Expected '{' after struct name")?;
+        
+        let mut fields = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let field_start = self.peek().span;
+            let field_name = if let TokenType::Identifier(name) = &self.peek().token_type {
+                name.clone()
+            } else {
+                return Err(ParseError {
+                    message: "Expected field name".to_string(),
+                    span: self.peek().span,
+                });
+            };
+            
+            self.advance();
+            self.consume(&TokenType::Colon, "Expected ':' after field name")?;
+            let field_type = self.parse_type()?;
+            
+            fields.push(StructField {
+                name: field_name,
+                field_type,
+                span: self.span_from(field_start),
+            });
+            
+            if !self.match_token(&TokenType::Comma) {
+                break;
+            }
+        }
+        
+        self.consume(&TokenType::RightBrace, "Expected '}' after struct fields")?;
+        
+        Ok(Statement::StructDeclaration {
+            name,
+            fields,
+            span: self.span_from(start),
+        })
+    }
+    
+    fn enum_declaration(&mut self, start: Span) -> Result<Statement, ParseError> {
+        let name = if let TokenType::Identifier(name) = &self.peek().token_type {
+            name.clone()
+        } else {
+            return Err(ParseError {
+                message: "Expected enum name".to_string(),
+                span: self.peek().span,
+            });
+        };
+        
+        self.advance();
+        self.consume(&TokenType::LeftBrace, "Expected '{' after enum name")?;
+        
+        let mut variants = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let variant_start = self.peek().span;
+            let variant_name = if let TokenType::Identifier(name) = &self.peek().token_type {
+                name.clone()
+            } else {
+                return Err(ParseError {
+                    message: "Expected variant name".to_string(),
+                    span: self.peek().span,
+                });
+            };
+            self.advance();
+            
+            let mut fields = Vec::new();
+            if self.match_token(&TokenType::LeftParen) {
+                while !self.check(&TokenType::RightParen) {
+                    fields.push(self.parse_type()?);
+                    if !self.match_token(&TokenType::Comma) {
+                        break;
+                    }
+                }
+                self.consume(&TokenType::RightParen, "Expected ')' after variant fields")?;
+            }
+            
+            variants.push(EnumVariant {
+                name: variant_name,
+                fields,
+                span: self.span_from(variant_start),
+            });
+            
+            if !self.match_token(&TokenType::Comma) {
+                break;
+            }
+        }
+        
+        self.consume(&TokenType::RightBrace, "Expected '}' after enum variants")?;
+        
+        Ok(Statement::EnumDeclaration {
+            name,
+            variants,
+            span: self.span_from(start),
         })
     }
     
@@ -222,35 +504,63 @@ impl<'a> Parser<'a> {
             Ok(Type::String)
         } else if self.match_identifier("void") {
             Ok(Type::Void)
+        } else if self.match_identifier("array") {
+            self.consume(&TokenType::LeftBracket, "Expected '[' after 'array'")?;
+            let element_type = self.parse_type()?;
+            self.consume(&TokenType::RightBracket, "Expected ']' after array element type")?;
+            Ok(Type::Array(Box::new(element_type)))
+        } else if self.match_token(&TokenType::Def) {
+            // Function types mirror declarations: def(int, int): int
+            self.consume(&TokenType::LeftParen, "Expected '(' after 'def'")?;
+            let mut params = Vec::new();
+            if !self.check(&TokenType::RightParen) {
+                loop {
+                    params.push(self.parse_type()?);
+                    if !self.match_token(&TokenType::Comma) {
+                        break;
+                    }
+                }
+            }
+            self.consume(&TokenType::RightParen, "Expected ')' after parameter types")?;
+            let return_type = if self.match_token(&TokenType::Colon) {
+                self.parse_type()?
+            } else {
+                Type::Void
+            };
+            Ok(Type::Function { params, return_type: Box::new(return_type) })
+        } else if let TokenType::Identifier(name) = &self.peek().token_type {
+            // Any other name refers to a struct or enum; semantic analysis checks it exists
+            let name = name.clone();
+            self.advance();
+            Ok(Type::Named(name))
         } else {
             Err(ParseError {
                 message: "Expected type".to_string(),
-                line: self.peek().line,
+                span: self.peek().span,
             })
         }
     }
 
     fn match_identifier(&mut self, name: &str) -> bool {
-        if let Some(token) = self.tokens.get(self.current) {
-            if let TokenType::Identifier(id) = &token.token_type {
-                if id == name {
-                    self.current += 1;
-                    return true;
-                }
-            }
+        if let Some(token) = self.tokens.get(self.current)
+            && let TokenType::Identifier(id) = &token.token_type
+            && id == name
+        {
+            self.current += 1;
+            return true;
         }
         false
     }
     
     fn import_declaration(&mut self) -> Result<Statement, ParseError> {
-        self.consume(&TokenType::Import, "Expected 'import' keyword")?;
+        let start = self.consume(&TokenType::Import, "Expected 'import' keyword")?.span;
         
         let module = if let TokenType::Identifier(name) = &self.peek().token_type {
             name.clone()
         } else {
             return Err(ParseError {
                 message: "Expected module name".to_string(),
-                line: self.peek().line,
+                span: self.peek().span,
             });
         };
         
@@ -262,34 +572,34 @@ impl<'a> Parser<'a> {
             } else {
                 return Err(ParseError {
                     message: "Expected alias name".to_string(),
-                    line: self.peek().line,
+                    span: self.peek().span,
                 });
             };
             
             self.consume(&TokenType::Identifier(alias.clone().unwrap()), "Expected alias name")?;
             self.consume(&TokenType::Semicolon, "Expected ';' after import statement")?;
             
-            Ok(Statement::Import { module, alias })
+            Ok(Statement::Import { module, alias, span: self.span_from(start) })
         } else if self.match_token(&TokenType::From) {
             // Handle from ... import ...
             let items = self.parse_import_list()?;
             self.consume(&TokenType::Semicolon, "Expected ';' after import statement")?;
-            Ok(Statement::ImportFrom { module, items })
+            Ok(Statement::ImportFrom { module, items, span: self.span_from(start) })
         } else {
             self.consume(&TokenType::Semicolon, "Expected ';' after import statement")?;
-            Ok(Statement::Import { module, alias: None })
+            Ok(Statement::Import { module, alias: None, span: self.span_from(start) })
         }
     }
     
-    fn from_import_declaration(&mut self) -> Result<Statement, ParseError> {
-        self.consume(&TokenType::From, "Expected 'from' keyword")?;
+    fn import_from_declaration(&mut self) -> Result<Statement, ParseError> {
+        let start = self.consume(&TokenType::From, "Expected 'from' keyword")?.span;
         
         let module = if let TokenType::Identifier(name) = &self.peek().token_type {
             name.clone()
         } else {
             return Err(ParseError {
                 message: "Expected module name".to_string(),
-                line: self.peek().line,
+                span: self.peek().span,
             });
         };
         
@@ -298,7 +608,7 @@ impl<'a> Parser<'a> {
         
         let items = self.parse_import_list()?;
         self.consume(&TokenType::Semicolon, "Expected ';' after import statement")?;
-        Ok(Statement::ImportFrom { module, items })
+        Ok(Statement::ImportFrom { module, items, span: self.span_from(start) })
     }
 
     fn parse_import_list(&mut self) -> Result<Vec<(String, Option<String>)>, ParseError> {
@@ -314,7 +624,7 @@ impl<'a> Parser<'a> {
             } else {
                 return Err(ParseError {
                     message: "Expected import item".to_string(),
-                    line: self.peek().line,
+                    span: self.peek().span,
                 });
             };
             
@@ -326,7 +636,7 @@ impl<'a> Parser<'a> {
                 } else {
                     return Err(ParseError {
                         message: "Expected alias name".to_string(),
-                        line: self.peek().line,
+                        span: self.peek().span,
                     });
                 };
                 
@@ -352,28 +662,41 @@ impl<'a> Parser<'a> {
     }
     
     fn statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.peek().span;
+        
         if self.check(&TokenType::LeftBrace) {
-            return Ok(Statement::Block { statements: self.block()? });
+            let statements = self.block()?;
+            return Ok(Statement::Block { statements, span: self.span_from(start) });
         }
         
         if self.match_token(&TokenType::If) {
-            return self.if_statement();
+            return self.if_statement(start);
         }
         
         if self.match_token(&TokenType::While) {
-            return self.while_statement();
+            return self.while_statement(start);
+        }
+        
+        if self.match_token(&TokenType::For) {
+            return self.for_statement(start);
+        }
+        
+        if self.match_token(&TokenType::Match) {
+            let scrutinee = self.match_scrutinee()?;
+            let arms = self.match_arms(Self::match_statement_arm)?;
+            return Ok(Statement::Match { scrutinee, arms, span: self.span_from(start) });
         }
         
         if self.match_token(&TokenType::Return) {
-            return self.return_statement();
+            return self.return_statement(start);
         }
         
         if self.match_token(&TokenType::Break) {
-            return self.break_statement();
+            return self.break_statement(start);
         }
         
         if self.match_token(&TokenType::Continue) {
-            return self.continue_statement();
+            return self.continue_statement(start);
         }
         
         self.expression_statement()
@@ -384,14 +707,16 @@ impl<'a> Parser<'a> {
         
         let mut statements = Vec::new();
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            statements.push(self.declaration()?);
+            if let Some(statement) = self.recovering_declaration() {
+                statements.push(statement);
+            }
         }
         
         self.consume(&TokenType::RightBrace, "Expected '}' after block")?;
         Ok(statements)
     }
     
-    fn if_statement(&mut self) -> Result<Statement, ParseError> {
+    fn if_statement(&mut self, start: Span) -> Result<Statement, ParseError> {
         self.consume(&TokenType::LeftParen, "Expected '(' after 'if'")?;
         let condition = Box::new(self.expression()?);
         self.consume(&TokenType::RightParen, "Expected ')' after if condition")?;
@@ -408,20 +733,177 @@ impl<'a> Parser<'a> {
             condition,
             then_branch,
             else_branch,
+            span: self.span_from(start),
         })
     }
     
-    fn while_statement(&mut self) -> Result<Statement, ParseError> {
+    fn while_statement(&mut self, start: Span) -> Result<Statement, ParseError> {
         self.consume(&TokenType::LeftParen, "Expected '(' after 'while'")?;
         let condition = Box::new(self.expression()?);
         self.consume(&TokenType::RightParen, "Expected ')' after while condition")?;
         
         let body = Box::new(self.statement()?);
         
-        Ok(Statement::While { condition, body })
+        Ok(Statement::While { condition, body, span: self.span_from(start) })
+    }
+    
+    fn match_scrutinee(&mut self) -> Result<Box<Expr>, ParseError> {
+        self.consume(&TokenType::LeftParen, "Expected '(' after 'match'")?;
+        let scrutinee = Box::new(self.expression()?);
+        self.consume(&TokenType::RightParen, "Expected ')' after match value")?;
+        Ok(scrutinee)
+    }
+    
+    /// Parses `{ pattern => body ... }`, with `arm_body` parsing each body and
+    /// whatever separates it from the next arm.
+    fn match_arms<B>(&mut self, mut arm_body: impl FnMut(&mut Self) -> Result<B, ParseError>) -> Result<Vec<MatchArm<B>>, ParseError> {
+        self.consume(&TokenType::LeftBrace, "Expected '{' after match value")?;
+        
+        let mut arms = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let arm_start = self.peek().span;
+            let pattern = self.pattern()?;
+            self.consume(&TokenType::FatArrow, "Expected '=>' after pattern")?;
+            let body = arm_body(self)?;
+            arms.push(MatchArm { pattern, body, span: self.span_from(arm_start) });
+        }
+        
+        self.consume(&TokenType::RightBrace, "Expected '}' after match arms")?;
+        Ok(arms)
     }
     
-    fn return_statement(&mut self) -> Result<Statement, ParseError> {
+    /// Parses the body of a match statement arm: a statement, or an
+    /// expression ended by `,` or `;` as in a match expression.
+    fn match_statement_arm(&mut self) -> Result<Statement, ParseError> {
+        let starts_statement = matches!(
+            self.peek().token_type,
+            TokenType::LeftBrace
+                | TokenType::Store
+                | TokenType::If
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Match
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue
+        );
+        if starts_statement {
+            let body = self.statement()?;
+            self.match_token(&TokenType::Comma);
+            return Ok(body);
+        }
+        
+        let expr = self.expression()?;
+        if !self.check(&TokenType::RightBrace) && !self.match_token(&TokenType::Semicolon) {
+            self.consume(&TokenType::Comma, "Expected ',' after match arm")?;
+        }
+        Ok(Statement::Expression(expr))
+    }
+    
+    fn pattern(&mut self) -> Result<Pattern, ParseError> {
+        let start = self.peek().span;
+        let token_type = self.peek().token_type.clone();
+        match token_type {
+            TokenType::Identifier(name) => {
+                self.advance();
+                if name == "_" {
+                    return Ok(Pattern::Wildcard { span: start });
+                }
+                if !self.match_token(&TokenType::ColonColon) {
+                    return Ok(Pattern::Binding { name, span: start });
+                }
+                
+                let variant = self.variant_name()?;
+                let mut fields = Vec::new();
+                if self.match_token(&TokenType::LeftParen) {
+                    while !self.check(&TokenType::RightParen) {
+                        fields.push(self.pattern()?);
+                        if !self.match_token(&TokenType::Comma) {
+                            break;
+                        }
+                    }
+                    self.consume(&TokenType::RightParen, "Expected ')' after variant patterns")?;
+                }
+                Ok(Pattern::Variant { enum_name: name, variant, fields, span: self.span_from(start) })
+            }
+            TokenType::Minus => {
+                self.advance();
+                let value = match self.peek().token_type {
+                    TokenType::Integer(value) => Literal::Integer(-value),
+                    TokenType::IntMinMagnitude => Literal::Integer(i64::MIN),
+                    TokenType::Float(value) => Literal::Float(-value),
+                    _ => return Err(ParseError {
+                        message: "Expected a number after '-' in pattern".to_string(),
+                        span: self.peek().span,
+                    }),
+                };
+                self.advance();
+                Ok(Pattern::Literal { value, span: self.span_from(start) })
+            }
+            _ => {
+                let value = match token_type {
+                    TokenType::Integer(value) => Literal::Integer(value),
+                    TokenType::Float(value) => Literal::Float(value),
+                    TokenType::String(value) => Literal::String(value),
+                    TokenType::True => Literal::Boolean(true),
+                    TokenType::False => Literal::Boolean(false),
+                    _ => return Err(ParseError {
+                        message: "Expected pattern".to_string(),
+                        span: start,
+                    }),
+                };
+                self.advance();
+                Ok(Pattern::Literal { value, span: start })
+            }
+        }
+    }
+    
+    fn variant_name(&mut self) -> Result<String, ParseError> {
+        if let TokenType::Identifier(name) = &self.peek().token_type {
+            let name = name.clone();
+            self.advance();
+            Ok(name)
+        } else {
+            Err(ParseError {
+                message: "Expected variant name after '::'".to_string(),
+                span: self.peek().span,
+            })
+        }
+    }
+    
+    fn for_statement(&mut self, start: Span) -> Result<Statement, ParseError> {
+        self.consume(&TokenType::LeftParen, "Expected '(' after 'for'")?;
+        
+        let variable = if let TokenType::Identifier(name) = &self.peek().token_type {
+            name.clone()
+        } else {
+            return Err(ParseError {
+                message: "Expected loop variable name".to_string(),
+                span: self.peek().span,
+            });
+        };
+        self.advance();
+        
+        self.consume(&TokenType::In, "Expected 'in' after loop variable")?;
+        
+        let mut iterable = self.expression()?;
+        if self.match_token(&TokenType::DotDot) {
+            let end = self.expression()?;
+            iterable = Expr::Range {
+                span: iterable.span().to(end.span()),
+                start: Box::new(iterable),
+                end: Box::new(end),
+            };
+        }
+        
+        self.consume(&TokenType::RightParen, "Expected ')' after for loop header")?;
+        
+        let body = Box::new(self.statement()?);
+        
+        Ok(Statement::For { variable, iterable: Box::new(iterable), body, span: self.span_from(start) })
+    }
+    
+    fn return_statement(&mut self, start: Span) -> Result<Statement, ParseError> {
         let value = if self.check(&TokenType::Semicolon) {
             None
         } else {
@@ -429,17 +911,17 @@ impl<'a> Parser<'a> {
         };
         
         self.consume(&TokenType::Semicolon, "Expected ';' after return value")?;
-        Ok(Statement::Return { value })
+        Ok(Statement::Return { value, span: self.span_from(start) })
     }
     
-    fn break_statement(&mut self) -> Result<Statement, ParseError> {
+    fn break_statement(&mut self, start: Span) -> Result<Statement, ParseError> {
         self.consume(&TokenType::Semicolon, "Expected ';' after 'break'")?;
-        Ok(Statement::Break)
+        Ok(Statement::Break { span: self.span_from(start) })
     }
     
-    fn continue_statement(&mut self) -> Result<Statement, ParseError> {
+    fn continue_statement(&mut self, start: Span) -> Result<Statement, ParseError> {
         self.consume(&TokenType::Semicolon, "Expected ';' after 'continue'")?;
-        Ok(Statement::Continue)
+        Ok(Statement::Continue { span: self.span_from(start) })
     }
     
     fn expression_statement(&mut self) -> Result<Statement, ParseError> {
@@ -459,16 +941,38 @@ impl<'a> Parser<'a> {
             let equals = self.previous().clone();
             let value = self.assignment()?;
             
-            if let Expr::Variable(name) = expr {
+            if let Expr::Variable { name, span } = expr {
+                let span = span.to(value.span());
                 return Ok(Expr::Assign {
                     name,
                     value: Box::new(value),
+                    span,
+                });
+            }
+            
+            if let Expr::Get { object, name, span } = expr {
+                let span = span.to(value.span());
+                return Ok(Expr::Set {
+                    object,
+                    name,
+                    value: Box::new(value),
+                    span,
+                });
+            }
+            
+            if let Expr::Index { sequence, index, span } = expr {
+                let span = span.to(value.span());
+                return Ok(Expr::SetIndex {
+                    sequence,
+                    index,
+                    value: Box::new(value),
+                    span,
                 });
             }
             
             return Err(ParseError {
                 message: "Invalid assignment target".to_string(),
-                line: equals.line,
+                span: equals.span,
             });
         }
         
@@ -481,10 +985,12 @@ impl<'a> Parser<'a> {
         while self.match_token(&TokenType::Or) {
             let operator = self.previous().clone();
             let right = self.and()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator: self.binary_operator_from_token(&operator)?,
                 right: Box::new(right),
+                span,
             };
         }
         
@@ -497,10 +1003,12 @@ impl<'a> Parser<'a> {
         while self.match_token(&TokenType::And) {
             let operator = self.previous().clone();
             let right = self.equality()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator: self.binary_operator_from_token(&operator)?,
                 right: Box::new(right),
+                span,
             };
         }
         
@@ -513,10 +1021,12 @@ impl<'a> Parser<'a> {
         while self.match_token(&TokenType::EqualEqual) || self.match_token(&TokenType::NotEqual) {
             let operator = self.previous().clone();
             let right = self.comparison()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator: self.binary_operator_from_token(&operator)?,
                 right: Box::new(right),
+                span,
             };
         }
         
@@ -532,10 +1042,12 @@ impl<'a> Parser<'a> {
             || self.match_token(&TokenType::LessEqual) {
             let operator = self.previous().clone();
             let right = self.term()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator: self.binary_operator_from_token(&operator)?,
                 right: Box::new(right),
+                span,
             };
         }
         
@@ -548,10 +1060,12 @@ impl<'a> Parser<'a> {
         while self.match_token(&TokenType::Plus) || self.match_token(&TokenType::Minus) {
             let operator = self.previous().clone();
             let right = self.factor()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator: self.binary_operator_from_token(&operator)?,
                 right: Box::new(right),
+                span,
             };
         }
         
@@ -566,10 +1080,12 @@ impl<'a> Parser<'a> {
             || self.match_token(&TokenType::Percent) {
             let operator = self.previous().clone();
             let right = self.unary()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator: self.binary_operator_from_token(&operator)?,
                 right: Box::new(right),
+                span,
             };
         }
         
@@ -579,10 +1095,24 @@ impl<'a> Parser<'a> {
     fn unary(&mut self) -> Result<Expr, ParseError> {
         if self.match_token(&TokenType::Minus) || self.match_token(&TokenType::Not) {
             let operator = self.previous().clone();
+            let is_negation = operator.token_type == TokenType::Minus;
+            if is_negation && self.match_token(&TokenType::IntMinMagnitude) {
+                return Ok(Expr::Literal { value: Literal::Integer(i64::MIN), span: self.span_from(operator.span) });
+            }
             let right = self.unary()?;
+            let span = operator.span.to(right.span());
+            // Only folding makes an i64::MIN literal, so negating one again
+            // can't be anything but an overflow
+            if is_negation && matches!(right, Expr::Literal { value: Literal::Integer(i64::MIN), .. }) {
+                return Err(ParseError {
+                    message: format!("Negating {} overflows int", i64::MIN),
+                    span,
+                });
+            }
             return Ok(Expr::Unary {
                 operator: self.unary_operator_from_token(&operator)?,
                 operand: Box::new(right),
+                span,
             });
         }
         
@@ -602,11 +1132,17 @@ impl<'a> Parser<'a> {
                 } else {
                     return Err(ParseError {
                         message: "Expected property name after '.'".to_string(),
-                        line: self.peek().line,
+                        span: self.peek().span,
                     });
                 };
                 self.consume(&TokenType::Identifier(prop_name.clone()), "Expected property name after '.'")?;
-                expr = Expr::Get { object: Box::new(expr), name: prop_name };
+                let span = self.span_from(expr.span());
+                expr = Expr::Get { object: Box::new(expr), name: prop_name, span };
+            } else if self.match_token(&TokenType::LeftBracket) {
+                let index = self.expression()?;
+                self.consume(&TokenType::RightBracket, "Expected ']' after index")?;
+                let span = self.span_from(expr.span());
+                expr = Expr::Index { sequence: Box::new(expr), index: Box::new(index), span };
             } else {
                 break;
             }
@@ -629,50 +1165,73 @@ impl<'a> Parser<'a> {
         
         self.consume(&TokenType::RightParen, "Expected ')' after arguments")?;
         
+        let span = self.span_from(callee.span());
         Ok(Expr::Call {
             callee: Box::new(callee),
             arguments,
+            span,
         })
     }
     
     fn primary(&mut self) -> Result<Expr, ParseError> {
+        let span = self.peek().span;
+        
         if self.match_token(&TokenType::Integer(0)) {
             let token = self.previous();
             if let TokenType::Integer(value) = token.token_type.clone() {
-                return Ok(Expr::Literal(Literal::Integer(value)));
+                return Ok(Expr::Literal { value: Literal::Integer(value), span });
             }
         }
         
+        if self.check(&TokenType::IntMinMagnitude) {
+            return Err(ParseError {
+                message: format!("Integer literal is too large for int, whose largest value is {}", i64::MAX),
+                span,
+            });
+        }
+        
         if self.match_token(&TokenType::Float(0.0)) {
             let token = self.previous();
             if let TokenType::Float(value) = token.token_type.clone() {
-                return Ok(Expr::Literal(Literal::Float(value)));
+                return Ok(Expr::Literal { value: Literal::Float(value), span });
             }
         }
         
         if self.match_token(&TokenType::String("".to_string())) {
             let token = self.previous();
             if let TokenType::String(value) = token.token_type.clone() {
-                return Ok(Expr::Literal(Literal::String(value)));
+                return Ok(Expr::Literal { value: Literal::String(value), span });
             }
         }
         
+        if let TokenType::FormatString(parts) = &self.peek().token_type {
+            let parts = parts.clone();
+            self.advance();
+            return self.format_string(parts, span);
+        }
+        
         if self.match_token(&TokenType::True) {
-            return Ok(Expr::Literal(Literal::Boolean(true)));
+            return Ok(Expr::Literal { value: Literal::Boolean(true), span });
         }
         
         if self.match_token(&TokenType::False) {
-            return Ok(Expr::Literal(Literal::Boolean(false)));
+            return Ok(Expr::Literal { value: Literal::Boolean(false), span });
         }
         
         if self.match_token(&TokenType::Null) {
-            return Ok(Expr::Literal(Literal::Null));
+            return Ok(Expr::Literal { value: Literal::Null, span });
         }
         
         if let TokenType::Identifier(name) = &self.peek().token_type {
             let name = name.clone();
             self.consume(&TokenType::Identifier(name.clone()), "Expected identifier")?;
-            return Ok(Expr::Variable(name));
+            if self.at_struct_literal() {
+                return self.struct_literal(name, span);
+            }
+            if self.match_token(&TokenType::ColonColon) {
+                return self.enum_variant(name, span);
+            }
+            return Ok(Expr::Variable { name, span });
         }
         
         if self.match_token(&TokenType::LeftParen) {
@@ -681,12 +1240,99 @@ impl<'a> Parser<'a> {
             return Ok(expr);
         }
         
+        if self.match_token(&TokenType::Def) {
+            return self.lambda(span);
+        }
+        
+        if self.match_token(&TokenType::Match) {
+            let scrutinee = self.match_scrutinee()?;
+            let arms = self.match_arms(|parser| {
+                let body = parser.expression()?;
+                if !parser.check(&TokenType::RightBrace) {
+                    parser.consume(&TokenType::Comma, "Expected ',' after match arm")?;
+                }
+                Ok(body)
+            })?;
+            return Ok(Expr::Match { scrutinee, arms, span: self.span_from(span) });
+        }
+        
+        if self.match_token(&TokenType::LeftBracket) {
+            let mut elements = Vec::new();
+            while !self.check(&TokenType::RightBracket) {
+                elements.push(self.expression()?);
+                if !self.match_token(&TokenType::Comma) {
+                    break;
+                }
+            }
+            self.consume(&TokenType::RightBracket, "Expected ']' after array elements")?;
+            return Ok(Expr::Array { elements, span: self.span_from(span) });
+        }
+        
         Err(ParseError {
             message: format!("Expected expression, got {:?}", self.peek().token_type),
-            line: self.peek().line,
+            span: self.peek().span,
         })
     }
     
+    fn enum_variant(&mut self, enum_name: String, start: Span) -> Result<Expr, ParseError> {
+        let variant = self.variant_name()?;
+        let mut arguments = Vec::new();
+        if self.match_token(&TokenType::LeftParen) {
+            while !self.check(&TokenType::RightParen) {
+                arguments.push(self.expression()?);
+                if !self.match_token(&TokenType::Comma) {
+                    break;
+                }
+            }
+            self.consume(&TokenType::RightParen, "Expected ')' after variant arguments")?;
+        }
+        Ok(Expr::EnumVariant { enum_name, variant, arguments, span: self.span_from(start) })
+    }
+    
+    /// Whether the tokens after a name start a struct literal body: `{ }` or
+    /// `{ field: ...`. Looking past the brace keeps `while (x) { ... }` and
+    /// friends from being mistaken for construction.
+    fn at_struct_literal(&self) -> bool {
+        if !self.check(&TokenType::LeftBrace) {
+            return false;
+        }
+        match self.tokens.get(self.current + 1).map(|token| &token.token_type) {
+            Some(TokenType::RightBrace) => true,
+            Some(TokenType::Identifier(_)) => matches!(
+                self.tokens.get(self.current + 2).map(|token| &token.token_type),
+                Some(TokenType::Colon)
+            ),
+            _ => false,
+        }
+    }
+    
+    fn struct_literal(&mut self, name: String, start: Span) -> Result<Expr, ParseError> {
+        self.consume(&TokenType::LeftBrace, "Expected '{' after struct name")?;
+        
+        let mut fields = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            let field_name = if let TokenType::Identifier(name) = &self.peek().token_type {
+                name.clone()
+            } else {
+                return Err(ParseError {
+                    message: "Expected field name".to_string(),
+                    span: self.peek().span,
+                });
+            };
+            
+            self.advance();
+            self.consume(&TokenType::Colon, "Expected ':' after field name")?;
+            fields.push((field_name, self.expression()?));
+            
+            if !self.match_token(&TokenType::Comma) {
+                break;
+            }
+        }
+        
+        self.consume(&TokenType::RightBrace, "Expected '}' after struct fields")?;
+        Ok(Expr::StructLiteral { name, fields, span: self.span_from(start) })
+    }
+    
     fn binary_operator_from_token(&self, token: &Token) -> Result<BinaryOperator, ParseError> {
         match &token.token_type {
             TokenType::Plus => Ok(BinaryOperator::Plus),
@@ -704,7 +1350,7 @@ impl<'a> Parser<'a> {
             TokenType::Or => Ok(BinaryOperator::Or),
             _ => Err(ParseError {
                 message: format!("Invalid binary operator: {:?}", token.token_type),
-                line: token.line,
+                span: token.span,
             }),
         }
     }
@@ -715,11 +1361,16 @@ impl<'a> Parser<'a> {
             TokenType::Not => Ok(UnaryOperator::Not),
             _ => Err(ParseError {
                 message: format!("Invalid unary operator: {:?}", token.token_type),
-                line: token.line,
+                span: token.span,
             }),
         }
     }
     
+    /// Span from `start` up to and including the most recently consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span)
+    }
+    
     fn match_token(&mut self, token_type: &TokenType) -> bool {
         if self.check(token_type) {
             self.advance();
@@ -728,6 +1379,35 @@ impl<'a> Parser<'a> {
         false
     }
     
+    /// Parses the expressions and specs the lexer split an `f"..."` into.
+    fn format_string(&mut self, parts: Vec<FormatStringPart>, span: Span) -> Result<Expr, ParseError> {
+        let mut parsed = Vec::new();
+        for part in parts {
+            match part {
+                FormatStringPart::Literal(text) => parsed.push(FormatPart::Literal(text)),
+                FormatStringPart::Expr { tokens, spec } => {
+                    let mut parser = Parser::new(&tokens);
+                    let expr = parser.expression()?;
+                    if let Some(error) = std::mem::take(&mut parser.errors).into_iter().next() {
+                        return Err(error);
+                    }
+                    if !parser.is_at_end() {
+                        return Err(ParseError {
+                            message: "Expected '}' after the expression in a format string".to_string(),
+                            span: parser.peek().span,
+                        });
+                    }
+                    let spec = match spec {
+                        Some((text, span)) => format_spec(&text, span)?,
+                        None => FormatSpec::default(),
+                    };
+                    parsed.push(FormatPart::Expr { expr: Box::new(expr), spec });
+                }
+            }
+        }
+        Ok(Expr::FormatString { parts: parsed, span })
+    }
+    
     fn check(&self, token_type: &TokenType) -> bool {
         if self.is_at_end() {
             return false;
@@ -761,7 +1441,43 @@ impl<'a> Parser<'a> {
         
         Err(ParseError {
             message: message.to_string(),
-            line: self.peek().line,
+            span: self.peek().span,
         })
     }
-}
\ No newline at end of file
+}
+
+/// Parses the `[<|>][0][width][.precision]` spec of an interpolation.
+fn format_spec(text: &str, span: Span) -> Result<FormatSpec, ParseError> {
+    let error = |message: String| ParseError { message, span };
+    let mut rest = text;
+    let number = |rest: &mut &str| -> Result<Option<usize>, ParseError> {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let (number, after) = rest.split_at(digits);
+        *rest = after;
+        if number.is_empty() {
+            return Ok(None);
+        }
+        number.parse().map(Some).map_err(|_| error(format!("Width or precision '{}' is too large", number)))
+    };
+    
+    let left_align = rest.starts_with('<');
+    if let Some(after) = rest.strip_prefix(['<', '>']) {
+        rest = after;
+    }
+    let zero_pad = rest.starts_with('0');
+    let width = number(&mut rest)?;
+    let precision = match rest.strip_prefix('.') {
+        Some(after) => {
+            rest = after;
+            Some(number(&mut rest)?.ok_or_else(|| error(format!("Expected digits after '.' in format spec '{}'", text)))?)
+        }
+        None => None,
+    };
+    if !rest.is_empty() {
+        return Err(error(format!("Invalid format spec '{}'; expected [<|>][0][width][.precision]", text)));
+    }
+    if left_align && zero_pad {
+        return Err(error(format!("Format spec '{}' can't both left-align and pad with zeros", text)));
+    }
+    Ok(FormatSpec { left_align, zero_pad, width, precision })
+}
@@ -1,20 +1,21 @@
 #[cfg(test)]
-mod tests {
-    use crate::parser::parse;
-    use crate::ast::{Statement, Expr, Literal, BinaryOperator};
+mod parser_tests {
+    use crate::parser::{parse, parse_strict};
+    use crate::ast::{Statement, Expr, Literal, BinaryOperator, Type, Pattern, FormatPart, FormatSpec};
     use crate::lexer::tokenize;
     
     #[test]
     fn test_variable_declaration() {
         let source = "store x = 42;";
         let tokens = tokenize(source).unwrap();
-        let program = parse(&tokens).unwrap();
+        let (program, errors) = parse(&tokens);
+        assert!(errors.is_empty());
         
         match &program.statements[0] {
-            Statement::LetDeclaration { name, initializer: Some(init), is_exported: _ } => {
+            Statement::LetDeclaration { name, initializer: Some(init), is_exported: _, .. } => {
                 assert_eq!(name, "x");
                 match init {
-                    Expr::Literal(Literal::Integer(42)) => (),
+                    Expr::Literal { value: Literal::Integer(42), .. } => (),
                     _ => panic!("Expected integer literal 42"),
                 }
             },
@@ -22,11 +23,35 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_most_negative_integer() {
+        let initializer = |source: &str| match parse_strict(&tokenize(source).unwrap()).unwrap().statements.remove(0) {
+            Statement::LetDeclaration { initializer: Some(init), .. } => init,
+            _ => panic!("Expected let declaration"),
+        };
+        
+        assert!(matches!(initializer("store x = -9223372036854775808;"), Expr::Literal { value: Literal::Integer(i64::MIN), .. }));
+        assert!(matches!(initializer("store x = -128i8;"), Expr::Unary { .. }));
+        
+        // Only the literal itself is folded, so negating it again overflows
+        for source in ["store x = - -9223372036854775808;", "store x = -(-9223372036854775808);"] {
+            let error = parse_strict(&tokenize(source).unwrap()).unwrap_err();
+            assert_eq!(error.0[0].message, "Negating -9223372036854775808 overflows int", "{}", source);
+        }
+        match initializer("store n = match (k) { -9223372036854775808 => 1, _ => 0 };") {
+            Expr::Match { arms, .. } => {
+                assert!(matches!(arms[0].pattern, Pattern::Literal { value: Literal::Integer(i64::MIN), .. }));
+            },
+            _ => panic!("Expected match expression"),
+        }
+    }
+    
     #[test]
     fn test_function_declaration() {
         let source = "def add(x, y) { return x + y; }";
         let tokens = tokenize(source).unwrap();
-        let program = parse(&tokens).unwrap();
+        let (program, errors) = parse(&tokens);
+        assert!(errors.is_empty());
         
         match &program.statements[0] {
             Statement::FunctionDeclaration { name, parameters, body, .. } => {
@@ -44,16 +69,17 @@ mod tests {
     fn test_binary_expression() {
         let source = "x + y;";
         let tokens = tokenize(source).unwrap();
-        let program = parse(&tokens).unwrap();
+        let (program, errors) = parse(&tokens);
+        assert!(errors.is_empty());
         
         match &program.statements[0] {
-            Statement::Expression(Expr::Binary { left, operator: BinaryOperator::Plus, right }) => {
+            Statement::Expression(Expr::Binary { left, operator: BinaryOperator::Plus, right, .. }) => {
                 match &**left {
-                    Expr::Variable(name) => assert_eq!(name, "x"),
+                    Expr::Variable { name, .. } => assert_eq!(name, "x"),
                     _ => panic!("Expected variable x"),
                 }
                 match &**right {
-                    Expr::Variable(name) => assert_eq!(name, "y"),
+                    Expr::Variable { name, .. } => assert_eq!(name, "y"),
                     _ => panic!("Expected variable y"),
                 }
             },
@@ -65,10 +91,11 @@ mod tests {
     fn test_if_statement() {
         let source = "if (x > 5) { y = 10; } else { y = 0; }";
         let tokens = tokenize(source).unwrap();
-        let program = parse(&tokens).unwrap();
+        let (program, errors) = parse(&tokens);
+        assert!(errors.is_empty());
         
         match &program.statements[0] {
-            Statement::If { condition, then_branch: _, else_branch: Some(_) } => {
+            Statement::If { condition, then_branch: _, else_branch: Some(_), .. } => {
                 match &**condition {
                     Expr::Binary { operator: BinaryOperator::Greater, .. } => (),
                     _ => panic!("Expected greater than operation"),
@@ -77,4 +104,295 @@ mod tests {
             _ => panic!("Expected if statement"),
         }
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_for_range() {
+        let tokens = tokenize("for (i in 0..n) { print(i); }").unwrap();
+        let (program, errors) = parse(&tokens);
+        assert!(errors.is_empty());
+        
+        match &program.statements[0] {
+            Statement::For { variable, iterable, .. } => {
+                assert_eq!(variable, "i");
+                match iterable.as_ref() {
+                    Expr::Range { start, end, .. } => {
+                        assert!(matches!(start.as_ref(), Expr::Literal { value: Literal::Integer(0), .. }));
+                        assert!(matches!(end.as_ref(), Expr::Variable { name, .. } if name == "n"));
+                    },
+                    _ => panic!("Expected range"),
+                }
+            },
+            _ => panic!("Expected for statement"),
+        }
+    }
+    
+    #[test]
+    fn test_arrays() {
+        let tokens = tokenize("store xs: array[int] = [1, 2]; xs[0] = xs[1];").unwrap();
+        let (program, errors) = parse(&tokens);
+        assert!(errors.is_empty());
+        
+        match &program.statements[0] {
+            Statement::LetDeclaration { var_type, initializer: Some(Expr::Array { elements, .. }), .. } => {
+                assert_eq!(var_type, &Some(Type::Array(Box::new(Type::Integer))));
+                assert_eq!(elements.len(), 2);
+            },
+            _ => panic!("Expected array declaration"),
+        }
+        match &program.statements[1] {
+            Statement::Expression(Expr::SetIndex { sequence, value, .. }) => {
+                assert!(matches!(sequence.as_ref(), Expr::Variable { name, .. } if name == "xs"));
+                assert!(matches!(value.as_ref(), Expr::Index { .. }));
+            },
+            _ => panic!("Expected element assignment"),
+        }
+    }
+    
+    #[test]
+    fn test_structs() {
+        let source = "struct Point { x: int, y: float }\nstore p: Point = Point { x: 1, y: 2.0 };\np.x = p.x;\nwhile (p) {}";
+        let tokens = tokenize(source).unwrap();
+        let (program, errors) = parse(&tokens);
+        assert!(errors.is_empty());
+        
+        match &program.statements[0] {
+            Statement::StructDeclaration { name, fields, .. } => {
+                assert_eq!(name, "Point");
+                let fields: Vec<(&str, &Type)> = fields.iter().map(|f| (f.name.as_str(), &f.field_type)).collect();
+                assert_eq!(fields, vec![("x", &Type::Integer), ("y", &Type::Float)]);
+            },
+            _ => panic!("Expected struct declaration"),
+        }
+        match &program.statements[1] {
+            Statement::LetDeclaration { var_type, initializer: Some(Expr::StructLiteral { name, fields, .. }), .. } => {
+                assert_eq!(var_type, &Some(Type::Named("Point".to_string())));
+                assert_eq!(name, "Point");
+                assert_eq!(fields.len(), 2);
+            },
+            _ => panic!("Expected struct literal"),
+        }
+        match &program.statements[2] {
+            Statement::Expression(Expr::Set { name, value, .. }) => {
+                assert_eq!(name, "x");
+                assert!(matches!(value.as_ref(), Expr::Get { .. }));
+            },
+            _ => panic!("Expected field assignment"),
+        }
+        // A brace after a condition still starts the loop body
+        assert!(matches!(&program.statements[3], Statement::While { .. }));
+    }
+    
+    #[test]
+    fn test_enums_and_match() {
+        let source = "\
+enum Shape { Circle(float), Empty }
+match (s) {
+    Shape::Circle(r) => print(r),
+    Shape::Empty => { print(0); }
+    _ => print(-1);
+}
+store n = match (k) { -1 => \"neg\", x => \"other\" };";
+        let tokens = tokenize(source).unwrap();
+        let (program, errors) = parse(&tokens);
+        assert!(errors.is_empty(), "{:?}", errors);
+        
+        match &program.statements[0] {
+            Statement::EnumDeclaration { name, variants, .. } => {
+                assert_eq!(name, "Shape");
+                let variants: Vec<(&str, usize)> = variants.iter().map(|v| (v.name.as_str(), v.fields.len())).collect();
+                assert_eq!(variants, vec![("Circle", 1), ("Empty", 0)]);
+            },
+            _ => panic!("Expected enum declaration"),
+        }
+        match &program.statements[1] {
+            Statement::Match { arms, .. } => {
+                assert_eq!(arms.len(), 3);
+                match &arms[0].pattern {
+                    Pattern::Variant { enum_name, variant, fields, .. } => {
+                        assert_eq!((enum_name.as_str(), variant.as_str()), ("Shape", "Circle"));
+                        assert!(matches!(&fields[..], [Pattern::Binding { name, .. }] if name == "r"));
+                    },
+                    other => panic!("Expected variant pattern, got {:?}", other),
+                }
+                assert!(matches!(arms[1].body, Statement::Block { .. }));
+                assert!(matches!(arms[2].pattern, Pattern::Wildcard { .. }));
+            },
+            _ => panic!("Expected match statement"),
+        }
+        match &program.statements[2] {
+            Statement::LetDeclaration { initializer: Some(Expr::Match { arms, .. }), .. } => {
+                assert!(matches!(arms[0].pattern, Pattern::Literal { value: Literal::Integer(-1), .. }));
+                assert!(matches!(arms[1].pattern, Pattern::Binding { .. }));
+            },
+            _ => panic!("Expected match expression"),
+        }
+    }
+    
+    #[test]
+    fn test_lambdas_and_function_types() {
+        let source = "\
+def apply(f: def(int): int, x: int): int { return f(x); }
+store square = def(x: int) => x * x;
+store log = def(message: string) { println(message); };
+make_adder(1)(2);";
+        let tokens = tokenize(source).unwrap();
+        let (program, errors) = parse(&tokens);
+        assert!(errors.is_empty(), "{:?}", errors);
+        
+        match &program.statements[0] {
+            Statement::FunctionDeclaration { parameters, .. } => {
+                assert_eq!(parameters[0].param_type, Type::Function {
+                    params: vec![Type::Integer],
+                    return_type: Box::new(Type::Integer),
+                });
+            },
+            _ => panic!("Expected function declaration"),
+        }
+        match &program.statements[1] {
+            Statement::LetDeclaration { initializer: Some(Expr::Function { parameters, body, return_type, .. }), .. } => {
+                assert_eq!(parameters.len(), 1);
+                assert!(return_type.is_none());
+                assert!(matches!(&body[..], [Statement::Return { value: Some(_), .. }]));
+            },
+            _ => panic!("Expected lambda"),
+        }
+        assert!(matches!(
+            &program.statements[2],
+            Statement::LetDeclaration { initializer: Some(Expr::Function { .. }), .. }
+        ));
+        match &program.statements[3] {
+            Statement::Expression(Expr::Call { callee, .. }) => {
+                assert!(matches!(callee.as_ref(), Expr::Call { .. }));
+            },
+            _ => panic!("Expected call"),
+        }
+    }
+    
+    #[test]
+    fn test_node_spans() {
+        let source = "def main() {\n    store total = 1 + 2;\n}";
+        let tokens = tokenize(source).unwrap();
+        let (program, errors) = parse(&tokens);
+        assert!(errors.is_empty());
+        
+        let function = &program.statements[0];
+        assert_eq!(function.span().start, 0);
+        assert_eq!(function.span().end, source.len());
+        
+        match function {
+            Statement::FunctionDeclaration { body, .. } => {
+                let span = body[0].span();
+                assert_eq!((span.line, span.column), (2, 5));
+                assert_eq!(&source[span.start..span.end], "store total = 1 + 2;");
+            },
+            _ => panic!("Expected function declaration"),
+        }
+    }
+    
+    #[test]
+    fn test_error_span() {
+        let tokens = tokenize("store x = 1;\nstore = 2;").unwrap();
+        let err = parse(&tokens).1.remove(0);
+        assert_eq!((err.span.line, err.span.column), (2, 7));
+    }
+    
+    #[test]
+    fn test_reports_every_syntax_error() {
+        let source = "\
+def main() {
+    store = 1;
+    store ok = 2;
+    print(ok +);
+    store also_ok = 3;
+}
+
+def (broken) {
+    return 1;
+}
+
+def helper() {
+    return 4;
+}";
+        let tokens = tokenize(source).unwrap();
+        let (program, errors) = parse(&tokens);
+        
+        let lines: Vec<usize> = errors.iter().map(|e| e.span.line).collect();
+        assert_eq!(lines, vec![2, 4, 8]);
+        
+        // Statements around the errors are kept in the partial program
+        assert_eq!(program.statements.len(), 2);
+        match &program.statements[0] {
+            Statement::FunctionDeclaration { name, body, .. } => {
+                assert_eq!(name, "main");
+                assert_eq!(body.len(), 2);
+            },
+            _ => panic!("Expected function declaration"),
+        }
+        match &program.statements[1] {
+            Statement::FunctionDeclaration { name, .. } => assert_eq!(name, "helper"),
+            _ => panic!("Expected function declaration"),
+        }
+    }
+    
+    #[test]
+    fn test_recovers_from_stray_closing_brace() {
+        let tokens = tokenize("} store x = 1; store y = ;").unwrap();
+        let (program, errors) = parse(&tokens);
+        
+        assert_eq!(errors.len(), 2);
+        assert_eq!(program.statements.len(), 1);
+        assert!(parse_strict(&tokens).is_err());
+    }
+    
+    #[test]
+    fn test_format_string() {
+        let tokens = tokenize("f\"{a}/{b + 1:<6} {c:08.3}\";").unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        let Statement::Expression(Expr::FormatString { parts, .. }) = &program.statements[0] else {
+            panic!("Expected a format string");
+        };
+        
+        let specs: Vec<&FormatSpec> = parts.iter()
+            .filter_map(|part| match part {
+                FormatPart::Expr { spec, .. } => Some(spec),
+                FormatPart::Literal(_) => None,
+            })
+            .collect();
+        assert_eq!(specs, [
+            &FormatSpec::default(),
+            &FormatSpec { left_align: true, zero_pad: false, width: Some(6), precision: None },
+            &FormatSpec { left_align: false, zero_pad: true, width: Some(8), precision: Some(3) },
+        ]);
+        assert!(matches!(&parts[1], FormatPart::Literal(text) if text == "/"));
+        assert!(matches!(&parts[2], FormatPart::Expr { expr, .. } if matches!(**expr, Expr::Binary { .. })));
+        assert_eq!(specs[2].printf_flags(), "08.3");
+        assert_eq!(specs[1].pad("ab"), "ab    ");
+        assert_eq!(specs[2].pad("-1.500"), "-001.500");
+        
+        let error = |source: &str| parse_strict(&tokenize(source).unwrap()).unwrap_err().0.remove(0);
+        let err = error("f\"{x:5.}\";");
+        assert_eq!(err.message, "Expected digits after '.' in format spec '5.'");
+        assert_eq!(err.span.column, 6);
+        assert_eq!(error("f\"{x:<05}\";").message, "Format spec '<05' can't both left-align and pad with zeros");
+        assert_eq!(error("f\"{x y}\";").message, "Expected '}' after the expression in a format string");
+    }
+    
+    #[test]
+    fn test_doc_comments() {
+        let source = "/// The answer.\nexport store answer = 42;\n/// Says hi.\n/// Twice.\ndef hi() {\n    /// Unused.\n    store x = 1;\n}\n/// Dropped.\nprint(1);\ndef bare() {}";
+        let program = parse_strict(&tokenize(source).unwrap()).unwrap();
+        let docs: Vec<Option<&str>> = program.statements.iter()
+            .map(|stmt| match stmt {
+                Statement::LetDeclaration { doc, .. } | Statement::FunctionDeclaration { doc, .. } => doc.as_deref(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(docs, [Some("The answer."), Some("Says hi.\nTwice."), None, None]);
+        match &program.statements[1] {
+            Statement::FunctionDeclaration { body, .. } => {
+                assert!(matches!(&body[0], Statement::LetDeclaration { doc: Some(doc), .. } if doc == "Unused."));
+            }
+            _ => panic!("Expected function declaration"),
+        }
+    }
+}
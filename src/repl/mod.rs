@@ -0,0 +1,273 @@
+//! Interactive read-eval-print loop
+//!
+//! Each input is lexed, parsed and analyzed on its own, then run by an
+//! [`Interpreter`] that keeps its globals and functions between inputs. The
+//! semantic analyzer likewise keeps the declarations of earlier inputs, so
+//! later ones are checked against them.
+
+use crate::ast::{Expr, Program, Statement, Type};
+use crate::diagnostics::{self, Renderer};
+use crate::execution_engine::ExecutionError;
+use crate::interpreter::Interpreter;
+use crate::lexer::tokenize;
+use crate::parser::parse_strict;
+use crate::semantic::IncrementalAnalyzer;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+#[cfg(test)]
+mod tests;
+
+const HELP: &str = "\
+Enter statements or expressions; the value of an expression is printed.
+Inputs continue over several lines until their braces are balanced.
+
+Commands:
+  :type <expr>   show the type of an expression
+  :ast <input>   show the syntax tree of an input
+  :load <file>   run the declarations and statements of a file
+  :help          show this message
+  :quit          exit the REPL";
+
+#[derive(Default)]
+pub struct Repl {
+    analyzer: IncrementalAnalyzer,
+    interpreter: Interpreter,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs one complete input and returns the value to print, if any.
+    pub fn eval(&mut self, input: &str) -> Result<Option<String>, ExecutionError> {
+        let program = self.analyzer.analyze(parse(input)?, None)?;
+        // Expressions that only have an effect don't print anything
+        let echoes = match program.program.statements.last() {
+            Some(Statement::Expression(expr)) if !matches!(expr, Expr::Assign { .. } | Expr::Set { .. } | Expr::SetIndex { .. }) => {
+                self.analyzer.type_of(expr.clone()).is_ok_and(|ty| ty != Type::Void)
+            }
+            _ => false,
+        };
+        let value = self.interpreter.execute_incremental(&program, None)?;
+        Ok(value.filter(|_| echoes).map(|value| value.to_string()))
+    }
+
+    /// Runs a whole file, making its declarations available to later inputs.
+    /// Its `main` function is declared but not called.
+    pub fn load(&mut self, path: &Path) -> Result<(), ExecutionError> {
+        let source = std::fs::read_to_string(path)?;
+        let program = self.analyzer.analyze(parse(&source)?, Some(path))?;
+        self.interpreter.execute_incremental(&program, path.to_str())?;
+        Ok(())
+    }
+
+    pub fn type_of(&mut self, input: &str) -> Result<Type, ExecutionError> {
+        let expr = parse_expression(input)?;
+        Ok(self.analyzer.type_of(expr)?)
+    }
+
+    pub fn ast(&self, input: &str) -> Result<String, ExecutionError> {
+        let program = parse(input)?;
+        Ok(match program.statements.as_slice() {
+            [Statement::Expression(expr)] => format!("{:#?}", expr),
+            statements => format!("{:#?}", statements),
+        })
+    }
+
+    /// Reads inputs from the terminal until end of input or `:quit`.
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        let mut editor = DefaultEditor::new()?;
+        let history = history_path();
+        if let Some(history) = &history {
+            // There is no history the first time the REPL runs
+            let _ = editor.load_history(history);
+        }
+        println!("Nlang REPL. Type :help for help, :quit to exit.");
+
+        let mut buffer = String::new();
+        loop {
+            let prompt = if buffer.is_empty() { ">>> " } else { "... " };
+            let line = match editor.readline(prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => {
+                    buffer.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            };
+            if buffer.is_empty() && line.trim().is_empty() {
+                continue;
+            }
+            if !buffer.is_empty() {
+                buffer.push('\n');
+            }
+            buffer.push_str(&line);
+            if !is_complete(&buffer) {
+                continue;
+            }
+
+            let input = std::mem::take(&mut buffer);
+            editor.add_history_entry(input.as_str())?;
+            if !self.handle(&input) {
+                break;
+            }
+        }
+
+        if let Some(history) = &history {
+            editor.save_history(history)?;
+        }
+        Ok(())
+    }
+
+    /// Handles one complete input, printing its result. Returns `false` once
+    /// the user asked to quit.
+    fn handle(&mut self, input: &str) -> bool {
+        let trimmed = input.trim();
+        let (command, argument) = match trimmed.strip_prefix(':') {
+            Some(command) => command.split_once(char::is_whitespace).unwrap_or((command, "")),
+            None => ("", trimmed),
+        };
+        let argument = argument.trim();
+        let result = match command {
+            "" => self.eval(input).map(|value| value.unwrap_or_default()),
+            "type" | "t" => self.type_of(argument).map(|ty| ty.to_string()),
+            "ast" => self.ast(argument),
+            "load" | "l" => self.load(Path::new(argument)).map(|()| format!("Loaded {}", argument)),
+            "help" | "h" => Ok(HELP.to_string()),
+            "quit" | "q" | "exit" => return false,
+            other => Ok(format!("Unknown command ':{}'. Type :help for a list of commands.", other)),
+        };
+        match result {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => println!("{}", output),
+            Err(error) => {
+                // Diagnostics point into the file for `:load`, else into the input
+                let (path, source) = match command {
+                    "" => ("<repl>".to_string(), input.to_string()),
+                    "load" | "l" => (argument.to_string(), std::fs::read_to_string(argument).unwrap_or_default()),
+                    _ => ("<repl>".to_string(), argument.to_string()),
+                };
+                let renderer = Renderer::for_stderr();
+                for diagnostic in diagnostics::from_execution_error(&error) {
+                    eprint!("{}", renderer.render(&diagnostic, &path, &source));
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Parses an input, adding the `;` a final statement may be missing.
+fn parse(input: &str) -> Result<Program, ExecutionError> {
+    let trimmed = input.trim_end();
+    let tokens = if trimmed.ends_with(';') || trimmed.ends_with('}') {
+        tokenize(trimmed)?
+    } else {
+        tokenize(&format!("{};", trimmed))?
+    };
+    Ok(parse_strict(&tokens)?)
+}
+
+fn parse_expression(input: &str) -> Result<Expr, ExecutionError> {
+    let program = parse(input)?;
+    match <[Statement; 1]>::try_from(program.statements) {
+        Ok([Statement::Expression(expr)]) => Ok(expr),
+        _ => Err(ExecutionError::NotImplemented { message: format!("'{}' is not an expression", input) }),
+    }
+}
+
+/// Whether every bracket, string and block comment opened in `input` has
+/// been closed, ignoring brackets in strings and comments. An input is read
+/// until it is complete.
+pub fn is_complete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut chars = input.chars().peekable();
+    let mut previous = None;
+    while let Some(c) = chars.next() {
+        // Like the lexer, only an `r` that starts a word can open a raw string
+        let starts_word = !previous.is_some_and(|p: char| p.is_alphanumeric() || p == '_');
+        previous = Some(c);
+        match c {
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            '"' => {
+                let closed = skip_string(&mut chars, false, 0);
+                if !closed {
+                    return false;
+                }
+            }
+            'r' if starts_word && matches!(chars.peek(), Some('"' | '#')) => {
+                let mut hashes = 0;
+                while chars.next_if_eq(&'#').is_some() {
+                    hashes += 1;
+                }
+                // Without a quote it isn't a string, and the lexer says so
+                if chars.next_if_eq(&'"').is_some() && !skip_string(&mut chars, true, hashes) {
+                    return false;
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // Block comments nest; one left open needs more lines
+                let mut comments = 1;
+                while comments > 0 {
+                    match chars.next() {
+                        Some('/') if chars.peek() == Some(&'*') => {
+                            chars.next();
+                            comments += 1;
+                        }
+                        Some('*') if chars.peek() == Some(&'/') => {
+                            chars.next();
+                            comments -= 1;
+                        }
+                        Some(_) => {}
+                        None => return false,
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    depth <= 0
+}
+
+/// Skips the rest of a string whose opening `"` has been consumed, and
+/// whether it was closed. `raw` strings have no escapes and close with
+/// `hashes` `#`s after their quote.
+fn skip_string(chars: &mut Peekable<Chars>, raw: bool, hashes: usize) -> bool {
+    let multi_line = chars.clone().take(2).eq(['"', '"']);
+    if multi_line {
+        chars.nth(1);
+    }
+    let closing = format!("{}{}", if multi_line { "\"\"\"" } else { "\"" }, "#".repeat(hashes));
+    loop {
+        if chars.clone().take(closing.len()).eq(closing.chars()) {
+            chars.nth(closing.len() - 1);
+            return true;
+        }
+        match chars.next() {
+            Some('\\') if !raw => {
+                chars.next();
+            }
+            Some(_) => {}
+            None => return false,
+        }
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".nlang_history"))
+}
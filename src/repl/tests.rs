@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod repl_tests {
+    use crate::ast::Type;
+    use crate::repl::{is_complete, Repl};
+
+    #[test]
+    fn test_state_persists_between_inputs() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.eval("store x = 20;").unwrap(), None);
+        assert_eq!(repl.eval("def double(n: int): int { return n * 2; }").unwrap(), None);
+        assert_eq!(repl.eval("double(x) + 2").unwrap(), Some("42".to_string()));
+
+        // Declarations can be replaced, and assignments don't echo
+        assert_eq!(repl.eval("store x = \"hi\";").unwrap(), None);
+        assert_eq!(repl.eval("x = x + \"!\"").unwrap(), None);
+        assert_eq!(repl.eval("x").unwrap(), Some("hi!".to_string()));
+        assert_eq!(repl.type_of("x").unwrap(), Type::String);
+    }
+
+    #[test]
+    fn test_failed_input_leaves_state_unchanged() {
+        let mut repl = Repl::new();
+        repl.eval("store x = 1;").unwrap();
+        assert!(repl.eval("store x = 2; store y = missing;").is_err());
+        assert_eq!(repl.type_of("x").unwrap(), Type::Integer);
+        assert!(repl.type_of("y").is_err());
+        assert_eq!(repl.eval("x").unwrap(), Some("1".to_string()));
+    }
+
+    #[test]
+    fn test_void_calls_do_not_echo() {
+        let mut repl = Repl::new();
+        repl.eval("def greet() { store _unused = 0; }").unwrap();
+        assert_eq!(repl.eval("greet()").unwrap(), None);
+        assert!(repl.ast("1 + 2").unwrap().starts_with("Binary {"));
+    }
+
+    #[test]
+    fn test_multi_line_input_completion() {
+        assert!(is_complete("store x = 1;"));
+        assert!(!is_complete("def f() {"));
+        assert!(!is_complete("def f() {\n    store s = \"}\"; // }"));
+        assert!(is_complete("def f() {\n    store s = \"}\";\n}"));
+        assert!(is_complete("/* { */ 1 + 1"));
+        assert!(is_complete("/* /* { */ ( */ 1 + 1"));
+        assert!(!is_complete("/* /* */ 1 + 1"));
+        assert!(!is_complete("def f() { /* } */"));
+        assert!(!is_complete("store s = \"\"\""));
+        assert!(!is_complete("store s = \"\"\"\n    \" ) \"\"\n"));
+        assert!(is_complete("store s = \"\"\"\n    {\n    \"\"\";"));
+        assert!(is_complete("store s = \"\";"));
+        assert!(!is_complete("store s = r#\"say \"hi\"\n"));
+        assert!(is_complete("store s = r#\"{\"}\"#;"));
+        assert!(is_complete("store s = r##\"\"\"\n(\n\"\"\"##;"));
+        assert!(is_complete("store s = r\"\\\";"));
+        assert!(is_complete("store bar = 1; println(bar);"));
+        assert!(!is_complete("store s = \"abc\n"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Exhaustiveness and reachability checking for `match`
+//!
+//! Patterns are checked with the usefulness algorithm from Maranget's
+//! "Warnings for pattern matching": a pattern is useful with respect to some
+//! earlier patterns if there is a value it matches that none of them do. An
+//! arm that isn't useful is unreachable, and a match is exhaustive when a
+//! wildcard after its last arm would not be useful.
+
+use crate::ast::{EnumVariant, Literal, Pattern, Type};
+use std::collections::HashMap;
+
+/// The result of checking one `match`.
+#[derive(Debug, Default)]
+pub struct MatchCoverage {
+    /// Indices of the arms that can never be reached.
+    pub unreachable_arms: Vec<usize>,
+    /// Patterns for the values no arm covers, e.g. `Shape::Empty`. For types
+    /// with too many values to list, this is just `_`.
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Constructor {
+    Variant(String),
+    Boolean(bool),
+    Integer(i64),
+    Float(u64),
+    String(String),
+}
+
+/// A pattern reduced to what matters for coverage: bindings are wildcards.
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Constructor(Constructor, Vec<Pat>),
+}
+
+impl Pat {
+    fn lower(pattern: &Pattern) -> Pat {
+        match pattern {
+            Pattern::Wildcard { .. } | Pattern::Binding { .. } => Pat::Wild,
+            Pattern::Literal { value, .. } => {
+                let constructor = match value {
+                    Literal::Boolean(b) => Constructor::Boolean(*b),
+                    Literal::Integer(i) => Constructor::Integer(*i),
+                    Literal::Float(f) => Constructor::Float(f.to_bits()),
+                    Literal::String(s) => Constructor::String(s.clone()),
+                    Literal::Null => return Pat::Wild,
+                };
+                Pat::Constructor(constructor, Vec::new())
+            }
+            Pattern::Variant { variant, fields, .. } => {
+                Pat::Constructor(Constructor::Variant(variant.clone()), fields.iter().map(Pat::lower).collect())
+            }
+        }
+    }
+}
+
+/// Checks the arm patterns of a match on a value of type `scrutinee_type`.
+/// The patterns must already have been type checked against it.
+pub fn check_match(enums: &HashMap<String, Vec<EnumVariant>>, scrutinee_type: &Type, patterns: &[&Pattern]) -> MatchCoverage {
+    let checker = Checker { enums };
+    let rows: Vec<Vec<Pat>> = patterns.iter().map(|pattern| vec![Pat::lower(pattern)]).collect();
+    let types = [scrutinee_type.clone()];
+
+    let unreachable_arms = (0..rows.len())
+        .filter(|&i| !checker.is_useful(&rows[..i], &rows[i], &types))
+        .collect();
+
+    let mut missing = Vec::new();
+    if checker.is_useful(&rows, &[Pat::Wild], &types) {
+        match checker.constructors(scrutinee_type) {
+            Some(constructors) => {
+                for constructor in constructors {
+                    let arity = checker.field_types(scrutinee_type, &constructor).len();
+                    let candidate = [Pat::Constructor(constructor.clone(), vec![Pat::Wild; arity])];
+                    if checker.is_useful(&rows, &candidate, &types) {
+                        missing.push(describe(scrutinee_type, &constructor, arity));
+                    }
+                }
+            }
+            None => missing.push("_".to_string()),
+        }
+    }
+
+    MatchCoverage { unreachable_arms, missing }
+}
+
+fn describe(scrutinee_type: &Type, constructor: &Constructor, arity: usize) -> String {
+    match constructor {
+        Constructor::Variant(variant) => {
+            let prefix = format!("{}::{}", scrutinee_type, variant);
+            if arity == 0 {
+                prefix
+            } else {
+                format!("{}({})", prefix, vec!["_"; arity].join(", "))
+            }
+        }
+        Constructor::Boolean(b) => b.to_string(),
+        _ => "_".to_string(),
+    }
+}
+
+struct Checker<'a> {
+    enums: &'a HashMap<String, Vec<EnumVariant>>,
+}
+
+impl Checker<'_> {
+    /// Every constructor of `ty`, for types with finitely many.
+    fn constructors(&self, ty: &Type) -> Option<Vec<Constructor>> {
+        match ty {
+            Type::Boolean => Some(vec![Constructor::Boolean(true), Constructor::Boolean(false)]),
+            Type::Named(name) => self.enums.get(name).map(|variants| {
+                variants.iter().map(|variant| Constructor::Variant(variant.name.clone())).collect()
+            }),
+            _ => None,
+        }
+    }
+
+    /// The types of the values `constructor` carries.
+    fn field_types(&self, ty: &Type, constructor: &Constructor) -> Vec<Type> {
+        match (ty, constructor) {
+            (Type::Named(name), Constructor::Variant(variant)) => self.enums.get(name)
+                .and_then(|variants| variants.iter().find(|v| v.name == *variant))
+                .map(|variant| variant.fields.clone())
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether some value matched by the pattern row `candidate` is matched
+    /// by none of `rows`.
+    fn is_useful(&self, rows: &[Vec<Pat>], candidate: &[Pat], types: &[Type]) -> bool {
+        let Some((head, rest)) = candidate.split_first() else {
+            return rows.is_empty();
+        };
+        let ty = &types[0];
+
+        match head {
+            Pat::Constructor(constructor, fields) => {
+                self.is_useful_specialized(rows, constructor, fields.clone(), rest, types)
+            }
+            Pat::Wild => {
+                let used: Vec<&Constructor> = rows.iter()
+                    .filter_map(|row| match &row[0] {
+                        Pat::Constructor(constructor, _) => Some(constructor),
+                        Pat::Wild => None,
+                    })
+                    .collect();
+
+                match self.constructors(ty) {
+                    // Every constructor appears, so try each of them in turn
+                    Some(all) if all.iter().all(|constructor| used.contains(&constructor)) => {
+                        all.iter().any(|constructor| {
+                            let arity = self.field_types(ty, constructor).len();
+                            self.is_useful_specialized(rows, constructor, vec![Pat::Wild; arity], rest, types)
+                        })
+                    }
+                    // Some value isn't covered by any constructor row, so only
+                    // the wildcard rows matter
+                    _ => {
+                        let defaults: Vec<Vec<Pat>> = rows.iter()
+                            .filter(|row| matches!(row[0], Pat::Wild))
+                            .map(|row| row[1..].to_vec())
+                            .collect();
+                        self.is_useful(&defaults, rest, &types[1..])
+                    }
+                }
+            }
+        }
+    }
+
+    /// `is_useful` for a candidate whose head is `constructor(fields)`,
+    /// considering only the rows that can match that constructor.
+    fn is_useful_specialized(&self, rows: &[Vec<Pat>], constructor: &Constructor, fields: Vec<Pat>, rest: &[Pat], types: &[Type]) -> bool {
+        let field_types = self.field_types(&types[0], constructor);
+        let arity = fields.len();
+
+        let specialized: Vec<Vec<Pat>> = rows.iter()
+            .filter_map(|row| {
+                let mut specialized = match &row[0] {
+                    Pat::Constructor(other, other_fields) if other == constructor => other_fields.clone(),
+                    Pat::Constructor(..) => return None,
+                    Pat::Wild => vec![Pat::Wild; arity],
+                };
+                specialized.extend_from_slice(&row[1..]);
+                Some(specialized)
+            })
+            .collect();
+
+        let mut candidate = fields;
+        candidate.extend_from_slice(rest);
+        let mut candidate_types = field_types;
+        candidate_types.resize(arity, Type::Error);
+        candidate_types.extend_from_slice(&types[1..]);
+        self.is_useful(&specialized, &candidate, &candidate_types)
+    }
+}
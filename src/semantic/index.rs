@@ -0,0 +1,140 @@
+//! Where the names in a program are declared and used
+//!
+//! Analysis records a [`Definition`] for every name it brings into scope and
+//! a [`Reference`] for every use of a name it resolves. Editor features like
+//! hover, go-to-definition and completion are answered from this index
+//! rather than by analyzing the program again.
+
+use crate::ast::Type;
+use crate::span::Span;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Function,
+    Variable,
+    Parameter,
+    /// A module imported under an alias, as in `import math as m;`
+    Module,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+    /// Void for modules, which have no type.
+    pub ty: Type,
+    /// The declaration, in `module` if the name was imported.
+    pub span: Span,
+    /// The file the name was imported from.
+    pub module: Option<PathBuf>,
+    /// The `///` doc comment of the declaration.
+    pub doc: Option<String>,
+    /// The byte offsets of the analyzed source the name can be used in.
+    pub visible: Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    pub ty: Type,
+    /// Index into [`SymbolIndex::definitions`]; `None` for built-in functions.
+    pub definition: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolIndex {
+    pub definitions: Vec<Definition>,
+    // Keyed by span because return type inference analyzes parts of a
+    // function before the function itself is analyzed; the last use
+    // recorded at a span is the one analysis settled on
+    references: HashMap<Span, Reference>,
+}
+
+impl SymbolIndex {
+    pub(super) fn define(&mut self, definition: Definition) -> usize {
+        self.definitions.push(definition);
+        self.definitions.len() - 1
+    }
+
+    pub(super) fn refer(&mut self, reference: Reference) {
+        self.references.insert(reference.span, reference);
+    }
+
+    /// The use of a name at exactly `span`.
+    pub fn reference(&self, span: Span) -> Option<&Reference> {
+        self.references.get(&span)
+    }
+
+    /// Forgets every recorded use, keeping the definitions.
+    pub(super) fn clear_references(&mut self) {
+        self.references.clear();
+    }
+
+    /// The innermost use of a name that covers the byte `offset`.
+    pub fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references.values()
+            .filter(|reference| reference.span.start <= offset && offset <= reference.span.end)
+            .min_by_key(|reference| reference.span.len())
+    }
+
+    pub fn definition_of(&self, reference: &Reference) -> Option<&Definition> {
+        reference.definition.map(|id| &self.definitions[id])
+    }
+
+    /// The definition the declaration at `span` in the analyzed source gives
+    /// `name`, like the variable of `store x = 1;`.
+    pub fn declared(&self, name: &str, span: Span) -> Option<&Definition> {
+        self.definitions.iter()
+            .rev()
+            .find(|definition| definition.module.is_none() && definition.span == span && definition.name == name)
+    }
+
+    /// The name declared in `source` whose declaration covers the byte
+    /// `offset` at the name itself, with the span of the name. A
+    /// declaration's span covers all of it, as in `store x = 1;`, and its
+    /// name is the first whole word in it that spells the name.
+    pub fn declaration_at(&self, source: &str, offset: usize) -> Option<(&Definition, Span)> {
+        self.definitions.iter()
+            .filter(|definition| definition.module.is_none() && definition.span.start <= offset && offset <= definition.span.end)
+            .filter_map(|definition| {
+                let name = name_in(source, definition.span, &definition.name)?;
+                (name.start <= offset && offset <= name.end).then_some((definition, name))
+            })
+            .min_by_key(|(definition, _)| definition.span.len())
+    }
+
+    /// The names that can be used at the byte `offset`, with a name that is
+    /// shadowed by a later or more deeply nested one left out.
+    pub fn visible_at(&self, offset: usize) -> Vec<&Definition> {
+        let mut visible: HashMap<&str, &Definition> = HashMap::new();
+        for definition in &self.definitions {
+            if definition.visible.contains(&offset) {
+                visible.insert(&definition.name, definition);
+            }
+        }
+        let mut definitions: Vec<&Definition> = visible.into_values().collect();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+        definitions
+    }
+}
+
+/// The first whole-word occurrence of `name` within `span` of `source`.
+fn name_in(source: &str, span: Span, name: &str) -> Option<Span> {
+    let text = source.get(span.start..span.end.min(source.len()))?;
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let (found, _) = text.match_indices(name).find(|&(i, _)| {
+        !text[..i].ends_with(is_word) && !text[i + name.len()..].starts_with(is_word)
+    })?;
+    let before = &text[..found];
+    let line = span.line + before.matches('\n').count();
+    let column = match before.rfind('\n') {
+        Some(newline) => before[newline + 1..].chars().count() + 1,
+        None => span.column + before.chars().count(),
+    };
+    let start = span.start + found;
+    Some(Span::new(start, start + name.len(), line, column))
+}
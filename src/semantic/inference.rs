@@ -0,0 +1,665 @@
+//! Inference of the parameter types a program leaves out
+//!
+//! Every parameter written without a type gets a type variable, and the
+//! program is walked once to collect what is known about each variable: a
+//! value used as a condition must be a bool, one passed where a function
+//! expects an `int` must be an int, and the arguments a function is called
+//! with tell the types of its parameters. A parameter passed on as another
+//! parameter is unified with it, so both end up with the same type.
+//!
+//! Each variable then takes the one type its uses agree on. Arithmetic and
+//! comparisons only tell that a value is a number, which defaults to `int`
+//! when nothing says which number. Uses that disagree, or a parameter
+//! nothing is known about, are reported rather than guessed at.
+//!
+//! An exported function is also called by the modules importing it, so
+//! the calls in its own module don't decide its parameter types, and a
+//! function nothing calls yet may be called by a later piece of a REPL
+//! session. Either one's parameter that is only known to be a number is
+//! reported too, since the callers that would say which are out of sight.
+
+use super::{SemanticAnalyzer, SemanticError, Symbol};
+use crate::ast::{BinaryOperator, Expr, FormatPart, Literal, MatchArm, Parameter, Pattern, Statement, Type, UnaryOperator};
+use crate::span::Span;
+use std::collections::{HashMap, HashSet};
+
+/// What is known about the type of a value.
+#[derive(Debug, Clone)]
+enum Ty {
+    Known(Type),
+    /// The type of an unannotated parameter, still to be worked out.
+    Var(usize),
+}
+
+#[derive(Debug, Default)]
+struct Variable {
+    /// Union-find parent; a variable is the root of its class if it is its own.
+    parent: usize,
+    /// Types the variable's uses require, with where each use is.
+    exact: Vec<(Type, Span)>,
+    /// Used in arithmetic or a comparison, so it is some kind of number.
+    numeric: bool,
+}
+
+/// An unannotated parameter and its variable.
+struct Unknown {
+    name: String,
+    function: String,
+    span: Span,
+    var: usize,
+}
+
+struct Inference<'a> {
+    analyzer: &'a SemanticAnalyzer,
+    variables: Vec<Variable>,
+    unknowns: Vec<Unknown>,
+    // Variable of each unannotated parameter, by its span
+    vars: HashMap<Span, usize>,
+    // Parameter types of each function declared in the program
+    functions: HashMap<String, (Vec<Ty>, Option<Type>)>,
+    // Functions declared with `export`
+    exported: HashSet<String>,
+    // Functions called from outside their own body
+    called: HashSet<String>,
+    // Named functions being walked, innermost last
+    enclosing: Vec<String>,
+    // Names declared by the code walked so far; `None` when the walk can't
+    // tell their type
+    scopes: Vec<HashMap<String, Option<Ty>>>,
+    // Return type written for each function being walked
+    returns: Vec<Option<Type>>,
+}
+
+/// Infers the types of the parameters declared without one in `statements`,
+/// keyed by the parameter's span. Parameters that can't be inferred are
+/// given the error type and reported.
+pub(super) fn infer_parameter_types(analyzer: &SemanticAnalyzer, statements: &[Statement]) -> (HashMap<Span, Type>, Vec<SemanticError>) {
+    let mut inference = Inference {
+        analyzer,
+        variables: Vec::new(),
+        unknowns: Vec::new(),
+        vars: HashMap::new(),
+        functions: HashMap::new(),
+        exported: HashSet::new(),
+        called: HashSet::new(),
+        enclosing: Vec::new(),
+        scopes: vec![HashMap::new()],
+        returns: Vec::new(),
+    };
+    // Functions can be called before they are declared
+    for statement in statements {
+        if let Statement::FunctionDeclaration { name, parameters, return_type, is_exported, .. } = statement {
+            inference.declare_function(name, parameters, return_type, *is_exported);
+        }
+    }
+    inference.statements(statements);
+    inference.solve()
+}
+
+impl Inference<'_> {
+    fn declare_function(&mut self, name: &str, parameters: &[Parameter], return_type: &Option<Type>, exported: bool) -> Vec<Ty> {
+        let types: Vec<Ty> = parameters.iter().map(|param| self.parameter(name, param)).collect();
+        self.functions.insert(name.to_string(), (types.clone(), return_type.clone()));
+        if exported {
+            self.exported.insert(name.to_string());
+        }
+        types
+    }
+
+    /// The type of `param`, a new variable if it was written without one.
+    fn parameter(&mut self, function: &str, param: &Parameter) -> Ty {
+        if param.param_type != Type::Unknown {
+            return Ty::Known(param.param_type.clone());
+        }
+        if let Some(&var) = self.vars.get(&param.span) {
+            return Ty::Var(var);
+        }
+        let var = self.variables.len();
+        self.variables.push(Variable { parent: var, ..Variable::default() });
+        self.vars.insert(param.span, var);
+        self.unknowns.push(Unknown { name: param.name.clone(), function: function.to_string(), span: param.span, var });
+        Ty::Var(var)
+    }
+
+    fn find(&mut self, var: usize) -> usize {
+        let parent = self.variables[var].parent;
+        if parent == var {
+            return var;
+        }
+        let root = self.find(parent);
+        self.variables[var].parent = root;
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            let merged = std::mem::take(&mut self.variables[b]);
+            self.variables[b].parent = a;
+            self.variables[a].exact.extend(merged.exact);
+            self.variables[a].numeric |= merged.numeric;
+        }
+    }
+
+    /// Records that a value of type `actual`, at `span`, is used where a
+    /// value of type `expected` is wanted.
+    fn unify(&mut self, actual: Option<&Ty>, expected: Option<&Ty>, span: Span) {
+        match (actual, expected) {
+            (Some(Ty::Var(a)), Some(Ty::Var(b))) => self.union(*a, *b),
+            (Some(Ty::Var(var)), Some(Ty::Known(ty))) | (Some(Ty::Known(ty)), Some(Ty::Var(var)))
+                if !matches!(ty, Type::Error | Type::Void | Type::Unknown) => {
+                let root = self.find(*var);
+                self.variables[root].exact.push((ty.clone(), span));
+            }
+            _ => {}
+        }
+    }
+
+    fn expect(&mut self, actual: Option<&Ty>, expected: Type, span: Span) {
+        self.unify(actual, Some(&Ty::Known(expected)), span);
+    }
+
+    fn numeric(&mut self, ty: Option<&Ty>) {
+        if let Some(Ty::Var(var)) = ty {
+            let root = self.find(*var);
+            self.variables[root].numeric = true;
+        }
+    }
+
+    fn declare(&mut self, name: &str, ty: Option<Ty>) {
+        self.scopes.last_mut().unwrap().insert(name.to_string(), ty);
+    }
+
+    fn lookup(&self, name: &str) -> Option<Ty> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(ty) = scope.get(name) {
+                return ty.clone();
+            }
+        }
+        if let Some((params, return_type)) = self.functions.get(name) {
+            let params = params.iter()
+                .map(|param| match param {
+                    Ty::Known(ty) => Some(ty.clone()),
+                    Ty::Var(_) => None,
+                })
+                .collect::<Option<Vec<Type>>>()?;
+            let return_type = Box::new(return_type.clone().unwrap_or(Type::Void));
+            return Some(Ty::Known(Type::Function { params, return_type }));
+        }
+        // Names declared by earlier pieces of a REPL session
+        match self.analyzer.get_symbol(name).ok()? {
+            Symbol::Variable { var_type } => Some(Ty::Known(var_type)),
+            Symbol::Function { return_type, parameters } => Some(Ty::Known(Type::Function {
+                params: parameters.into_iter().map(|param| param.param_type).collect(),
+                return_type: Box::new(return_type),
+            })),
+            Symbol::Namespace { .. } => None,
+        }
+    }
+
+    fn scoped<T>(&mut self, walk: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        let result = walk(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expr) => {
+                self.expr(expr);
+            }
+            Statement::LetDeclaration { name, var_type, initializer, .. } => {
+                let ty = initializer.as_ref().and_then(|init| self.expr(init));
+                match var_type {
+                    Some(var_type) => {
+                        if let Some(init) = initializer {
+                            self.expect(ty.as_ref(), var_type.clone(), init.span());
+                        }
+                        self.declare(name, Some(Ty::Known(var_type.clone())));
+                    }
+                    None => self.declare(name, ty),
+                }
+            }
+            Statement::FunctionDeclaration { name, parameters, body, return_type, is_exported, .. } => {
+                let types = self.declare_function(name, parameters, return_type, *is_exported);
+                self.enclosing.push(name.clone());
+                self.function(parameters, types, body, return_type);
+                self.enclosing.pop();
+            }
+            Statement::Block { statements, .. } => self.scoped(|this| this.statements(statements)),
+            Statement::If { condition, then_branch, else_branch, .. } => {
+                let ty = self.expr(condition);
+                self.expect(ty.as_ref(), Type::Boolean, condition.span());
+                self.scoped(|this| this.statement(then_branch));
+                if let Some(else_branch) = else_branch {
+                    self.scoped(|this| this.statement(else_branch));
+                }
+            }
+            Statement::While { condition, body, .. } => {
+                let ty = self.expr(condition);
+                self.expect(ty.as_ref(), Type::Boolean, condition.span());
+                self.scoped(|this| this.statement(body));
+            }
+            Statement::For { variable, iterable, body, .. } => {
+                let element = match self.expr(iterable) {
+                    _ if matches!(iterable.as_ref(), Expr::Range { .. }) => Some(Ty::Known(Type::Integer)),
+                    Some(Ty::Known(Type::Array(element))) => Some(Ty::Known(*element)),
+                    _ => None,
+                };
+                self.scoped(|this| {
+                    this.declare(variable, element);
+                    this.statement(body);
+                });
+            }
+            Statement::Match { scrutinee, arms, .. } => {
+                self.arms(scrutinee, arms, |this, body| {
+                    this.statement(body);
+                    None
+                });
+            }
+            Statement::Return { value: Some(value), .. } => {
+                let ty = self.expr(value);
+                if let Some(Some(return_type)) = self.returns.last().cloned() {
+                    self.expect(ty.as_ref(), return_type, value.span());
+                }
+            }
+            Statement::Return { value: None, .. }
+            | Statement::StructDeclaration { .. }
+            | Statement::EnumDeclaration { .. }
+            | Statement::Break { .. }
+            | Statement::Continue { .. }
+            | Statement::Import { .. }
+            | Statement::ImportFrom { .. }
+            | Statement::AssignMain { .. } => {}
+        }
+    }
+
+    fn function(&mut self, parameters: &[Parameter], types: Vec<Ty>, body: &[Statement], return_type: &Option<Type>) {
+        self.returns.push(return_type.clone());
+        self.scoped(|this| {
+            for (param, ty) in parameters.iter().zip(types) {
+                this.declare(&param.name, Some(ty));
+            }
+            this.statements(body);
+        });
+        self.returns.pop();
+    }
+
+    /// Walks the arms of a match on `scrutinee`, declaring what each arm's
+    /// pattern binds. Returns the type of the first arm's body.
+    fn arms<B>(&mut self, scrutinee: &Expr, arms: &[MatchArm<B>], mut body: impl FnMut(&mut Self, &B) -> Option<Ty>) -> Option<Ty> {
+        let scrutinee_type = self.expr(scrutinee);
+        let mut result = None;
+        for (i, arm) in arms.iter().enumerate() {
+            let ty = self.scoped(|this| {
+                this.pattern(&arm.pattern, scrutinee_type.as_ref());
+                body(this, &arm.body)
+            });
+            if i == 0 {
+                result = ty;
+            }
+        }
+        result
+    }
+
+    fn pattern(&mut self, pattern: &Pattern, ty: Option<&Ty>) {
+        match pattern {
+            Pattern::Wildcard { .. } => {}
+            Pattern::Binding { name, .. } => self.declare(name, ty.cloned()),
+            Pattern::Literal { value, span } => {
+                if let Some(literal_type) = literal_type(value) {
+                    self.expect(ty, literal_type, *span);
+                }
+            }
+            Pattern::Variant { enum_name, variant, fields, span } => {
+                self.expect(ty, Type::Named(enum_name.clone()), *span);
+                let field_types = self.analyzer.enums.get(enum_name)
+                    .and_then(|variants| variants.iter().find(|v| v.name == *variant))
+                    .map(|v| v.fields.clone())
+                    .unwrap_or_default();
+                for (i, field) in fields.iter().enumerate() {
+                    let field_type = field_types.get(i).cloned().map(Ty::Known);
+                    self.pattern(field, field_type.as_ref());
+                }
+            }
+        }
+    }
+
+    /// Walks `expr`, returning what is known about its type.
+    fn expr(&mut self, expr: &Expr) -> Option<Ty> {
+        match expr {
+            Expr::Literal { value, .. } => literal_type(value).map(Ty::Known),
+            Expr::Variable { name, .. } => self.lookup(name),
+            Expr::Binary { left, operator, right, .. } => {
+                let left_type = self.expr(left);
+                let right_type = self.expr(right);
+                self.binary(operator, (left, left_type), (right, right_type))
+            }
+            Expr::Unary { operator: UnaryOperator::Negate, operand, .. } => {
+                let ty = self.expr(operand);
+                self.numeric(ty.as_ref());
+                ty
+            }
+            Expr::Unary { operator: UnaryOperator::Not, operand, .. } => {
+                let ty = self.expr(operand);
+                self.expect(ty.as_ref(), Type::Boolean, operand.span());
+                Some(Ty::Known(Type::Boolean))
+            }
+            Expr::Call { callee, arguments, .. } => self.call(callee, arguments),
+            Expr::Function { parameters, body, return_type, .. } => {
+                let types: Vec<Ty> = parameters.iter().map(|param| self.parameter("lambda", param)).collect();
+                self.function(parameters, types.clone(), body, return_type);
+                let params = types.into_iter()
+                    .map(|ty| match ty {
+                        Ty::Known(ty) => Some(ty),
+                        Ty::Var(_) => None,
+                    })
+                    .collect::<Option<Vec<Type>>>()?;
+                let return_type = Box::new(return_type.clone().unwrap_or(Type::Void));
+                Some(Ty::Known(Type::Function { params, return_type }))
+            }
+            Expr::Get { object, name, .. } => {
+                let object_type = self.expr(object);
+                self.field(object, object_type, name)
+            }
+            Expr::Set { object, name, value, .. } => {
+                let object_type = self.expr(object);
+                let field_type = self.field(object, object_type, name);
+                let value_type = self.expr(value);
+                self.unify(value_type.as_ref(), field_type.as_ref(), value.span());
+                value_type
+            }
+            Expr::Index { sequence, index, .. } => self.element(sequence, index),
+            Expr::SetIndex { sequence, index, value, .. } => {
+                let element_type = self.element(sequence, index);
+                let value_type = self.expr(value);
+                self.unify(value_type.as_ref(), element_type.as_ref(), value.span());
+                value_type
+            }
+            Expr::StructLiteral { name, fields, .. } => {
+                let declared = self.analyzer.structs.get(name).cloned().unwrap_or_default();
+                for (field_name, value) in fields {
+                    let value_type = self.expr(value);
+                    if let Some(field) = declared.iter().find(|field| field.name == *field_name) {
+                        self.expect(value_type.as_ref(), field.field_type.clone(), value.span());
+                    }
+                }
+                Some(Ty::Known(Type::Named(name.clone())))
+            }
+            Expr::EnumVariant { enum_name, variant, arguments, .. } => {
+                let field_types = self.analyzer.enums.get(enum_name)
+                    .and_then(|variants| variants.iter().find(|v| v.name == *variant))
+                    .map(|v| v.fields.clone())
+                    .unwrap_or_default();
+                for (i, argument) in arguments.iter().enumerate() {
+                    let argument_type = self.expr(argument);
+                    if let Some(field_type) = field_types.get(i) {
+                        self.expect(argument_type.as_ref(), field_type.clone(), argument.span());
+                    }
+                }
+                Some(Ty::Known(Type::Named(enum_name.clone())))
+            }
+            Expr::Match { scrutinee, arms, .. } => self.arms(scrutinee, arms, |this, body| this.expr(body)),
+            Expr::Array { elements, .. } => {
+                let mut element_type = None;
+                for element in elements {
+                    let ty = self.expr(element);
+                    match &element_type {
+                        None => element_type = ty,
+                        Some(first) => {
+                            let first = first.clone();
+                            self.unify(ty.as_ref(), Some(&first), element.span());
+                        }
+                    }
+                }
+                match element_type? {
+                    Ty::Known(ty) => Some(Ty::Known(Type::Array(Box::new(ty)))),
+                    Ty::Var(_) => None,
+                }
+            }
+            Expr::Assign { name, value, .. } => {
+                let value_type = self.expr(value);
+                let target = self.lookup(name);
+                self.unify(value_type.as_ref(), target.as_ref(), value.span());
+                value_type
+            }
+            Expr::Range { start, end, .. } => {
+                for bound in [start, end] {
+                    let ty = self.expr(bound);
+                    self.expect(ty.as_ref(), Type::Integer, bound.span());
+                }
+                None
+            }
+            Expr::FormatString { parts, .. } => {
+                for part in parts {
+                    if let FormatPart::Expr { expr, spec } = part {
+                        // Only floats take a precision
+                        let ty = self.expr(expr);
+                        if spec.precision.is_some() {
+                            self.expect(ty.as_ref(), Type::Float, expr.span());
+                        }
+                    }
+                }
+                Some(Ty::Known(Type::String))
+            }
+        }
+    }
+
+    fn binary(&mut self, operator: &BinaryOperator, (left, left_type): (&Expr, Option<Ty>), (right, right_type): (&Expr, Option<Ty>)) -> Option<Ty> {
+        let is_string = |ty: &Option<Ty>| matches!(ty, Some(Ty::Known(Type::String)));
+        let is_number = |ty: &Option<Ty>| matches!(ty, Some(Ty::Known(Type::Integer | Type::Float)));
+        match operator {
+            BinaryOperator::And | BinaryOperator::Or => {
+                self.expect(left_type.as_ref(), Type::Boolean, left.span());
+                self.expect(right_type.as_ref(), Type::Boolean, right.span());
+                Some(Ty::Known(Type::Boolean))
+            }
+            BinaryOperator::EqualEqual | BinaryOperator::NotEqual => {
+                // Ints and floats compare with each other, anything else only
+                // with its own type
+                for (ty, other, span) in [(&left_type, &right_type, left.span()), (&right_type, &left_type, right.span())] {
+                    match other {
+                        Some(Ty::Known(_)) if is_number(other) => self.numeric(ty.as_ref()),
+                        _ => self.unify(ty.as_ref(), other.as_ref(), span),
+                    }
+                }
+                Some(Ty::Known(Type::Boolean))
+            }
+            _ if is_string(&left_type) || is_string(&right_type) => {
+                self.expect(left_type.as_ref(), Type::String, left.span());
+                self.expect(right_type.as_ref(), Type::String, right.span());
+                match operator {
+                    BinaryOperator::Plus => Some(Ty::Known(Type::String)),
+                    _ => Some(Ty::Known(Type::Boolean)),
+                }
+            }
+            _ => {
+                self.numeric(left_type.as_ref());
+                self.numeric(right_type.as_ref());
+                match operator {
+                    BinaryOperator::Less | BinaryOperator::LessEqual | BinaryOperator::Greater | BinaryOperator::GreaterEqual => {
+                        Some(Ty::Known(Type::Boolean))
+                    }
+                    BinaryOperator::Slash => Some(Ty::Known(Type::Float)),
+                    // An int operand takes the other operand's type
+                    _ => match (left_type, right_type) {
+                        (Some(Ty::Known(Type::Float)), _) | (_, Some(Ty::Known(Type::Float))) => Some(Ty::Known(Type::Float)),
+                        (Some(Ty::Var(var)), _) | (_, Some(Ty::Var(var))) => Some(Ty::Var(var)),
+                        (left_type, _) => left_type,
+                    },
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, callee: &Expr, arguments: &[Expr]) -> Option<Ty> {
+        let argument_types: Vec<Option<Ty>> = arguments.iter().map(|argument| self.expr(argument)).collect();
+        let name = match callee {
+            Expr::Variable { name, .. } if !self.scopes.iter().any(|scope| scope.contains_key(name)) => name,
+            _ => {
+                let Some(Ty::Known(Type::Function { params, return_type })) = self.expr(callee) else {
+                    return None;
+                };
+                for ((argument, ty), param) in arguments.iter().zip(&argument_types).zip(params) {
+                    self.expect(ty.as_ref(), param, argument.span());
+                }
+                return Some(Ty::Known(*return_type));
+            }
+        };
+
+        if let Some((params, return_type)) = self.functions.get(name).cloned() {
+            if self.enclosing.last() != Some(name) {
+                self.called.insert(name.clone());
+            }
+            // The arguments of one caller among many don't tell an exported
+            // function's parameter types
+            if !self.exported.contains(name) {
+                for ((argument, ty), param) in arguments.iter().zip(&argument_types).zip(&params) {
+                    self.unify(ty.as_ref(), Some(param), argument.span());
+                }
+            }
+            return return_type.map(Ty::Known);
+        }
+        if let Ok(Symbol::Function { return_type, parameters }) = self.analyzer.get_symbol(name) {
+            for ((argument, ty), param) in arguments.iter().zip(&argument_types).zip(parameters) {
+                self.expect(ty.as_ref(), param.param_type, argument.span());
+            }
+            return Some(Ty::Known(return_type));
+        }
+        self.builtin_call(name, arguments, &argument_types)
+    }
+
+    /// What a call to a built-in function tells about its arguments. Only
+    /// a parameter every overload agrees on says anything, and `print` and
+    /// the generic built-ins take any type.
+    fn builtin_call(&mut self, name: &str, arguments: &[Expr], argument_types: &[Option<Ty>]) -> Option<Ty> {
+        if name == "push" && let [_, value] = arguments
+            && let Some(Ty::Known(Type::Array(element))) = &argument_types[0] {
+            self.expect(argument_types[1].as_ref(), (**element).clone(), value.span());
+            return Some(Ty::Known(Type::Void));
+        }
+        if matches!(name, "print" | "println" | "len" | "push" | "assert_eq" | "assert_ne") {
+            return self.analyzer.std_lib.get_builtin_function(name).map(|builtin| Ty::Known(builtin.return_type.clone()));
+        }
+        let overloads: Vec<_> = self.analyzer.std_lib.functions.iter()
+            .filter(|builtin| builtin.name == name && builtin.parameters.len() == arguments.len())
+            .collect();
+        let first = overloads.first()?;
+        for (i, argument) in arguments.iter().enumerate() {
+            if overloads.iter().all(|overload| overload.parameters[i] == first.parameters[i]) {
+                self.expect(argument_types[i].as_ref(), first.parameters[i].clone(), argument.span());
+            }
+        }
+        overloads.iter()
+            .all(|overload| overload.return_type == first.return_type)
+            .then(|| Ty::Known(first.return_type.clone()))
+    }
+
+    /// The type of `object.name`. A value of unknown type whose field is
+    /// used must be the one struct with a field of that name, if there is one.
+    fn field(&mut self, object: &Expr, object_type: Option<Ty>, name: &str) -> Option<Ty> {
+        let struct_name = match object_type? {
+            Ty::Known(Type::Named(struct_name)) => struct_name,
+            Ty::Known(_) => return None,
+            var @ Ty::Var(_) => {
+                let mut candidates = self.analyzer.structs.iter()
+                    .filter(|(_, fields)| fields.iter().any(|field| field.name == name))
+                    .map(|(struct_name, _)| struct_name.clone());
+                let struct_name = candidates.next()?;
+                if candidates.next().is_some() {
+                    return None;
+                }
+                self.expect(Some(&var), Type::Named(struct_name.clone()), object.span());
+                struct_name
+            }
+        };
+        self.analyzer.structs.get(&struct_name)?.iter()
+            .find(|field| field.name == name)
+            .map(|field| Ty::Known(field.field_type.clone()))
+    }
+
+    fn element(&mut self, sequence: &Expr, index: &Expr) -> Option<Ty> {
+        let sequence_type = self.expr(sequence);
+        let index_type = self.expr(index);
+        self.expect(index_type.as_ref(), Type::Integer, index.span());
+        match sequence_type? {
+            Ty::Known(Type::Array(element)) => Some(Ty::Known(*element)),
+            _ => None,
+        }
+    }
+
+    fn solve(mut self) -> (HashMap<Span, Type>, Vec<SemanticError>) {
+        let mut types = HashMap::new();
+        let mut errors = Vec::new();
+        let unknowns = std::mem::take(&mut self.unknowns);
+        for unknown in unknowns {
+            let root = self.find(unknown.var);
+            let variable = &self.variables[root];
+            let mut distinct: Vec<&(Type, Span)> = Vec::new();
+            for constraint in &variable.exact {
+                if !distinct.iter().any(|(ty, _)| *ty == constraint.0) {
+                    distinct.push(constraint);
+                }
+            }
+            // Lambdas are only called through values, whose calls tell nothing
+            let unseen_callers = self.exported.contains(&unknown.function)
+                || (self.functions.contains_key(&unknown.function) && !self.called.contains(&unknown.function));
+            let ty = match distinct.as_slice() {
+                [(ty, _)] => ty.clone(),
+                [] if variable.numeric && unseen_callers => {
+                    errors.push(SemanticError {
+                        message: format!(
+                            "Cannot infer whether parameter '{}' of function '{}' is an int or a float; add a type annotation",
+                            unknown.name, unknown.function
+                        ),
+                        span: Some(unknown.span),
+                        module: None,
+                    });
+                    Type::Error
+                }
+                [] if variable.numeric => Type::Integer,
+                [] => {
+                    errors.push(SemanticError {
+                        message: format!(
+                            "Cannot infer the type of parameter '{}' of function '{}'; add a type annotation",
+                            unknown.name, unknown.function
+                        ),
+                        span: Some(unknown.span),
+                        module: None,
+                    });
+                    Type::Error
+                }
+                [(first, first_span), (second, second_span), ..] => {
+                    errors.push(SemanticError {
+                        message: format!(
+                            "Cannot infer the type of parameter '{}' of function '{}': it is used as {} on line {} and as {} on line {}; add a type annotation",
+                            unknown.name, unknown.function, first, first_span.line, second, second_span.line
+                        ),
+                        span: Some(unknown.span),
+                        module: None,
+                    });
+                    Type::Error
+                }
+            };
+            types.insert(unknown.span, ty);
+        }
+        (types, errors)
+    }
+}
+
+fn literal_type(literal: &Literal) -> Option<Type> {
+    match literal {
+        Literal::Integer(_) => Some(Type::Integer),
+        Literal::Float(_) => Some(Type::Float),
+        Literal::Boolean(_) => Some(Type::Boolean),
+        Literal::String(_) => Some(Type::String),
+        Literal::Null => None,
+    }
+}
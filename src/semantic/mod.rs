@@ -1,10 +1,20 @@
-use crate::ast::{Program, Statement, Expr, Type, Literal, Parameter, BinaryOperator};
+use crate::ast::{Program, Statement, Expr, Type, Literal, Parameter, BinaryOperator, StructField, EnumVariant, MatchArm, Pattern, FormatPart, FormatSpec};
+use crate::span::Span;
 use crate::std_lib::StdLib;
-use crate::lexer::Lexer;
-use crate::parser::Parser;
+use crate::lexer::{Lexer, LexerError};
+use crate::parser::{parse_strict, ParseErrors};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::rc::Rc;
+
+mod exhaustiveness;
+mod index;
+mod inference;
+mod typed;
+
+pub use index::{Definition, DefinitionKind, Reference, SymbolIndex};
+pub use typed::{SymbolId, TypeTable, TypedProgram};
 
 #[cfg(test)]
 mod tests;
@@ -12,23 +22,269 @@ mod tests;
 #[derive(Debug)]
 pub struct SemanticError {
     pub message: String,
+    pub span: Option<Span>,
+    /// What went wrong in a module that couldn't be imported; `span` is
+    /// then the import.
+    pub module: Option<Box<BrokenModule>>,
+}
+
+impl SemanticError {
+    /// Attaches a location to an error that doesn't already have one.
+    pub fn with_span(mut self, span: Span) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
 }
 
 impl std::fmt::Display for SemanticError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Semantic error: {}", self.message)
+        match self.span {
+            Some(span) => write!(f, "Semantic error on line {}, column {}: {}", span.line, span.column, self.message)?,
+            None => write!(f, "Semantic error: {}", self.message)?,
+        }
+        if let Some(module) = &self.module {
+            write!(f, "\n{}", module)?;
+        }
+        Ok(())
     }
 }
 
 impl std::error::Error for SemanticError {}
 
-pub fn analyze(program: Program) -> Result<Program, SemanticError> {
+/// A module that couldn't be imported, with its source so that its errors
+/// can be shown where they are.
+#[derive(Debug)]
+pub struct BrokenModule {
+    pub path: PathBuf,
+    pub source: String,
+    pub errors: ModuleErrors,
+}
+
+/// The errors of the first phase that failed on a module. Their spans are
+/// in the module's source.
+#[derive(Debug)]
+pub enum ModuleErrors {
+    Lexer(LexerError),
+    Parser(ParseErrors),
+    Semantic(Vec<SemanticError>),
+}
+
+impl ModuleErrors {
+    fn count(&self) -> usize {
+        match self {
+            ModuleErrors::Lexer(_) => 1,
+            ModuleErrors::Parser(errors) => errors.0.len(),
+            ModuleErrors::Semantic(errors) => errors.len(),
+        }
+    }
+}
+
+impl std::fmt::Display for BrokenModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = self.path.display();
+        match &self.errors {
+            ModuleErrors::Lexer(error) => write!(f, "{}: {}", path, error),
+            ModuleErrors::Parser(errors) => write!(f, "{}: {}", path, errors),
+            ModuleErrors::Semantic(errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}: {}", path, error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Every semantic error found in a program, in source order.
+#[derive(Debug)]
+pub struct SemanticErrors(pub Vec<SemanticError>);
+
+impl std::fmt::Display for SemanticErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SemanticErrors {}
+
+/// Something suspicious that doesn't stop the program from running.
+#[derive(Debug, Clone)]
+pub struct SemanticWarning {
+    pub message: String,
+    pub span: Span,
+    pub help: Option<String>,
+}
+
+/// A function or variable a module exports, as the modules importing it
+/// see it.
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub name: String,
+    pub kind: ExportKind,
+    /// The declaration, in the module's source.
+    pub span: Span,
+    /// The `///` doc comment of the declaration.
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExportKind {
+    Function { parameters: Vec<Parameter>, return_type: Type },
+    Variable { var_type: Type },
+}
+
+/// Analyzes the module at `path` and returns what it exports, in the order
+/// it declares them. Fails if the module or one it imports has an error.
+pub fn module_exports(path: &Path) -> Result<Vec<Export>, SemanticError> {
+    let mut analyzer = SemanticAnalyzer::new_with_file_path(Some(path));
+    Ok(analyzer.load_module(path)?.exports())
+}
+
+/// The outcome of analyzing a program without stopping at the first error.
+#[derive(Debug)]
+pub struct Analysis {
+    pub program: Program,
+    pub errors: Vec<SemanticError>,
+    pub warnings: Vec<SemanticWarning>,
+    pub index: SymbolIndex,
+    pub types: TypeTable,
+}
+
+impl Analysis {
+    /// The analyzed program with what analysis found out about it. Only
+    /// meaningful when `errors` is empty.
+    pub fn into_typed(self) -> TypedProgram {
+        TypedProgram { program: self.program, types: self.types, symbols: Rc::new(self.index) }
+    }
+}
+
+pub fn analyze(program: Program) -> Result<TypedProgram, SemanticErrors> {
     analyze_with_file_path(program, None)
 }
 
-pub fn analyze_with_file_path(program: Program, file_path: Option<&std::path::Path>) -> Result<Program, SemanticError> {
+pub fn analyze_with_file_path(program: Program, file_path: Option<&std::path::Path>) -> Result<TypedProgram, SemanticErrors> {
+    let analysis = check(program, file_path);
+    if analysis.errors.is_empty() {
+        Ok(analysis.into_typed())
+    } else {
+        Err(SemanticErrors(analysis.errors))
+    }
+}
+
+/// Analyzes a whole program, collecting every error and warning.
+///
+/// Statements that fail analysis are left out of the returned program, so it
+/// should only be executed or compiled when `errors` is empty.
+pub fn check(program: Program, file_path: Option<&std::path::Path>) -> Analysis {
+    check_program(program, file_path, true)
+}
+
+/// Like [`check`], but for a module that is imported rather than run, so it
+/// needn't have a `main` function.
+pub fn check_module(program: Program, file_path: Option<&Path>) -> Analysis {
+    check_program(program, file_path, false)
+}
+
+fn check_program(program: Program, file_path: Option<&Path>, is_main_program: bool) -> Analysis {
     let mut analyzer = SemanticAnalyzer::new_with_file_path(file_path);
-    analyzer.analyze_program(program, true) // true indicates this is the main program
+    let program = analyzer.analyze_program(program, is_main_program);
+    
+    let position = |span: Option<Span>| span.map_or((usize::MAX, usize::MAX), |s| (s.line, s.column));
+    analyzer.errors.sort_by_key(|e| position(e.span));
+    analyzer.warnings.sort_by_key(|w| position(Some(w.span)));
+    
+    Analysis {
+        program,
+        errors: analyzer.errors,
+        warnings: analyzer.warnings,
+        index: analyzer.index,
+        types: analyzer.types,
+    }
+}
+
+/// Analyzes a program one piece at a time, keeping what earlier pieces
+/// declared in scope. This is what the REPL checks each input with.
+pub struct IncrementalAnalyzer {
+    analyzer: SemanticAnalyzer,
+}
+
+impl Default for IncrementalAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IncrementalAnalyzer {
+    pub fn new() -> Self {
+        IncrementalAnalyzer { analyzer: SemanticAnalyzer::new() }
+    }
+
+    /// Analyzes `program` as a continuation of the pieces analyzed so far,
+    /// resolving its imports relative to `file_path`. Top-level declarations
+    /// replace earlier ones with the same name. A piece with errors leaves
+    /// the analyzer as it was.
+    pub fn analyze(&mut self, program: Program, file_path: Option<&Path>) -> Result<TypedProgram, SemanticErrors> {
+        // Spans start over in every piece, so only its own uses are kept
+        self.analyzer.index.clear_references();
+        self.analyzer.types = TypeTable::default();
+        let globals = self.analyzer.scopes[0].clone();
+        let structs = self.analyzer.structs.clone();
+        let enums = self.analyzer.enums.clone();
+        let current_dir = self.analyzer.current_dir.clone();
+
+        for stmt in &program.statements {
+            match stmt {
+                Statement::LetDeclaration { name, .. } | Statement::FunctionDeclaration { name, .. } => {
+                    self.analyzer.scopes[0].remove(name);
+                }
+                Statement::StructDeclaration { name, .. } | Statement::EnumDeclaration { name, .. } => {
+                    self.analyzer.structs.remove(name);
+                    self.analyzer.enums.remove(name);
+                }
+                _ => {}
+            }
+        }
+        if let Some(parent) = file_path.and_then(Path::parent) {
+            self.analyzer.current_dir = parent.to_path_buf();
+        }
+
+        let program = self.analyzer.analyze_program(program, false);
+        self.analyzer.current_dir = current_dir;
+        self.analyzer.warnings.clear();
+        let mut errors = std::mem::take(&mut self.analyzer.errors);
+        if errors.is_empty() {
+            return Ok(TypedProgram {
+                program,
+                types: std::mem::take(&mut self.analyzer.types),
+                symbols: Rc::new(self.analyzer.index.clone()),
+            });
+        }
+
+        self.analyzer.scopes[0] = globals;
+        self.analyzer.structs = structs;
+        self.analyzer.enums = enums;
+        errors.sort_by_key(|e| e.span.map_or((usize::MAX, usize::MAX), |s| (s.line, s.column)));
+        Err(SemanticErrors(errors))
+    }
+
+    /// Returns the type `expr` would have if it were evaluated now.
+    pub fn type_of(&mut self, expr: Expr) -> Result<Type, SemanticErrors> {
+        let result = self.analyzer.analyze_expr(expr).and_then(|expr| self.analyzer.infer_type(&expr));
+        self.analyzer.errors.clear();
+        self.analyzer.warnings.clear();
+        result.map_err(|e| SemanticErrors(vec![e]))
+    }
 }
 
 struct SemanticAnalyzer {
@@ -42,12 +298,69 @@ struct SemanticAnalyzer {
     module_cache: HashMap<PathBuf, ModuleInfo>,
     // Current working directory for resolving relative imports
     current_dir: PathBuf,
+    // Local variables declared in each scope, for unused-variable warnings
+    locals: Vec<Vec<LocalVariable>>,
+    // Fields of each struct declared in the program, in declaration order
+    structs: HashMap<String, Vec<StructField>>,
+    // Variants of each enum declared in the program, in declaration order
+    enums: HashMap<String, Vec<EnumVariant>>,
+    // Result type of each match expression, keyed by its span, since its arms
+    // can only be typed inside the scopes their bindings are defined in
+    match_types: HashMap<Span, Type>,
+    // Types inferred for the parameters written without one, keyed by the
+    // parameter's span
+    parameter_types: HashMap<Span, Type>,
+    // Definition of each name in each scope that the index records
+    declared: Vec<HashMap<String, usize>>,
+    // Byte offset each scope ends at, for where its names are visible
+    scope_ends: Vec<usize>,
+    index: SymbolIndex,
+    types: TypeTable,
+    errors: Vec<SemanticError>,
+    warnings: Vec<SemanticWarning>,
+}
+
+#[derive(Debug)]
+struct LocalVariable {
+    name: String,
+    span: Span,
+    used: bool,
 }
 
 #[derive(Debug, Clone)]
 struct ModuleInfo {
+    path: PathBuf,
     // Exported symbols from the module
     exported_symbols: HashMap<String, Symbol>,
+    // Where each exported symbol is declared in the module
+    export_spans: HashMap<String, Span>,
+    // The doc comments of the exported symbols that have one
+    export_docs: HashMap<String, String>,
+}
+
+impl ModuleInfo {
+    fn exports(&self) -> Vec<Export> {
+        let mut exports: Vec<Export> = self.exported_symbols.iter()
+            .filter_map(|(name, symbol)| {
+                let kind = match symbol {
+                    Symbol::Function { return_type, parameters } => ExportKind::Function {
+                        parameters: parameters.clone(),
+                        return_type: return_type.clone(),
+                    },
+                    Symbol::Variable { var_type } => ExportKind::Variable { var_type: var_type.clone() },
+                    Symbol::Namespace { .. } => return None,
+                };
+                Some(Export {
+                    name: name.clone(),
+                    kind,
+                    span: self.export_spans.get(name).copied().unwrap_or_default(),
+                    doc: self.export_docs.get(name).cloned(),
+                })
+            })
+            .collect();
+        exports.sort_by_key(|export| export.span.start);
+        exports
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -69,13 +382,7 @@ impl SemanticAnalyzer {
     }
     
     fn new_with_file_path(file_path: Option<&std::path::Path>) -> Self {
-        let current_dir = if let Some(path) = file_path {
-            // Use the directory of the file being analyzed
-            path.parent().unwrap_or_else(|| std::path::Path::new(".")).to_path_buf()
-        } else {
-            // Fallback to current working directory
-            std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
-        };
+        let current_dir = module_dir(file_path);
         
         Self {
             scopes: vec![HashMap::new()], // Global scope
@@ -83,9 +390,28 @@ impl SemanticAnalyzer {
             std_lib: StdLib::new(),
             module_cache: HashMap::new(),
             current_dir,
+            locals: vec![Vec::new()],
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            match_types: HashMap::new(),
+            parameter_types: HashMap::new(),
+            declared: vec![HashMap::new()],
+            scope_ends: vec![usize::MAX],
+            index: SymbolIndex::default(),
+            types: TypeTable::default(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
         }
     }
     
+    fn report(&mut self, error: SemanticError) {
+        self.errors.push(error);
+    }
+    
+    fn warn(&mut self, message: String, span: Span, help: Option<String>) {
+        self.warnings.push(SemanticWarning { message, span, help });
+    }
+    
     fn has_return_statement(&self, statements: &[Statement]) -> bool {
         for stmt in statements {
             if self.statement_has_return(stmt) {
@@ -100,42 +426,72 @@ impl SemanticAnalyzer {
             Statement::Return { .. } => true,
             Statement::If { then_branch, else_branch, .. } => {
                 self.statement_has_return(then_branch) || 
-                else_branch.as_ref().map_or(false, |else_stmt| self.statement_has_return(else_stmt))
+                else_branch.as_ref().is_some_and(|else_stmt| self.statement_has_return(else_stmt))
             }
-            Statement::While { body, .. } => {
+            Statement::While { body, .. } | Statement::For { body, .. } => {
                 self.statement_has_return(body)
             }
-            Statement::Block { statements } => {
+            Statement::Block { statements, .. } => {
                 self.has_return_statement(statements)
             }
+            Statement::Match { arms, .. } => {
+                arms.iter().any(|arm| self.statement_has_return(&arm.body))
+            }
             _ => false,
         }
     }
     
+    /// Infers the return type of a function without an explicit one from its
+    /// first `return` statement, defaulting to void.
+    fn infer_return_type(&mut self, parameters: &[Parameter], body: &[Statement]) -> Type {
+        // Inference re-analyzes parts of the body, which is analyzed properly
+        // afterwards; keep its diagnostics out of the results
+        let (errors, warnings) = (self.errors.len(), self.warnings.len());
+        
+        // Enter function scope to analyze return statements
+        self.begin_scope();
+        
+        // Add parameters to the scope for analysis
+        for param in parameters {
+            let _ = self.define_symbol(
+                param.name.clone(), 
+                Symbol::Variable { var_type: param.param_type.clone() }
+            );
+        }
+        
+        // Look for return statements to infer type
+        let return_type = self.find_return_type_in_statements(body).unwrap_or(Type::Void);
+        
+        // Exit the temporary scope
+        self.end_scope();
+        self.errors.truncate(errors);
+        self.warnings.truncate(warnings);
+        return_type
+    }
+    
     fn find_return_type_in_statements(&mut self, statements: &[Statement]) -> Option<Type> {
         // First pass: analyze variable declarations to build the symbol table
         for stmt in statements.iter() {
-            match stmt {
-                Statement::LetDeclaration { name, initializer, .. } => {
-                    // Analyze the initializer if present
-                     let var_type = if let Some(init_expr) = initializer {
-                         match self.analyze_expr(init_expr.clone()) {
-                            Ok(analyzed_expr) => {
-                                match self.infer_type(&analyzed_expr) {
-                                    Ok(inferred_type) => inferred_type,
-                                    Err(_) => Type::Integer, // Default fallback
-                                }
+            if let Statement::LetDeclaration { name, var_type: declared_type, initializer, .. } = stmt {
+                // Analyze the initializer if present
+                let var_type = if let Some(declared_type) = declared_type {
+                    declared_type.clone()
+                } else if let Some(init_expr) = initializer {
+                    match self.analyze_expr(init_expr.clone()) {
+                        Ok(analyzed_expr) => {
+                            match self.infer_type(&analyzed_expr) {
+                                Ok(inferred_type) => inferred_type,
+                                Err(_) => Type::Integer, // Default fallback
                             }
-                            Err(_) => Type::Integer, // Default fallback
                         }
-                    } else {
-                        Type::Integer // Default type for uninitialized variables
-                    };
-                    
-                    // Define the variable in the current scope
-                    let _ = self.define_symbol(name.clone(), Symbol::Variable { var_type });
-                }
-                _ => {} // Skip other statements in first pass
+                        Err(_) => Type::Integer, // Default fallback
+                    }
+                } else {
+                    Type::Integer // Default type for uninitialized variables
+                };
+                
+                // Define the variable in the current scope
+                let _ = self.define_symbol(name.clone(), Symbol::Variable { var_type });
             }
         }
         
@@ -150,7 +506,7 @@ impl SemanticAnalyzer {
     
     fn find_return_type_in_statement(&mut self, stmt: &Statement) -> Option<Type> {
         match stmt {
-            Statement::Return { value } => {
+            Statement::Return { value, .. } => {
                 if let Some(return_expr) = value {
                     match self.analyze_expr(*return_expr.clone()) {
                         Ok(analyzed_expr) => {
@@ -165,51 +521,137 @@ impl SemanticAnalyzer {
                 }
                 None
             }
-            Statement::If { condition: _, then_branch, else_branch } => {
+            Statement::If { then_branch, else_branch, .. } => {
                 if let Some(return_type) = self.find_return_type_in_statement(then_branch) {
                     return Some(return_type);
                 }
-                if let Some(else_stmt) = else_branch {
-                    if let Some(return_type) = self.find_return_type_in_statement(else_stmt) {
-                        return Some(return_type);
-                    }
+                if let Some(else_stmt) = else_branch
+                    && let Some(return_type) = self.find_return_type_in_statement(else_stmt) {
+                    return Some(return_type);
                 }
                 None
             }
-            Statement::While { condition: _, body } => {
+            Statement::While { body, .. } => {
                 self.find_return_type_in_statement(body)
             }
-            Statement::Block { statements } => {
+            Statement::For { variable, iterable, body, .. } => {
+                let element_type = self.analyze_expr(*iterable.clone())
+                    .and_then(|iterable| self.loop_variable_type(&iterable))
+                    .unwrap_or(Type::Error);
+                self.begin_scope();
+                let _ = self.define_symbol(variable.clone(), Symbol::Variable { var_type: element_type });
+                let return_type = self.find_return_type_in_statement(body);
+                self.end_scope();
+                return_type
+            }
+            Statement::Block { statements, .. } => {
                 self.find_return_type_in_statements(statements)
             }
+            Statement::Match { scrutinee, arms, .. } => {
+                let scrutinee_type = self.analyze_expr(*scrutinee.clone())
+                    .and_then(|scrutinee| self.infer_type(&scrutinee))
+                    .unwrap_or(Type::Error);
+                for arm in arms {
+                    self.begin_scope();
+                    let _ = self.bind_pattern(&arm.pattern, &scrutinee_type);
+                    let return_type = self.find_return_type_in_statement(&arm.body);
+                    self.end_scope();
+                    if return_type.is_some() {
+                        return return_type;
+                    }
+                }
+                None
+            }
             _ => None,
         }
     }
     
-    fn analyze_program(&mut self, program: Program, is_main_program: bool) -> Result<Program, SemanticError> {
-        // First pass: collect function declarations (without return type inference)
+    fn analyze_program(&mut self, program: Program, is_main_program: bool) -> Program {
+        // First pass: collect struct, enum and function declarations (without return type inference)
+        for stmt in &program.statements {
+            if let Statement::StructDeclaration { name, fields, span } = stmt {
+                if self.structs.contains_key(name) {
+                    self.report(SemanticError {
+                        message: format!("Struct '{}' is already defined", name),
+                        span: Some(*span),
+                        module: None,
+                    });
+                } else {
+                    self.structs.insert(name.clone(), fields.clone());
+                }
+            }
+        }
         for stmt in &program.statements {
-            if let Statement::FunctionDeclaration { name, parameters, return_type, .. } = stmt {
+            if let Statement::EnumDeclaration { name, variants, span } = stmt {
+                if self.enums.contains_key(name) || self.structs.contains_key(name) {
+                    self.report(SemanticError {
+                        message: format!("Enum '{}' is already defined", name),
+                        span: Some(*span),
+                        module: None,
+                    });
+                } else {
+                    self.enums.insert(name.clone(), variants.clone());
+                }
+            }
+        }
+        let (parameter_types, errors) = inference::infer_parameter_types(self, &program.statements);
+        self.parameter_types.extend(parameter_types);
+        for error in errors {
+            self.report(error);
+        }
+        for stmt in &program.statements {
+            if let Statement::FunctionDeclaration { name, parameters, return_type, doc, span, .. } = stmt {
                 let func_return_type = return_type.clone().unwrap_or(Type::Void);
-                self.define_symbol(name.clone(), Symbol::Function { 
+                match self.define_symbol(name.clone(), Symbol::Function { 
                     return_type: func_return_type, 
-                    parameters: parameters.clone() 
-                })?;
+                    parameters: self.resolve_parameters(parameters.clone()),
+                }) {
+                    Ok(()) => {
+                        self.record_definition(name, DefinitionKind::Function, *span);
+                        self.document_definition(name, doc.clone());
+                    }
+                    Err(e) => self.report(e.with_span(*span)),
+                }
             }
         }
         
         // Second pass: analyze all statements
-        let mut analyzed_statements = Vec::new();
-        for stmt in program.statements {
-            analyzed_statements.push(self.analyze_statement(stmt)?);
-        }
+        let statements = self.analyze_statements(program.statements);
         
         // Validate that a main function exists and has the correct signature (only for main program)
-        if is_main_program {
-            self.validate_main_function()?;
+        if is_main_program && let Err(e) = self.validate_main_function() {
+            self.report(e);
         }
         
-        Ok(Program { statements: analyzed_statements })
+        Program { statements }
+    }
+    
+    /// Analyzes a sequence of statements, recording the errors of any that
+    /// fail and carrying on with the rest.
+    fn analyze_statements(&mut self, statements: Vec<Statement>) -> Vec<Statement> {
+        let mut analyzed = Vec::new();
+        let mut diverged = false;
+        for stmt in statements {
+            if diverged {
+                self.warn("Unreachable statement".to_string(), stmt.span(), None);
+                // Only the first unreachable statement of a block is reported
+                diverged = false;
+            }
+            let diverges = matches!(stmt, Statement::Return { .. } | Statement::Break { .. } | Statement::Continue { .. });
+            match self.analyze_statement(stmt) {
+                Ok(stmt) => analyzed.push(stmt),
+                Err(e) => self.report(e),
+            }
+            diverged |= diverges;
+        }
+        analyzed
+    }
+    
+    /// Records `error` unless `ty` is already poisoned by an earlier error.
+    fn report_unless_poisoned(&mut self, ty: &Type, error: SemanticError) {
+        if *ty != Type::Error {
+            self.report(error);
+        }
     }
     
     fn analyze_statement(&mut self, stmt: Statement) -> Result<Statement, SemanticError> {
@@ -218,62 +660,110 @@ impl SemanticAnalyzer {
                 let analyzed_expr = self.analyze_expr(expr)?;
                 Ok(Statement::Expression(analyzed_expr))
             },
-            Statement::LetDeclaration { name, initializer, is_exported } => {
-                let analyzed_initializer = match initializer {
-                    Some(expr) => Some(self.analyze_expr(expr)?),
+            Statement::LetDeclaration { name, var_type: declared_type, initializer, is_exported, doc, span } => {
+                if let Some(declared) = &declared_type
+                    && let Err(e) = self.check_type_exists(declared, span) {
+                    let _ = self.define_symbol(name, Symbol::Variable { var_type: Type::Error });
+                    return Err(e);
+                }
+                let analyzed_initializer = match initializer.map(|expr| self.analyze_expr(expr)) {
+                    Some(Ok(expr)) => Some(expr),
+                    Some(Err(e)) => {
+                        // Poison the variable so its later uses don't report again
+                        let _ = self.define_symbol(name, Symbol::Variable { var_type: Type::Error });
+                        return Err(e);
+                    }
                     None => None,
                 };
                 
                 // Infer type from initializer or use default
                 let var_type = if let Some(ref init) = analyzed_initializer {
-                    self.infer_type(init)?
+                    match self.infer_type_expecting(init, declared_type.as_ref()) {
+                        Ok(var_type) => {
+                            // An empty array only has a type when one is declared
+                            self.types.record(init.span(), var_type.clone());
+                            var_type
+                        }
+                        Err(e) => {
+                            let var_type = declared_type.unwrap_or(Type::Error);
+                            let _ = self.define_symbol(name, Symbol::Variable { var_type });
+                            return Err(e);
+                        }
+                    }
                 } else {
-                    Type::Integer // Default type for uninitialized variables
+                    declared_type.clone().unwrap_or(Type::Integer) // Default type for uninitialized variables
                 };
                 
-                self.define_symbol(name.clone(), Symbol::Variable { var_type })?;
+                // An explicit type wins, so a bad initializer is reported only once
+                let mismatch = match &declared_type {
+                    Some(declared) if var_type != *declared && var_type != Type::Error => Some(SemanticError {
+                        message: format!("Type mismatch in declaration of '{}': expected {}, got {}", name, declared, var_type),
+                        span: Some(span),
+                        module: None,
+                    }),
+                    _ => None,
+                };
+                let var_type = declared_type.clone().unwrap_or(var_type);
+                
+                self.define_symbol(name.clone(), Symbol::Variable { var_type })
+                    .map_err(|e| e.with_span(span))?;
+                self.record_definition(&name, DefinitionKind::Variable, span);
+                self.document_definition(&name, doc.clone());
+                self.declare_local(&name, span);
+                if let Some(error) = mismatch {
+                    return Err(error);
+                }
                 
                 Ok(Statement::LetDeclaration {
                     name,
+                    var_type: declared_type,
                     initializer: analyzed_initializer,
                     is_exported,
+                    doc,
+                    span,
                 })
             },
-            Statement::FunctionDeclaration { name, parameters, body, return_type, is_exported } => {
-                let func_return_type = return_type.clone().unwrap_or(Type::Void);
-                
-                // If no explicit return type, try to infer it from return statements
-                let mut inferred_return_type = func_return_type.clone();
-                if return_type.is_none() {
-                    // Enter function scope to analyze return statements
-                    self.begin_scope();
-                    
-                    // Add parameters to the scope for analysis
-                    for param in &parameters {
-                        self.define_symbol(
-                            param.name.clone(), 
-                            Symbol::Variable { var_type: param.param_type.clone() }
-                        )?;
-                    }
-                    
-                    // Look for return statements to infer type
-                    if let Some(return_type) = self.find_return_type_in_statements(&body) {
-                        inferred_return_type = return_type;
+            Statement::FunctionDeclaration { name, parameters, body, return_type, is_exported, is_test, doc, span } => {
+                let parameters = self.resolve_parameters(parameters);
+                if is_test && self.scopes.len() > 1 {
+                    self.report(SemanticError {
+                        message: format!("Test function '{}' must be declared at the top level", name),
+                        span: Some(span),
+                        module: None,
+                    });
+                }
+                if is_test && !parameters.is_empty() {
+                    self.report(SemanticError {
+                        message: format!("Test function '{}' must not take parameters", name),
+                        span: Some(span),
+                        module: None,
+                    });
+                }
+                for param in &parameters {
+                    if let Err(e) = self.check_type_exists(&param.param_type, param.span) {
+                        self.report(e);
                     }
-                    
-                    // Exit the temporary scope
-                    self.end_scope();
-                    
-                    // Update the function's return type in the symbol table
-                    if inferred_return_type != Type::Void {
-                        // Remove the old entry and add the new one with correct return type
-                        if let Some(current_scope) = self.scopes.last_mut() {
-                            current_scope.insert(name.clone(), Symbol::Function {
-                                return_type: inferred_return_type.clone(),
-                                parameters: parameters.clone(),
-                            });
-                        }
+                }
+                if let Some(return_type) = &return_type
+                    && let Err(e) = self.check_type_exists(return_type, span) {
+                    self.report(e);
+                }
+                // If no explicit return type, try to infer it from return statements
+                let inferred_return_type = match &return_type {
+                    Some(return_type) => return_type.clone(),
+                    None => self.infer_return_type(&parameters, &body),
+                };
+                
+                // Update the function's return type in the symbol table
+                if return_type.is_none() && inferred_return_type != Type::Void {
+                    // Remove the old entry and add the new one with correct return type
+                    if let Some(current_scope) = self.scopes.last_mut() {
+                        current_scope.insert(name.clone(), Symbol::Function {
+                            return_type: inferred_return_type.clone(),
+                            parameters: parameters.clone(),
+                        });
                     }
+                    self.update_definition_type(&name);
                 }
                 
                 // Set current function context
@@ -281,28 +771,24 @@ impl SemanticAnalyzer {
                 self.current_function_return_type = Some(inferred_return_type.clone());
                 
                 // Enter function scope for actual analysis
-                self.begin_scope();
+                self.begin_scope_within(span);
                 
                 // Add parameters to the scope
-                for param in &parameters {
-                    self.define_symbol(
-                        param.name.clone(), 
-                        Symbol::Variable { var_type: param.param_type.clone() }
-                    )?;
-                }
+                self.define_parameters(&parameters);
                 
-                // Analyze function body
-                let mut analyzed_body = Vec::new();
+                // Check if non-void function has return statement (recursively).
+                // Statements that fail analysis are dropped from the analyzed
+                // body, so a `return` with a bad value is looked for before
+                let has_return = self.has_return_statement(&body);
                 
-                for stmt in body {
-                    analyzed_body.push(self.analyze_statement(stmt)?);
-                }
+                // Analyze function body
+                let analyzed_body = self.analyze_statements(body);
                 
-                // Check if non-void function has return statement (recursively)
-                let has_return = self.has_return_statement(&analyzed_body);
                 if inferred_return_type != Type::Void && !has_return {
-                    return Err(SemanticError {
+                    self.report_unless_poisoned(&inferred_return_type, SemanticError {
                         message: format!("Function '{}' with return type {:?} must have a return statement", name, inferred_return_type),
+                        span: Some(span),
+                        module: None,
                     });
                 }
                 
@@ -316,60 +802,151 @@ impl SemanticAnalyzer {
                     body: analyzed_body,
                     return_type: Some(inferred_return_type),
                     is_exported,
+                    is_test,
+                    doc,
+                    span,
                 })
             },
-            Statement::Block { statements } => {
-                self.begin_scope();
-                
-                let mut analyzed_statements = Vec::new();
-                for stmt in statements {
-                    analyzed_statements.push(self.analyze_statement(stmt)?);
+            Statement::StructDeclaration { name, fields, span } => {
+                // Top-level structs were collected before analysis started
+                if self.scopes.len() > 1 {
+                    return Err(SemanticError {
+                        message: format!("Struct '{}' must be declared at the top level", name),
+                        span: Some(span),
+                        module: None,
+                    });
                 }
                 
-                self.end_scope();
+                let mut seen = Vec::new();
+                for field in &fields {
+                    if seen.contains(&&field.name) {
+                        self.report(SemanticError {
+                            message: format!("Field '{}' is declared more than once in struct '{}'", field.name, name),
+                            span: Some(field.span),
+                            module: None,
+                        });
+                    }
+                    seen.push(&field.name);
+                    if let Err(e) = self.check_type_exists(&field.field_type, field.span) {
+                        self.report(e);
+                    }
+                }
                 
-                Ok(Statement::Block { statements: analyzed_statements })
+                Ok(Statement::StructDeclaration { name, fields, span })
             },
-            Statement::If { condition, then_branch, else_branch } => {
-                let analyzed_condition = self.analyze_expr(*condition)?;
-                
-                // Check that condition is of boolean type
-                if self.infer_type(&analyzed_condition)? != Type::Boolean {
+            Statement::EnumDeclaration { name, variants, span } => {
+                // Top-level enums were collected before analysis started
+                if self.scopes.len() > 1 {
                     return Err(SemanticError {
-                        message: "If condition must be of boolean type".to_string(),
+                        message: format!("Enum '{}' must be declared at the top level", name),
+                        span: Some(span),
+                        module: None,
                     });
                 }
                 
-                let analyzed_then = Box::new(self.analyze_statement(*then_branch)?);
-                let analyzed_else = match else_branch {
-                    Some(branch) => Some(Box::new(self.analyze_statement(*branch)?)),
-                    None => None,
-                };
+                let mut seen = Vec::new();
+                for variant in &variants {
+                    if seen.contains(&&variant.name) {
+                        self.report(SemanticError {
+                            message: format!("Variant '{}' is declared more than once in enum '{}'", variant.name, name),
+                            span: Some(variant.span),
+                            module: None,
+                        });
+                    }
+                    seen.push(&variant.name);
+                    for field_type in &variant.fields {
+                        if let Err(e) = self.check_type_exists(field_type, variant.span) {
+                            self.report(e);
+                        }
+                    }
+                }
+                
+                Ok(Statement::EnumDeclaration { name, variants, span })
+            },
+            Statement::Match { scrutinee, arms, span } => {
+                let (analyzed_scrutinee, scrutinee_type) = self.analyze_scrutinee(*scrutinee);
+                
+                let mut patterns_ok = true;
+                let mut analyzed_arms = Vec::new();
+                for arm in arms {
+                    self.begin_scope_within(arm.span);
+                    if let Err(e) = self.bind_pattern(&arm.pattern, &scrutinee_type) {
+                        self.report(e);
+                        patterns_ok = false;
+                    }
+                    let body = self.analyze_nested(arm.body);
+                    self.end_scope();
+                    analyzed_arms.push(MatchArm { pattern: arm.pattern, body, span: arm.span });
+                }
+                if patterns_ok {
+                    self.check_match_coverage(&scrutinee_type, &analyzed_arms, span);
+                }
+                
+                Ok(Statement::Match { scrutinee: Box::new(analyzed_scrutinee), arms: analyzed_arms, span })
+            },
+            Statement::Block { statements, span } => {
+                self.begin_scope_within(span);
+                let analyzed_statements = self.analyze_statements(statements);
+                self.end_scope();
+                
+                Ok(Statement::Block { statements: analyzed_statements, span })
+            },
+            Statement::If { condition, then_branch, else_branch, span } => {
+                let analyzed_condition = self.analyze_condition(*condition, "If");
+                let analyzed_then = self.analyze_nested(*then_branch);
+                let analyzed_else = else_branch.map(|branch| Box::new(self.analyze_nested(*branch)));
                 
                 Ok(Statement::If {
                     condition: Box::new(analyzed_condition),
-                    then_branch: analyzed_then,
+                    then_branch: Box::new(analyzed_then),
                     else_branch: analyzed_else,
+                    span,
                 })
             },
-            Statement::While { condition, body } => {
-                let analyzed_condition = self.analyze_expr(*condition)?;
-                
-                // Check that condition is of boolean type
-                if self.infer_type(&analyzed_condition)? != Type::Boolean {
-                    return Err(SemanticError {
-                        message: "While condition must be of boolean type".to_string(),
-                    });
-                }
-                
-                let analyzed_body = Box::new(self.analyze_statement(*body)?);
+            Statement::While { condition, body, span } => {
+                let analyzed_condition = self.analyze_condition(*condition, "While");
+                let analyzed_body = self.analyze_nested(*body);
                 
                 Ok(Statement::While {
                     condition: Box::new(analyzed_condition),
-                    body: analyzed_body,
+                    body: Box::new(analyzed_body),
+                    span,
                 })
             },
-            Statement::Return { value } => {
+            Statement::For { variable, iterable, body, span } => {
+                let iterable_span = iterable.span();
+                let (analyzed_iterable, element_type) = match self.analyze_expr(*iterable) {
+                    Ok(iterable) => {
+                        let element_type = self.loop_variable_type(&iterable).unwrap_or_else(|e| {
+                            self.report(e);
+                            Type::Error
+                        });
+                        (iterable, element_type)
+                    }
+                    Err(e) => {
+                        self.report(e);
+                        (Expr::Literal { value: Literal::Integer(0), span: iterable_span }, Type::Error)
+                    }
+                };
+                
+                // The loop variable is only visible inside the loop
+                self.begin_scope_within(span);
+                match self.define_symbol(variable.clone(), Symbol::Variable { var_type: element_type }) {
+                    Ok(()) => self.record_definition(&variable, DefinitionKind::Variable, span),
+                    Err(e) => self.report(e.with_span(span)),
+                }
+                self.declare_local(&variable, span);
+                let analyzed_body = self.analyze_nested(*body);
+                self.end_scope();
+                
+                Ok(Statement::For {
+                    variable,
+                    iterable: Box::new(analyzed_iterable),
+                    body: Box::new(analyzed_body),
+                    span,
+                })
+            },
+            Statement::Return { value, span } => {
                 let analyzed_value = match value {
                     Some(expr) => Some(Box::new(self.analyze_expr(*expr)?)),
                     None => None,
@@ -380,13 +957,15 @@ impl SemanticAnalyzer {
                     match (&analyzed_value, expected_return_type) {
                         (None, Type::Void) => {}, // void return with no value is OK
                         (Some(val), expected_type) => {
-                            let actual_type = self.infer_type(val)?;
-                            if actual_type != *expected_type {
+                            let actual_type = self.infer_type_expecting(val, Some(expected_type))?;
+                            if actual_type != *expected_type && actual_type != Type::Error && *expected_type != Type::Error {
                                 return Err(SemanticError {
                                     message: format!(
                                         "Return type mismatch: expected {:?}, got {:?}",
                                         expected_type, actual_type
                                     ),
+                                    span: Some(span),
+                                    module: None,
                                 });
                             }
                         },
@@ -396,42 +975,76 @@ impl SemanticAnalyzer {
                                     "Function expects return type {:?}, but no value returned",
                                     expected_type
                                 ),
+                                span: Some(span),
+                                module: None,
                             });
                         },
                         _ => {},
                     }
                 }
                 
-                Ok(Statement::Return { value: analyzed_value })
+                Ok(Statement::Return { value: analyzed_value, span })
             },
-            Statement::Import { module, alias } => {
+            Statement::Import { module, alias, span } => {
                 // Resolve and load the module
                 let module_path = self.resolve_module_path(&module);
-                let module_info = self.load_module(&module_path)?;
+                let module_info = match self.load_module(&module_path) {
+                    Ok(module_info) => module_info,
+                    Err(e) => {
+                        // Poison the names the module exports, as far as it
+                        // parses, so their uses don't report again
+                        let names = e.module.as_deref().map(exported_names).unwrap_or_default();
+                        if let Some(alias_name) = &alias {
+                            let _ = self.define_symbol(alias_name.clone(), Symbol::Namespace { module_name: module.clone() });
+                        }
+                        for name in names {
+                            let name = match &alias {
+                                Some(alias_name) => format!("{}.{}", alias_name, name),
+                                None => name,
+                            };
+                            let _ = self.define_symbol(name, Symbol::Variable { var_type: Type::Error });
+                        }
+                        return Err(e.with_span(span));
+                    }
+                };
                 
                 // Add the module's exported symbols to the current scope
                 if let Some(alias_name) = &alias {
                     // Create a namespace symbol for the alias
-                    self.define_symbol(alias_name.clone(), Symbol::Namespace { module_name: module.clone() })?;
+                    self.define_symbol(alias_name.clone(), Symbol::Namespace { module_name: module.clone() })
+                        .map_err(|e| e.with_span(span))?;
+                    self.record_import(alias_name, &module_info, None, span);
                     
                     // Create a namespace for the module under the alias
                     for (symbol_name, symbol) in &module_info.exported_symbols {
                         let namespaced_name = format!("{}.{}", alias_name, symbol_name);
-                        self.define_symbol(namespaced_name, symbol.clone())?;
+                        self.define_symbol(namespaced_name.clone(), symbol.clone()).map_err(|e| e.with_span(span))?;
+                        self.record_import(&namespaced_name, &module_info, Some(symbol_name), span);
                     }
                 } else {
                     // Add all exported symbols directly to the current scope
                     for (symbol_name, symbol) in &module_info.exported_symbols {
-                        self.define_symbol(symbol_name.clone(), symbol.clone())?;
+                        self.define_symbol(symbol_name.clone(), symbol.clone()).map_err(|e| e.with_span(span))?;
+                        self.record_import(symbol_name, &module_info, Some(symbol_name), span);
                     }
                 }
                 
-                Ok(Statement::Import { module, alias })
+                Ok(Statement::Import { module, alias, span })
             },
-            Statement::ImportFrom { module, items } => {
+            Statement::ImportFrom { module, items, span } => {
                 // Resolve and load the module
                 let module_path = self.resolve_module_path(&module);
-                let module_info = self.load_module(&module_path)?;
+                let module_info = match self.load_module(&module_path) {
+                    Ok(module_info) => module_info,
+                    Err(e) => {
+                        // Poison the imported names so their uses don't report again
+                        for (item, alias) in &items {
+                            let name = alias.as_ref().unwrap_or(item);
+                            let _ = self.define_symbol(name.clone(), Symbol::Variable { var_type: Type::Error });
+                        }
+                        return Err(e.with_span(span));
+                    }
+                };
                 
                 // Import specific items from the module
                 for (item, alias) in &items {
@@ -439,62 +1052,145 @@ impl SemanticAnalyzer {
                     
                     // Check if the item exists in the module's exported symbols
                     if let Some(symbol) = module_info.exported_symbols.get(item) {
-                        self.define_symbol(symbol_name.clone(), symbol.clone())?;
+                        match self.define_symbol(symbol_name.clone(), symbol.clone()) {
+                            Ok(()) => self.record_import(symbol_name, &module_info, Some(item), span),
+                            Err(e) => self.report(e.with_span(span)),
+                        }
                     } else {
-                        return Err(SemanticError {
+                        self.report(SemanticError {
                             message: format!("Symbol '{}' not found in module '{}'", item, module),
+                            span: Some(span),
+                            module: None,
                         });
                     }
                 }
                 
-                Ok(Statement::ImportFrom { module, items })
+                Ok(Statement::ImportFrom { module, items, span })
             },
-            Statement::AssignMain { function_name } => {
+            Statement::AssignMain { function_name, span } => {
                 // Validate that the function exists
                 if !self.symbol_exists(&function_name) {
                     return Err(SemanticError {
                         message: format!("Function '{}' not found for ASSIGN_MAIN", function_name),
+                        span: Some(span),
+                        module: None,
                     });
                 }
                 
                 // Validate that it's actually a function
-                match self.get_symbol(&function_name)? {
+                match self.get_symbol(&function_name).map_err(|e| e.with_span(span))? {
                     Symbol::Function { .. } => {},
                     _ => {
                         return Err(SemanticError {
                             message: format!("'{}' is not a function and cannot be assigned as main", function_name),
+                            span: Some(span),
+                            module: None,
                         });
                     }
                 }
                 
-                Ok(Statement::AssignMain { function_name })
+                Ok(Statement::AssignMain { function_name, span })
             },
-            Statement::Break => {
+            Statement::Break { span } => {
                 // Break statements are valid - they will be handled by the interpreter
-                Ok(Statement::Break)
+                Ok(Statement::Break { span })
             },
-            Statement::Continue => {
+            Statement::Continue { span } => {
                 // Continue statements are valid - they will be handled by the interpreter
-                Ok(Statement::Continue)
+                Ok(Statement::Continue { span })
             },
         }
     }
     
+    /// Analyzes the body of an `if` or loop, recording its error and
+    /// substituting an empty block if it fails.
+    fn analyze_nested(&mut self, stmt: Statement) -> Statement {
+        let span = stmt.span();
+        self.analyze_statement(stmt).unwrap_or_else(|e| {
+            self.report(e);
+            Statement::Block { statements: Vec::new(), span }
+        })
+    }
+    
+    /// Analyzes a loop or branch condition and checks that it is boolean.
+    fn analyze_condition(&mut self, condition: Expr, kind: &str) -> Expr {
+        let span = condition.span();
+        let analyzed = match self.analyze_expr(condition) {
+            Ok(expr) => expr,
+            Err(e) => {
+                self.report(e);
+                return Expr::Literal { value: Literal::Boolean(false), span };
+            }
+        };
+        
+        match self.infer_type(&analyzed) {
+            Ok(Type::Boolean) | Ok(Type::Error) => {}
+            Ok(_) => self.report(SemanticError {
+                message: format!("{} condition must be of boolean type", kind),
+                span: Some(span),
+                module: None,
+            }),
+            Err(e) => self.report(e),
+        }
+        analyzed
+    }
+    
+    /// The type of the loop variable when iterating over `iterable`, which
+    /// must be an integer range or an array.
+    fn loop_variable_type(&self, iterable: &Expr) -> Result<Type, SemanticError> {
+        if let Expr::Range { start, end, .. } = iterable {
+            for bound in [start, end] {
+                match self.infer_type(bound)? {
+                    Type::Integer | Type::Error => {}
+                    other => return Err(SemanticError {
+                        message: format!("Range bounds must be integers, got {}", other),
+                        span: Some(bound.span()),
+                        module: None,
+                    }),
+                }
+            }
+            return Ok(Type::Integer);
+        }
+        
+        match self.infer_type(iterable)? {
+            Type::Array(element_type) => Ok(*element_type),
+            Type::Error => Ok(Type::Error),
+            other => Err(SemanticError {
+                message: format!("Cannot iterate over a value of type {}", other),
+                span: Some(iterable.span()),
+                module: None,
+            }),
+        }
+    }
+    
+    /// Analyzes `expr` and records its type.
     fn analyze_expr(&mut self, expr: Expr) -> Result<Expr, SemanticError> {
+        let expr = self.analyze_expr_kind(expr)?;
+        if let Ok(ty) = self.infer_type(&expr) {
+            self.types.record(expr.span(), ty);
+        }
+        Ok(expr)
+    }
+    
+    fn analyze_expr_kind(&mut self, expr: Expr) -> Result<Expr, SemanticError> {
         match expr {
-            Expr::Variable(name) => {
+            Expr::Variable { name, span } => {
                 // Check if variable is declared
                 if !self.symbol_exists(&name) {
                     return Err(SemanticError {
                         message: format!("Undefined variable: {}", name),
+                        span: Some(span),
+                        module: None,
                     });
                 }
-                Ok(Expr::Variable(name))
+                self.mark_used(&name);
+                self.record_reference(&name, span);
+                Ok(Expr::Variable { name, span })
             },
-            Expr::Literal(literal) => {
-                Ok(Expr::Literal(literal))
+            Expr::Literal { value, span } => {
+                Ok(Expr::Literal { value, span })
             },
-            Expr::Binary { left, operator, right } => {
+            Expr::Binary { left, operator, right, span } => {
                 let analyzed_left = Box::new(self.analyze_expr(*left)?);
                 let analyzed_right = Box::new(self.analyze_expr(*right)?);
                 
@@ -503,6 +1199,7 @@ impl SemanticAnalyzer {
                     left: analyzed_left.clone(),
                     operator: operator.clone(),
                     right: analyzed_right.clone(),
+                    span,
                 };
                 
                 // This will perform proper type checking and promotion
@@ -512,26 +1209,31 @@ impl SemanticAnalyzer {
                     left: analyzed_left,
                     operator,
                     right: analyzed_right,
+                    span,
                 })
             },
-            Expr::Unary { operator, operand } => {
+            Expr::Unary { operator, operand, span } => {
                 let analyzed_operand = Box::new(self.analyze_expr(*operand)?);
                 
                 // Type checking for unary operations
                 match operator {
                     crate::ast::UnaryOperator::Not => {
                         let operand_type = self.infer_type(&analyzed_operand)?;
-                        if operand_type != Type::Boolean {
+                        if operand_type != Type::Boolean && operand_type != Type::Error {
                             return Err(SemanticError {
                                 message: "Operand of 'not' must be of boolean type".to_string(),
+                                span: Some(span),
+                                module: None,
                             });
                         }
                     },
                     crate::ast::UnaryOperator::Negate => {
                         let operand_type = self.infer_type(&analyzed_operand)?;
-                        if operand_type != Type::Integer && operand_type != Type::Float {
+                        if operand_type != Type::Integer && operand_type != Type::Float && operand_type != Type::Error {
                             return Err(SemanticError {
                                 message: "Operand of unary minus must be of numeric type".to_string(),
+                                span: Some(span),
+                                module: None,
                             });
                         }
                     },
@@ -540,37 +1242,36 @@ impl SemanticAnalyzer {
                 Ok(Expr::Unary {
                     operator,
                     operand: analyzed_operand,
+                    span,
                 })
             },
-            Expr::Call { callee, arguments } => {
+            Expr::Call { callee, arguments, span } => {
                 // Handle different types of function calls
+                let callee_span = callee.span();
                 let func_name = match callee.as_ref() {
-                    Expr::Variable(name) => name.clone(),
-                    Expr::Get { object, name } => {
-                        // Handle module-qualified function calls (e.g., math.add())
-                        if let Expr::Variable(namespace_name) = object.as_ref() {
+                    Expr::Variable { name, .. } if !self.is_variable(name) => name.clone(),
+                    // Handle module-qualified function calls (e.g., math.add())
+                    Expr::Get { object, name, .. } => match object.as_ref() {
+                        Expr::Variable { name: namespace_name, .. } if !self.is_variable(namespace_name) => {
                             format!("{}.{}", namespace_name, name)
-                        } else {
-                            return Err(SemanticError {
-                                message: "Complex function calls not yet supported".to_string(),
-                            });
                         }
+                        _ => return self.analyze_function_value_call(*callee, arguments, span),
                     },
-                    _ => {
-                        return Err(SemanticError {
-                            message: "Complex function calls not yet supported".to_string(),
-                        });
-                    }
+                    // Anything else has to evaluate to a function value
+                    _ => return self.analyze_function_value_call(*callee, arguments, span),
                 };
                 
-                let mut analyzed_arguments = Vec::new();                                                          
-                for arg in arguments {                                                                            
-                    analyzed_arguments.push(self.analyze_expr(arg)?);                                             
-                }                                                                                                 
+                self.record_reference(&func_name, callee_span);
+                let mut analyzed_arguments = Vec::new();
+                for arg in arguments {
+                    analyzed_arguments.push(self.analyze_expr(arg)?);
+                }
                                                                                                                   
                 // Check function signature
                     // Check if it's a built-in function first
-                    if self.std_lib.is_builtin_function(&func_name) {
+                    if let Some(result) = self.check_generic_builtin(&func_name, &analyzed_arguments) {
+                        result.map_err(|e| e.with_span(span))?;
+                    } else if self.std_lib.is_builtin_function(&func_name) {
                         // Get argument types for overload resolution
                         let mut arg_types = Vec::new();
                         for arg in &analyzed_arguments {
@@ -591,6 +1292,8 @@ impl SemanticAnalyzer {
                                             builtin_func.parameters.len(),
                                             analyzed_arguments.len()
                                         ),
+                                        span: Some(span),
+                                        module: None,
                                     });
                                 }
 
@@ -604,7 +1307,7 @@ impl SemanticAnalyzer {
                                         continue;
                                     }
                                     
-                                    if arg_type != *param_type {
+                                    if arg_type != *param_type && arg_type != Type::Error {
                                         return Err(SemanticError {
                                             message: format!(
                                                 "Type mismatch in argument {} of built-in function '{}': expected {:?}, got {:?}",
@@ -613,33 +1316,39 @@ impl SemanticAnalyzer {
                                                 param_type,
                                                 arg_type
                                             ),
+                                            span: Some(span),
+                                            module: None,
                                         });
                                     }
                                 }
                             } else {
                                 return Err(SemanticError {
                                     message: format!("No matching overload found for built-in function '{}'", func_name),
+                                    span: Some(span),
+                                    module: None,
                                 });
                             }
                         }
                     } else {
                         // Check user-defined functions
-                        if let Ok(Symbol::Function { parameters, .. }) = self.get_symbol(&func_name) {                     
-                            if analyzed_arguments.len() != parameters.len() {                                         
-                                return Err(SemanticError {                                                            
+                        if let Ok(Symbol::Function { parameters, .. }) = self.get_symbol(&func_name) {
+                            if analyzed_arguments.len() != parameters.len() {
+                                return Err(SemanticError {
                                     message: format!(
                                         "Function '{}' expects {} arguments, but {} were provided",
                                         func_name,
                                         parameters.len(),
                                         analyzed_arguments.len()
                                     ),
+                                    span: Some(span),
+                                    module: None,
                                 });
                             }
 
                             for (i, arg) in analyzed_arguments.iter().enumerate() {
-                                let arg_type = self.infer_type(arg)?;
                                 let param_type = &parameters[i].param_type;
-                                if arg_type != *param_type {
+                                let arg_type = self.infer_type_expecting(arg, Some(param_type))?;
+                                if arg_type != *param_type && arg_type != Type::Error && *param_type != Type::Error {
                                     return Err(SemanticError {
                                         message: format!(
                                             "Type mismatch in argument {} of function '{}': expected {:?}, got {:?}",
@@ -648,108 +1357,686 @@ impl SemanticAnalyzer {
                                             param_type,
                                             arg_type
                                         ),
+                                        span: Some(span),
+                                        module: None,
                                     });
                                 }
                             }
-                        } else {
+                        } else if !matches!(self.get_symbol(&func_name), Ok(Symbol::Variable { var_type: Type::Error })) {
+                            // A poisoned import has been reported already
                             return Err(SemanticError {
                                 message: format!("Undefined function '{}'", func_name),
+                                span: Some(span),
+                                module: None,
                             });
                         }
                     }
 
                 Ok(Expr::Call {
-                    callee: Box::new(Expr::Variable(func_name)),
+                    callee: Box::new(Expr::Variable { name: func_name, span: callee_span }),
                     arguments: analyzed_arguments,
+                    span,
                 })
             },
-            Expr::Function { parameters, body, return_type } => {
-                // Enter function scope
-                self.begin_scope();
-                
-                // Add parameters to the scope
+            Expr::Function { parameters, body, return_type, span } => {
+                let parameters = self.resolve_parameters(parameters);
                 for param in &parameters {
-                    self.define_symbol(
-                        param.name.clone(), 
-                        Symbol::Variable { var_type: param.param_type.clone() }
-                    )?;
+                    if let Err(e) = self.check_type_exists(&param.param_type, param.span) {
+                        self.report(e);
+                    }
                 }
+                if let Some(return_type) = &return_type
+                    && let Err(e) = self.check_type_exists(return_type, span) {
+                    self.report(e);
+                }
+                let return_type = match return_type {
+                    Some(return_type) => return_type,
+                    None => self.infer_return_type(&parameters, &body),
+                };
                 
-                // Analyze function body
-                let mut analyzed_body = Vec::new();
-                for stmt in body {
-                    analyzed_body.push(self.analyze_statement(stmt)?);
+                // Returns inside the body belong to the lambda, not the
+                // function it is written in
+                let previous_return_type = self.current_function_return_type.replace(return_type.clone());
+                
+                // Enter function scope; the body can also see every variable
+                // in the enclosing scopes, which the lambda captures
+                self.begin_scope_within(span);
+                
+                // Add parameters to the scope
+                self.define_parameters(&parameters);
+                
+                // Analyze function body, looking for a return first as for functions
+                let has_return = self.has_return_statement(&body);
+                let analyzed_body = self.analyze_statements(body);
+                if return_type != Type::Void && !has_return {
+                    self.report_unless_poisoned(&return_type, SemanticError {
+                        message: format!("Lambda with return type {} must have a return statement", return_type),
+                        span: Some(span),
+                        module: None,
+                    });
                 }
                 
                 // Exit function scope
                 self.end_scope();
+                self.current_function_return_type = previous_return_type;
                 
+                // The return type is always filled in so the lambda's type
+                // can be read straight off the analyzed expression
                 Ok(Expr::Function {
                     parameters,
                     body: analyzed_body,
-                    return_type,
+                    return_type: Some(return_type),
+                    span,
                 })
             },
-            Expr::Get { object, name } => {
+            Expr::Get { object, name, span } => {
                 // Handle module-qualified access (e.g., math_utils.multiply)
-                if let Expr::Variable(module_name) = object.as_ref() {
+                if let Expr::Variable { name: module_name, .. } = object.as_ref() {
                     // First, check if the module_name is a valid namespace
                     if let Ok(Symbol::Namespace { .. }) = self.get_symbol(module_name) {
                         let qualified_name = format!("{}.{}", module_name, name);
                         if self.symbol_exists(&qualified_name) {
+                            self.record_reference(&qualified_name, span);
                             // Convert the Get expression to a Variable expression with the qualified name
-                            return Ok(Expr::Variable(qualified_name));
+                            return Ok(Expr::Variable { name: qualified_name, span });
                         } else {
                             return Err(SemanticError {
                                 message: format!("Symbol '{}' not found in namespace '{}'", name, module_name),
+                                span: Some(span),
+                                module: None,
                             });
                         }
                     }
                 }
                 
-                // For other cases, analyze the object normally
+                // Otherwise this is a field access
                 let analyzed_object = Box::new(self.analyze_expr(*object)?);
-                Ok(Expr::Get { object: analyzed_object, name })
+                self.field_type(&analyzed_object, &name, span)?;
+                Ok(Expr::Get { object: analyzed_object, name, span })
             },
-            Expr::Set { object, name, value } => {
+            Expr::Set { object, name, value, span } => {
                 let analyzed_object = Box::new(self.analyze_expr(*object)?);
                 let analyzed_value = Box::new(self.analyze_expr(*value)?);
-                Ok(Expr::Set { object: analyzed_object, name, value: analyzed_value })
+                
+                let field_type = self.field_type(&analyzed_object, &name, span)?;
+                let value_type = self.infer_type_expecting(&analyzed_value, Some(&field_type))?;
+                if value_type != field_type && value_type != Type::Error && field_type != Type::Error {
+                    return Err(SemanticError {
+                        message: format!("Type mismatch in assignment to field '{}': expected {}, got {}", name, field_type, value_type),
+                        span: Some(span),
+                        module: None,
+                    });
+                }
+                
+                Ok(Expr::Set { object: analyzed_object, name, value: analyzed_value, span })
+            },
+            Expr::StructLiteral { name, fields, span } => {
+                let mut analyzed_fields = Vec::new();
+                for (field_name, value) in fields {
+                    analyzed_fields.push((field_name, self.analyze_expr(value)?));
+                }
+                self.check_struct_literal(&name, &analyzed_fields, span)?;
+                Ok(Expr::StructLiteral { name, fields: analyzed_fields, span })
+            },
+            Expr::EnumVariant { enum_name, variant, arguments, span } => {
+                let mut analyzed_arguments = Vec::new();
+                for argument in arguments {
+                    analyzed_arguments.push(self.analyze_expr(argument)?);
+                }
+                self.check_enum_variant(&enum_name, &variant, &analyzed_arguments, span)?;
+                Ok(Expr::EnumVariant { enum_name, variant, arguments: analyzed_arguments, span })
+            },
+            Expr::Match { scrutinee, arms, span } => {
+                let (analyzed_scrutinee, scrutinee_type) = self.analyze_scrutinee(*scrutinee);
+                
+                let mut patterns_ok = true;
+                let mut result_type: Option<Type> = None;
+                let mut analyzed_arms = Vec::new();
+                for arm in arms {
+                    self.begin_scope_within(arm.span);
+                    if let Err(e) = self.bind_pattern(&arm.pattern, &scrutinee_type) {
+                        self.report(e);
+                        patterns_ok = false;
+                    }
+                    let body = self.analyze_expr(arm.body)
+                        .and_then(|body| self.infer_type(&body).map(|body_type| (body, body_type)));
+                    self.end_scope();
+                    let (body, body_type) = body?;
+                    
+                    match &result_type {
+                        Some(expected) if *expected != body_type && *expected != Type::Error && body_type != Type::Error => {
+                            return Err(SemanticError {
+                                message: format!("Match arms have incompatible types: expected {}, got {}", expected, body_type),
+                                span: Some(body.span()),
+                                module: None,
+                            });
+                        }
+                        Some(expected) if *expected != Type::Error => {}
+                        _ => result_type = Some(body_type),
+                    }
+                    analyzed_arms.push(MatchArm { pattern: arm.pattern, body, span: arm.span });
+                }
+                if patterns_ok {
+                    self.check_match_coverage(&scrutinee_type, &analyzed_arms, span);
+                }
+                
+                self.match_types.insert(span, result_type.unwrap_or(Type::Void));
+                Ok(Expr::Match { scrutinee: Box::new(analyzed_scrutinee), arms: analyzed_arms, span })
             },
-            Expr::Index { sequence, index } => {
+            Expr::Index { sequence, index, span } => {
                 let analyzed_sequence = Box::new(self.analyze_expr(*sequence)?);
                 let analyzed_index = Box::new(self.analyze_expr(*index)?);
-                Ok(Expr::Index { sequence: analyzed_sequence, index: analyzed_index })
+                let analyzed = Expr::Index { sequence: analyzed_sequence, index: analyzed_index, span };
+                self.infer_type(&analyzed)?;
+                Ok(analyzed)
+            },
+            Expr::SetIndex { sequence, index, value, span } => {
+                let analyzed_sequence = Box::new(self.analyze_expr(*sequence)?);
+                let analyzed_index = Box::new(self.analyze_expr(*index)?);
+                let analyzed_value = Box::new(self.analyze_expr(*value)?);
+                
+                let element_type = self.element_type(&analyzed_sequence, &analyzed_index)?;
+                let value_type = self.infer_type_expecting(&analyzed_value, Some(&element_type))?;
+                if element_type != value_type && element_type != Type::Error && value_type != Type::Error {
+                    return Err(SemanticError {
+                        message: format!("Type mismatch in element assignment: expected {}, got {}", element_type, value_type),
+                        span: Some(analyzed_value.span()),
+                        module: None,
+                    });
+                }
+                
+                Ok(Expr::SetIndex { sequence: analyzed_sequence, index: analyzed_index, value: analyzed_value, span })
+            },
+            Expr::Array { elements, span } => {
+                let mut analyzed_elements = Vec::new();
+                for element in elements {
+                    analyzed_elements.push(self.analyze_expr(element)?);
+                }
+                let analyzed = Expr::Array { elements: analyzed_elements, span };
+                // Empty literals are typed by their context instead
+                if let Expr::Array { elements, .. } = &analyzed && !elements.is_empty() {
+                    self.infer_type(&analyzed)?;
+                }
+                Ok(analyzed)
+            },
+            Expr::Assign { name, value, span } => {
+                // Check if variable is declared
+                if !self.symbol_exists(&name) {
+                    return Err(SemanticError {
+                        message: format!("Cannot assign to undeclared variable: {}", name),
+                        span: Some(span),
+                        module: None,
+                    });
+                }
+                
+                let analyzed_value = Box::new(self.analyze_expr(*value)?);
+                
+                // Type checking for assignment
+                let var_symbol = self.get_symbol(&name).map_err(|e| e.with_span(span))?;
+                if let Symbol::Variable { var_type } = var_symbol {
+                    let value_type = self.infer_type_expecting(&analyzed_value, Some(&var_type))?;
+                    if var_type != value_type && var_type != Type::Error && value_type != Type::Error {
+                        return Err(SemanticError {
+                            message: format!("Type mismatch in assignment: expected {:?}, got {:?}", var_type, value_type),
+                            span: Some(span),
+                            module: None,
+                        });
+                    }
+                }
+                
+                Ok(Expr::Assign { name, value: analyzed_value, span })
+            },
+            Expr::Range { start, end, span } => {
+                let analyzed_start = Box::new(self.analyze_expr(*start)?);
+                let analyzed_end = Box::new(self.analyze_expr(*end)?);
+                Ok(Expr::Range { start: analyzed_start, end: analyzed_end, span })
+            },
+            Expr::FormatString { parts, span } => {
+                let mut analyzed_parts = Vec::new();
+                for part in parts {
+                    let FormatPart::Expr { expr, spec } = part else {
+                        analyzed_parts.push(part);
+                        continue;
+                    };
+                    let analyzed = self.analyze_expr(*expr)?;
+                    self.check_interpolation(&analyzed, &spec)?;
+                    analyzed_parts.push(FormatPart::Expr { expr: Box::new(analyzed), spec });
+                }
+                Ok(Expr::FormatString { parts: analyzed_parts, span })
             },
-            Expr::Assign { name, value } => {
-                // Check if variable is declared
-                if !self.symbol_exists(&name) {
+        }
+    }
+    
+    /// Like `infer_type`, but lets an empty array literal take its type from
+    /// the context it's used in, since it has no elements to infer it from.
+    /// Whether `name` refers to a variable rather than a declared function or
+    /// namespace, so calling it means calling the function value it holds.
+    fn is_variable(&self, name: &str) -> bool {
+        matches!(self.get_symbol(name), Ok(Symbol::Variable { .. }))
+    }
+    
+    /// Analyzes a call whose callee is an expression producing a function
+    /// value, such as a parameter, a lambda or the result of another call.
+    fn analyze_function_value_call(&mut self, callee: Expr, arguments: Vec<Expr>, span: Span) -> Result<Expr, SemanticError> {
+        let analyzed_callee = self.analyze_expr(callee)?;
+        let mut analyzed_arguments = Vec::new();
+        for arg in arguments {
+            analyzed_arguments.push(self.analyze_expr(arg)?);
+        }
+        
+        match self.infer_type(&analyzed_callee)? {
+            Type::Function { params, .. } => {
+                if analyzed_arguments.len() != params.len() {
                     return Err(SemanticError {
-                        message: format!("Cannot assign to undeclared variable: {}", name),
+                        message: format!(
+                            "Function value expects {} arguments, but {} were provided",
+                            params.len(),
+                            analyzed_arguments.len()
+                        ),
+                        span: Some(span),
+                        module: None,
                     });
                 }
-                
-                let analyzed_value = Box::new(self.analyze_expr(*value)?);
-                
-                // Type checking for assignment
-                let var_symbol = self.get_symbol(&name)?;
-                if let Symbol::Variable { var_type } = var_symbol {
-                    let value_type = self.infer_type(&analyzed_value)?;
-                    if var_type != value_type {
+                for (i, (arg, param_type)) in analyzed_arguments.iter().zip(&params).enumerate() {
+                    let arg_type = self.infer_type_expecting(arg, Some(param_type))?;
+                    if arg_type != *param_type && arg_type != Type::Error && *param_type != Type::Error {
                         return Err(SemanticError {
-                            message: format!("Type mismatch in assignment: expected {:?}, got {:?}", var_type, value_type),
+                            message: format!(
+                                "Type mismatch in argument {} of function value: expected {}, got {}",
+                                i + 1,
+                                param_type,
+                                arg_type
+                            ),
+                            span: Some(arg.span()),
+                            module: None,
                         });
                     }
                 }
-                
-                Ok(Expr::Assign { name, value: analyzed_value })
+            }
+            Type::Error => {}
+            other => {
+                return Err(SemanticError {
+                    message: format!("Cannot call a value of type {}", other),
+                    span: Some(analyzed_callee.span()),
+                    module: None,
+                });
+            }
+        }
+        
+        Ok(Expr::Call {
+            callee: Box::new(analyzed_callee),
+            arguments: analyzed_arguments,
+            span,
+        })
+    }
+    
+    fn infer_type_expecting(&self, expr: &Expr, expected: Option<&Type>) -> Result<Type, SemanticError> {
+        match (expr, expected) {
+            (Expr::Array { elements, .. }, Some(expected @ Type::Array(_))) if elements.is_empty() => Ok(expected.clone()),
+            _ => self.infer_type(expr),
+        }
+    }
+    
+    /// Checks that `expr` can be written into a format string with `spec`.
+    /// Only ints, floats, bools and strings can be, since those are what
+    /// every backend knows how to turn into text.
+    fn check_interpolation(&self, expr: &Expr, spec: &FormatSpec) -> Result<(), SemanticError> {
+        let ty = self.infer_type(expr)?;
+        let error = |message: String| Err(SemanticError { message, span: Some(expr.span()), module: None });
+        match ty {
+            Type::Integer | Type::Float | Type::Boolean | Type::String | Type::Error => {}
+            other => return error(format!("Cannot interpolate a value of type {} into a format string", other)),
+        }
+        if spec.precision.is_some() && !matches!(ty, Type::Float | Type::Error) {
+            return error(format!("A precision in a format spec only applies to floats, not {}", ty));
+        }
+        let zero_padded_number = matches!(ty, Type::Integer | Type::Error)
+            || (ty == Type::Float && spec.precision.is_some());
+        if spec.zero_pad && !zero_padded_number {
+            return error(format!("Zero padding in a format spec only applies to ints and to floats with a precision, not {}", ty));
+        }
+        Ok(())
+    }
+    
+    /// Reports an error if `ty` names a struct or enum that hasn't been declared.
+    fn check_type_exists(&self, ty: &Type, span: Span) -> Result<(), SemanticError> {
+        match ty {
+            Type::Named(name) if !self.structs.contains_key(name) && !self.enums.contains_key(name) => Err(SemanticError {
+                message: format!("Unknown type: {}", name),
+                span: Some(span),
+                module: None,
+            }),
+            Type::Array(element_type) => self.check_type_exists(element_type, span),
+            _ => Ok(()),
+        }
+    }
+    
+    /// The type of `object.field`.
+    fn field_type(&self, object: &Expr, field: &str, span: Span) -> Result<Type, SemanticError> {
+        match self.infer_type(object)? {
+            Type::Named(name) if !self.enums.contains_key(&name) => {
+                let fields = self.structs.get(&name).ok_or_else(|| SemanticError {
+                    message: format!("Unknown type: {}", name),
+                    span: Some(span),
+                    module: None,
+                })?;
+                fields.iter()
+                    .find(|f| f.name == field)
+                    .map(|f| f.field_type.clone())
+                    .ok_or_else(|| SemanticError {
+                        message: format!("Struct '{}' has no field named '{}'", name, field),
+                        span: Some(span),
+                        module: None,
+                    })
+            }
+            Type::Error => Ok(Type::Error),
+            other => Err(SemanticError {
+                message: format!("Cannot access field '{}' on a value of type {}", field, other),
+                span: Some(span),
+                module: None,
+            }),
+        }
+    }
+    
+    /// Checks that a struct literal sets every declared field exactly once,
+    /// each with a value of the field's type.
+    fn check_struct_literal(&self, name: &str, fields: &[(String, Expr)], span: Span) -> Result<(), SemanticError> {
+        let declared = self.structs.get(name).ok_or_else(|| SemanticError {
+            message: format!("Unknown struct: {}", name),
+            span: Some(span),
+            module: None,
+        })?;
+        
+        let mut seen: Vec<&String> = Vec::new();
+        for (field_name, value) in fields {
+            if seen.contains(&field_name) {
+                return Err(SemanticError {
+                    message: format!("Field '{}' is specified more than once", field_name),
+                    span: Some(value.span()),
+                    module: None,
+                });
+            }
+            seen.push(field_name);
+            
+            let field = declared.iter().find(|f| f.name == *field_name).ok_or_else(|| SemanticError {
+                message: format!("Struct '{}' has no field named '{}'", name, field_name),
+                span: Some(value.span()),
+                module: None,
+            })?;
+            let value_type = self.infer_type_expecting(value, Some(&field.field_type))?;
+            if value_type != field.field_type && value_type != Type::Error {
+                return Err(SemanticError {
+                    message: format!(
+                        "Type mismatch in field '{}' of '{}': expected {}, got {}",
+                        field_name, name, field.field_type, value_type
+                    ),
+                    span: Some(value.span()),
+                    module: None,
+                });
+            }
+        }
+        
+        if let Some(missing) = declared.iter().find(|f| !seen.contains(&&f.name)) {
+            return Err(SemanticError {
+                message: format!("Missing field '{}' in initializer of '{}'", missing.name, name),
+                span: Some(span),
+                module: None,
+            });
+        }
+        Ok(())
+    }
+    
+    /// Checks that `Enum::Variant(arguments)` names a declared variant and
+    /// passes a value of the right type for each of its fields.
+    fn check_enum_variant(&self, enum_name: &str, variant: &str, arguments: &[Expr], span: Span) -> Result<(), SemanticError> {
+        let field_types = self.variant_fields(enum_name, variant, span)?;
+        if arguments.len() != field_types.len() {
+            return Err(SemanticError {
+                message: format!(
+                    "Variant '{}::{}' expects {} values, but {} were provided",
+                    enum_name, variant, field_types.len(), arguments.len()
+                ),
+                span: Some(span),
+                module: None,
+            });
+        }
+        
+        for (i, (argument, field_type)) in arguments.iter().zip(field_types).enumerate() {
+            let argument_type = self.infer_type_expecting(argument, Some(field_type))?;
+            if argument_type != *field_type && argument_type != Type::Error {
+                return Err(SemanticError {
+                    message: format!(
+                        "Type mismatch in argument {} of variant '{}::{}': expected {}, got {}",
+                        i + 1, enum_name, variant, field_type, argument_type
+                    ),
+                    span: Some(argument.span()),
+                    module: None,
+                });
+            }
+        }
+        Ok(())
+    }
+    
+    /// The payload types of `Enum::Variant`.
+    fn variant_fields(&self, enum_name: &str, variant: &str, span: Span) -> Result<&[Type], SemanticError> {
+        let variants = self.enums.get(enum_name).ok_or_else(|| SemanticError {
+            message: format!("Unknown enum: {}", enum_name),
+            span: Some(span),
+            module: None,
+        })?;
+        variants.iter()
+            .find(|v| v.name == variant)
+            .map(|v| v.fields.as_slice())
+            .ok_or_else(|| SemanticError {
+                message: format!("Enum '{}' has no variant named '{}'", enum_name, variant),
+                span: Some(span),
+                module: None,
+            })
+    }
+    
+    /// Analyzes the value a match inspects, poisoning its type if it fails.
+    fn analyze_scrutinee(&mut self, scrutinee: Expr) -> (Expr, Type) {
+        let span = scrutinee.span();
+        let result = self.analyze_expr(scrutinee)
+            .and_then(|scrutinee| self.infer_type(&scrutinee).map(|scrutinee_type| (scrutinee, scrutinee_type)));
+        result.unwrap_or_else(|e| {
+            self.report(e);
+            (Expr::Literal { value: Literal::Null, span }, Type::Error)
+        })
+    }
+    
+    /// Type checks `pattern` against a value of type `expected` and defines
+    /// the variables it binds in the current scope. If the pattern is
+    /// invalid, its variables are still defined, with poisoned types.
+    fn bind_pattern(&mut self, pattern: &Pattern, expected: &Type) -> Result<(), SemanticError> {
+        let mut bindings = Vec::new();
+        let result = self.pattern_bindings(pattern, expected, &mut bindings);
+        if result.is_err() {
+            bindings.clear();
+            collect_pattern_names(pattern, &mut bindings);
+        }
+        
+        for (name, var_type, span) in bindings {
+            if self.define_symbol(name.clone(), Symbol::Variable { var_type }).is_ok() {
+                self.record_definition(&name, DefinitionKind::Variable, span);
+                self.declare_local(&name, span);
+            }
+        }
+        result
+    }
+    
+    fn pattern_bindings(&self, pattern: &Pattern, expected: &Type, bindings: &mut Vec<(String, Type, Span)>) -> Result<(), SemanticError> {
+        match pattern {
+            Pattern::Wildcard { .. } => Ok(()),
+            Pattern::Binding { name, span } => {
+                if bindings.iter().any(|(bound, _, _)| bound == name) {
+                    return Err(SemanticError {
+                        message: format!("Variable '{}' is bound more than once in the same pattern", name),
+                        span: Some(*span),
+                        module: None,
+                    });
+                }
+                bindings.push((name.clone(), expected.clone(), *span));
+                Ok(())
+            }
+            Pattern::Literal { value, span } => {
+                let literal_type = self.infer_type(&Expr::Literal { value: value.clone(), span: *span })?;
+                if literal_type != *expected && *expected != Type::Error {
+                    return Err(SemanticError {
+                        message: format!("Pattern type mismatch: expected {}, got {}", expected, literal_type),
+                        span: Some(*span),
+                        module: None,
+                    });
+                }
+                Ok(())
+            }
+            Pattern::Variant { enum_name, variant, fields, span } => {
+                let field_types = self.variant_fields(enum_name, variant, *span)?;
+                let pattern_type = Type::Named(enum_name.clone());
+                if pattern_type != *expected && *expected != Type::Error {
+                    return Err(SemanticError {
+                        message: format!("Pattern type mismatch: expected {}, got {}", expected, pattern_type),
+                        span: Some(*span),
+                        module: None,
+                    });
+                }
+                if fields.len() != field_types.len() {
+                    return Err(SemanticError {
+                        message: format!(
+                            "Variant '{}::{}' has {} fields, but the pattern has {}",
+                            enum_name, variant, field_types.len(), fields.len()
+                        ),
+                        span: Some(*span),
+                        module: None,
+                    });
+                }
+                for (field, field_type) in fields.iter().zip(field_types) {
+                    self.pattern_bindings(field, field_type, bindings)?;
+                }
+                Ok(())
+            }
+        }
+    }
+    
+    /// Reports the values a match doesn't cover, and warns about arms that
+    /// can never be reached because earlier arms match everything they do.
+    fn check_match_coverage<B>(&mut self, scrutinee_type: &Type, arms: &[MatchArm<B>], span: Span) {
+        if *scrutinee_type == Type::Error {
+            return;
+        }
+        
+        let patterns: Vec<&Pattern> = arms.iter().map(|arm| &arm.pattern).collect();
+        let coverage = exhaustiveness::check_match(&self.enums, scrutinee_type, &patterns);
+        for index in coverage.unreachable_arms {
+            self.warn("Unreachable match arm".to_string(), arms[index].pattern.span(), None);
+        }
+        if coverage.missing.is_empty() {
+            return;
+        }
+        
+        let message = if coverage.missing.iter().any(|missing| missing == "_") {
+            format!("Non-exhaustive match: values of type {} are not all covered; add a `_` arm", scrutinee_type)
+        } else {
+            format!("Non-exhaustive match: missing {}", coverage.missing.join(", "))
+        };
+        self.report(SemanticError { message, span: Some(span), module: None });
+    }
+    
+    /// The type of `sequence[index]`.
+    fn element_type(&self, sequence: &Expr, index: &Expr) -> Result<Type, SemanticError> {
+        let element_type = match self.infer_type(sequence)? {
+            Type::Array(element_type) => *element_type,
+            Type::Error => Type::Error,
+            other => return Err(SemanticError {
+                message: format!("Cannot index into a value of type {}", other),
+                span: Some(sequence.span()),
+                module: None,
+            }),
+        };
+        
+        match self.infer_type(index)? {
+            Type::Integer | Type::Error => Ok(element_type),
+            other => Err(SemanticError {
+                message: format!("Array index must be an integer, got {}", other),
+                span: Some(index.span()),
+                module: None,
+            }),
+        }
+    }
+    
+    /// Checks calls to the built-ins that work on values of any type, such as
+    /// arrays of any element type, which can't be described by a fixed
+    /// `StdLib` signature. Returns `None` for calls the regular built-in
+    /// lookup should handle.
+    fn check_generic_builtin(&self, name: &str, arguments: &[Expr]) -> Option<Result<(), SemanticError>> {
+        match name {
+            "len" if arguments.len() == 1 => match self.infer_type(&arguments[0]) {
+                Ok(Type::Array(_)) => Some(Ok(())),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
             },
+            "push" => Some(self.check_push(arguments)),
+            "assert_eq" | "assert_ne" => Some(self.check_comparison_assertion(name, arguments)),
+            _ => None,
+        }
+    }
+    
+    /// `assert_eq` and `assert_ne` compare two values of the same type.
+    fn check_comparison_assertion(&self, name: &str, arguments: &[Expr]) -> Result<(), SemanticError> {
+        if arguments.len() != 2 {
+            return Err(SemanticError {
+                message: format!("Built-in function '{}' expects 2 arguments, but {} were provided", name, arguments.len()),
+                span: None,
+                module: None,
+            });
+        }
+        
+        let left_type = self.infer_type(&arguments[0])?;
+        let right_type = self.infer_type_expecting(&arguments[1], Some(&left_type))?;
+        if left_type != right_type && left_type != Type::Error && right_type != Type::Error {
+            return Err(SemanticError {
+                message: format!("Built-in function '{}' compares values of the same type, got {} and {}", name, left_type, right_type),
+                span: Some(arguments[1].span()),
+                module: None,
+            });
+        }
+        Ok(())
+    }
+    
+    fn check_push(&self, arguments: &[Expr]) -> Result<(), SemanticError> {
+        if arguments.len() != 2 {
+            return Err(SemanticError {
+                message: format!("Built-in function 'push' expects 2 arguments, but {} were provided", arguments.len()),
+                span: None,
+                module: None,
+            });
+        }
+        
+        let element_type = match self.infer_type(&arguments[0])? {
+            Type::Array(element_type) => *element_type,
+            Type::Error => return Ok(()),
+            other => return Err(SemanticError {
+                message: format!("Type mismatch in argument 1 of built-in function 'push': expected an array, got {}", other),
+                span: Some(arguments[0].span()),
+                module: None,
+            }),
+        };
+        
+        let value_type = self.infer_type_expecting(&arguments[1], Some(&element_type))?;
+        if value_type != element_type && value_type != Type::Error && element_type != Type::Error {
+            return Err(SemanticError {
+                message: format!("Type mismatch in argument 2 of built-in function 'push': expected {}, got {}", element_type, value_type),
+                span: Some(arguments[1].span()),
+                module: None,
+            });
         }
+        Ok(())
     }
     
     fn infer_type(&self, expr: &Expr) -> Result<Type, SemanticError> {
+        let span = expr.span();
         match expr {
-            Expr::Literal(literal) => {
+            Expr::Literal { value: literal, .. } => {
                 match literal {
                     Literal::Integer(_) => Ok(Type::Integer),
                     Literal::Float(_) => Ok(Type::Float),
@@ -758,20 +2045,27 @@ impl SemanticAnalyzer {
                     Literal::Null => Ok(Type::Void), // Null literals have void type
                 }
             },
-            Expr::Variable(name) => {
-                match self.get_symbol(name)? {
+            Expr::Variable { name, .. } => {
+                match self.get_symbol(name).map_err(|e| e.with_span(span))? {
                     Symbol::Variable { var_type } => Ok(var_type),
-                    Symbol::Function { .. } => Err(SemanticError {
-                        message: format!("Expected variable, found function: {}", name),
+                    // A named function used as a value
+                    Symbol::Function { return_type, parameters } => Ok(Type::Function {
+                        params: parameters.into_iter().map(|param| param.param_type).collect(),
+                        return_type: Box::new(return_type),
                     }),
                     Symbol::Namespace { .. } => Err(SemanticError {
                         message: format!("Cannot use namespace '{}' as a value", name),
+                        span: Some(span),
+                        module: None,
                     }),
                 }
             },
-            Expr::Binary { left, operator, right } => {
+            Expr::Binary { left, operator, right, .. } => {
                 let left_type = self.infer_type(left)?;
                 let right_type = self.infer_type(right)?;
+                if left_type == Type::Error || right_type == Type::Error {
+                    return Ok(Type::Error);
+                }
                 
                 match operator {
                     BinaryOperator::Plus => {
@@ -785,6 +2079,8 @@ impl SemanticAnalyzer {
                             } else {
                                 Err(SemanticError {
                                     message: format!("Cannot perform arithmetic on {:?} and {:?}", left_type, right_type),
+                                    span: Some(span),
+                                    module: None,
                                 })
                             }
                         } else if left_type == Type::Integer && right_type == Type::Integer {
@@ -792,6 +2088,8 @@ impl SemanticAnalyzer {
                         } else {
                             Err(SemanticError {
                                 message: format!("Cannot perform arithmetic on {:?} and {:?}", left_type, right_type),
+                                span: Some(span),
+                                module: None,
                             })
                         }
                     },
@@ -804,11 +2102,15 @@ impl SemanticAnalyzer {
                                 } else {
                                     Err(SemanticError {
                                         message: format!("Cannot perform arithmetic on {:?} and {:?}", left_type, right_type),
+                                        span: Some(span),
+                                        module: None,
                                     })
                                 }
                             } else {
                                 Err(SemanticError {
                                     message: format!("Cannot perform arithmetic on {:?} and {:?}", left_type, right_type),
+                                    span: Some(span),
+                                    module: None,
                                 })
                             }
                         } else if left_type == Type::Integer && right_type == Type::Integer {
@@ -816,6 +2118,8 @@ impl SemanticAnalyzer {
                         } else {
                             Err(SemanticError {
                                 message: format!("Cannot perform arithmetic on {:?} and {:?}", left_type, right_type),
+                                span: Some(span),
+                                module: None,
                             })
                         }
                     },
@@ -827,6 +2131,8 @@ impl SemanticAnalyzer {
                         } else {
                             Err(SemanticError {
                                 message: format!("Cannot perform division on {:?} and {:?}", left_type, right_type),
+                                span: Some(span),
+                                module: None,
                             })
                         }
                     },
@@ -837,6 +2143,8 @@ impl SemanticAnalyzer {
                         } else {
                             Err(SemanticError {
                                 message: "Modulo operator requires integer operands".to_string(),
+                                span: Some(span),
+                                module: None,
                             })
                         }
                     },
@@ -849,6 +2157,8 @@ impl SemanticAnalyzer {
                         } else {
                             Err(SemanticError {
                                 message: format!("Cannot compare {:?} and {:?}", left_type, right_type),
+                                span: Some(span),
+                                module: None,
                             })
                         }
                     },
@@ -861,6 +2171,8 @@ impl SemanticAnalyzer {
                         } else {
                             Err(SemanticError {
                                 message: format!("Cannot compare {:?} and {:?}", left_type, right_type),
+                                span: Some(span),
+                                module: None,
                             })
                         }
                     },
@@ -871,13 +2183,18 @@ impl SemanticAnalyzer {
                         } else {
                             Err(SemanticError {
                                 message: "Logical operators require boolean operands".to_string(),
+                                span: Some(span),
+                                module: None,
                             })
                         }
                     },
                 }
             },
-            Expr::Unary { operator, operand } => {
+            Expr::Unary { operator, operand, .. } => {
                 let operand_type = self.infer_type(operand)?;
+                if operand_type == Type::Error {
+                    return Ok(Type::Error);
+                }
                 match operator {
                     crate::ast::UnaryOperator::Not => {
                         if operand_type == Type::Boolean {
@@ -885,6 +2202,8 @@ impl SemanticAnalyzer {
                         } else {
                             Err(SemanticError {
                                 message: "Logical NOT requires boolean operand".to_string(),
+                                span: Some(span),
+                                module: None,
                             })
                         }
                     },
@@ -894,6 +2213,8 @@ impl SemanticAnalyzer {
                         } else {
                             Err(SemanticError {
                                 message: "Negation requires numeric operand".to_string(),
+                                span: Some(span),
+                                module: None,
                             })
                         }
                     },
@@ -902,7 +2223,7 @@ impl SemanticAnalyzer {
             Expr::Call { callee, .. } => {
                 // For function calls, we need to look up the return type in the symbol table
                 match callee.as_ref() {
-                    Expr::Variable(func_name) => {
+                    Expr::Variable { name: func_name, .. } if !self.is_variable(func_name) => {
                         // Check built-in functions first
                         if self.std_lib.is_builtin_function(func_name) {
                             // For type inference, we need to analyze the arguments first to get their types
@@ -912,6 +2233,8 @@ impl SemanticAnalyzer {
                             } else {
                                 Err(SemanticError {
                                     message: format!("Built-in function '{}' not found", func_name),
+                                    span: Some(span),
+                                    module: None,
                                 })
                             }
                         } else {
@@ -920,65 +2243,139 @@ impl SemanticAnalyzer {
                                 Ok(Symbol::Function { return_type, .. }) => Ok(return_type),
                                 Ok(Symbol::Variable { .. }) => Err(SemanticError {
                                     message: format!("Expected function, found variable: {}", func_name),
+                                    span: Some(span),
+                                    module: None,
                                 }),
                                 Ok(Symbol::Namespace { .. }) => Err(SemanticError {
                                     message: format!("Expected function, found namespace: {}", func_name),
+                                    span: Some(span),
+                                    module: None,
                                 }),
                                 Err(_) => Err(SemanticError {
                                     message: format!("Undefined function: {}", func_name),
+                                    span: Some(span),
+                                    module: None,
                                 }),
                             }
                         }
                     },
-                    Expr::Get { object, name } => {
+                    Expr::Get { object, name, .. }
+                        if let Expr::Variable { name: namespace_name, .. } = object.as_ref()
+                            && !self.is_variable(namespace_name) => {
                         // Handle namespace function calls (e.g., math.add())
-                        if let Expr::Variable(namespace_name) = object.as_ref() {
-                            let qualified_name = format!("{}.{}", namespace_name, name);
-                            match self.get_symbol(&qualified_name) {
-                                Ok(Symbol::Function { return_type, .. }) => Ok(return_type),
-                                Ok(_) => Err(SemanticError {
-                                    message: format!("'{}' is not a function", qualified_name),
-                                }),
-                                Err(_) => Err(SemanticError {
-                                    message: format!("Undefined function: {}", qualified_name),
-                                }),
-                            }
-                        } else {
-                            Err(SemanticError {
-                                message: "Complex function call expressions not yet supported".to_string(),
-                            })
+                        let qualified_name = format!("{}.{}", namespace_name, name);
+                        match self.get_symbol(&qualified_name) {
+                            Ok(Symbol::Function { return_type, .. }) => Ok(return_type),
+                            Ok(Symbol::Variable { var_type: Type::Error }) => Ok(Type::Error),
+                            Ok(_) => Err(SemanticError {
+                                message: format!("'{}' is not a function", qualified_name),
+                                span: Some(span),
+                                module: None,
+                            }),
+                            Err(_) => Err(SemanticError {
+                                message: format!("Undefined function: {}", qualified_name),
+                                span: Some(span),
+                                module: None,
+                            }),
                         }
                     },
-                    _ => Err(SemanticError {
-                        message: "Complex function call expressions not yet supported".to_string(),
-                    }),
+                    // Calling a function value
+                    _ => match self.infer_type(callee)? {
+                        Type::Function { return_type, .. } => Ok(*return_type),
+                        Type::Error => Ok(Type::Error),
+                        other => Err(SemanticError {
+                            message: format!("Cannot call a value of type {}", other),
+                            span: Some(callee.span()),
+                            module: None,
+                        }),
+                    },
+                }
+            },
+            Expr::FormatString { .. } => Ok(Type::String),
+            Expr::Array { elements, .. } => {
+                let Some((first, rest)) = elements.split_first() else {
+                    return Err(SemanticError {
+                        message: "Cannot infer the type of an empty array; add a type annotation such as `store xs: array[int] = [];`".to_string(),
+                        span: Some(span),
+                        module: None,
+                    });
+                };
+                
+                let element_type = self.infer_type(first)?;
+                for element in rest {
+                    let other = self.infer_type(element)?;
+                    if other != element_type && other != Type::Error && element_type != Type::Error {
+                        return Err(SemanticError {
+                            message: format!("Array elements must all have the same type: expected {}, got {}", element_type, other),
+                            span: Some(element.span()),
+                            module: None,
+                        });
+                    }
                 }
+                Ok(Type::Array(Box::new(element_type)))
             },
-            Expr::Get { object, name } => {
+            Expr::Index { sequence, index, .. } | Expr::SetIndex { sequence, index, .. } => {
+                self.element_type(sequence, index)
+            },
+            Expr::Get { object, name, .. } => {
                 // Handle namespace variable access (e.g., math.PI)
-                if let Expr::Variable(namespace_name) = object.as_ref() {
+                if let Expr::Variable { name: namespace_name, .. } = object.as_ref()
+                    && let Ok(Symbol::Namespace { .. }) = self.get_symbol(namespace_name) {
                     let qualified_name = format!("{}.{}", namespace_name, name);
                     match self.get_symbol(&qualified_name) {
                         Ok(Symbol::Variable { var_type }) => Ok(var_type),
                         Ok(Symbol::Function { .. }) => Err(SemanticError {
                             message: format!("'{}' is a function, not a variable", qualified_name),
+                            span: Some(span),
+                            module: None,
                         }),
                         Ok(Symbol::Namespace { .. }) => Err(SemanticError {
                             message: format!("'{}' is a namespace, not a variable", qualified_name),
+                            span: Some(span),
+                            module: None,
                         }),
                         Err(_) => Err(SemanticError {
                             message: format!("Symbol '{}' not found in namespace '{}'", name, namespace_name),
+                            span: Some(span),
+                            module: None,
                         }),
                     }
+                } else {
+                    self.field_type(object, name, span)
+                }
+            },
+            Expr::Set { object, name, .. } => self.field_type(object, name, span),
+            Expr::StructLiteral { name, .. } => {
+                if self.structs.contains_key(name) {
+                    Ok(Type::Named(name.clone()))
                 } else {
                     Err(SemanticError {
-                        message: "Complex field access expressions not yet supported".to_string(),
+                        message: format!("Unknown struct: {}", name),
+                        span: Some(span),
+                        module: None,
                     })
                 }
             },
+            Expr::EnumVariant { enum_name, variant, .. } => {
+                self.variant_fields(enum_name, variant, span)?;
+                Ok(Type::Named(enum_name.clone()))
+            },
+            Expr::Match { .. } => {
+                Ok(self.match_types.get(&span).cloned().unwrap_or(Type::Error))
+            },
+            Expr::Function { parameters, return_type, .. } => {
+                Ok(Type::Function {
+                    params: parameters.iter().map(|param| param.param_type.clone()).collect(),
+                    return_type: Box::new(return_type.clone().unwrap_or(Type::Void)),
+                })
+            },
+            // An assignment has the value it stores, of the variable's type
+            Expr::Assign { name, .. } => self.infer_type(&Expr::Variable { name: name.clone(), span }),
             _ => {
                 Err(SemanticError {
                     message: "Type inference not implemented for this expression type".to_string(),
+                    span: Some(span),
+                    module: None,
                 })
             },
         }
@@ -989,6 +2386,8 @@ impl SemanticAnalyzer {
         if self.std_lib.is_builtin_function(&name) {
             return Err(SemanticError {
                 message: format!("Cannot redefine built-in function '{}'", name),
+                span: None,
+                module: None,
             });
         }
         
@@ -996,6 +2395,8 @@ impl SemanticAnalyzer {
         if self.scopes.last().unwrap().contains_key(&name) {
             return Err(SemanticError {
                 message: format!("Symbol '{}' already defined in current scope", name),
+                span: None,
+                module: None,
             });
         }
         
@@ -1022,29 +2423,156 @@ impl SemanticAnalyzer {
         
         Err(SemanticError {
             message: format!("Symbol '{}' not found", name),
+            span: None,
+            module: None,
         })
     }
     
     fn begin_scope(&mut self) {
         self.scopes.push(HashMap::new());
+        self.locals.push(Vec::new());
+        self.declared.push(HashMap::new());
+        let end = self.scope_ends.last().copied().unwrap_or(usize::MAX);
+        self.scope_ends.push(end);
+    }
+    
+    /// Enters a scope whose names can be used until the end of `span`.
+    fn begin_scope_within(&mut self, span: Span) {
+        self.begin_scope();
+        if let Some(end) = self.scope_ends.last_mut() {
+            *end = span.end;
+        }
     }
     
     fn end_scope(&mut self) {
         self.scopes.pop();
+        self.declared.pop();
+        self.scope_ends.pop();
+        for local in self.locals.pop().unwrap_or_default() {
+            if !local.used && !local.name.starts_with('_') {
+                self.warn(
+                    format!("Unused variable: {}", local.name),
+                    local.span,
+                    Some(format!("if this is intentional, prefix it with an underscore: `_{}`", local.name)),
+                );
+            }
+        }
     }
     
-    fn resolve_module_path(&self, module_name: &str) -> PathBuf {
-        // Convert module name to file path
-        // e.g., "math.utils" -> "math/utils.nlang"
-        let path_parts: Vec<&str> = module_name.split('.').collect();
-        let mut path = self.current_dir.clone();
-        
-        for part in &path_parts[..path_parts.len() - 1] {
-            path.push(part);
+    /// Tracks a variable declared inside a function so it can be reported if
+    /// it's never read. Globals may be used by importers, so they're skipped.
+    fn declare_local(&mut self, name: &str, span: Span) {
+        if self.scopes.len() > 1 && let Some(locals) = self.locals.last_mut() {
+            locals.push(LocalVariable { name: name.to_string(), span, used: false });
         }
-        
-        path.push(format!("{}.nlang", path_parts.last().unwrap()));
-        path
+    }
+    
+    fn mark_used(&mut self, name: &str) {
+        // The innermost scope that defines the name is the one being read
+        for (scope, locals) in self.scopes.iter().zip(self.locals.iter_mut()).rev() {
+            if scope.contains_key(name) {
+                if let Some(local) = locals.iter_mut().rev().find(|local| local.name == name) {
+                    local.used = true;
+                }
+                return;
+            }
+        }
+    }
+    
+    /// Gives the parameters written without a type the type inferred for them.
+    fn resolve_parameters(&self, mut parameters: Vec<Parameter>) -> Vec<Parameter> {
+        for param in &mut parameters {
+            if param.param_type == Type::Unknown {
+                param.param_type = self.parameter_types.get(&param.span).cloned().unwrap_or(Type::Error);
+            }
+        }
+        parameters
+    }
+    
+    fn define_parameters(&mut self, parameters: &[Parameter]) {
+        for param in parameters {
+            match self.define_symbol(param.name.clone(), Symbol::Variable { var_type: param.param_type.clone() }) {
+                Ok(()) => self.record_definition(&param.name, DefinitionKind::Parameter, param.span),
+                Err(e) => self.report(e.with_span(param.span)),
+            }
+        }
+    }
+    
+    /// Adds the name just defined in the current scope to the index.
+    /// Top-level functions can be called from anywhere in the file; other
+    /// names only after their declaration.
+    fn record_definition(&mut self, name: &str, kind: DefinitionKind, span: Span) {
+        let start = if kind == DefinitionKind::Function && self.scopes.len() == 1 { 0 } else { span.start };
+        self.push_definition(name, kind, span, None, start);
+    }
+    
+    /// Adds a name an import just defined to the index, pointing at its
+    /// declaration as `export_name` in the module, or at the module itself.
+    fn record_import(&mut self, name: &str, module: &ModuleInfo, export_name: Option<&String>, import_span: Span) {
+        let (kind, span) = match export_name {
+            Some(export_name) => {
+                let kind = match module.exported_symbols.get(export_name) {
+                    Some(Symbol::Function { .. }) => DefinitionKind::Function,
+                    _ => DefinitionKind::Variable,
+                };
+                (kind, module.export_spans.get(export_name).copied().unwrap_or_default())
+            }
+            None => (DefinitionKind::Module, Span::default()),
+        };
+        self.push_definition(name, kind, span, Some(module.path.clone()), import_span.start);
+        if let Some(export_name) = export_name {
+            self.document_definition(name, module.export_docs.get(export_name).cloned());
+        }
+    }
+    
+    fn push_definition(&mut self, name: &str, kind: DefinitionKind, span: Span, module: Option<PathBuf>, start: usize) {
+        let ty = self.symbol_type(name);
+        let end = self.scope_ends.last().copied().unwrap_or(usize::MAX);
+        let id = self.index.define(Definition { name: name.to_string(), kind, ty, span, module, doc: None, visible: start..end });
+        if let Some(declared) = self.declared.last_mut() {
+            declared.insert(name.to_string(), id);
+        }
+    }
+    
+    /// Attaches a doc comment to the name just defined in the current scope.
+    fn document_definition(&mut self, name: &str, doc: Option<String>) {
+        if let Some(&id) = self.declared.last().and_then(|declared| declared.get(name)) {
+            self.index.definitions[id].doc = doc;
+        }
+    }
+    
+    /// Refreshes the indexed type of a function whose return type was inferred.
+    fn update_definition_type(&mut self, name: &str) {
+        if let Some(&id) = self.declared.last().and_then(|declared| declared.get(name)) {
+            self.index.definitions[id].ty = self.symbol_type(name);
+        }
+    }
+    
+    fn symbol_type(&self, name: &str) -> Type {
+        self.infer_type(&Expr::Variable { name: name.to_string(), span: Span::default() }).unwrap_or(Type::Void)
+    }
+    
+    /// Adds a use of `name` to the index, linked to the definition it
+    /// resolves to.
+    fn record_reference(&mut self, name: &str, span: Span) {
+        let definition = self.scopes.iter().zip(&self.declared).rev()
+            .find(|(scope, _)| scope.contains_key(name))
+            .and_then(|(_, declared)| declared.get(name).copied());
+        let ty = if self.symbol_exists(name) {
+            self.symbol_type(name)
+        } else if let Some(builtin) = self.std_lib.get_builtin_function(name) {
+            Type::Function {
+                params: builtin.parameters.clone(),
+                return_type: Box::new(builtin.return_type.clone()),
+            }
+        } else {
+            return;
+        };
+        self.index.refer(Reference { name: name.to_string(), span, ty, definition });
+    }
+    
+    fn resolve_module_path(&self, module_name: &str) -> PathBuf {
+        resolve_module_path(&self.current_dir, module_name)
     }
     
     fn load_module(&mut self, module_path: &Path) -> Result<ModuleInfo, SemanticError> {
@@ -1057,32 +2585,47 @@ impl SemanticAnalyzer {
         let source = fs::read_to_string(module_path)
             .map_err(|e| SemanticError {
                 message: format!("Failed to read module file '{}': {}", module_path.display(), e),
+                span: None,
+                module: None,
             })?;
         
+        // Locations inside the module refer to its own source, so its errors
+        // travel with it and the caller points at the import
+        let broken = |errors: ModuleErrors| {
+            let count = errors.count();
+            SemanticError {
+                message: format!(
+                    "Module '{}' has {} error{}",
+                    module_path.display(), count, if count == 1 { "" } else { "s" }
+                ),
+                span: None,
+                module: Some(Box::new(BrokenModule { path: module_path.to_path_buf(), source: source.clone(), errors })),
+            }
+        };
+        
         // Parse the module
         let mut lexer = Lexer::new(&source);
-        let tokens = lexer.tokenize()
-            .map_err(|e| SemanticError {
-                message: format!("Lexer error in module '{}': {}", module_path.display(), e),
-            })?;
-        
-        let mut parser = Parser::new(&tokens);
-        let program = parser.parse_program()
-            .map_err(|e| SemanticError {
-                message: format!("Parser error in module '{}': {}", module_path.display(), e),
-            })?;
+        let tokens = lexer.tokenize().map_err(|e| broken(ModuleErrors::Lexer(e)))?;
+        let program = parse_strict(&tokens).map_err(|e| broken(ModuleErrors::Parser(e)))?;
         
-        // Analyze the module to extract exported symbols
-        let mut module_analyzer = SemanticAnalyzer::new();
-        let analyzed_program = module_analyzer.analyze_program(Program { statements: program }, false)?; // false indicates this is not the main program
+        // Analyze the module to extract exported symbols, resolving its own
+        // imports relative to it
+        let mut module_analyzer = SemanticAnalyzer::new_with_file_path(Some(module_path));
+        let analyzed_program = module_analyzer.analyze_program(program, false); // false indicates this is not the main program
+        if !module_analyzer.errors.is_empty() {
+            let mut errors = module_analyzer.errors;
+            errors.sort_by_key(|e| e.span.map_or((usize::MAX, usize::MAX), |s| (s.line, s.column)));
+            return Err(broken(ModuleErrors::Semantic(errors)));
+        }
         
         // Extract exported symbols
-        let mut exported_symbols = HashMap::new();
-        self.extract_exported_symbols(&analyzed_program.statements, &mut exported_symbols)?;
-        
-        let module_info = ModuleInfo {
-            exported_symbols,
+        let mut module_info = ModuleInfo {
+            path: module_path.to_path_buf(),
+            exported_symbols: HashMap::new(),
+            export_spans: HashMap::new(),
+            export_docs: HashMap::new(),
         };
+        module_analyzer.extract_exported_symbols(&analyzed_program.statements, &mut module_info)?;
         
         // Cache the module
         self.module_cache.insert(module_path.to_path_buf(), module_info.clone());
@@ -1090,31 +2633,35 @@ impl SemanticAnalyzer {
         Ok(module_info)
     }
     
-    fn extract_exported_symbols(&self, statements: &[Statement], symbols: &mut HashMap<String, Symbol>) -> Result<(), SemanticError> {
+    fn extract_exported_symbols(&self, statements: &[Statement], module: &mut ModuleInfo) -> Result<(), SemanticError> {
         for stmt in statements {
             match stmt {
-                Statement::FunctionDeclaration { name, parameters, return_type, is_exported, .. } => {
-                    if *is_exported {
-                        let func_return_type = return_type.clone().unwrap_or(Type::Void);
-                        symbols.insert(name.clone(), Symbol::Function {
-                            return_type: func_return_type,
-                            parameters: parameters.clone(),
-                        });
+                Statement::FunctionDeclaration { name, parameters, return_type, is_exported: true, doc, span, .. } => {
+                    let func_return_type = return_type.clone().unwrap_or(Type::Void);
+                    module.exported_symbols.insert(name.clone(), Symbol::Function {
+                        return_type: func_return_type,
+                        parameters: parameters.clone(),
+                    });
+                    module.export_spans.insert(name.clone(), *span);
+                    if let Some(doc) = doc {
+                        module.export_docs.insert(name.clone(), doc.clone());
                     }
                 },
-                Statement::LetDeclaration { name, initializer, is_exported } => {
-                    if *is_exported {
-                        // Infer type from initializer if available
-                        let var_type = if let Some(init_expr) = initializer {
+                Statement::LetDeclaration { name, initializer, is_exported: true, doc, span, .. } => {
+                    // Infer type from initializer if available
+                    let var_type = if let Some(init_expr) = initializer {
                         self.infer_type(init_expr)?
-                        } else {
-                            Type::Integer // Default type for uninitialized variables
-                        };
-                        symbols.insert(name.clone(), Symbol::Variable { var_type });
+                    } else {
+                        Type::Integer // Default type for uninitialized variables
+                    };
+                    module.exported_symbols.insert(name.clone(), Symbol::Variable { var_type });
+                    module.export_spans.insert(name.clone(), *span);
+                    if let Some(doc) = doc {
+                        module.export_docs.insert(name.clone(), doc.clone());
                     }
                 },
-                Statement::Block { statements } => {
-                    self.extract_exported_symbols(statements, symbols)?;
+                Statement::Block { statements, .. } => {
+                    self.extract_exported_symbols(statements, module)?;
                 },
                 _ => {}
             }
@@ -1130,6 +2677,8 @@ impl SemanticAnalyzer {
                 if !parameters.is_empty() {
                     return Err(SemanticError {
                         message: "Main function should not have parameters".to_string(),
+                        span: None,
+                        module: None,
                     });
                 }
                 
@@ -1137,6 +2686,8 @@ impl SemanticAnalyzer {
                 if return_type != Type::Void {
                     return Err(SemanticError {
                         message: "Main function should return void or have no return type".to_string(),
+                        span: None,
+                        module: None,
                     });
                 }
                 
@@ -1144,10 +2695,75 @@ impl SemanticAnalyzer {
             },
             Ok(_) => Err(SemanticError {
                 message: "Symbol 'main' exists but is not a function".to_string(),
+                span: None,
+                module: None,
             }),
             Err(_) => Err(SemanticError {
                 message: "No main function found. Programs must have a main function as entry point".to_string(),
+                span: None,
+                module: None,
             }),
         }
     }
-}
\ No newline at end of file
+}
+
+/// The file an `import` of `module_name` refers to, relative to the
+/// directory of the importing file.
+/// The names a module that failed analysis exports; none if it doesn't
+/// lex and parse.
+fn exported_names(module: &BrokenModule) -> Vec<String> {
+    fn collect(statements: &[Statement], names: &mut Vec<String>) {
+        for statement in statements {
+            match statement {
+                Statement::FunctionDeclaration { name, is_exported: true, .. }
+                | Statement::LetDeclaration { name, is_exported: true, .. } => names.push(name.clone()),
+                Statement::Block { statements, .. } => collect(statements, names),
+                _ => {}
+            }
+        }
+    }
+    
+    let mut names = Vec::new();
+    if let ModuleErrors::Semantic(_) = module.errors
+        && let Ok(tokens) = Lexer::new(&module.source).tokenize()
+        && let Ok(program) = parse_strict(&tokens) {
+        collect(&program.statements, &mut names);
+    }
+    names
+}
+
+/// The directory the imports of the file at `file_path` are resolved in:
+/// the file's own, or the working directory for code that isn't in a file.
+pub fn module_dir(file_path: Option<&Path>) -> PathBuf {
+    match file_path {
+        Some(path) => path.parent().unwrap_or_else(|| Path::new(".")).to_path_buf(),
+        None => std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+    }
+}
+
+pub fn resolve_module_path(current_dir: &Path, module_name: &str) -> PathBuf {
+    // Convert module name to file path
+    // e.g., "math.utils" -> "math/utils.nlang"
+    let path_parts: Vec<&str> = module_name.split('.').collect();
+    let mut path = current_dir.to_path_buf();
+    
+    for part in &path_parts[..path_parts.len() - 1] {
+        path.push(part);
+    }
+    
+    path.push(format!("{}.nlang", path_parts.last().unwrap()));
+    path
+}
+
+/// Every variable `pattern` binds, typed as poisoned.
+fn collect_pattern_names(pattern: &Pattern, names: &mut Vec<(String, Type, Span)>) {
+    match pattern {
+        Pattern::Binding { name, span } => names.push((name.clone(), Type::Error, *span)),
+        Pattern::Variant { fields, .. } => {
+            for field in fields {
+                collect_pattern_names(field, names);
+            }
+        }
+        Pattern::Wildcard { .. } | Pattern::Literal { .. } => {}
+    }
+}
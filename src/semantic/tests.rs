@@ -1,15 +1,16 @@
 #[cfg(test)]
-mod tests {
-    use crate::semantic::analyze;
+mod semantic_tests {
+    use crate::ast::{Expr, Statement, Type};
+    use crate::semantic::{analyze, analyze_with_file_path, check, DefinitionKind, ModuleErrors};
 
     use crate::lexer::tokenize;
-    use crate::parser::parse;
+    use crate::parser::parse_strict;
     
     #[test]
     fn test_semantic_analysis_basic() {
         let source = "def main() { store x = 42; }";
         let tokens = tokenize(source).unwrap();
-        let program = parse(&tokens).unwrap();
+        let program = parse_strict(&tokens).unwrap();
         let analyzed_program = analyze(program);
         
         match &analyzed_program {
@@ -24,7 +25,7 @@ mod tests {
     fn test_undefined_variable() {
         let source = "x;";
         let tokens = tokenize(source).unwrap();
-        let program = parse(&tokens).unwrap();
+        let program = parse_strict(&tokens).unwrap();
         
         // This should fail semantic analysis because x is not defined
         let result = analyze(program);
@@ -35,7 +36,7 @@ mod tests {
     fn test_variable_assignment() {
         let source = "def main() { store x = 42; x = 10; }";
         let tokens = tokenize(source).unwrap();
-        let program = parse(&tokens).unwrap();
+        let program = parse_strict(&tokens).unwrap();
         let analyzed_program = analyze(program);
         
         assert!(analyzed_program.is_ok());
@@ -43,9 +44,9 @@ mod tests {
     
     #[test]
     fn test_function_declaration() {
-        let source = "def add(x, y) { return x + y; } def main() { }";
+        let source = "def add(x, y) { return x + y; } def main() { print(add(1, 2)); }";
         let tokens = tokenize(source).unwrap();
-        let program = parse(&tokens).unwrap();
+        let program = parse_strict(&tokens).unwrap();
         let analyzed_program = analyze(program);
         
         match &analyzed_program {
@@ -55,4 +56,505 @@ mod tests {
         
         assert!(analyzed_program.is_ok());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_error_span() {
+        let source = "def main() {\n    store x = 1;\n    print(y);\n}";
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        
+        let err = analyze(program).unwrap_err().0.remove(0);
+        let span = err.span.expect("undefined variable should carry a location");
+        assert_eq!((span.line, span.column), (3, 11));
+        assert!(err.to_string().contains("line 3, column 11"));
+    }
+    
+    #[test]
+    fn test_collects_every_error() {
+        let source = "\
+def main() {
+    store a = missing;
+    store b = a + 1;
+    store c = 1 + true;
+    if (c) {
+        print(other);
+    }
+    print(b);
+}";
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        let analysis = check(program, None);
+        
+        let lines: Vec<usize> = analysis.errors.iter().map(|e| e.span.unwrap().line).collect();
+        // `a` is poisoned by its bad initializer, so `a + 1` isn't reported,
+        // and neither is the poisoned `c` used as a condition
+        assert_eq!(lines, vec![2, 4, 6]);
+        assert!(analysis.errors[0].message.contains("missing"));
+        assert!(analysis.errors[2].message.contains("other"));
+    }
+    
+    #[test]
+    fn test_warnings_are_separate() {
+        let source = "\
+def main() {
+    store unused = 1;
+    store _ignored = 2;
+    store used = 3;
+    print(used);
+    return;
+    print(used);
+}";
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        let analysis = check(program, None);
+        
+        assert!(analysis.errors.is_empty());
+        let warnings: Vec<(usize, &str)> = analysis.warnings.iter()
+            .map(|w| (w.span.line, w.message.as_str()))
+            .collect();
+        assert_eq!(warnings, vec![(2, "Unused variable: unused"), (7, "Unreachable statement")]);
+    }
+    
+    #[test]
+    fn test_for_loop_variable() {
+        let source = "\
+def main() {
+    for (i in 0..3) {
+        if (i) {}
+    }
+    for (j in 0..\"3\") {}
+    print(i);
+}";
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        let analysis = check(program, None);
+        
+        // `i` is an int and goes out of scope after its loop
+        let errors: Vec<usize> = analysis.errors.iter().map(|e| e.span.unwrap().line).collect();
+        assert_eq!(errors, vec![3, 5, 6]);
+        assert!(analysis.errors[0].message.contains("boolean"));
+        assert!(analysis.errors[1].message.contains("Range bounds must be integers"));
+    }
+    
+    #[test]
+    fn test_array_types() {
+        let source = "\
+def first(xs: array[int]): int {
+    return xs[0];
+}
+
+def main() {
+    store xs = [1, 2, 3];
+    store empty: array[string] = [];
+    push(empty, \"ok\");
+    print(first(xs) + len(empty));
+    store mixed = [1, \"two\"];
+    store unknown = [];
+    push(xs, \"four\");
+    xs[\"0\"] = 1;
+}";
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        let analysis = check(program, None);
+        
+        let errors: Vec<(usize, &str)> = analysis.errors.iter()
+            .map(|e| (e.span.unwrap().line, e.message.as_str()))
+            .collect();
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors[0].0, 10);
+        assert!(errors[0].1.starts_with("Array elements must all have the same type"));
+        assert_eq!(errors[1].0, 11);
+        assert!(errors[1].1.starts_with("Cannot infer the type of an empty array"));
+        assert_eq!(errors[2], (12, "Type mismatch in argument 2 of built-in function 'push': expected int, got string"));
+        assert_eq!(errors[3], (13, "Array index must be an integer, got string"));
+    }
+    
+    #[test]
+    fn test_struct_types() {
+        let source = "\
+struct Point {
+    x: int,
+    y: int,
+}
+
+def main() {
+    store p = Point { x: 1, y: 2 };
+    p.x = p.y + 1;
+    store q = Point { x: 1 };
+    store r = Point { x: 1, y: \"2\" };
+    print(p.z);
+    p.y = \"three\";
+    store s: Shape = p;
+}";
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        let analysis = check(program, None);
+        
+        let errors: Vec<(usize, &str)> = analysis.errors.iter()
+            .map(|e| (e.span.unwrap().line, e.message.as_str()))
+            .collect();
+        assert_eq!(errors, vec![
+            (9, "Missing field 'y' in initializer of 'Point'"),
+            (10, "Type mismatch in field 'y' of 'Point': expected int, got string"),
+            (11, "Struct 'Point' has no field named 'z'"),
+            (12, "Type mismatch in assignment to field 'y': expected int, got string"),
+            (13, "Unknown type: Shape"),
+        ]);
+    }
+    
+    #[test]
+    fn test_enum_types() {
+        let source = "\
+enum Shape {
+    Circle(float),
+    Rect(float, float),
+}
+
+def main() {
+    store s = Shape::Circle(1.0);
+    store area: float = match (s) { Shape::Circle(r) => r * r, Shape::Rect(w, h) => w * h };
+    store t = Shape::Rect(1.0);
+    store u = Shape::Square(2.0);
+    match (s) { Shape::Circle(1) => print(1), _ => print(2), }
+    store v = match (s) { Shape::Circle(_) => 1, _ => \"two\" };
+    print(s.radius);
+}";
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        let analysis = check(program, None);
+        
+        let errors: Vec<(usize, &str)> = analysis.errors.iter()
+            .map(|e| (e.span.unwrap().line, e.message.as_str()))
+            .collect();
+        assert_eq!(errors, vec![
+            (9, "Variant 'Shape::Rect' expects 2 values, but 1 were provided"),
+            (10, "Enum 'Shape' has no variant named 'Square'"),
+            (11, "Pattern type mismatch: expected float, got int"),
+            (12, "Match arms have incompatible types: expected int, got string"),
+            (13, "Cannot access field 'radius' on a value of type Shape"),
+        ]);
+    }
+    
+    #[test]
+    fn test_match_exhaustiveness() {
+        let source = "\
+enum Shape {
+    Circle(float),
+    Rect(float, float),
+    Empty,
+}
+
+enum Opt {
+    Some(Shape),
+    None,
+}
+
+def main() {
+    store s = Shape::Empty;
+    match (s) { Shape::Circle(_) => print(1), }
+    match (Opt::Some(s)) { Opt::Some(Shape::Empty) => print(1), Opt::None => print(2), }
+    match (3) { 1 => print(1), }
+    match (true) { true => print(1), false => print(2), }
+    match (s) { _ => print(1), Shape::Empty => print(2), }
+    match (Opt::None) { Opt::Some(x) => print(1), Opt::Some(Shape::Empty) => print(2), Opt::None => print(3), }
+}";
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        let analysis = check(program, None);
+        
+        let errors: Vec<(usize, &str)> = analysis.errors.iter()
+            .map(|e| (e.span.unwrap().line, e.message.as_str()))
+            .collect();
+        assert_eq!(errors, vec![
+            (14, "Non-exhaustive match: missing Shape::Rect(_, _), Shape::Empty"),
+            (15, "Non-exhaustive match: missing Opt::Some(_)"),
+            (16, "Non-exhaustive match: values of type int are not all covered; add a `_` arm"),
+        ]);
+        let warnings: Vec<(usize, &str)> = analysis.warnings.iter()
+            .map(|w| (w.span.line, w.message.as_str()))
+            .collect();
+        assert_eq!(warnings, vec![
+            (18, "Unreachable match arm"),
+            (19, "Unused variable: x"),
+            (19, "Unreachable match arm"),
+        ]);
+    }
+    
+    #[test]
+    fn test_function_values() {
+        let source = "\
+def apply(f: def(int): int, x: int): int {
+    return f(x);
+}
+
+def make_adder(n: int): def(int): int {
+    return def(x: int) => x + n;
+}
+
+def main() {
+    store add = make_adder(1);
+    store total: int = apply(add, 2) + make_adder(2)(3);
+    apply(def(s: string) => s, 1);
+    add(\"one\");
+    add(1, 2);
+    total(1);
+    store broken = def(x: int): int { print(x); };
+}";
+        let tokens = tokenize(source).unwrap();
+        let program = parse_strict(&tokens).unwrap();
+        let analysis = check(program, None);
+        
+        let errors: Vec<(usize, &str)> = analysis.errors.iter()
+            .map(|e| (e.span.unwrap().line, e.message.as_str()))
+            .collect();
+        assert_eq!(errors, vec![
+            (12, "Type mismatch in argument 1 of function 'apply': expected Function { params: [Integer], return_type: Integer }, got Function { params: [String], return_type: String }"),
+            (13, "Type mismatch in argument 1 of function value: expected int, got string"),
+            (14, "Function value expects 1 arguments, but 2 were provided"),
+            (15, "Cannot call a value of type int"),
+            (16, "Lambda with return type int must have a return statement"),
+        ]);
+    }
+    
+    #[test]
+    fn test_typed_program() {
+        let source = "\
+def half(n: int): float {
+    return n / 2;
+}
+def main() {
+    store names: array[string] = [];
+    store scale = def(x: float) => x * 2.0;
+    print(scale(half(3)) > 1.0);
+}";
+        let tokens = tokenize(source).unwrap();
+        let typed = analyze(parse_strict(&tokens).unwrap()).unwrap();
+        
+        let Statement::FunctionDeclaration { body, .. } = &typed.program.statements[1] else { panic!() };
+        let Statement::LetDeclaration { initializer: Some(names), .. } = &body[0] else { panic!() };
+        assert_eq!(typed.type_of(names), Some(&Type::Array(Box::new(Type::String))));
+        
+        let Statement::Expression(call) = &body[2] else { panic!() };
+        assert_eq!(typed.type_of(call), Some(&Type::Void));
+        let Expr::Call { callee: print, arguments, .. } = call else { panic!() };
+        let Expr::Binary { left: scaled, .. } = &arguments[0] else { panic!() };
+        let Expr::Call { callee: scale, arguments, .. } = scaled.as_ref() else { panic!() };
+        let Expr::Call { callee: half, .. } = &arguments[0] else { panic!() };
+        assert_eq!(typed.type_of(&arguments[0]), Some(&Type::Float));
+        assert_eq!(typed.type_of(scaled), Some(&Type::Float));
+        
+        let kind = |callee: &Expr| typed.symbol_of(callee).map(|id| typed.definition(id).kind);
+        assert_eq!(kind(scale), Some(DefinitionKind::Variable));
+        assert_eq!(kind(half), Some(DefinitionKind::Function));
+        assert_eq!(kind(print), None);
+    }
+    
+    #[test]
+    fn test_parameter_inference() {
+        let source = "\
+struct Point { x: float, y: float }
+def area(radius) {
+    return 3.14 * radius * radius;
+}
+def twice(n) {
+    return n * 2;
+}
+def quad(m) {
+    return twice(twice(m));
+}
+def label(name, loud) {
+    if (loud) { return name + \"!\"; }
+    return name;
+}
+def norm(p) {
+    return p.x * p.x + p.y * p.y;
+}
+def main() {
+    print(area(2.5));
+    print(quad(3));
+    print(label(\"a\", false));
+    print(norm(Point { x: 3.0, y: 4.0 }));
+}";
+        let tokens = tokenize(source).unwrap();
+        let typed = analyze(parse_strict(&tokens).unwrap()).unwrap();
+        let signatures: Vec<(String, Vec<Type>, Option<Type>)> = typed.program.statements.iter()
+            .filter_map(|statement| match statement {
+                Statement::FunctionDeclaration { name, parameters, return_type, .. } if name != "main" => {
+                    Some((name.clone(), parameters.iter().map(|param| param.param_type.clone()).collect(), return_type.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(signatures, vec![
+            ("area".to_string(), vec![Type::Float], Some(Type::Float)),
+            ("twice".to_string(), vec![Type::Integer], Some(Type::Integer)),
+            ("quad".to_string(), vec![Type::Integer], Some(Type::Integer)),
+            ("label".to_string(), vec![Type::String, Type::Boolean], Some(Type::String)),
+            ("norm".to_string(), vec![Type::Named("Point".to_string())], Some(Type::Float)),
+        ]);
+    }
+    
+    #[test]
+    fn test_parameter_inference_errors() {
+        let source = "\
+def show(x) {
+    print(x);
+}
+def unused(u) {
+    return 1;
+}
+def square(n) {
+    return n * square(n - 1);
+}
+export def half(h) {
+    return h / 2;
+}
+def main() {
+    show(1);
+    show(\"one\");
+    print(half(4));
+}";
+        let tokens = tokenize(source).unwrap();
+        let analysis = check(parse_strict(&tokens).unwrap(), None);
+        let errors: Vec<&str> = analysis.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(errors, vec![
+            "Cannot infer the type of parameter 'x' of function 'show': it is used as int on line 14 and as string on line 15; add a type annotation",
+            "Cannot infer the type of parameter 'u' of function 'unused'; add a type annotation",
+            "Cannot infer whether parameter 'n' of function 'square' is an int or a float; add a type annotation",
+            "Cannot infer whether parameter 'h' of function 'half' is an int or a float; add a type annotation",
+        ]);
+    }
+    
+    #[test]
+    fn test_exported_parameter_types() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("geometry.nlang"), "\
+export def circle_area(radius: float) {
+    return 3.14159 * radius * radius;
+}
+export def distance(dx) {
+    return abs_float(dx);
+}
+def main() {
+    print(circle_area(5.0));
+    print(distance(1.0));
+}
+").unwrap();
+        let main = dir.path().join("main.nlang");
+        // The module's own calls don't decide what importers may pass
+        let source = "import geometry;\ndef main() {\n    print(circle_area(2.0) + distance(-1.5));\n}";
+        let analysis = check(parse_strict(&tokenize(source).unwrap()).unwrap(), Some(&main));
+        assert!(analysis.errors.is_empty(), "{:?}", analysis.errors);
+        
+        std::fs::write(dir.path().join("geometry.nlang"), "\
+export def circle_area(radius) {
+    return 3.14159 * radius * radius;
+}
+def main() {
+    print(circle_area(5));
+}
+").unwrap();
+        let analysis = check(parse_strict(&tokenize(source).unwrap()).unwrap(), Some(&main));
+        let ModuleErrors::Semantic(errors) = &analysis.errors[0].module.as_ref().unwrap().errors else {
+            panic!("expected semantic errors, got {:?}", analysis.errors);
+        };
+        assert_eq!(errors[0].message, "Cannot infer whether parameter 'radius' of function 'circle_area' is an int or a float; add a type annotation");
+    }
+    
+    #[test]
+    fn test_format_string_checks() {
+        let source = "\
+def show(ratio) {
+    return f\"{ratio:.2}\";
+}
+def main() {
+    store xs = [1];
+    store label = show(0.5) + f\"{len(xs):03} {true:>5}\";
+    println(f\"{xs}\");
+    println(f\"{1:.1} {label:02}\");
+}";
+        let tokens = tokenize(source).unwrap();
+        let analysis = check(parse_strict(&tokens).unwrap(), None);
+        let errors: Vec<&str> = analysis.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(errors, vec![
+            "Cannot interpolate a value of type array[int] into a format string",
+            "A precision in a format spec only applies to floats, not int",
+        ]);
+        assert_eq!(analysis.errors[0].span.unwrap().column, 16);
+        
+        let program = analyze(parse_strict(&tokenize("def show(ratio) {\n    return f\"{ratio:.2}\";\n}\ndef main() {\n    store s = show(1.5);\n}").unwrap()).unwrap()).unwrap();
+        let Statement::FunctionDeclaration { parameters, .. } = &program.program.statements[0] else {
+            panic!("Expected a function");
+        };
+        assert_eq!(parameters[0].param_type, Type::Float);
+        let Statement::FunctionDeclaration { body, .. } = &program.program.statements[1] else {
+            panic!("Expected a function");
+        };
+        let Statement::LetDeclaration { initializer: Some(call), .. } = &body[0] else {
+            panic!("Expected a declaration");
+        };
+        assert_eq!(program.type_of(call), Some(&Type::String));
+    }
+    
+    #[test]
+    fn test_export_docs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("util.nlang"), "\
+/// Doubles `x`.
+export def double(x: int): int {
+    return x * 2;
+}
+/// The number of fingers.
+export store ten = 10;
+export def bare() {}
+").unwrap();
+        let main = dir.path().join("main.nlang");
+        let source = "import util;\n/// Entry point.\ndef main() {\n    print(double(ten));\n    bare();\n}";
+        let program = analyze_with_file_path(parse_strict(&tokenize(source).unwrap()).unwrap(), Some(&main)).unwrap();
+        let doc = |name: &str| {
+            let definition = program.symbols.definitions.iter().find(|definition| definition.name == name).unwrap();
+            definition.doc.as_deref()
+        };
+        assert_eq!(doc("double"), Some("Doubles `x`."));
+        assert_eq!(doc("ten"), Some("The number of fingers."));
+        assert_eq!(doc("bare"), None);
+        assert_eq!(doc("main"), Some("Entry point."));
+    }
+    
+    #[test]
+    fn test_no_cascading_errors() {
+        let source = "def f(): int {\n    return nope;\n}\ndef g() {\n    store h = def(): int { return nada; };\n}\ndef main() {}";
+        let analysis = check(parse_strict(&tokenize(source).unwrap()).unwrap(), None);
+        let errors: Vec<&str> = analysis.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(errors, ["Undefined variable: nope", "Undefined variable: nada"]);
+        
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.nlang"), "export def bad(): int {\n    return missing;\n}\n").unwrap();
+        let main = dir.path().join("main.nlang");
+        let source = "from lib import bad;\ndef main() {\n    store x = bad();\n    print(bad() + x);\n}";
+        let analysis = check(parse_strict(&tokenize(source).unwrap()).unwrap(), Some(&main));
+        assert_eq!(analysis.errors.len(), 1, "{:?}", analysis.errors);
+        assert!(analysis.errors[0].message.ends_with("lib.nlang' has 1 error"));
+        assert_eq!(analysis.errors[0].span.unwrap().line, 1);
+        // The module's own errors come along, located in its source
+        let module = analysis.errors[0].module.as_ref().unwrap();
+        assert!(module.path.ends_with("lib.nlang"));
+        let ModuleErrors::Semantic(errors) = &module.errors else {
+            panic!("expected semantic errors, got {:?}", module.errors);
+        };
+        assert_eq!(errors[0].message, "Undefined variable: missing");
+        let span = errors[0].span.unwrap();
+        assert_eq!(&module.source[span.start..span.end], "missing");
+        
+        // A plain import poisons the module's exports too, with or without an alias
+        for source in [
+            "import lib;\ndef main() {\n    store x = bad();\n    print(bad() + x);\n}",
+            "import lib as l;\ndef main() {\n    store x = l.bad() + 1;\n    print(l.bad() + x);\n}",
+        ] {
+            let analysis = check(parse_strict(&tokenize(source).unwrap()).unwrap(), Some(&main));
+            let errors: Vec<&str> = analysis.errors.iter().map(|e| e.message.as_str()).collect();
+            assert_eq!(errors.len(), 1, "{}: {:?}", source, errors);
+            assert!(errors[0].ends_with("lib.nlang' has 1 error"));
+        }
+    }
+}
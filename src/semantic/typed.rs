@@ -0,0 +1,68 @@
+//! What analysis found out about a program
+//!
+//! Analysis records the [`Type`] of every expression it checks, and the
+//! [`SymbolIndex`] links every name to the definition it refers to. Backends
+//! read both from a [`TypedProgram`] rather than working them out again.
+
+use super::index::{Definition, SymbolIndex};
+use crate::ast::{Expr, Program, Type};
+use crate::span::Span;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Index of a definition in [`SymbolIndex::definitions`].
+pub type SymbolId = usize;
+
+#[derive(Debug, Clone, Default)]
+pub struct TypeTable {
+    // Keyed by span like the symbol index's references, and for the same
+    // reason: the last type recorded at a span is the one analysis settled on
+    types: HashMap<Span, Type>,
+}
+
+impl TypeTable {
+    pub(super) fn record(&mut self, span: Span, ty: Type) {
+        self.types.insert(span, ty);
+    }
+
+    /// The type of an analyzed expression; `None` for expressions that have
+    /// no type of their own, like ranges.
+    pub fn type_of(&self, expr: &Expr) -> Option<&Type> {
+        self.types.get(&expr.span())
+    }
+
+    /// Every type recorded, in no particular order.
+    pub fn types(&self) -> impl Iterator<Item = &Type> {
+        self.types.values()
+    }
+}
+
+/// An analyzed program, with the type of each of its expressions and the
+/// definition of each of its names.
+#[derive(Debug, Clone)]
+pub struct TypedProgram {
+    pub program: Program,
+    pub types: TypeTable,
+    /// Shared, so functions can keep what their names resolve to after the
+    /// program they were declared in is gone.
+    pub symbols: Rc<SymbolIndex>,
+}
+
+impl TypedProgram {
+    pub fn type_of(&self, expr: &Expr) -> Option<&Type> {
+        self.types.type_of(expr)
+    }
+
+    /// The definition the name `expr` refers to. `None` for built-in
+    /// functions and for expressions that aren't names.
+    pub fn symbol_of(&self, expr: &Expr) -> Option<SymbolId> {
+        match expr {
+            Expr::Variable { span, .. } => self.symbols.reference(*span)?.definition,
+            _ => None,
+        }
+    }
+
+    pub fn definition(&self, id: SymbolId) -> &Definition {
+        &self.symbols.definitions[id]
+    }
+}
@@ -0,0 +1,49 @@
+//! Source positions shared by tokens, AST nodes and diagnostics
+
+use std::fmt;
+
+/// A region of source text.
+///
+/// `start` and `end` are byte offsets into the source (end exclusive);
+/// `line` and `column` are the 1-based position of `start`, with the
+/// column counted in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self { start, end, line, column }
+    }
+
+    /// Returns a span covering everything from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        if other.end <= self.end {
+            return self;
+        }
+        Span {
+            start: self.start,
+            end: other.end,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
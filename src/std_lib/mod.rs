@@ -2,6 +2,7 @@
 //! This module contains the built-in functions and types available in Nlang
 
 use crate::ast::{Expr, Literal, Type};
+use crate::span::Span;
 use std::io::{self, Write};
 
 pub struct StdLib {
@@ -59,6 +60,22 @@ impl StdLib {
                     implementation: builtin_len,
                 },
                 
+                // Array Functions
+                // The analyzer checks `len` and `push` on arrays of any
+                // element type, so the array types here are placeholders
+                BuiltInFunction {
+                    name: "len".to_string(),
+                    parameters: vec![Type::Array(Box::new(Type::Integer))],
+                    return_type: Type::Integer,
+                    implementation: builtin_len,
+                },
+                BuiltInFunction {
+                    name: "push".to_string(),
+                    parameters: vec![Type::Array(Box::new(Type::Integer)), Type::Integer],
+                    return_type: Type::Void,
+                    implementation: builtin_push,
+                },
+                
                 // Type Conversion Functions
                 BuiltInFunction {
                     name: "int".to_string(),
@@ -130,6 +147,28 @@ impl StdLib {
                     return_type: Type::Boolean,
                     implementation: builtin_bool,
                 },
+                
+                // Assertions, for `test` functions
+                // The analyzer checks `assert_eq` and `assert_ne` on values
+                // of any matching types, so their types here are placeholders
+                BuiltInFunction {
+                    name: "assert".to_string(),
+                    parameters: vec![Type::Boolean],
+                    return_type: Type::Void,
+                    implementation: builtin_assert,
+                },
+                BuiltInFunction {
+                    name: "assert_eq".to_string(),
+                    parameters: vec![Type::Integer, Type::Integer],
+                    return_type: Type::Void,
+                    implementation: builtin_assert_eq,
+                },
+                BuiltInFunction {
+                    name: "assert_ne".to_string(),
+                    parameters: vec![Type::Integer, Type::Integer],
+                    return_type: Type::Void,
+                    implementation: builtin_assert_ne,
+                },
             ],
             types: vec![
                 BuiltInType {
@@ -227,10 +266,15 @@ impl Default for StdLib {
     }
 }
 
+// Builtins produce values, not source nodes, so their results carry no location
+fn literal(value: Literal) -> Expr {
+    Expr::Literal { value, span: Span::default() }
+}
+
 // Helper function to extract string value from expression
 fn extract_string_value(expr: &Expr) -> Result<String, String> {
     match expr {
-        Expr::Literal(Literal::String(s)) => Ok(s.clone()),
+        Expr::Literal { value: Literal::String(s), .. } => Ok(s.clone()),
         _ => Err("Expected string literal".to_string()),
     }
 }
@@ -238,7 +282,7 @@ fn extract_string_value(expr: &Expr) -> Result<String, String> {
 // Helper function to extract integer value from expression
 fn extract_integer_value(expr: &Expr) -> Result<i64, String> {
     match expr {
-        Expr::Literal(Literal::Integer(i)) => Ok(*i),
+        Expr::Literal { value: Literal::Integer(i), .. } => Ok(*i),
         _ => Err("Expected integer literal".to_string()),
     }
 }
@@ -247,16 +291,15 @@ fn extract_integer_value(expr: &Expr) -> Result<i64, String> {
 #[allow(dead_code)]
 fn extract_float_value(expr: &Expr) -> Result<f64, String> {
     match expr {
-        Expr::Literal(Literal::Float(f)) => Ok(*f),
+        Expr::Literal { value: Literal::Float(f), .. } => Ok(*f),
         _ => Err("Expected float literal".to_string()),
     }
 }
 
 // Helper function to extract boolean value from expression
-#[allow(dead_code)]
 fn extract_boolean_value(expr: &Expr) -> Result<bool, String> {
     match expr {
-        Expr::Literal(Literal::Boolean(b)) => Ok(*b),
+        Expr::Literal { value: Literal::Boolean(b), .. } => Ok(*b),
         _ => Err("Expected boolean literal".to_string()),
     }
 }
@@ -264,11 +307,11 @@ fn extract_boolean_value(expr: &Expr) -> Result<bool, String> {
 // Helper function to convert any expression to string for printing
 fn expr_to_string(expr: &Expr) -> Result<String, String> {
     match expr {
-        Expr::Literal(Literal::String(s)) => Ok(s.clone()),
-        Expr::Literal(Literal::Integer(i)) => Ok(i.to_string()),
-        Expr::Literal(Literal::Float(f)) => Ok(f.to_string()),
-        Expr::Literal(Literal::Boolean(b)) => Ok(b.to_string()),
-        Expr::Literal(Literal::Null) => Ok("null".to_string()),
+        Expr::Literal { value: Literal::String(s), .. } => Ok(s.clone()),
+        Expr::Literal { value: Literal::Integer(i), .. } => Ok(i.to_string()),
+        Expr::Literal { value: Literal::Float(f), .. } => Ok(f.to_string()),
+        Expr::Literal { value: Literal::Boolean(b), .. } => Ok(b.to_string()),
+        Expr::Literal { value: Literal::Null, .. } => Ok("null".to_string()),
         _ => Err("Cannot convert expression to string".to_string()),
     }
 }
@@ -283,7 +326,7 @@ fn builtin_print(args: &[Expr]) -> Result<Expr, String> {
     print!("{}", text);
     io::stdout().flush().map_err(|e| format!("IO error: {}", e))?;
     
-    Ok(Expr::Literal(Literal::Null))
+    Ok(literal(Literal::Null))
 }
 
 fn builtin_println(args: &[Expr]) -> Result<Expr, String> {
@@ -294,7 +337,7 @@ fn builtin_println(args: &[Expr]) -> Result<Expr, String> {
     let text = expr_to_string(&args[0])?;
     println!("{}", text);
     
-    Ok(Expr::Literal(Literal::Null))
+    Ok(literal(Literal::Null))
 }
 
 fn builtin_input(_args: &[Expr]) -> Result<Expr, String> {
@@ -310,7 +353,7 @@ fn builtin_input(_args: &[Expr]) -> Result<Expr, String> {
         }
     }
     
-    Ok(Expr::Literal(Literal::String(input)))
+    Ok(literal(Literal::String(input)))
 }
 
 fn builtin_len(args: &[Expr]) -> Result<Expr, String> {
@@ -318,8 +361,27 @@ fn builtin_len(args: &[Expr]) -> Result<Expr, String> {
         return Err("len() takes exactly 1 argument".to_string());
     }
     
+    if let Expr::Array { elements, .. } = &args[0] {
+        return Ok(literal(Literal::Integer(elements.len() as i64)));
+    }
+    
     let text = extract_string_value(&args[0])?;
-    Ok(Expr::Literal(Literal::Integer(text.len() as i64)))
+    Ok(literal(Literal::Integer(text.len() as i64)))
+}
+
+fn builtin_push(args: &[Expr]) -> Result<Expr, String> {
+    if args.len() != 2 {
+        return Err("push() takes exactly 2 arguments".to_string());
+    }
+    
+    match &args[0] {
+        Expr::Array { elements, span } => {
+            let mut elements = elements.clone();
+            elements.push(args[1].clone());
+            Ok(Expr::Array { elements, span: *span })
+        }
+        _ => Err("Expected array".to_string()),
+    }
 }
 
 fn builtin_int(args: &[Expr]) -> Result<Expr, String> {
@@ -331,7 +393,7 @@ fn builtin_int(args: &[Expr]) -> Result<Expr, String> {
     let parsed = text.trim().parse::<i64>()
         .map_err(|_| format!("Cannot convert '{}' to integer", text))?;
     
-    Ok(Expr::Literal(Literal::Integer(parsed)))
+    Ok(literal(Literal::Integer(parsed)))
 }
 
 fn builtin_int_from_float(args: &[Expr]) -> Result<Expr, String> {
@@ -340,7 +402,7 @@ fn builtin_int_from_float(args: &[Expr]) -> Result<Expr, String> {
     }
     
     let num = extract_float_value(&args[0])?;
-    Ok(Expr::Literal(Literal::Integer(num as i64)))
+    Ok(literal(Literal::Integer(num as i64)))
 }
 
 fn builtin_str(args: &[Expr]) -> Result<Expr, String> {
@@ -349,7 +411,7 @@ fn builtin_str(args: &[Expr]) -> Result<Expr, String> {
     }
     
     let num = extract_integer_value(&args[0])?;
-    Ok(Expr::Literal(Literal::String(num.to_string())))
+    Ok(literal(Literal::String(num.to_string())))
 }
 
 fn builtin_str_from_float(args: &[Expr]) -> Result<Expr, String> {
@@ -358,7 +420,7 @@ fn builtin_str_from_float(args: &[Expr]) -> Result<Expr, String> {
     }
     
     let num = extract_float_value(&args[0])?;
-    Ok(Expr::Literal(Literal::String(num.to_string())))
+    Ok(literal(Literal::String(num.to_string())))
 }
 
 fn builtin_float(args: &[Expr]) -> Result<Expr, String> {
@@ -370,7 +432,7 @@ fn builtin_float(args: &[Expr]) -> Result<Expr, String> {
     let parsed = text.trim().parse::<f64>()
         .map_err(|_| format!("Cannot convert '{}' to float", text))?;
     
-    Ok(Expr::Literal(Literal::Float(parsed)))
+    Ok(literal(Literal::Float(parsed)))
 }
 
 fn builtin_abs(args: &[Expr]) -> Result<Expr, String> {
@@ -379,7 +441,7 @@ fn builtin_abs(args: &[Expr]) -> Result<Expr, String> {
     }
     
     let num = extract_integer_value(&args[0])?;
-    Ok(Expr::Literal(Literal::Integer(num.abs())))
+    Ok(literal(Literal::Integer(num.abs())))
 }
 
 fn builtin_abs_float(args: &[Expr]) -> Result<Expr, String> {
@@ -388,7 +450,7 @@ fn builtin_abs_float(args: &[Expr]) -> Result<Expr, String> {
     }
     
     let num = extract_float_value(&args[0])?;
-    Ok(Expr::Literal(Literal::Float(num.abs())))
+    Ok(literal(Literal::Float(num.abs())))
 }
 
 fn builtin_max(args: &[Expr]) -> Result<Expr, String> {
@@ -398,7 +460,7 @@ fn builtin_max(args: &[Expr]) -> Result<Expr, String> {
     
     let a = extract_integer_value(&args[0])?;
     let b = extract_integer_value(&args[1])?;
-    Ok(Expr::Literal(Literal::Integer(a.max(b))))
+    Ok(literal(Literal::Integer(a.max(b))))
 }
 
 fn builtin_min(args: &[Expr]) -> Result<Expr, String> {
@@ -408,7 +470,7 @@ fn builtin_min(args: &[Expr]) -> Result<Expr, String> {
     
     let a = extract_integer_value(&args[0])?;
     let b = extract_integer_value(&args[1])?;
-    Ok(Expr::Literal(Literal::Integer(a.min(b))))
+    Ok(literal(Literal::Integer(a.min(b))))
 }
 
 fn builtin_pow(args: &[Expr]) -> Result<Expr, String> {
@@ -424,7 +486,7 @@ fn builtin_pow(args: &[Expr]) -> Result<Expr, String> {
     }
     
     let result = base.pow(exp as u32);
-    Ok(Expr::Literal(Literal::Integer(result)))
+    Ok(literal(Literal::Integer(result)))
 }
 
 fn builtin_bool(args: &[Expr]) -> Result<Expr, String> {
@@ -433,7 +495,42 @@ fn builtin_bool(args: &[Expr]) -> Result<Expr, String> {
     }
     
     let num = extract_integer_value(&args[0])?;
-    Ok(Expr::Literal(Literal::Boolean(num != 0)))
+    Ok(literal(Literal::Boolean(num != 0)))
+}
+
+fn builtin_assert(args: &[Expr]) -> Result<Expr, String> {
+    if args.len() != 1 {
+        return Err("assert() takes exactly 1 argument".to_string());
+    }
+    
+    if !extract_boolean_value(&args[0])? {
+        return Err("Assertion failed: condition is false".to_string());
+    }
+    Ok(literal(Literal::Null))
+}
+
+fn builtin_assert_eq(args: &[Expr]) -> Result<Expr, String> {
+    if args.len() != 2 {
+        return Err("assert_eq() takes exactly 2 arguments".to_string());
+    }
+    
+    let (left, right) = (expr_to_string(&args[0])?, expr_to_string(&args[1])?);
+    if left != right {
+        return Err(format!("Assertion failed: left == right (left: {}, right: {})", left, right));
+    }
+    Ok(literal(Literal::Null))
+}
+
+fn builtin_assert_ne(args: &[Expr]) -> Result<Expr, String> {
+    if args.len() != 2 {
+        return Err("assert_ne() takes exactly 2 arguments".to_string());
+    }
+    
+    let (left, right) = (expr_to_string(&args[0])?, expr_to_string(&args[1])?);
+    if left == right {
+        return Err(format!("Assertion failed: left != right (both are {})", left));
+    }
+    Ok(literal(Literal::Null))
 }
 
 #[cfg(test)]
@@ -462,22 +559,34 @@ mod tests {
     
     #[test]
     fn test_string_length() {
-        let args = vec![Expr::Literal(Literal::String("hello".to_string()))];
+        let args = vec![literal(Literal::String("hello".to_string()))];
         let result = builtin_len(&args).unwrap();
         
         match result {
-            Expr::Literal(Literal::Integer(5)) => {},
+            Expr::Literal { value: Literal::Integer(5), .. } => {},
             _ => panic!("Expected integer 5"),
         }
     }
     
+    #[test]
+    fn test_array_functions() {
+        let array = Expr::Array {
+            elements: vec![literal(Literal::Integer(1)), literal(Literal::Integer(2))],
+            span: Span::default(),
+        };
+        let pushed = builtin_push(&[array, literal(Literal::Integer(3))]).unwrap();
+        
+        let result = builtin_len(&[pushed]).unwrap();
+        assert!(matches!(result, Expr::Literal { value: Literal::Integer(3), .. }));
+    }
+    
     #[test]
     fn test_int_conversion() {
-        let args = vec![Expr::Literal(Literal::String("42".to_string()))];
+        let args = vec![literal(Literal::String("42".to_string()))];
         let result = builtin_int(&args).unwrap();
         
         match result {
-            Expr::Literal(Literal::Integer(42)) => {},
+            Expr::Literal { value: Literal::Integer(42), .. } => {},
             _ => panic!("Expected integer 42"),
         }
     }
@@ -485,24 +594,40 @@ mod tests {
     #[test]
     fn test_mathematical_functions() {
         // Test abs
-        let args = vec![Expr::Literal(Literal::Integer(-5))];
+        let args = vec![literal(Literal::Integer(-5))];
         let result = builtin_abs(&args).unwrap();
-        assert!(matches!(result, Expr::Literal(Literal::Integer(5))));
+        assert!(matches!(result, Expr::Literal { value: Literal::Integer(5), .. }));
         
         // Test max
         let args = vec![
-            Expr::Literal(Literal::Integer(3)),
-            Expr::Literal(Literal::Integer(7))
+            literal(Literal::Integer(3)),
+            literal(Literal::Integer(7))
         ];
         let result = builtin_max(&args).unwrap();
-        assert!(matches!(result, Expr::Literal(Literal::Integer(7))));
+        assert!(matches!(result, Expr::Literal { value: Literal::Integer(7), .. }));
         
         // Test min
         let args = vec![
-            Expr::Literal(Literal::Integer(3)),
-            Expr::Literal(Literal::Integer(7))
+            literal(Literal::Integer(3)),
+            literal(Literal::Integer(7))
         ];
         let result = builtin_min(&args).unwrap();
-        assert!(matches!(result, Expr::Literal(Literal::Integer(3))));
+        assert!(matches!(result, Expr::Literal { value: Literal::Integer(3), .. }));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_assertions() {
+        assert!(builtin_assert(&[literal(Literal::Boolean(true))]).is_ok());
+        assert_eq!(builtin_assert(&[literal(Literal::Boolean(false))]).unwrap_err(), "Assertion failed: condition is false");
+        
+        let one = literal(Literal::Integer(1));
+        let two = literal(Literal::Integer(2));
+        assert!(builtin_assert_eq(&[one.clone(), one.clone()]).is_ok());
+        assert_eq!(
+            builtin_assert_eq(&[one.clone(), two.clone()]).unwrap_err(),
+            "Assertion failed: left == right (left: 1, right: 2)"
+        );
+        assert!(builtin_assert_ne(&[one.clone(), two]).is_ok());
+        assert!(builtin_assert_ne(&[one.clone(), one]).is_err());
+    }
+}
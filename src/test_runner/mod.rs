@@ -0,0 +1,88 @@
+//! Finding and running `test def` functions
+//!
+//! A test file is checked like an imported module, so it needs no `main`.
+//! Every test runs in a fresh interpreter that has loaded the file, so one
+//! test can't see globals another one changed.
+
+use crate::ast::{Program, Statement};
+use crate::execution_engine::ExecutionError;
+use crate::interpreter::{Interpreter, InterpreterError};
+use crate::lexer::tokenize;
+use crate::parser::parse_strict;
+use crate::semantic::{self, SemanticErrors, TypedProgram};
+use crate::span::Span;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests;
+
+/// A `test def` function found in a file.
+#[derive(Debug, Clone)]
+pub struct TestCase {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    /// The error a failing test stopped with.
+    pub outcome: Result<(), InterpreterError>,
+    pub duration: Duration,
+}
+
+/// The tests of one file, ready to run.
+pub struct TestFile {
+    /// `None` for files without tests, which aren't analyzed.
+    program: Option<TypedProgram>,
+    file_path: Option<String>,
+    pub tests: Vec<TestCase>,
+}
+
+impl TestFile {
+    /// Lexes, parses and analyzes `source`. Files without tests are
+    /// returned without being analyzed, since they won't be run.
+    pub fn load(source: &str, path: &Path) -> Result<TestFile, ExecutionError> {
+        let tokens = tokenize(source)?;
+        let program = parse_strict(&tokens)?;
+        let file_path = path.to_str().map(str::to_string);
+        if find_tests(&program).is_empty() {
+            return Ok(TestFile { program: None, file_path, tests: Vec::new() });
+        }
+
+        let analysis = semantic::check_module(program, Some(path));
+        if !analysis.errors.is_empty() {
+            return Err(SemanticErrors(analysis.errors).into());
+        }
+        let tests = find_tests(&analysis.program);
+        Ok(TestFile { program: Some(analysis.into_typed()), file_path, tests })
+    }
+
+    /// Runs the test called `name` in a fresh interpreter.
+    pub fn run(&self, name: &str) -> TestResult {
+        let start = Instant::now();
+        let outcome = self.run_in_isolation(name);
+        TestResult { name: name.to_string(), outcome, duration: start.elapsed() }
+    }
+
+    fn run_in_isolation(&self, name: &str) -> Result<(), InterpreterError> {
+        let mut interpreter = Interpreter::new();
+        if let Some(program) = &self.program {
+            interpreter.execute_incremental(program, self.file_path.as_deref())?;
+        }
+        interpreter.call_function(name, &[])?;
+        Ok(())
+    }
+}
+
+fn find_tests(program: &Program) -> Vec<TestCase> {
+    program.statements.iter()
+        .filter_map(|statement| match statement {
+            Statement::FunctionDeclaration { name, is_test: true, span, .. } => {
+                Some(TestCase { name: name.clone(), span: *span })
+            }
+            _ => None,
+        })
+        .collect()
+}
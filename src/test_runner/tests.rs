@@ -0,0 +1,69 @@
+#[cfg(test)]
+mod test_runner_tests {
+    use crate::interpreter::InterpreterError;
+    use crate::test_runner::TestFile;
+    use std::path::Path;
+
+    fn failure(file: &TestFile, name: &str) -> String {
+        file.run(name).outcome.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_run_tests() {
+        let source = r#"
+            store counter = 0;
+            def double(x: int): int {
+                return x * 2;
+            }
+            test def doubles() {
+                counter = counter + 1;
+                assert_eq(double(2), 4);
+                assert_ne(double(2), 5);
+                assert(counter == 1);
+            }
+            test def sees_fresh_globals() {
+                counter = counter + 1;
+                assert_eq(counter, 1);
+            }
+            test def compares_strings() {
+                assert_eq(str(double(1)), "3");
+            }
+            test def compares_arrays() {
+                assert_ne([1, 2], [1, 2]);
+            }
+            test def checks_condition() {
+                assert(double(1) > 2);
+            }
+            def test(x: int): int {
+                return x;
+            }
+        "#;
+        let file = TestFile::load(source, Path::new("math_test.nlang")).unwrap();
+        let names: Vec<&str> = file.tests.iter().map(|test| test.name.as_str()).collect();
+        assert_eq!(names, ["doubles", "sees_fresh_globals", "compares_strings", "compares_arrays", "checks_condition"]);
+
+        assert!(file.run("doubles").outcome.is_ok());
+        assert!(file.run("sees_fresh_globals").outcome.is_ok());
+        assert_eq!(
+            failure(&file, "compares_strings"),
+            "Assertion failed: left == right (left: \"2\", right: \"3\") (line 17, column 17)"
+        );
+        assert_eq!(
+            failure(&file, "compares_arrays"),
+            "Assertion failed: left != right (both are [1, 2]) (line 20, column 17)"
+        );
+        assert!(matches!(
+            file.run("checks_condition").outcome,
+            Err(InterpreterError::At { error, .. }) if matches!(*error, InterpreterError::AssertionFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_tests() {
+        let error = TestFile::load("test def takes(x: int) {}", Path::new("bad.nlang")).err().unwrap();
+        assert!(error.to_string().contains("Test function 'takes' must not take parameters"));
+
+        let error = TestFile::load("test def compares() { assert_eq(1, \"1\"); }", Path::new("bad.nlang")).err().unwrap();
+        assert!(error.to_string().contains("Built-in function 'assert_eq' compares values of the same type, got int and string"));
+    }
+}